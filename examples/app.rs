@@ -8,20 +8,15 @@ use std::{
 };
 
 use colored::Colorize as _;
-use salish::{
-    endpoint::Endpoint,
-    message::{Destination, Message},
-    policy::Policy,
-    router::MessageRouter,
-};
+use salish::{endpoint::Endpoint, message::Message, router::MessageRouter};
 
 /// Example App struct representing some application state
 #[derive(Debug)]
 struct App<'a> {
     // Application message router, yielding a Task from each message handler
-    pub router: MessageRouter<'static, Task, u32>,
+    pub router: MessageRouter<'static, Task>,
 
-    temp_endpoints: Vec<Endpoint<'a, TempMessage, Task, u32>>,
+    temp_endpoints: Vec<Endpoint<'a, TempMessage, Task>>,
 
     count: Arc<AtomicU64>,
 }
@@ -30,13 +25,11 @@ impl<'a> App<'a> {
     fn new() -> Self {
         let router = MessageRouter::new();
 
-        let app = Self {
+        Self {
             router,
             temp_endpoints: Vec::new(),
             count: Arc::new(AtomicU64::new(0)),
-        };
-
-        app
+        }
     }
 }
 
@@ -109,12 +102,12 @@ fn main() {
             temp: 21.22,
         }));
 
-        if let Some(tasks) = tasks {
+        if let Some(tasks) = tasks.results() {
             //assert_eq!(tasks.len(), 100000);
             count += tasks.len() as u64;
         }
 
-        if count % 10000000u64 == 0 && count > 0 {
+        if count.is_multiple_of(10000000u64) && count > 0 {
             // Calculate messages per second
             let elapsed = last_time.elapsed().as_secs_f64();
             let messages_per_second = (count - last_count) as f64 / elapsed;
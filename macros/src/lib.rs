@@ -0,0 +1,52 @@
+//! Procedural macros for Salish
+//!
+//! These are re-exported by `salish` with the `registration` feature, and should be used through it.
+
+use proc_macro::TokenStream;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, ItemFn, Type};
+
+/// Register a free function as a static endpoint of the payload type given as argument.
+///
+/// The function takes the same arguments as the handler of
+/// `MessageRouter::static_endpoint()`, and is added to routers created with
+/// `MessageRouter::with_registered_handlers()` whose handler result type matches the return type of the function.
+///
+/// ```ignore
+/// #[salish::handler(TempMessage)]
+/// fn log_temperature(_src: Option<SourceRef>, msg: TempMessage) {
+///     println!("{msg:?}");
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn handler(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let payload = parse_macro_input!(attr as Type);
+    let handler = parse_macro_input!(item as ItemFn);
+
+    if let Some(generics) = handler.sig.generics.lt_token {
+        return syn::Error::new(generics.span, "registered handlers can't be generic")
+            .to_compile_error()
+            .into();
+    }
+
+    let name = &handler.sig.ident;
+    let register = format_ident!("__salish_register_{}", name);
+
+    quote! {
+        #handler
+
+        #[doc(hidden)]
+        fn #register(router: &dyn ::std::any::Any) -> bool {
+            ::salish::registration::register::<#payload, _, _>(router, #name)
+        }
+
+        ::salish::registration::inventory::submit! {
+            ::salish::registration::RegisteredHandler::new(
+                ::std::concat!(::std::module_path!(), "::", ::std::stringify!(#name)),
+                ::std::stringify!(#payload),
+                #register,
+            )
+        }
+    }
+    .into()
+}
@@ -0,0 +1,308 @@
+//! Batching of frames sent by a [`Bridge`](super::Bridge)
+//!
+//! High rate streams of small messages spend most of their time in per-frame overhead, such as a syscall
+//! per frame. With batching enabled in the [`BridgeConfig`], frames are queued and sent together as a single
+//! batch frame once [`BridgeConfig::max_batch`] frames are queued, or the oldest queued frame has waited
+//! for [`BridgeConfig::max_delay`], whichever comes first.
+//!
+//! Batch frames are [`WireMessage`]s with the reserved identifier [`BATCH_ID`], carrying the encoded frames.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use tracing::{error, trace};
+
+use super::{
+    compression::{Compression, Compressor},
+    flow::{BridgeStalled, Flow, FlowControl},
+    heartbeat::{Heartbeat, Liveness, PeerRecovered, PeerUnreachable},
+    BridgeError, Codec, Transport, WireDest, WireMessage,
+};
+
+/// Identifier of batch frames, which is reserved and can't be used for payload types
+pub const BATCH_ID: &str = "salish::batch";
+
+/// Interval at which an idle batch thread checks for shutdown
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Configuration of a [`Bridge`](super::Bridge)
+#[derive(Debug, Clone, Copy)]
+pub struct BridgeConfig {
+    /// Maximum number of frames sent in a batch. Frames are sent immediately without batching if this is 1.
+    pub max_batch: usize,
+
+    /// Maximum time a frame waits in the queue for more frames to batch with
+    pub max_delay: Duration,
+
+    /// Compression algorithm of sent frames, used once all peers accept it
+    pub compression: Compression,
+
+    /// Minimum size in bytes of compressed frames. Smaller frames are sent as is.
+    pub compression_threshold: usize,
+
+    /// Maximum number of frames in flight before sending blocks for the peer to consume them.
+    /// Flow control is disabled if this is 0.
+    pub window: usize,
+
+    /// Maximum time sending blocks for the peer to consume frames, after which the frame is dropped
+    pub stall_timeout: Duration,
+
+    /// Interval at which [heartbeats](super::heartbeat) are sent to peers. Heartbeats are disabled if this is zero.
+    pub heartbeat_interval: Duration,
+
+    /// Time without a heartbeat from a peer after which it's reported unreachable
+    pub heartbeat_timeout: Duration,
+}
+
+impl Default for BridgeConfig {
+    /// Batching, compression, flow control and heartbeats are disabled by default
+    fn default() -> Self {
+        Self {
+            max_batch: 1,
+            max_delay: Duration::ZERO,
+            compression: Compression::None,
+            compression_threshold: 256,
+            window: 0,
+            stall_timeout: Duration::from_secs(1),
+            heartbeat_interval: Duration::ZERO,
+            heartbeat_timeout: Duration::from_secs(3),
+        }
+    }
+}
+
+impl BridgeConfig {
+    /// Check if frames are batched
+    pub fn is_batching(&self) -> bool {
+        self.max_batch > 1
+    }
+}
+
+/// Frames waiting to be sent
+#[derive(Debug, Default)]
+struct Pending {
+    frames: Vec<Vec<u8>>,
+
+    /// Time the oldest frame was queued
+    since: Option<Instant>,
+}
+
+/// Sends frames over a transport, batching, compressing and flow controlling them if enabled
+pub(crate) struct Outbound<T, C> {
+    transport: Arc<T>,
+    codec: C,
+    config: BridgeConfig,
+    compressor: Compressor,
+    flow: FlowControl,
+    liveness: Liveness,
+    pending: Mutex<Pending>,
+    queued: Condvar,
+}
+
+impl<T, C> std::fmt::Debug for Outbound<T, C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Outbound")
+            .field("config", &self.config)
+            .field("compressor", &self.compressor)
+            .field("flow", &self.flow)
+            .field("liveness", &self.liveness)
+            .finish()
+    }
+}
+
+impl<T, C> Outbound<T, C> {
+    /// Wake the batch thread, so it can check for shutdown
+    pub(crate) fn wake(&self) {
+        self.queued.notify_all();
+    }
+}
+
+impl<T: Transport, C: Codec> Outbound<T, C> {
+    pub(crate) fn new(transport: Arc<T>, codec: C, config: BridgeConfig) -> Self {
+        Self {
+            transport,
+            codec,
+            config,
+            compressor: Compressor::new(config.compression, config.compression_threshold),
+            flow: FlowControl::new(config.window, config.stall_timeout),
+            liveness: Liveness::new(config.heartbeat_interval, config.heartbeat_timeout),
+            pending: Mutex::new(Pending::default()),
+            queued: Condvar::new(),
+        }
+    }
+
+    /// Send a frame, or queue it if batching is enabled
+    pub(crate) fn send(&self, frame: &[u8]) -> Result<(), BridgeError> {
+        if !self.config.is_batching() {
+            return self.transmit(frame);
+        }
+
+        let mut pending = self.pending.lock().unwrap();
+        pending.frames.push(frame.to_vec());
+
+        if pending.frames.len() >= self.config.max_batch {
+            let frames = std::mem::take(&mut *pending).frames;
+            drop(pending);
+            return self.send_batch(frames);
+        }
+
+        if pending.since.is_none() {
+            pending.since = Some(Instant::now());
+            self.queued.notify_one();
+        }
+
+        Ok(())
+    }
+
+    /// Send queued frames once they have waited for the maximum delay until shutdown, then send any remaining frames
+    pub(crate) fn run(&self, shutdown: &AtomicBool) {
+        let mut pending = self.pending.lock().unwrap();
+
+        while !shutdown.load(Ordering::Relaxed) {
+            let Some(since) = pending.since else {
+                pending = self.queued.wait_timeout(pending, POLL_INTERVAL).unwrap().0;
+                continue;
+            };
+
+            let deadline = since + self.config.max_delay;
+            let now = Instant::now();
+
+            if now < deadline {
+                pending = self.queued.wait_timeout(pending, deadline - now).unwrap().0;
+                continue;
+            }
+
+            let frames = std::mem::take(&mut *pending).frames;
+            drop(pending);
+
+            if let Err(e) = self.send_batch(frames) {
+                error!("Failed to send batch: {e}");
+            }
+
+            pending = self.pending.lock().unwrap();
+        }
+
+        // Send frames queued before shutdown
+        let frames = std::mem::take(&mut *pending).frames;
+        drop(pending);
+
+        if let Err(e) = self.send_batch(frames) {
+            error!("Failed to send batch: {e}");
+        }
+    }
+
+    /// Send frames as a single batch frame, or as a plain frame if there is only one
+    fn send_batch(&self, frames: Vec<Vec<u8>>) -> Result<(), BridgeError> {
+        match frames.len() {
+            0 => Ok(()),
+            1 => self.transmit(&frames[0]),
+            len => {
+                trace!("Sending batch of {len} frames");
+
+                let wire = WireMessage {
+                    type_name: BATCH_ID.into(),
+                    payload: self.codec.encode(&frames)?,
+                    dest: WireDest::Broadcast,
+                    traceparent: None,
+                };
+
+                self.transmit(&wire.to_frame(&self.codec)?)
+            }
+        }
+    }
+
+    /// Announce the capabilities of the bridge to peers
+    pub(crate) fn announce(&self) -> Result<(), BridgeError> {
+        self.transport
+            .send_frame(&self.compressor.announcement(&self.codec)?)
+    }
+
+    /// Record the capabilities of a peer, replying if the peer requested it
+    pub(crate) fn update_capabilities(&self, payload: &[u8]) -> Result<(), BridgeError> {
+        match self
+            .compressor
+            .update(&self.codec, self.codec.decode(payload)?)?
+        {
+            Some(reply) => self.transport.send_frame(&reply),
+            None => Ok(()),
+        }
+    }
+
+    /// Handle a flow control frame from a peer, acknowledging pings and returning acknowledged credits
+    pub(crate) fn update_flow(&self, payload: &[u8]) -> Result<(), BridgeError> {
+        match self.codec.decode(payload)? {
+            Flow::Ping { session, seq } => self
+                .transport
+                .send_frame(&Flow::Ack { session, seq }.to_frame(&self.codec)?),
+            Flow::Ack { session, seq } => {
+                self.flow.ack(session, seq);
+                Ok(())
+            }
+        }
+    }
+
+    /// Take a stall of the bridge which has not been reported yet
+    pub(crate) fn take_stall(&self) -> Option<BridgeStalled> {
+        self.flow.take_stall().then(|| BridgeStalled {
+            transport: std::any::type_name::<T>(),
+            window: self.flow.window(),
+        })
+    }
+
+    /// Send a heartbeat to peers if one is due. Heartbeats bypass batching and flow control.
+    pub(crate) fn heartbeat(&self) -> Result<(), BridgeError> {
+        match self.liveness.heartbeat_due() {
+            Some(heartbeat) => self.transport.send_frame(&heartbeat.to_frame(&self.codec)?),
+            None => Ok(()),
+        }
+    }
+
+    /// Record a heartbeat frame from a peer, returning [`PeerRecovered`] if the peer was unreachable
+    pub(crate) fn update_heartbeat(
+        &self,
+        payload: &[u8],
+    ) -> Result<Option<PeerRecovered>, BridgeError> {
+        let heartbeat: Heartbeat = self.codec.decode(payload)?;
+
+        Ok(self
+            .liveness
+            .seen(heartbeat)
+            .map(|unreachable_for| PeerRecovered {
+                transport: std::any::type_name::<T>(),
+                peer: heartbeat.session,
+                unreachable_for,
+            }))
+    }
+
+    /// Take the peers which became unreachable since the last call
+    pub(crate) fn take_unreachable(&self) -> Vec<PeerUnreachable> {
+        self.liveness
+            .take_unreachable()
+            .into_iter()
+            .map(|(peer, silent_for)| PeerUnreachable {
+                transport: std::any::type_name::<T>(),
+                peer,
+                silent_for,
+            })
+            .collect()
+    }
+
+    /// Send a frame over the transport once a credit is available, compressing it if negotiated
+    fn transmit(&self, frame: &[u8]) -> Result<(), BridgeError> {
+        let ping = self.flow.acquire(&*self.transport, &self.codec)?;
+
+        match self.compressor.compress(&self.codec, frame)? {
+            Some(compressed) => self.transport.send_frame(&compressed)?,
+            None => self.transport.send_frame(frame)?,
+        }
+
+        match ping {
+            Some(ping) => self.transport.send_frame(&ping),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,66 @@
+//! Wire codecs
+//!
+//! A [`Codec`] serializes both the [`WireMessage`](super::WireMessage) envelope and the payloads inside it.
+//! [`Bincode`] is always available and is the default for all bridges. [`Postcard`] produces smaller frames,
+//! and [`Json`] produces human readable frames which are easier to debug.
+//!
+//! All peers of a bridge must use the same codec.
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::BridgeError;
+
+/// Serialization format of bridged frames
+pub trait Codec: Clone + Send + Sync + 'static {
+    /// Encode a value into bytes
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, BridgeError>;
+
+    /// Decode a value from bytes
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, BridgeError>;
+}
+
+/// [bincode](https://docs.rs/bincode) codec
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Bincode;
+
+impl Codec for Bincode {
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, BridgeError> {
+        Ok(bincode::serialize(value)?)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, BridgeError> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+}
+
+/// [postcard](https://docs.rs/postcard) codec, using a compact varint encoding
+#[cfg(feature = "postcard")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Postcard;
+
+#[cfg(feature = "postcard")]
+impl Codec for Postcard {
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, BridgeError> {
+        postcard::to_allocvec(value).map_err(|e| BridgeError::Codec(e.to_string()))
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, BridgeError> {
+        postcard::from_bytes(bytes).map_err(|e| BridgeError::Codec(e.to_string()))
+    }
+}
+
+/// [JSON](https://docs.rs/serde_json) codec
+#[cfg(feature = "json")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json;
+
+#[cfg(feature = "json")]
+impl Codec for Json {
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, BridgeError> {
+        serde_json::to_vec(value).map_err(|e| BridgeError::Codec(e.to_string()))
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, BridgeError> {
+        serde_json::from_slice(bytes).map_err(|e| BridgeError::Codec(e.to_string()))
+    }
+}
@@ -0,0 +1,232 @@
+//! Compression of frames sent by a [`Bridge`](super::Bridge)
+//!
+//! Verbose payloads sent over constrained links can be compressed by setting [`BridgeConfig::compression`].
+//! Frames of at least [`BridgeConfig::compression_threshold`] bytes are compressed, and sent as
+//! [`WireMessage`]s with the reserved identifier [`COMPRESSED_ID`]. Batches are compressed as a whole.
+//!
+//! Bridges exchange [`Capabilities`] frames naming the algorithms they can decompress. Every bridge announces
+//! its capabilities when it starts, and bridges with compression enabled request the capabilities of their
+//! peers. A bridge only compresses frames once it has heard from a peer, and all peers it has heard from
+//! accept the configured algorithm, so peers built without support for the algorithm receive plain frames.
+//!
+//! [`BridgeConfig::compression`]: super::BridgeConfig::compression
+//! [`BridgeConfig::compression_threshold`]: super::BridgeConfig::compression_threshold
+
+use anylock::{AnyLock, ParkingLotMutex};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use super::{BridgeError, Codec, WireDest, WireMessage};
+
+/// Identifier of compressed frames, which is reserved and can't be used for payload types
+pub const COMPRESSED_ID: &str = "salish::compressed";
+
+/// Identifier of [`Capabilities`] frames, which is reserved and can't be used for payload types
+pub const CAPABILITIES_ID: &str = "salish::capabilities";
+
+/// Maximum size of a decompressed frame, so corrupt or malicious frames can't exhaust memory
+#[cfg(feature = "lz4")]
+const MAX_DECOMPRESSED: usize = 16 * 1024 * 1024;
+
+/// Frame compression algorithm
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Compression {
+    /// Frames are not compressed
+    #[default]
+    None,
+
+    /// LZ4 block compression, which requires the `lz4` feature
+    Lz4,
+}
+
+impl Compression {
+    /// Check if this build supports the algorithm
+    pub fn is_supported(self) -> bool {
+        match self {
+            Compression::None => true,
+            Compression::Lz4 => cfg!(feature = "lz4"),
+        }
+    }
+
+    /// Capability flag of the algorithm
+    fn flag(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Lz4 => 1,
+        }
+    }
+
+    /// Capability flags of the algorithms supported by this build
+    fn supported() -> u8 {
+        [Compression::Lz4]
+            .into_iter()
+            .filter(|compression| compression.is_supported())
+            .fold(0, |flags, compression| flags | compression.flag())
+    }
+
+    fn compress(self, frame: &[u8]) -> Result<Vec<u8>, BridgeError> {
+        match self {
+            Compression::None => Ok(frame.to_vec()),
+
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => Ok(lz4_flex::compress_prepend_size(frame)),
+
+            #[allow(unreachable_patterns)]
+            _ => Err(BridgeError::Codec(format!(
+                "{self:?} compression is not supported"
+            ))),
+        }
+    }
+
+    fn decompress(self, data: &[u8]) -> Result<Vec<u8>, BridgeError> {
+        match self {
+            Compression::None => Ok(data.to_vec()),
+
+            #[cfg(feature = "lz4")]
+            Compression::Lz4 => {
+                let (len, _) = lz4_flex::block::uncompressed_size(data)
+                    .map_err(|e| BridgeError::Codec(e.to_string()))?;
+
+                if len > MAX_DECOMPRESSED {
+                    return Err(BridgeError::FrameTooLarge(len));
+                }
+
+                lz4_flex::decompress_size_prepended(data)
+                    .map_err(|e| BridgeError::Codec(e.to_string()))
+            }
+
+            #[allow(unreachable_patterns)]
+            _ => Err(BridgeError::Codec(format!(
+                "{self:?} compression is not supported"
+            ))),
+        }
+    }
+}
+
+/// Payload of a compressed frame
+#[derive(Debug, Serialize, Deserialize)]
+struct Compressed {
+    compression: Compression,
+    frame: Vec<u8>,
+}
+
+/// Compression algorithms a bridge can decompress, sent over the control plane of bridges
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// Flags of the compression algorithms the bridge can decompress
+    pub compression: u8,
+
+    /// True if receiving bridges should reply with their own capabilities
+    pub request: bool,
+}
+
+/// Compression state of a bridge
+#[derive(Debug)]
+pub(crate) struct Compressor {
+    compression: Compression,
+    threshold: usize,
+
+    /// Flags of the algorithms accepted by all peers heard from, or `None` before hearing from any peer
+    accepted: ParkingLotMutex<Option<u8>>,
+}
+
+impl Compressor {
+    pub(crate) fn new(compression: Compression, threshold: usize) -> Self {
+        Self {
+            compression,
+            threshold,
+            accepted: ParkingLotMutex::new(None),
+        }
+    }
+
+    /// Create a frame announcing the capabilities of this bridge, requesting replies if compression is enabled
+    pub(crate) fn announcement(&self, codec: &impl Codec) -> Result<Vec<u8>, BridgeError> {
+        self.capabilities_frame(codec, self.compression != Compression::None)
+    }
+
+    /// Record the capabilities of a peer, returning a reply frame if the peer requested one
+    pub(crate) fn update(
+        &self,
+        codec: &impl Codec,
+        capabilities: Capabilities,
+    ) -> Result<Option<Vec<u8>>, BridgeError> {
+        {
+            let mut accepted = self.accepted.write();
+            let flags = accepted.map_or(capabilities.compression, |flags| {
+                flags & capabilities.compression
+            });
+
+            if *accepted != Some(flags) {
+                debug!("Peers accept compression flags {flags:#x}");
+                *accepted = Some(flags);
+            }
+        }
+
+        if capabilities.request {
+            self.capabilities_frame(codec, false).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Check if all peers heard from accept the configured algorithm
+    pub(crate) fn is_negotiated(&self) -> bool {
+        let flag = self.compression.flag();
+        flag != 0
+            && self
+                .accepted
+                .read()
+                .is_some_and(|flags| flags & flag == flag)
+    }
+
+    /// Compress a frame if compression is negotiated and the frame is large enough.
+    /// Returns `None` if the frame should be sent as is.
+    pub(crate) fn compress(
+        &self,
+        codec: &impl Codec,
+        frame: &[u8],
+    ) -> Result<Option<Vec<u8>>, BridgeError> {
+        if frame.len() < self.threshold || !self.is_negotiated() {
+            return Ok(None);
+        }
+
+        let wire = WireMessage {
+            type_name: COMPRESSED_ID.into(),
+            payload: codec.encode(&Compressed {
+                compression: self.compression,
+                frame: self.compression.compress(frame)?,
+            })?,
+            dest: WireDest::Broadcast,
+            traceparent: None,
+        };
+
+        let compressed = wire.to_frame(codec)?;
+
+        // Incompressible frames are sent as is
+        Ok((compressed.len() < frame.len()).then_some(compressed))
+    }
+
+    fn capabilities_frame(
+        &self,
+        codec: &impl Codec,
+        request: bool,
+    ) -> Result<Vec<u8>, BridgeError> {
+        let wire = WireMessage {
+            type_name: CAPABILITIES_ID.into(),
+            payload: codec.encode(&Capabilities {
+                compression: Compression::supported(),
+                request,
+            })?,
+            dest: WireDest::Broadcast,
+            traceparent: None,
+        };
+
+        wire.to_frame(codec)
+    }
+}
+
+/// Decompress the payload of a compressed frame
+pub(crate) fn decompress(codec: &impl Codec, payload: &[u8]) -> Result<Vec<u8>, BridgeError> {
+    let compressed: Compressed = codec.decode(payload)?;
+    compressed.compression.decompress(&compressed.frame)
+}
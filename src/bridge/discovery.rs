@@ -0,0 +1,187 @@
+//! Discovery of the payload types handled by remote routers
+//!
+//! A [`Bridge`](super::Bridge) with discovery enabled periodically sends an [`Advertisement`] naming its node,
+//! and listing the identifiers of the registered payload types which have endpoints in its local router.
+//! Advertisements are valid for a time-to-live, and are sent three times per time-to-live, so a node which
+//! stops advertising expires after missing a few advertisements. A bridge withdraws its advertisement when
+//! it is dropped.
+//!
+//! Unicast messages with no local endpoint are sent to one of the nodes which advertised their payload type,
+//! selected in round robin order, and delivered to a single endpoint in the router of that node.
+//! Messages sent to [`Destination::Remote`](crate::message::Destination::Remote) are sent by the bridges
+//! which have live advertisements from the destination node, whatever payload types it advertised.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use anylock::{AnyLock, ParkingLotMutex, ParkingLotRwLock};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use crate::message::NodeId;
+
+/// Identifier of [`Advertisement`] frames, which is reserved and can't be used for payload types
+pub const ADVERTISEMENT_ID: &str = "salish::advertisement";
+
+/// Payload types a node can handle, sent over the control plane of bridges
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Advertisement {
+    /// Identity of the advertising node
+    pub node: String,
+
+    /// Identifiers of the payload types the node has endpoints for
+    pub types: Vec<String>,
+
+    /// Milliseconds after which the advertisement expires. Zero withdraws previous advertisements.
+    pub ttl_ms: u64,
+}
+
+/// Payload types advertised by a remote node
+#[derive(Debug)]
+struct RemoteNode {
+    types: HashSet<String>,
+    expires: Instant,
+}
+
+/// Advertisement state of a bridge
+#[derive(Debug)]
+pub(crate) struct Discovery {
+    /// Identity of the local node
+    node: String,
+
+    /// Identifier of the local node
+    id: NodeId,
+
+    /// Time-to-live of local advertisements
+    ttl: Duration,
+
+    /// Time the next advertisement is due
+    next_advertisement: ParkingLotMutex<Instant>,
+
+    /// Latest advertisement of each remote node
+    nodes: ParkingLotRwLock<HashMap<String, RemoteNode>>,
+
+    /// Next index for round robin selection of remote nodes
+    next_index: AtomicUsize,
+}
+
+impl Discovery {
+    pub(crate) fn new(node: String, ttl: Duration) -> Self {
+        Self {
+            id: NodeId::new(&node),
+            node,
+            ttl,
+            next_advertisement: ParkingLotMutex::new(Instant::now()),
+            nodes: ParkingLotRwLock::new(HashMap::new()),
+            next_index: AtomicUsize::new(0),
+        }
+    }
+
+    /// Get the identity of the local node
+    pub(crate) fn node(&self) -> &str {
+        &self.node
+    }
+
+    /// Get the identifier of the local node
+    pub(crate) fn id(&self) -> NodeId {
+        self.id
+    }
+
+    /// Check if an advertisement is due, scheduling the next advertisement if it is
+    pub(crate) fn advertisement_due(&self) -> bool {
+        let now = Instant::now();
+        let mut next = self.next_advertisement.write();
+
+        if now >= *next {
+            *next = now + self.ttl / 3;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Create an advertisement of the local node handling payload types `types`
+    pub(crate) fn advertisement(&self, types: Vec<String>) -> Advertisement {
+        Advertisement {
+            node: self.node.clone(),
+            types,
+            ttl_ms: self.ttl.as_millis() as u64,
+        }
+    }
+
+    /// Create an advertisement withdrawing the local node
+    pub(crate) fn withdrawal(&self) -> Advertisement {
+        Advertisement {
+            node: self.node.clone(),
+            types: Vec::new(),
+            ttl_ms: 0,
+        }
+    }
+
+    /// Record an advertisement from a remote node, replacing its previous advertisement.
+    /// If the node was not known, the local node advertises immediately so the new node learns of it.
+    pub(crate) fn update(&self, advertisement: Advertisement) {
+        if advertisement.node == self.node {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut nodes = self.nodes.write();
+        nodes.retain(|_, remote| remote.expires > now);
+
+        if advertisement.ttl_ms == 0 {
+            debug!("Node {} withdrew its advertisement", advertisement.node);
+            nodes.remove(&advertisement.node);
+            return;
+        }
+
+        let remote = RemoteNode {
+            types: advertisement.types.into_iter().collect(),
+            expires: now + Duration::from_millis(advertisement.ttl_ms),
+        };
+
+        if nodes.insert(advertisement.node.clone(), remote).is_none() {
+            debug!("Discovered node {}", advertisement.node);
+            *self.next_advertisement.write() = now;
+        }
+    }
+
+    /// Get the nodes with live advertisements of payload type `id`, sorted by identity
+    pub(crate) fn nodes(&self, id: &str) -> Vec<String> {
+        let now = Instant::now();
+        let mut nodes: Vec<String> = self
+            .nodes
+            .read()
+            .iter()
+            .filter(|(_, remote)| remote.expires > now && remote.types.contains(id))
+            .map(|(node, _)| node.clone())
+            .collect();
+
+        nodes.sort();
+        nodes
+    }
+
+    /// Check if a remote node has a live advertisement
+    pub(crate) fn is_live(&self, node: NodeId) -> bool {
+        let now = Instant::now();
+        self.nodes
+            .read()
+            .iter()
+            .any(|(name, remote)| remote.expires > now && NodeId::new(name) == node)
+    }
+
+    /// Select a node with a live advertisement of payload type `id` in round robin order
+    pub(crate) fn select(&self, id: &str) -> Option<String> {
+        let nodes = self.nodes(id);
+
+        if nodes.is_empty() {
+            return None;
+        }
+
+        let index = self.next_index.fetch_add(1, Ordering::Relaxed);
+        Some(nodes[index % nodes.len()].clone())
+    }
+}
@@ -0,0 +1,352 @@
+//! Encrypted transports
+//!
+//! [`EncryptedTransport`] wraps any [`Transport`], sealing each frame with XChaCha20-Poly1305 so payloads
+//! crossing machines are neither readable nor modifiable by anyone without the key of the sender.
+//!
+//! Transports may be connectionless or broadcast, so there is no interactive handshake. Instead every frame
+//! opens with a header carrying the identity of the sender and the nonce of the frame. The header is
+//! authenticated along with the ciphertext, and the receiver decrypts with the key its [`KeyProvider`] holds
+//! for that identity. Frames from unknown identities, frames which fail authentication, and replayed frames
+//! are dropped.
+//!
+//! Nonces are the 16 byte random session identifier of the sending transport followed by a 64 bit frame
+//! counter, so nonces are never reused under a key, even by peers restarting with the same key. Receivers
+//! track a sliding window of counters per session and reject frames they have already accepted.
+
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use anylock::{AnyLock, ParkingLotMutex};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use rand::RngCore as _;
+use tracing::warn;
+
+use super::{BridgeError, Transport};
+
+/// Frame format version
+const VERSION: u8 = 1;
+
+/// Length of the session identifier at the start of each nonce
+const SESSION_LEN: usize = 16;
+
+/// Length of an XChaCha20-Poly1305 nonce
+const NONCE_LEN: usize = 24;
+
+/// Number of counters below the highest accepted counter which are still accepted if they arrive out of order
+const REPLAY_WINDOW: u64 = 64;
+
+/// 256 bit XChaCha20-Poly1305 key
+pub type Key = [u8; 32];
+
+/// Provides the identity of the local peer, and the keys of trusted peers
+pub trait KeyProvider: Send + Sync + 'static {
+    /// Identity of the local peer, sent in the header of every frame. Must be at most 255 bytes.
+    fn identity(&self) -> &str;
+
+    /// Get the key frames from `peer` are encrypted with, or `None` if the peer is not trusted.
+    ///
+    /// The local peer encrypts frames with the key of its own identity.
+    fn key(&self, peer: &str) -> Option<Key>;
+}
+
+/// [`KeyProvider`] with a fixed set of keys
+#[derive(Clone)]
+pub struct StaticKeys {
+    identity: String,
+    keys: HashMap<String, Key>,
+}
+
+impl std::fmt::Debug for StaticKeys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StaticKeys")
+            .field("identity", &self.identity)
+            .field("peers", &self.keys.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl StaticKeys {
+    /// Create a provider for the local peer `identity`, which encrypts frames with `key`
+    pub fn new(identity: impl Into<String>, key: Key) -> Self {
+        let identity = identity.into();
+        Self {
+            keys: HashMap::from([(identity.clone(), key)]),
+            identity,
+        }
+    }
+
+    /// Trust frames from `peer` encrypted with `key`
+    pub fn peer(mut self, peer: impl Into<String>, key: Key) -> Self {
+        self.keys.insert(peer.into(), key);
+        self
+    }
+}
+
+impl KeyProvider for StaticKeys {
+    fn identity(&self) -> &str {
+        &self.identity
+    }
+
+    fn key(&self, peer: &str) -> Option<Key> {
+        self.keys.get(peer).copied()
+    }
+}
+
+/// Reason a received frame was dropped
+#[derive(Debug)]
+enum Rejected {
+    Malformed,
+    UnknownPeer(String),
+    Authentication(String),
+    Replayed(String),
+}
+
+impl std::fmt::Display for Rejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rejected::Malformed => write!(f, "malformed encrypted frame"),
+            Rejected::UnknownPeer(peer) => write!(f, "frame from untrusted peer {peer}"),
+            Rejected::Authentication(peer) => write!(f, "frame from {peer} failed authentication"),
+            Rejected::Replayed(peer) => write!(f, "replayed frame from {peer}"),
+        }
+    }
+}
+
+/// Counters accepted from a sending session
+#[derive(Debug)]
+struct ReplayWindow {
+    /// Highest accepted counter
+    highest: u64,
+
+    /// Bit `n` is set if counter `highest - n` has been accepted
+    seen: u64,
+}
+
+impl ReplayWindow {
+    fn new(counter: u64) -> Self {
+        Self {
+            highest: counter,
+            seen: 1,
+        }
+    }
+
+    /// Record a counter, returning false if it was already accepted or is too old to tell
+    fn accept(&mut self, counter: u64) -> bool {
+        if counter > self.highest {
+            let shift = counter - self.highest;
+            self.seen = if shift >= REPLAY_WINDOW {
+                0
+            } else {
+                self.seen << shift
+            };
+            self.seen |= 1;
+            self.highest = counter;
+            return true;
+        }
+
+        let offset = self.highest - counter;
+        if offset >= REPLAY_WINDOW || self.seen & (1 << offset) != 0 {
+            return false;
+        }
+
+        self.seen |= 1 << offset;
+        true
+    }
+}
+
+/// [`Transport`] encrypting and authenticating the frames of an inner transport with keys from a [`KeyProvider`]
+pub struct EncryptedTransport<T, K> {
+    inner: T,
+    keys: K,
+
+    /// Random identifier of this transport, forming the first part of each nonce
+    session: [u8; SESSION_LEN],
+
+    /// Counter of sent frames, forming the last part of each nonce
+    counter: AtomicU64,
+
+    /// Replay windows of sessions frames have been received from, by sender identity and session
+    windows: ParkingLotMutex<HashMap<(String, [u8; SESSION_LEN]), ReplayWindow>>,
+}
+
+impl<T, K> std::fmt::Debug for EncryptedTransport<T, K>
+where
+    T: std::fmt::Debug,
+    K: KeyProvider,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EncryptedTransport")
+            .field("inner", &self.inner)
+            .field("identity", &self.keys.identity())
+            .field("sent", &self.counter.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+impl<T, K> EncryptedTransport<T, K>
+where
+    T: Transport,
+    K: KeyProvider,
+{
+    /// Encrypt frames sent over `inner` with keys from `keys`.
+    ///
+    /// Fails if the local identity is longer than 255 bytes, or `keys` has no key for it.
+    pub fn new(inner: T, keys: K) -> Result<Self, BridgeError> {
+        let identity = keys.identity();
+
+        if identity.len() > u8::MAX as usize {
+            return Err(BridgeError::Transport(format!(
+                "identity of {} bytes is too long",
+                identity.len()
+            )));
+        }
+
+        if keys.key(identity).is_none() {
+            return Err(BridgeError::Transport(format!("no key for {identity}")));
+        }
+
+        let mut session = [0; SESSION_LEN];
+        rand::rngs::OsRng.fill_bytes(&mut session);
+
+        Ok(Self {
+            inner,
+            keys,
+            session,
+            counter: AtomicU64::new(0),
+            windows: ParkingLotMutex::new(HashMap::new()),
+        })
+    }
+
+    /// Get a reference to the inner transport
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// Get a reference to the key provider
+    pub fn keys(&self) -> &K {
+        &self.keys
+    }
+
+    /// Encrypt a frame, prefixing it with the header
+    fn seal(&self, frame: &[u8]) -> Result<Vec<u8>, BridgeError> {
+        let identity = self.keys.identity();
+        let key = self
+            .keys
+            .key(identity)
+            .ok_or_else(|| BridgeError::Transport(format!("no key for {identity}")))?;
+
+        let mut nonce = [0; NONCE_LEN];
+        nonce[..SESSION_LEN].copy_from_slice(&self.session);
+        nonce[SESSION_LEN..]
+            .copy_from_slice(&self.counter.fetch_add(1, Ordering::Relaxed).to_be_bytes());
+
+        let mut sealed = Vec::with_capacity(2 + identity.len() + NONCE_LEN + frame.len() + 16);
+        sealed.push(VERSION);
+        sealed.push(identity.len() as u8);
+        sealed.extend_from_slice(identity.as_bytes());
+        let header_len = sealed.len();
+        sealed.extend_from_slice(&nonce);
+
+        let ciphertext = XChaCha20Poly1305::new(&key.into())
+            .encrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: frame,
+                    aad: &sealed[..header_len],
+                },
+            )
+            .map_err(|_| BridgeError::Transport("encryption failed".into()))?;
+
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    /// Authenticate and decrypt a frame from a peer
+    fn open(&self, sealed: &[u8]) -> Result<Vec<u8>, Rejected> {
+        let [VERSION, identity_len, rest @ ..] = sealed else {
+            return Err(Rejected::Malformed);
+        };
+        let header_len = 2 + *identity_len as usize;
+
+        if rest.len() < *identity_len as usize + NONCE_LEN {
+            return Err(Rejected::Malformed);
+        }
+
+        let identity =
+            std::str::from_utf8(&sealed[2..header_len]).map_err(|_| Rejected::Malformed)?;
+        let nonce = &sealed[header_len..header_len + NONCE_LEN];
+
+        let key = self
+            .keys
+            .key(identity)
+            .ok_or_else(|| Rejected::UnknownPeer(identity.into()))?;
+
+        let frame = XChaCha20Poly1305::new(&key.into())
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: &sealed[header_len + NONCE_LEN..],
+                    aad: &sealed[..header_len],
+                },
+            )
+            .map_err(|_| Rejected::Authentication(identity.into()))?;
+
+        // Only authenticated frames update replay windows, so forged frames can't advance them
+        let mut session = [0; SESSION_LEN];
+        session.copy_from_slice(&nonce[..SESSION_LEN]);
+        let counter = u64::from_be_bytes(nonce[SESSION_LEN..].try_into().unwrap());
+
+        let mut windows = self.windows.write();
+        let accepted = match windows.get_mut(&(identity.to_string(), session)) {
+            Some(window) => window.accept(counter),
+            None => {
+                windows.insert((identity.into(), session), ReplayWindow::new(counter));
+                true
+            }
+        };
+
+        if accepted {
+            Ok(frame)
+        } else {
+            Err(Rejected::Replayed(identity.into()))
+        }
+    }
+}
+
+impl<T, K> Transport for EncryptedTransport<T, K>
+where
+    T: Transport,
+    K: KeyProvider,
+{
+    fn connect(&self, addr: &str) -> Result<(), BridgeError> {
+        self.inner.connect(addr)
+    }
+
+    fn send_frame(&self, frame: &[u8]) -> Result<(), BridgeError> {
+        self.inner.send_frame(&self.seal(frame)?)
+    }
+
+    fn recv_frame(&self, timeout: Duration) -> Result<Option<Vec<u8>>, BridgeError> {
+        let deadline = Instant::now() + timeout;
+
+        // Keep receiving until a frame is accepted, so rejected frames don't cut the timeout short
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+
+            let Some(sealed) = self.inner.recv_frame(remaining)? else {
+                return Ok(None);
+            };
+
+            match self.open(&sealed) {
+                Ok(frame) => return Ok(Some(frame)),
+                Err(e) => warn!("Dropping {e}"),
+            }
+        }
+    }
+}
@@ -0,0 +1,193 @@
+//! Credit based flow control of frames sent by a [`Bridge`](super::Bridge)
+//!
+//! With a [`BridgeConfig::window`] set, a bridge allows at most that many frames to be in flight, sent but not
+//! yet consumed by the peer. Every half window the sender follows its frames with a ping, which the peer
+//! acknowledges once it has dispatched all frames received before the ping into its router, returning credits
+//! to the sender. When a bridge runs out of credits, sending blocks until the peer catches up, so a slow
+//! consumer backpressures the sender instead of filling socket buffers. A [`BridgeStalled`] message is
+//! broadcast into the local router when this happens, and if the peer doesn't catch up within
+//! [`BridgeConfig::stall_timeout`], the frame is dropped with [`BridgeError::Stalled`].
+//!
+//! Pings and acknowledgements are [`WireMessage`]s with the reserved identifier [`FLOW_ID`]. Every bridge
+//! acknowledges pings, whether or not it limits its own frames. Flow control is designed for bridges with a
+//! single peer, as acknowledgements from any peer return credits.
+//!
+//! [`BridgeConfig::window`]: super::BridgeConfig::window
+//! [`BridgeConfig::stall_timeout`]: super::BridgeConfig::stall_timeout
+
+use std::{
+    sync::{Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::{trace, warn};
+
+use super::{BridgeError, Codec, Transport, WireDest, WireMessage};
+
+/// Identifier of flow control frames, which is reserved and can't be used for payload types
+pub const FLOW_ID: &str = "salish::flow";
+
+/// Interval at which a stalled sender repeats its ping, in case it was lost
+const PING_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Broadcast into the local router when a bridge runs out of credits, and sending blocks until the peer
+/// consumes the frames in flight
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BridgeStalled {
+    /// Type name of the transport of the stalled bridge
+    pub transport: &'static str,
+
+    /// Maximum number of frames in flight
+    pub window: usize,
+}
+
+/// Flow control frame
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub(crate) enum Flow {
+    /// Request to acknowledge frames up to `seq` once they are consumed
+    Ping { session: u64, seq: u64 },
+
+    /// Frames up to `seq` of the sending session were consumed
+    Ack { session: u64, seq: u64 },
+}
+
+impl Flow {
+    pub(crate) fn to_frame(self, codec: &impl Codec) -> Result<Vec<u8>, BridgeError> {
+        let wire = WireMessage {
+            type_name: FLOW_ID.into(),
+            payload: codec.encode(&self)?,
+            dest: WireDest::Broadcast,
+            traceparent: None,
+        };
+
+        wire.to_frame(codec)
+    }
+}
+
+/// Credits of a sender
+#[derive(Debug, Default)]
+struct Credits {
+    /// Frames sent
+    sent: u64,
+
+    /// Frames acknowledged by the peer
+    acked: u64,
+
+    /// Frames covered by the latest ping
+    pinged: u64,
+
+    /// True while out of credits, until the peer returns credits
+    in_stall: bool,
+
+    /// True if a stall is waiting to be reported
+    stalled: bool,
+}
+
+/// Flow control state of a bridge
+#[derive(Debug)]
+pub(crate) struct FlowControl {
+    window: u64,
+    stall_timeout: Duration,
+
+    /// Random identifier of this sender, so acknowledgements of other senders are ignored
+    session: u64,
+
+    credits: Mutex<Credits>,
+    acked: Condvar,
+}
+
+impl FlowControl {
+    pub(crate) fn new(window: usize, stall_timeout: Duration) -> Self {
+        Self {
+            window: window as u64,
+            stall_timeout,
+            session: rand::random(),
+            credits: Mutex::new(Credits::default()),
+            acked: Condvar::new(),
+        }
+    }
+
+    /// Take a credit to send a frame, waiting for the peer to return credits if there are none.
+    ///
+    /// Returns the ping to send after the frame, if one is due.
+    pub(crate) fn acquire<C: Codec>(
+        &self,
+        transport: &impl Transport,
+        codec: &C,
+    ) -> Result<Option<Vec<u8>>, BridgeError> {
+        if self.window == 0 {
+            return Ok(None);
+        }
+
+        let deadline = Instant::now() + self.stall_timeout;
+        let mut credits = self.credits.lock().unwrap();
+
+        while credits.sent - credits.acked >= self.window {
+            let now = Instant::now();
+            if now >= deadline {
+                warn!(
+                    "Peer did not consume frames within {:?}",
+                    self.stall_timeout
+                );
+                return Err(BridgeError::Stalled);
+            }
+
+            if !credits.in_stall {
+                trace!(
+                    "Out of credits with {} frames in flight",
+                    credits.sent - credits.acked
+                );
+                credits.in_stall = true;
+                credits.stalled = true;
+            }
+
+            // Repeat the ping, in case it was lost
+            credits.pinged = credits.sent;
+            transport.send_frame(&self.ping(credits.sent).to_frame(codec)?)?;
+
+            let timeout = PING_INTERVAL.min(deadline - now);
+            credits = self.acked.wait_timeout(credits, timeout).unwrap().0;
+        }
+
+        credits.sent += 1;
+
+        if credits.sent - credits.pinged >= (self.window / 2).max(1) {
+            credits.pinged = credits.sent;
+            return self.ping(credits.sent).to_frame(codec).map(Some);
+        }
+
+        Ok(None)
+    }
+
+    /// Return credits acknowledged by the peer
+    pub(crate) fn ack(&self, session: u64, seq: u64) {
+        if session != self.session {
+            return;
+        }
+
+        let mut credits = self.credits.lock().unwrap();
+        if seq > credits.acked {
+            credits.acked = seq;
+            credits.in_stall = false;
+            self.acked.notify_all();
+        }
+    }
+
+    /// Take a stall which has not been reported yet
+    pub(crate) fn take_stall(&self) -> bool {
+        std::mem::take(&mut self.credits.lock().unwrap().stalled)
+    }
+
+    /// Get the maximum number of frames in flight
+    pub(crate) fn window(&self) -> usize {
+        self.window as usize
+    }
+
+    fn ping(&self, seq: u64) -> Flow {
+        Flow::Ping {
+            session: self.session,
+            seq,
+        }
+    }
+}
@@ -0,0 +1,176 @@
+//! Liveness of the peers of a [`Bridge`](super::Bridge)
+//!
+//! With a [`BridgeConfig::heartbeat_interval`] set, a bridge sends a heartbeat to its peers at that interval, and
+//! tracks the heartbeats it receives. A peer which doesn't send a heartbeat within
+//! [`BridgeConfig::heartbeat_timeout`] is reported by broadcasting [`PeerUnreachable`] into the local router, and
+//! [`PeerRecovered`] is broadcast once a heartbeat from the peer arrives again. Applications can subscribe to
+//! these messages to detect split links, without exchanging their own ping messages.
+//!
+//! Peers are identified by a random session of their bridge, which changes when the peer restarts. Heartbeats
+//! are [`WireMessage`]s with the reserved identifier [`HEARTBEAT_ID`]. Both peers should enable heartbeats,
+//! since a bridge without heartbeats enabled ignores the heartbeats it receives.
+//!
+//! [`BridgeConfig::heartbeat_interval`]: super::BridgeConfig::heartbeat_interval
+//! [`BridgeConfig::heartbeat_timeout`]: super::BridgeConfig::heartbeat_timeout
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::trace;
+
+use super::{BridgeError, Codec, WireDest, WireMessage};
+
+/// Identifier of heartbeat frames, which is reserved and can't be used for payload types
+pub const HEARTBEAT_ID: &str = "salish::heartbeat";
+
+/// Broadcast into the local router when a peer didn't send a heartbeat within the timeout
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerUnreachable {
+    /// Type name of the transport of the bridge
+    pub transport: &'static str,
+
+    /// Session of the unreachable peer
+    pub peer: u64,
+
+    /// Time since the last heartbeat of the peer
+    pub silent_for: Duration,
+}
+
+/// Broadcast into the local router when a heartbeat arrives from a peer which was unreachable
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerRecovered {
+    /// Type name of the transport of the bridge
+    pub transport: &'static str,
+
+    /// Session of the recovered peer
+    pub peer: u64,
+
+    /// Time since the peer was reported unreachable
+    pub unreachable_for: Duration,
+}
+
+/// Heartbeat frame
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub(crate) struct Heartbeat {
+    pub(crate) session: u64,
+}
+
+impl Heartbeat {
+    pub(crate) fn to_frame(self, codec: &impl Codec) -> Result<Vec<u8>, BridgeError> {
+        let wire = WireMessage {
+            type_name: HEARTBEAT_ID.into(),
+            payload: codec.encode(&self)?,
+            dest: WireDest::Broadcast,
+            traceparent: None,
+        };
+
+        wire.to_frame(codec)
+    }
+}
+
+#[derive(Debug)]
+struct Peer {
+    last_seen: Instant,
+    unreachable_since: Option<Instant>,
+}
+
+#[derive(Debug, Default)]
+struct LivenessState {
+    last_sent: Option<Instant>,
+    peers: HashMap<u64, Peer>,
+}
+
+/// Heartbeat state of a bridge
+#[derive(Debug)]
+pub(crate) struct Liveness {
+    interval: Duration,
+    timeout: Duration,
+
+    /// Random identifier of this bridge, so its own heartbeats are ignored
+    session: u64,
+
+    state: Mutex<LivenessState>,
+}
+
+impl Liveness {
+    pub(crate) fn new(interval: Duration, timeout: Duration) -> Self {
+        Self {
+            interval,
+            timeout,
+            session: rand::random(),
+            state: Mutex::new(LivenessState::default()),
+        }
+    }
+
+    /// Get the heartbeat to send, if heartbeats are enabled and the interval has passed since the last one
+    pub(crate) fn heartbeat_due(&self) -> Option<Heartbeat> {
+        if self.interval.is_zero() {
+            return None;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+
+        if state
+            .last_sent
+            .is_some_and(|sent| now.duration_since(sent) < self.interval)
+        {
+            return None;
+        }
+
+        state.last_sent = Some(now);
+        Some(Heartbeat {
+            session: self.session,
+        })
+    }
+
+    /// Record a heartbeat from a peer, returning the time it was unreachable if it recovered
+    pub(crate) fn seen(&self, heartbeat: Heartbeat) -> Option<Duration> {
+        if self.interval.is_zero() || heartbeat.session == self.session {
+            return None;
+        }
+
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        let peer = state.peers.entry(heartbeat.session).or_insert_with(|| {
+            trace!("First heartbeat from peer {:#x}", heartbeat.session);
+            Peer {
+                last_seen: now,
+                unreachable_since: None,
+            }
+        });
+
+        peer.last_seen = now;
+        peer.unreachable_since
+            .take()
+            .map(|since| now.duration_since(since))
+    }
+
+    /// Mark the peers without a heartbeat within the timeout as unreachable,
+    /// returning the session of each newly unreachable peer and the time since its last heartbeat
+    pub(crate) fn take_unreachable(&self) -> Vec<(u64, Duration)> {
+        if self.interval.is_zero() {
+            return Vec::new();
+        }
+
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+
+        state
+            .peers
+            .iter_mut()
+            .filter(|(_, peer)| peer.unreachable_since.is_none())
+            .filter_map(|(session, peer)| {
+                let silent_for = now.duration_since(peer.last_seen);
+                (silent_for >= self.timeout).then(|| {
+                    peer.unreachable_since = Some(now);
+                    (*session, silent_for)
+                })
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,423 @@
+//! Unix domain socket IPC bridge
+//!
+//! Bridges routers in two or more processes on the same host. One process listens on a socket path,
+//! and others connect to it. Connecting bridges automatically reconnect if the connection is lost.
+//!
+//! Frames are sent over the stream with a big-endian `u32` length prefix. Messages received over
+//! the socket are broadcast into the local router without a source.
+//!
+//! Only available on unix platforms.
+
+use std::{
+    io::{ErrorKind, Read, Write as _},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use anylock::{AnyLock, ParkingLotRwLock};
+use tracing::{debug, error, trace, warn};
+
+use crate::{endpoint::Endpoint, router::MessageRouter};
+
+use super::{register_forward, Bincode, BridgeError, BridgePayload, Codec, Decoders, TypeRegistry};
+
+/// Maximum size of a frame accepted from the socket
+pub const MAX_FRAME: usize = 16 * 1024 * 1024;
+
+/// Interval at which blocked threads check for shutdown
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Default delay between reconnection attempts
+const RECONNECT_DELAY: Duration = Duration::from_millis(500);
+
+/// Connected streams which forwarded frames are written to
+#[derive(Debug)]
+struct Connections {
+    next_id: AtomicU64,
+    streams: ParkingLotRwLock<Vec<(u64, UnixStream)>>,
+}
+
+impl Connections {
+    fn new() -> Self {
+        Self {
+            next_id: AtomicU64::new(0),
+            streams: ParkingLotRwLock::new(Vec::new()),
+        }
+    }
+
+    fn add(&self, stream: UnixStream) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.streams.write().push((id, stream));
+        id
+    }
+
+    fn remove(&self, id: u64) {
+        self.streams.write().retain(|(stream_id, _)| *stream_id != id);
+    }
+
+    fn len(&self) -> usize {
+        self.streams.read().len()
+    }
+
+    /// Write a length prefixed frame to all connections. Connections which fail are shut down,
+    /// which ends their reader thread.
+    fn send_frame(&self, frame: &[u8]) -> Result<(), BridgeError> {
+        if frame.len() > MAX_FRAME {
+            return Err(BridgeError::FrameTooLarge(frame.len()));
+        }
+
+        let len = (frame.len() as u32).to_be_bytes();
+
+        // Hold the write lock for the whole frame, so frames from different threads are not interleaved
+        let mut streams = self.streams.write();
+        streams.retain_mut(|(id, stream)| {
+            match stream.write_all(&len).and_then(|_| stream.write_all(frame)) {
+                Ok(()) => true,
+                Err(e) => {
+                    warn!("IPC connection {id} write failed: {e}");
+                    let _ = stream.shutdown(std::net::Shutdown::Both);
+                    false
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+/// Bridges registered payload types to other processes over a unix domain socket, encoding frames with codec `C`
+pub struct IpcBridge<R, C = Bincode> {
+    path: PathBuf,
+    listening: bool,
+    connections: Arc<Connections>,
+    decoders: Arc<Decoders<C>>,
+    router: MessageRouter<'static, R>,
+    shutdown: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<R, C> std::fmt::Debug for IpcBridge<R, C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IpcBridge")
+            .field("path", &self.path)
+            .field("listening", &self.listening)
+            .field("connections", &self.connections.len())
+            .field("types", &self.decoders)
+            .finish()
+    }
+}
+
+impl<R> IpcBridge<R>
+where
+    R: Default + Send + 'static,
+{
+    /// Listen for connections on a socket path. An existing socket file at the path is replaced.
+    pub fn listen(
+        path: impl AsRef<Path>,
+        router: &MessageRouter<'static, R>,
+    ) -> Result<Self, BridgeError> {
+        Self::listen_with_registry(path, router, Arc::new(TypeRegistry::new()))
+    }
+
+    /// Connect to a listening bridge at a socket path.
+    /// The connection is established in the background, and re-established whenever it is lost.
+    pub fn connect(
+        path: impl AsRef<Path>,
+        router: &MessageRouter<'static, R>,
+    ) -> Result<Self, BridgeError> {
+        Self::connect_with_delay(path, router, RECONNECT_DELAY)
+    }
+
+    /// Connect to a listening bridge, waiting `delay` between connection attempts
+    pub fn connect_with_delay(
+        path: impl AsRef<Path>,
+        router: &MessageRouter<'static, R>,
+        delay: Duration,
+    ) -> Result<Self, BridgeError> {
+        Self::connect_with_registry(path, router, delay, Arc::new(TypeRegistry::new()))
+    }
+}
+
+impl<R, C> IpcBridge<R, C>
+where
+    R: Default + Send + 'static,
+    C: Codec,
+{
+    /// Listen for connections on a socket path, identifying and encoding payloads with `registry`
+    pub fn listen_with_registry(
+        path: impl AsRef<Path>,
+        router: &MessageRouter<'static, R>,
+        registry: Arc<TypeRegistry<C>>,
+    ) -> Result<Self, BridgeError> {
+        let path = path.as_ref().to_path_buf();
+
+        match std::fs::remove_file(&path) {
+            Ok(()) => debug!("Removed stale socket {path:?}"),
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        let listener = UnixListener::bind(&path)?;
+        listener.set_nonblocking(true)?;
+
+        let mut bridge = Self::new(path, true, router, registry);
+        let (connections, decoders, shutdown) = bridge.shared();
+        let router = router.clone();
+
+        bridge.thread = Some(
+            std::thread::Builder::new()
+                .name("salish-ipc-listen".into())
+                .spawn(move || {
+                    let mut readers = Vec::new();
+
+                    while !shutdown.load(Ordering::Relaxed) {
+                        match listener.accept() {
+                            Ok((stream, _)) => {
+                                debug!("Accepted IPC connection");
+                                let (connections, decoders, shutdown) =
+                                    (connections.clone(), decoders.clone(), shutdown.clone());
+                                let router = router.clone();
+
+                                readers.push(std::thread::spawn(move || {
+                                    Self::serve(stream, &connections, &decoders, router, &shutdown)
+                                }));
+                            }
+                            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                                std::thread::sleep(POLL_INTERVAL)
+                            }
+                            Err(e) => {
+                                error!("IPC accept failed: {e}");
+                                std::thread::sleep(POLL_INTERVAL)
+                            }
+                        }
+                    }
+
+                    for reader in readers {
+                        let _ = reader.join();
+                    }
+                })?,
+        );
+
+        Ok(bridge)
+    }
+
+    /// Connect to a listening bridge, waiting `delay` between connection attempts,
+    /// and identifying and encoding payloads with `registry`
+    pub fn connect_with_registry(
+        path: impl AsRef<Path>,
+        router: &MessageRouter<'static, R>,
+        delay: Duration,
+        registry: Arc<TypeRegistry<C>>,
+    ) -> Result<Self, BridgeError> {
+        let mut bridge = Self::new(path.as_ref().to_path_buf(), false, router, registry);
+        let (connections, decoders, shutdown) = bridge.shared();
+        let path = bridge.path.clone();
+        let router = router.clone();
+
+        bridge.thread = Some(
+            std::thread::Builder::new()
+                .name("salish-ipc-connect".into())
+                .spawn(move || {
+                    while !shutdown.load(Ordering::Relaxed) {
+                        match UnixStream::connect(&path) {
+                            Ok(stream) => {
+                                debug!("Connected to {path:?}");
+                                Self::serve(stream, &connections, &decoders, router.clone(), &shutdown);
+                                debug!("Disconnected from {path:?}");
+                            }
+                            Err(e) => trace!("Connecting to {path:?} failed: {e}"),
+                        }
+
+                        if !shutdown.load(Ordering::Relaxed) {
+                            std::thread::sleep(delay);
+                        }
+                    }
+                })?,
+        );
+
+        Ok(bridge)
+    }
+
+    fn new(
+        path: PathBuf,
+        listening: bool,
+        router: &MessageRouter<'static, R>,
+        registry: Arc<TypeRegistry<C>>,
+    ) -> Self {
+        Self {
+            path,
+            listening,
+            connections: Arc::new(Connections::new()),
+            decoders: Arc::new(Decoders::new(registry)),
+            router: router.clone(),
+            shutdown: Arc::new(AtomicBool::new(false)),
+            thread: None,
+        }
+    }
+
+    /// Clone the state shared with background threads
+    fn shared(&self) -> (Arc<Connections>, Arc<Decoders<C>>, Arc<AtomicBool>) {
+        (
+            self.connections.clone(),
+            self.decoders.clone(),
+            self.shutdown.clone(),
+        )
+    }
+
+    /// Get the number of active connections
+    pub fn connections(&self) -> usize {
+        self.connections.len()
+    }
+
+    /// Register payload type `M` with the bridge.
+    ///
+    /// Messages of type `M` received over the socket are injected into the router, and messages of type `M`
+    /// dispatched in the local router are forwarded to all connections until the returned [`Endpoint`] is dropped.
+    pub fn register<M>(&self) -> Endpoint<'static, M, R>
+    where
+        M: BridgePayload,
+    {
+        let connections = self.connections.clone();
+        register_forward(&self.router, &self.decoders, move |frame| {
+            connections.send_frame(frame)
+        })
+    }
+
+    /// Send a payload directly to all connections, without dispatching it in the local router
+    pub fn send<M>(&self, payload: &M) -> Result<(), BridgeError>
+    where
+        M: BridgePayload,
+    {
+        self.connections
+            .send_frame(&self.decoders.encode_frame(payload)?)
+    }
+
+    /// Register a connected stream, and read frames from it until it closes or the bridge shuts down
+    fn serve(
+        stream: UnixStream,
+        connections: &Connections,
+        decoders: &Decoders<C>,
+        router: MessageRouter<'static, R>,
+        shutdown: &AtomicBool,
+    ) {
+        // Accepted streams may inherit non-blocking mode from the listener on some platforms
+        let reader = match stream
+            .set_nonblocking(false)
+            .and_then(|_| stream.try_clone())
+            .and_then(|reader| reader.set_read_timeout(Some(POLL_INTERVAL)).map(|_| reader))
+        {
+            Ok(reader) => reader,
+            Err(e) => {
+                error!("Failed to set up IPC connection: {e}");
+                return;
+            }
+        };
+
+        let id = connections.add(stream);
+        let mut frames = FrameReader::new(reader);
+
+        while !shutdown.load(Ordering::Relaxed) {
+            match frames.next_frame() {
+                Ok(Some(frame)) => {
+                    match decoders.decode_frame(&frame) {
+                        Ok(message) => {
+                            router.handle_message(message);
+                        }
+                        Err(e) => warn!("Dropping IPC frame: {e}"),
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    debug!("IPC connection {id} closed: {e}");
+                    break;
+                }
+            }
+        }
+
+        connections.remove(id);
+    }
+}
+
+impl<R, C> Drop for IpcBridge<R, C> {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+
+        for (_, stream) in self.connections.streams.read().iter() {
+            let _ = stream.shutdown(std::net::Shutdown::Both);
+        }
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+
+        if self.listening {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Reads length prefixed frames from a stream with a read timeout, retaining partial frames across timeouts
+struct FrameReader<T> {
+    stream: T,
+    pending: Vec<u8>,
+    buf: Vec<u8>,
+}
+
+impl<T: Read> FrameReader<T> {
+    fn new(stream: T) -> Self {
+        Self {
+            stream,
+            pending: Vec::new(),
+            buf: vec![0u8; 64 * 1024],
+        }
+    }
+
+    /// Take a complete frame from the pending bytes
+    fn take_frame(&mut self) -> Result<Option<Vec<u8>>, BridgeError> {
+        if self.pending.len() < 4 {
+            return Ok(None);
+        }
+
+        let len = u32::from_be_bytes([
+            self.pending[0],
+            self.pending[1],
+            self.pending[2],
+            self.pending[3],
+        ]) as usize;
+
+        if len > MAX_FRAME {
+            return Err(BridgeError::FrameTooLarge(len));
+        }
+
+        if self.pending.len() < 4 + len {
+            return Ok(None);
+        }
+
+        let frame = self.pending[4..4 + len].to_vec();
+        self.pending.drain(..4 + len);
+        Ok(Some(frame))
+    }
+
+    /// Get the next frame. Returns `Ok(None)` if no complete frame arrived before the read timeout,
+    /// or an error if the stream was closed.
+    fn next_frame(&mut self) -> Result<Option<Vec<u8>>, BridgeError> {
+        if let Some(frame) = self.take_frame()? {
+            return Ok(Some(frame));
+        }
+
+        match self.stream.read(&mut self.buf) {
+            Ok(0) => Err(BridgeError::Io(ErrorKind::UnexpectedEof.into())),
+            Ok(len) => {
+                self.pending.extend_from_slice(&self.buf[..len]);
+                self.take_frame()
+            }
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
@@ -0,0 +1,280 @@
+//! Bridging messages between routers in different processes or hosts
+//!
+//! Payload types are registered with a transport, which forwards messages of those types from the local
+//! [`MessageRouter`](crate::router::MessageRouter) to remote peers, and injects messages received from peers
+//! into the local router.
+//!
+//! Payloads are identified on the wire by identifiers registered in a [`TypeRegistry`], which must match
+//! between peers. Types without a registered identifier are identified by their Rust type name.
+//! Frames are serialized with a [`Codec`], which defaults to [`Bincode`].
+//!
+//! Besides the built-in UDP and unix socket bridges, a [`Bridge`] can run over any [`Transport`].
+//! Bridges can exchange [`discovery`] advertisements, so unicast messages without a local endpoint are
+//! delivered to a remote router which can handle them.
+//! High rate streams of small messages can be [`batch`]ed into fewer frames with a [`BridgeConfig`],
+//! which can also enable [`compression`] of frames sent over constrained links, [`flow`] control
+//! so slow peers backpressure senders, and [`heartbeat`]s reporting peers which became unreachable.
+//! With the `encryption` feature, any transport can be wrapped in an
+//! [`EncryptedTransport`](encrypted::EncryptedTransport) so frames aren't sent in plaintext.
+//! With the `tokio-bridge` feature, a [`TcpBridge`](tcp::TcpBridge) runs on tokio tasks, and [`reconnect`]s
+//! with exponential backoff when connections fail.
+
+use std::{
+    any::{type_name, TypeId},
+    collections::HashMap,
+    sync::Arc,
+};
+
+use anylock::{AnyLock, ParkingLotRwLock};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use tracing::error;
+
+use crate::{
+    endpoint::{Endpoint, EndpointId},
+    router::MessageRouter,
+    traits::{EndpointAddress as _, Payload},
+    Message,
+};
+
+pub mod batch;
+pub mod codec;
+pub mod compression;
+pub mod discovery;
+#[cfg(feature = "encryption")]
+pub mod encrypted;
+pub mod flow;
+pub mod heartbeat;
+#[cfg(unix)]
+pub mod ipc;
+pub mod reconnect;
+pub mod registry;
+#[cfg(feature = "tokio-bridge")]
+pub mod tcp;
+pub mod transport;
+pub mod udp;
+#[cfg(feature = "zmq")]
+pub mod zmq;
+
+pub use batch::BridgeConfig;
+pub use codec::{Bincode, Codec};
+pub use compression::Compression;
+pub use flow::BridgeStalled;
+pub use heartbeat::{PeerRecovered, PeerUnreachable};
+pub use reconnect::{Backoff, BridgeConnected, BridgeDisconnected};
+pub use registry::TypeRegistry;
+pub use transport::{Bridge, Transport};
+
+/// Bridge error
+#[derive(Debug)]
+pub enum BridgeError {
+    /// Socket IO error
+    Io(std::io::Error),
+
+    /// Failed to encode or decode a frame
+    Codec(String),
+
+    /// Received a payload type which has not been registered
+    UnknownType(String),
+
+    /// Encoded frame exceeds the maximum size supported by the transport
+    FrameTooLarge(usize),
+
+    /// Error reported by a [`Transport`] implementation
+    Transport(String),
+
+    /// Peer did not consume frames in flight within the stall timeout of the bridge
+    Stalled,
+}
+
+impl std::fmt::Display for BridgeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BridgeError::Io(e) => write!(f, "bridge IO error: {e}"),
+            BridgeError::Codec(e) => write!(f, "bridge codec error: {e}"),
+            BridgeError::UnknownType(name) => write!(f, "unregistered payload type {name}"),
+            BridgeError::FrameTooLarge(len) => write!(f, "frame of {len} bytes is too large"),
+            BridgeError::Transport(e) => write!(f, "bridge transport error: {e}"),
+            BridgeError::Stalled => write!(f, "bridge stalled waiting for peer to consume frames"),
+        }
+    }
+}
+
+impl std::error::Error for BridgeError {}
+
+impl From<std::io::Error> for BridgeError {
+    fn from(e: std::io::Error) -> Self {
+        BridgeError::Io(e)
+    }
+}
+
+impl From<bincode::Error> for BridgeError {
+    fn from(e: bincode::Error) -> Self {
+        BridgeError::Codec(e.to_string())
+    }
+}
+
+/// Message envelope sent between bridged routers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WireMessage {
+    /// Identifier of the payload type in the [`TypeRegistry`]
+    pub type_name: String,
+
+    /// Encoded payload
+    pub payload: Vec<u8>,
+
+    /// Destination of the message in receiving routers
+    pub dest: WireDest,
+
+    /// W3C `traceparent` of the span the message was sent in, which receiving routers continue the trace of
+    pub traceparent: Option<String>,
+}
+
+/// Destination of a [`WireMessage`] in receiving routers
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WireDest {
+    /// Broadcast to all endpoints registered for the payload type
+    #[default]
+    Broadcast,
+
+    /// Deliver to a single endpoint registered for the payload type, in the router of the named node.
+    /// Other nodes ignore the message.
+    Node(String),
+
+    /// Deliver to the endpoint `addr` in the router of the node identified by the [`NodeId`] `node`.
+    /// Other nodes ignore the message.
+    ///
+    /// [`NodeId`]: crate::message::NodeId
+    Endpoint { node: u64, addr: EndpointId },
+}
+
+impl WireMessage {
+    /// Encode a payload into a [`WireMessage`]
+    pub fn encode<M: Serialize>(codec: &impl Codec, payload: &M) -> Result<Self, BridgeError> {
+        Ok(Self {
+            type_name: type_name::<M>().into(),
+            payload: codec.encode(payload)?,
+            dest: WireDest::Broadcast,
+            traceparent: current_traceparent(),
+        })
+    }
+
+    /// Encode this message into a frame
+    pub fn to_frame(&self, codec: &impl Codec) -> Result<Vec<u8>, BridgeError> {
+        codec.encode(self)
+    }
+
+    /// Decode a message from a frame
+    pub fn from_frame(codec: &impl Codec, frame: &[u8]) -> Result<Self, BridgeError> {
+        codec.decode(frame)
+    }
+}
+
+/// Get the `traceparent` of the current span, if traces are propagated
+pub(crate) fn current_traceparent() -> Option<String> {
+    #[cfg(feature = "otel")]
+    {
+        crate::otel::current_traceparent()
+    }
+    #[cfg(not(feature = "otel"))]
+    {
+        None
+    }
+}
+
+/// Payload types registered with a bridge, and the forwarding endpoint of each type
+pub(crate) struct Decoders<C> {
+    registry: Arc<TypeRegistry<C>>,
+    origins: ParkingLotRwLock<HashMap<TypeId, EndpointId>>,
+}
+
+impl<C> std::fmt::Debug for Decoders<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Decoders")
+            .field("registry", &self.registry)
+            .field("types", &self.origins.read().len())
+            .finish()
+    }
+}
+
+impl<C: Codec> Decoders<C> {
+    pub(crate) fn new(registry: Arc<TypeRegistry<C>>) -> Self {
+        Self {
+            registry,
+            origins: ParkingLotRwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Encode a payload into a frame, registering its type in the registry under its type name if needed
+    pub(crate) fn encode_frame<M: BridgePayload>(
+        &self,
+        payload: &M,
+    ) -> Result<Vec<u8>, BridgeError> {
+        self.registry.register_default::<M>();
+        self.registry.encode_frame(payload)
+    }
+
+    /// Accept payload type `M` from peers, registering it in the registry under its type name if needed.
+    /// Decoded messages are broadcast, and are not delivered back to the `origin` forwarding endpoint.
+    pub(crate) fn register<M: BridgePayload>(&self, origin: EndpointId) {
+        self.registry.register_default::<M>();
+        self.origins.write().insert(TypeId::of::<M>(), origin);
+    }
+
+    /// Get the registry payload types are identified with
+    pub(crate) fn registry(&self) -> &Arc<TypeRegistry<C>> {
+        &self.registry
+    }
+
+    /// Get the payload types registered with the bridge
+    pub(crate) fn types(&self) -> Vec<TypeId> {
+        self.origins.read().keys().copied().collect()
+    }
+
+    /// Decode a frame into a [`Message`] of a payload type registered with the bridge
+    pub(crate) fn decode_frame(&self, frame: &[u8]) -> Result<Message, BridgeError> {
+        self.decode_wire(&WireMessage::from_frame(self.registry.codec(), frame)?)
+    }
+
+    /// Decode a [`WireMessage`] into a broadcast [`Message`] of a payload type registered with the bridge
+    pub(crate) fn decode_wire(&self, wire: &WireMessage) -> Result<Message, BridgeError> {
+        let (type_id, message) = self.registry.decode_typed(wire)?;
+
+        match self.origins.read().get(&type_id) {
+            Some(origin) => Ok(message.with_origin(*origin)),
+            None => Err(BridgeError::UnknownType(wire.type_name.clone())),
+        }
+    }
+}
+
+/// Create an [`Endpoint`] in `router` which encodes messages of type `M` into frames and passes them to `send`,
+/// and register a decoder for `M` which injects received messages without echoing them back to the endpoint.
+pub(crate) fn register_forward<M, R, C>(
+    router: &MessageRouter<'static, R>,
+    decoders: &Decoders<C>,
+    send: impl Fn(&[u8]) -> Result<(), BridgeError> + Send + Sync + 'static,
+) -> Endpoint<'static, M, R>
+where
+    M: BridgePayload,
+    R: Default + Send + 'static,
+    C: Codec,
+{
+    let registry = decoders.registry.clone();
+    let endpoint = router.create_endpoint::<M>().message(move |_src, msg| {
+        if let Err(e) = registry
+            .encode_frame(&msg)
+            .and_then(|frame| send(&frame))
+        {
+            error!("Failed to forward {}: {e}", type_name::<M>());
+        }
+        R::default()
+    });
+
+    decoders.register::<M>(endpoint.addr());
+    endpoint
+}
+
+/// Payload types which can be sent over a bridge
+pub trait BridgePayload: Payload + Clone + Serialize + DeserializeOwned + 'static {}
+
+impl<T> BridgePayload for T where T: Payload + Clone + Serialize + DeserializeOwned + 'static {}
@@ -0,0 +1,67 @@
+//! Reconnection of bridges to remote peers
+//!
+//! Bridges which connect to a remote peer retry failed connections after a [`Backoff`] delay, which grows
+//! exponentially with each failed attempt and is randomly shortened by a jitter fraction, so many clients
+//! losing the same peer don't reconnect in lockstep. Bridges publish [`BridgeConnected`] and
+//! [`BridgeDisconnected`] messages into their local router as connections are established and lost.
+
+use std::time::Duration;
+
+/// Exponential backoff between reconnection attempts
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    /// Delay before the first reconnection attempt
+    pub initial: Duration,
+
+    /// Maximum delay between reconnection attempts
+    pub max: Duration,
+
+    /// Factor the delay grows by after each failed attempt
+    pub multiplier: f64,
+
+    /// Fraction of the delay, between 0 and 1, which is randomly subtracted from each delay
+    pub jitter: f64,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.5,
+        }
+    }
+}
+
+impl Backoff {
+    /// Get the delay before reconnection attempt `attempt`, counting from zero
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.min(i32::MAX as u32) as i32;
+        let delay = (self.initial.as_secs_f64() * self.multiplier.powi(exponent))
+            .min(self.max.as_secs_f64());
+        let jitter = delay * self.jitter.clamp(0.0, 1.0) * rand::random::<f64>();
+
+        Duration::from_secs_f64(delay - jitter)
+    }
+}
+
+/// Published into the local router when a bridge establishes a connection with a peer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BridgeConnected {
+    /// Address of the peer
+    pub peer: String,
+}
+
+/// Published into the local router when a bridge loses the connection with a peer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BridgeDisconnected {
+    /// Address of the peer
+    pub peer: String,
+
+    /// Error which ended the connection
+    pub reason: String,
+
+    /// Delay before reconnecting, or `None` if the peer connected to this bridge and is expected to reconnect
+    pub retry_in: Option<Duration>,
+}
@@ -0,0 +1,252 @@
+//! Registry of payload types which can be reconstructed from frames
+//!
+//! [`TypeId`] and Rust type names are not stable across builds, so remote peers identify payload types by
+//! identifiers registered with a [`TypeRegistry`], such as `"temp_v1"`. The same registry can be shared
+//! by several bridges, which must agree with their peers on the identifier of each type.
+//!
+//! Types which are registered with a bridge without being registered in its registry are identified by
+//! their Rust type name.
+//!
+//! Payload types can be versioned by registering each version under its own identifier, such as `"temp_v2"`.
+//! Payloads encoded under identifiers of older versions, whether by peers running older builds or in
+//! previously recorded messages, are decoded into the current type by upgrade functions registered with
+//! [`TypeRegistry::register_upgrade()`].
+
+use std::{
+    any::{type_name, TypeId},
+    collections::HashMap,
+};
+
+use serde::de::DeserializeOwned;
+
+use anylock::{AnyLock, ParkingLotRwLock};
+use tracing::warn;
+
+use crate::Message;
+
+use super::{Bincode, BridgeError, BridgePayload, Codec, WireDest, WireMessage};
+
+/// Function decoding an encoded payload into a [`Message`]
+type DecodeFn = Box<dyn Fn(&[u8]) -> Result<Message, BridgeError> + Send + Sync>;
+
+/// Payload type registered under an identifier
+struct RegisteredType {
+    /// Type of decoded payloads
+    type_id: TypeId,
+
+    /// Name of the type payloads are encoded as, which differs from the decoded type for upgrades
+    type_name: &'static str,
+
+    decode: DecodeFn,
+}
+
+/// Maps stable identifiers to payload types, and encodes and decodes payloads with codec `C`
+pub struct TypeRegistry<C = Bincode> {
+    codec: C,
+    types: ParkingLotRwLock<HashMap<String, RegisteredType>>,
+    ids: ParkingLotRwLock<HashMap<TypeId, String>>,
+}
+
+impl<C> std::fmt::Debug for TypeRegistry<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map()
+            .entries(
+                self.types
+                    .read()
+                    .iter()
+                    .map(|(id, registered)| (id.clone(), registered.type_name)),
+            )
+            .finish()
+    }
+}
+
+impl Default for TypeRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TypeRegistry {
+    /// Create a registry using the default [`Bincode`] codec
+    pub fn new() -> Self {
+        Self::with_codec(Bincode)
+    }
+}
+
+impl<C: Codec> TypeRegistry<C> {
+    /// Create a registry which encodes payloads with `codec`
+    pub fn with_codec(codec: C) -> Self {
+        Self {
+            codec,
+            types: ParkingLotRwLock::new(HashMap::new()),
+            ids: ParkingLotRwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Get the codec of the registry
+    pub fn codec(&self) -> &C {
+        &self.codec
+    }
+
+    /// Register payload type `M` under identifier `id`.
+    ///
+    /// Registering a type again replaces its previous identifier, and registering an identifier
+    /// which belongs to another type replaces that type.
+    pub fn register<M: BridgePayload>(&self, id: impl Into<String>) {
+        let id = id.into();
+        let codec = self.codec.clone();
+
+        let decode = move |bytes: &[u8]| -> Result<Message, BridgeError> {
+            Ok(Message::broadcast(codec.decode::<M>(bytes)?))
+        };
+
+        let mut types = self.types.write();
+        let mut ids = self.ids.write();
+
+        if let Some(previous) = ids.remove(&TypeId::of::<M>()) {
+            types.remove(&previous);
+        }
+
+        Self::insert(
+            &mut types,
+            &mut ids,
+            id.clone(),
+            RegisteredType {
+                type_id: TypeId::of::<M>(),
+                type_name: type_name::<M>(),
+                decode: Box::new(decode),
+            },
+        );
+        ids.insert(TypeId::of::<M>(), id);
+    }
+
+    /// Register an upgrade for payloads encoded under identifier `id` as an older version `Old`,
+    /// which are decoded and converted into the current payload type `New` by `upgrade`.
+    ///
+    /// Upgrades are not chained, so each older version should be converted directly into the current type.
+    /// Payloads of type `New` are still encoded under the identifier `New` is registered under.
+    pub fn register_upgrade<Old, New>(
+        &self,
+        id: impl Into<String>,
+        upgrade: impl Fn(Old) -> New + Send + Sync + 'static,
+    ) where
+        Old: DeserializeOwned + 'static,
+        New: BridgePayload,
+    {
+        let codec = self.codec.clone();
+
+        let decode = move |bytes: &[u8]| -> Result<Message, BridgeError> {
+            Ok(Message::broadcast(upgrade(codec.decode::<Old>(bytes)?)))
+        };
+
+        let mut types = self.types.write();
+        let mut ids = self.ids.write();
+
+        Self::insert(
+            &mut types,
+            &mut ids,
+            id.into(),
+            RegisteredType {
+                type_id: TypeId::of::<New>(),
+                type_name: type_name::<Old>(),
+                decode: Box::new(decode),
+            },
+        );
+    }
+
+    /// Insert a registered type, removing the identifier of any type it replaces
+    fn insert(
+        types: &mut HashMap<String, RegisteredType>,
+        ids: &mut HashMap<TypeId, String>,
+        id: String,
+        registered: RegisteredType,
+    ) {
+        let type_name = registered.type_name;
+
+        if let Some(replaced) = types.insert(id.clone(), registered) {
+            warn!(
+                "{id} was registered for {}, replacing with {type_name}",
+                replaced.type_name
+            );
+
+            if ids.get(&replaced.type_id) == Some(&id) {
+                ids.remove(&replaced.type_id);
+            }
+        }
+    }
+
+    /// Register payload type `M` under its Rust type name, if it has not already been registered
+    pub(crate) fn register_default<M: BridgePayload>(&self) {
+        if !self.ids.read().contains_key(&TypeId::of::<M>()) {
+            self.register::<M>(type_name::<M>());
+        }
+    }
+
+    /// Get the identifier payload type `M` is registered under
+    pub fn id<M: 'static>(&self) -> Option<String> {
+        self.id_of(TypeId::of::<M>())
+    }
+
+    /// Get the identifier the payload type `type_id` is registered under
+    pub(crate) fn id_of(&self, type_id: TypeId) -> Option<String> {
+        self.ids.read().get(&type_id).cloned()
+    }
+
+    /// Check if an identifier has been registered
+    pub fn contains(&self, id: &str) -> bool {
+        self.types.read().contains_key(id)
+    }
+
+    /// Encode a payload of a registered type into a [`WireMessage`]
+    pub fn encode<M: BridgePayload>(&self, payload: &M) -> Result<WireMessage, BridgeError> {
+        let id = self
+            .id::<M>()
+            .ok_or_else(|| BridgeError::UnknownType(type_name::<M>().into()))?;
+
+        Ok(WireMessage {
+            type_name: id,
+            payload: self.codec.encode(payload)?,
+            dest: WireDest::Broadcast,
+            traceparent: super::current_traceparent(),
+        })
+    }
+
+    /// Decode a [`WireMessage`] into a broadcast [`Message`] of the registered payload type
+    pub fn decode(&self, wire: &WireMessage) -> Result<Message, BridgeError> {
+        self.decode_typed(wire).map(|(_, message)| message)
+    }
+
+    /// Decode a [`WireMessage`], returning the [`TypeId`] of the payload along with the message
+    pub(crate) fn decode_typed(
+        &self,
+        wire: &WireMessage,
+    ) -> Result<(TypeId, Message), BridgeError> {
+        let (type_id, message) = match self.types.read().get(&wire.type_name) {
+            Some(registered) => (registered.type_id, (registered.decode)(&wire.payload)?),
+            None => return Err(BridgeError::UnknownType(wire.type_name.clone())),
+        };
+
+        // Continue the trace of the sender
+        #[cfg(feature = "otel")]
+        let message = match wire
+            .traceparent
+            .as_deref()
+            .and_then(crate::otel::from_traceparent)
+        {
+            Some(context) => message.with_trace_context(context),
+            None => message,
+        };
+
+        Ok((type_id, message))
+    }
+
+    /// Encode a payload of a registered type into a frame
+    pub fn encode_frame<M: BridgePayload>(&self, payload: &M) -> Result<Vec<u8>, BridgeError> {
+        self.encode(payload)?.to_frame(&self.codec)
+    }
+
+    /// Decode a frame into a broadcast [`Message`] of the registered payload type
+    pub fn decode_frame(&self, frame: &[u8]) -> Result<Message, BridgeError> {
+        self.decode(&WireMessage::from_frame(&self.codec, frame)?)
+    }
+}
@@ -0,0 +1,390 @@
+//! Async TCP bridge running on tokio
+//!
+//! Bridges routers over TCP connections driven by tokio tasks. One bridge listens on an address, and others
+//! connect to it. Connecting bridges are supervised by a task which reconnects with a jittered exponential
+//! [`Backoff`] whenever the connection fails, and all bridges publish [`BridgeConnected`] and
+//! [`BridgeDisconnected`] messages into their local router, so transient network failures are handled
+//! without intervention from the application.
+//!
+//! Frames are sent over the stream with a big-endian `u32` length prefix. Messages received from peers are
+//! broadcast into the local router without a source. Frames are queued for each connection, and dropped
+//! for connections whose queue is full.
+//!
+//! Bridges must be created from within a tokio runtime, and their tasks are aborted when they are dropped.
+//! Requires the `tokio-bridge` feature.
+
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use anylock::{AnyLock, ParkingLotRwLock};
+use tokio::{
+    io::{AsyncReadExt as _, AsyncWriteExt as _},
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpListener, TcpStream, ToSocketAddrs,
+    },
+    sync::mpsc,
+    task::{JoinHandle, JoinSet},
+};
+use tracing::{debug, error, trace, warn};
+
+use crate::{endpoint::Endpoint, router::MessageRouter, Message};
+
+use super::{
+    reconnect::{Backoff, BridgeConnected, BridgeDisconnected},
+    register_forward, Bincode, BridgeError, BridgePayload, Codec, Decoders, TypeRegistry,
+};
+
+/// Maximum size of a frame accepted from a connection
+pub const MAX_FRAME: usize = 16 * 1024 * 1024;
+
+/// Number of frames queued for each connection before frames are dropped
+const QUEUE_LEN: usize = 1024;
+
+/// Delay after a failure to accept a connection
+const ACCEPT_DELAY: Duration = Duration::from_millis(100);
+
+/// Queue of length prefixed frames waiting to be written to a connection
+type Queue = mpsc::Sender<Arc<[u8]>>;
+
+/// Queues of connected peers which forwarded frames are written to
+#[derive(Debug)]
+struct Peers {
+    next_id: AtomicU64,
+    queues: ParkingLotRwLock<Vec<(u64, Queue)>>,
+}
+
+impl Peers {
+    fn new() -> Self {
+        Self {
+            next_id: AtomicU64::new(0),
+            queues: ParkingLotRwLock::new(Vec::new()),
+        }
+    }
+
+    fn add(&self, queue: Queue) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.queues.write().push((id, queue));
+        id
+    }
+
+    fn remove(&self, id: u64) {
+        self.queues.write().retain(|(queue_id, _)| *queue_id != id);
+    }
+
+    fn len(&self) -> usize {
+        self.queues.read().len()
+    }
+
+    /// Queue a length prefixed frame for all peers
+    fn send_frame(&self, frame: &[u8]) -> Result<(), BridgeError> {
+        if frame.len() > MAX_FRAME {
+            return Err(BridgeError::FrameTooLarge(frame.len()));
+        }
+
+        let mut prefixed = Vec::with_capacity(frame.len() + 4);
+        prefixed.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+        prefixed.extend_from_slice(frame);
+        let prefixed: Arc<[u8]> = prefixed.into();
+
+        for (id, queue) in self.queues.read().iter() {
+            if let Err(mpsc::error::TrySendError::Full(_)) = queue.try_send(prefixed.clone()) {
+                warn!("Dropping frame for TCP connection {id}, which is not keeping up");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Bridges registered payload types to peers over TCP connections driven by tokio tasks
+pub struct TcpBridge<R, C = Bincode> {
+    addr: String,
+    local_addr: Option<SocketAddr>,
+    peers: Arc<Peers>,
+    decoders: Arc<Decoders<C>>,
+    router: MessageRouter<'static, R>,
+    task: JoinHandle<()>,
+}
+
+impl<R, C> std::fmt::Debug for TcpBridge<R, C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TcpBridge")
+            .field("addr", &self.addr)
+            .field("listening", &self.local_addr.is_some())
+            .field("connections", &self.peers.len())
+            .field("types", &self.decoders)
+            .finish()
+    }
+}
+
+impl<R> TcpBridge<R>
+where
+    R: Default + Send + 'static,
+{
+    /// Listen for connections on `addr`
+    pub async fn listen(
+        addr: impl ToSocketAddrs,
+        router: &MessageRouter<'static, R>,
+    ) -> Result<Self, BridgeError> {
+        Self::listen_with_registry(addr, router, Arc::new(TypeRegistry::new())).await
+    }
+
+    /// Connect to a listening bridge at `addr`, which is resolved on each connection attempt.
+    /// The connection is established in the background, and re-established after `backoff` whenever it fails.
+    pub fn connect(
+        addr: impl Into<String>,
+        router: &MessageRouter<'static, R>,
+        backoff: Backoff,
+    ) -> Self {
+        Self::connect_with_registry(addr, router, backoff, Arc::new(TypeRegistry::new()))
+    }
+}
+
+impl<R, C> TcpBridge<R, C>
+where
+    R: Default + Send + 'static,
+    C: Codec,
+{
+    /// Listen for connections on `addr`, identifying and encoding payloads with `registry`
+    pub async fn listen_with_registry(
+        addr: impl ToSocketAddrs,
+        router: &MessageRouter<'static, R>,
+        registry: Arc<TypeRegistry<C>>,
+    ) -> Result<Self, BridgeError> {
+        let listener = TcpListener::bind(addr).await?;
+        let local_addr = listener.local_addr()?;
+        debug!("TCP bridge listening on {local_addr}");
+
+        let peers = Arc::new(Peers::new());
+        let decoders = Arc::new(Decoders::new(registry));
+
+        let task = {
+            let peers = peers.clone();
+            let decoders = decoders.clone();
+            let router = router.clone();
+
+            tokio::spawn(async move {
+                // Connection tasks are aborted along with the listener task
+                let mut connections = JoinSet::new();
+
+                loop {
+                    match listener.accept().await {
+                        Ok((stream, peer)) => {
+                            debug!("Accepted TCP connection from {peer}");
+                            let (peers, decoders) = (peers.clone(), decoders.clone());
+                            let router = router.clone();
+
+                            connections.spawn(async move {
+                                let peer = peer.to_string();
+                                let error =
+                                    Self::serve(stream, &peer, &peers, &decoders, &router).await;
+
+                                debug!("TCP connection from {peer} closed: {error}");
+                                router.handle_message(Message::broadcast(BridgeDisconnected {
+                                    peer,
+                                    reason: error.to_string(),
+                                    retry_in: None,
+                                }));
+                            });
+                        }
+                        Err(e) => {
+                            error!("TCP accept failed: {e}");
+                            tokio::time::sleep(ACCEPT_DELAY).await;
+                        }
+                    }
+
+                    while connections.try_join_next().is_some() {}
+                }
+            })
+        };
+
+        Ok(Self {
+            addr: local_addr.to_string(),
+            local_addr: Some(local_addr),
+            peers,
+            decoders,
+            router: router.clone(),
+            task,
+        })
+    }
+
+    /// Connect to a listening bridge at `addr`, reconnecting after `backoff`,
+    /// and identifying and encoding payloads with `registry`
+    pub fn connect_with_registry(
+        addr: impl Into<String>,
+        router: &MessageRouter<'static, R>,
+        backoff: Backoff,
+        registry: Arc<TypeRegistry<C>>,
+    ) -> Self {
+        let addr = addr.into();
+        let peers = Arc::new(Peers::new());
+        let decoders = Arc::new(Decoders::new(registry));
+
+        let task = {
+            let addr = addr.clone();
+            let peers = peers.clone();
+            let decoders = decoders.clone();
+            let router = router.clone();
+
+            tokio::spawn(async move {
+                let mut attempt = 0;
+
+                loop {
+                    let delay = match TcpStream::connect(&addr).await {
+                        Ok(stream) => {
+                            debug!("Connected to {addr}");
+                            attempt = 0;
+
+                            let error =
+                                Self::serve(stream, &addr, &peers, &decoders, &router).await;
+                            let delay = backoff.delay(attempt);
+
+                            warn!("Disconnected from {addr}: {error}, reconnecting in {delay:?}");
+                            router.handle_message(Message::broadcast(BridgeDisconnected {
+                                peer: addr.clone(),
+                                reason: error.to_string(),
+                                retry_in: Some(delay),
+                            }));
+                            delay
+                        }
+                        Err(e) => {
+                            let delay = backoff.delay(attempt);
+                            trace!("Connecting to {addr} failed: {e}, retrying in {delay:?}");
+                            attempt = attempt.saturating_add(1);
+                            delay
+                        }
+                    };
+
+                    tokio::time::sleep(delay).await;
+                }
+            })
+        };
+
+        Self {
+            addr,
+            local_addr: None,
+            peers,
+            decoders,
+            router: router.clone(),
+            task,
+        }
+    }
+
+    /// Get the address the bridge is listening on, or `None` if it connects to a peer
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    /// Get the number of active connections
+    pub fn connections(&self) -> usize {
+        self.peers.len()
+    }
+
+    /// Register payload type `M` with the bridge.
+    ///
+    /// Messages of type `M` received from peers are injected into the router, and messages of type `M`
+    /// dispatched in the local router are forwarded to all connections until the returned [`Endpoint`] is dropped.
+    pub fn register<M>(&self) -> Endpoint<'static, M, R>
+    where
+        M: BridgePayload,
+    {
+        let peers = self.peers.clone();
+        register_forward(&self.router, &self.decoders, move |frame| {
+            peers.send_frame(frame)
+        })
+    }
+
+    /// Send a payload directly to all connections, without dispatching it in the local router
+    pub fn send<M>(&self, payload: &M) -> Result<(), BridgeError>
+    where
+        M: BridgePayload,
+    {
+        self.peers.send_frame(&self.decoders.encode_frame(payload)?)
+    }
+
+    /// Register a connected stream, and exchange frames over it until it fails, returning the error which ended it
+    async fn serve(
+        stream: TcpStream,
+        peer: &str,
+        peers: &Peers,
+        decoders: &Decoders<C>,
+        router: &MessageRouter<'static, R>,
+    ) -> BridgeError {
+        if let Err(e) = stream.set_nodelay(true) {
+            debug!("Failed to disable Nagle's algorithm for {peer}: {e}");
+        }
+
+        let (reader, writer) = stream.into_split();
+        let (queue, frames) = mpsc::channel(QUEUE_LEN);
+        let id = peers.add(queue);
+
+        router.handle_message(Message::broadcast(BridgeConnected {
+            peer: peer.to_string(),
+        }));
+
+        let error = tokio::select! {
+            error = Self::read_frames(reader, decoders, router) => error,
+            error = Self::write_frames(writer, frames) => error,
+        };
+
+        peers.remove(id);
+        error
+    }
+
+    /// Read frames and dispatch them into the router until reading fails
+    async fn read_frames(
+        mut reader: OwnedReadHalf,
+        decoders: &Decoders<C>,
+        router: &MessageRouter<'static, R>,
+    ) -> BridgeError {
+        loop {
+            let len = match reader.read_u32().await {
+                Ok(len) => len as usize,
+                Err(e) => return e.into(),
+            };
+
+            if len > MAX_FRAME {
+                return BridgeError::FrameTooLarge(len);
+            }
+
+            let mut frame = vec![0; len];
+            if let Err(e) = reader.read_exact(&mut frame).await {
+                return e.into();
+            }
+
+            match decoders.decode_frame(&frame) {
+                Ok(message) => {
+                    router.handle_message(message);
+                }
+                Err(e) => warn!("Dropping TCP frame: {e}"),
+            }
+        }
+    }
+
+    /// Write queued frames until writing fails
+    async fn write_frames(
+        mut writer: OwnedWriteHalf,
+        mut frames: mpsc::Receiver<Arc<[u8]>>,
+    ) -> BridgeError {
+        while let Some(frame) = frames.recv().await {
+            if let Err(e) = writer.write_all(&frame).await {
+                return e.into();
+            }
+        }
+
+        BridgeError::Transport("connection queue closed".into())
+    }
+}
+
+impl<R, C> Drop for TcpBridge<R, C> {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
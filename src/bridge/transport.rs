@@ -0,0 +1,501 @@
+//! Pluggable frame transports
+//!
+//! A [`Transport`] moves opaque frames between peers. [`Bridge`] drives any transport,
+//! forwarding registered payload types from the local router and injecting received frames,
+//! so routers can be bridged over an existing messaging fabric by implementing the trait.
+
+use std::{
+    any::{type_name, TypeId},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, OnceLock,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use tracing::{debug, error, trace, warn};
+
+use crate::{
+    endpoint::Endpoint,
+    message::Destination,
+    router::MessageRouter,
+    traits::{internal::SalishMessageInternal as _, EndpointAddress as _},
+    Message,
+};
+
+use super::{
+    batch::{BridgeConfig, Outbound, BATCH_ID},
+    compression::{self, CAPABILITIES_ID, COMPRESSED_ID},
+    discovery::{Advertisement, Discovery, ADVERTISEMENT_ID},
+    flow::FLOW_ID,
+    heartbeat::HEARTBEAT_ID,
+    register_forward, Bincode, BridgeError, BridgePayload, Codec, Decoders, TypeRegistry, WireDest,
+    WireMessage,
+};
+
+/// Interval at which the receiver thread checks for shutdown
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Transport carrying frames between bridged routers
+pub trait Transport: Send + Sync + 'static {
+    /// Connect to a remote peer at a transport specific address
+    fn connect(&self, addr: &str) -> Result<(), BridgeError>;
+
+    /// Send a frame to all connected peers
+    fn send_frame(&self, frame: &[u8]) -> Result<(), BridgeError>;
+
+    /// Receive the next frame from any peer.
+    /// Returns `Ok(None)` if no frame arrived within `timeout`.
+    fn recv_frame(&self, timeout: Duration) -> Result<Option<Vec<u8>>, BridgeError>;
+}
+
+/// Bridges registered payload types to peers over a [`Transport`], encoding frames with codec `C`
+pub struct Bridge<T, R, C = Bincode>
+where
+    T: Transport,
+{
+    transport: Arc<T>,
+    outbound: Arc<Outbound<T, C>>,
+    decoders: Arc<Decoders<C>>,
+    discovery: Arc<OnceLock<Discovery>>,
+
+    /// Frame withdrawing the advertisement of this node, sent on drop
+    withdrawal: Option<Vec<u8>>,
+
+    router: MessageRouter<'static, R>,
+    shutdown: Arc<AtomicBool>,
+    receiver: Option<JoinHandle<()>>,
+    batcher: Option<JoinHandle<()>>,
+}
+
+impl<T, R, C> std::fmt::Debug for Bridge<T, R, C>
+where
+    T: Transport,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Bridge")
+            .field("transport", &std::any::type_name::<T>())
+            .field("outbound", &self.outbound)
+            .field("types", &self.decoders)
+            .finish()
+    }
+}
+
+impl<T, R> Bridge<T, R>
+where
+    T: Transport,
+    R: Default + Send + 'static,
+{
+    /// Create a bridge over `transport`, and spawn a thread injecting received frames into `router`
+    pub fn new(transport: T, router: &MessageRouter<'static, R>) -> Result<Self, BridgeError> {
+        Self::with_registry(transport, router, Arc::new(TypeRegistry::new()))
+    }
+}
+
+impl<T, R, C> Bridge<T, R, C>
+where
+    T: Transport,
+    R: Default + Send + 'static,
+    C: Codec,
+{
+    /// Create a bridge over `transport` which identifies and encodes payloads with `registry`
+    pub fn with_registry(
+        transport: T,
+        router: &MessageRouter<'static, R>,
+        registry: Arc<TypeRegistry<C>>,
+    ) -> Result<Self, BridgeError> {
+        Self::with_config(transport, router, registry, BridgeConfig::default())
+    }
+
+    /// Create a bridge over `transport` which identifies and encodes payloads with `registry`,
+    /// and batches frames as configured by `config`
+    pub fn with_config(
+        transport: T,
+        router: &MessageRouter<'static, R>,
+        registry: Arc<TypeRegistry<C>>,
+        config: BridgeConfig,
+    ) -> Result<Self, BridgeError> {
+        if !config.compression.is_supported() {
+            return Err(BridgeError::Codec(format!(
+                "{:?} compression is not supported by this build",
+                config.compression
+            )));
+        }
+
+        let transport = Arc::new(transport);
+        let outbound = Arc::new(Outbound::new(
+            transport.clone(),
+            registry.codec().clone(),
+            config,
+        ));
+        let decoders = Arc::new(Decoders::new(registry));
+        let discovery = Arc::new(OnceLock::new());
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let receiver = {
+            let transport = transport.clone();
+            let outbound = outbound.clone();
+            let decoders = decoders.clone();
+            let discovery = discovery.clone();
+            let shutdown = shutdown.clone();
+            let router = router.clone();
+
+            std::thread::Builder::new()
+                .name("salish-bridge".into())
+                .spawn(move || {
+                    Self::receive(transport, outbound, decoders, discovery, router, shutdown)
+                })?
+        };
+
+        let batcher = if config.is_batching() {
+            let outbound = outbound.clone();
+            let shutdown = shutdown.clone();
+
+            Some(
+                std::thread::Builder::new()
+                    .name("salish-bridge-batch".into())
+                    .spawn(move || outbound.run(&shutdown))?,
+            )
+        } else {
+            None
+        };
+
+        Ok(Self {
+            transport,
+            outbound,
+            decoders,
+            discovery,
+            withdrawal: None,
+            router: router.clone(),
+            shutdown,
+            receiver: Some(receiver),
+            batcher,
+        })
+    }
+
+    /// Enable [discovery](super::discovery) under the identity `node`, advertising the registered payload types
+    /// with endpoints in the local router to peers, with advertisements expiring after `ttl`.
+    ///
+    /// Unicast messages of registered types with no local endpoint are then sent to a peer which advertised
+    /// the type. Identities must be unique among peers. Discovery can only be enabled once.
+    pub fn with_discovery(mut self, node: impl Into<String>, ttl: Duration) -> Self {
+        let discovery = Discovery::new(node.into(), ttl);
+        let withdrawal = Self::advertisement_frame(&self.decoders, discovery.withdrawal());
+
+        if let Err(discovery) = self.discovery.set(discovery) {
+            warn!(
+                "Discovery is already enabled, ignoring identity {}",
+                discovery.node()
+            );
+            return self;
+        }
+
+        match withdrawal {
+            Ok(frame) => self.withdrawal = Some(frame),
+            Err(e) => error!("Failed to encode advertisement withdrawal: {e}"),
+        }
+        self
+    }
+
+    /// Get the remote nodes with live advertisements of payload type `M`, sorted by identity
+    pub fn remote_nodes<M: 'static>(&self) -> Vec<String> {
+        match (self.discovery.get(), self.decoders.registry().id::<M>()) {
+            (Some(discovery), Some(id)) => discovery.nodes(&id),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Get a reference to the transport of the bridge
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    /// Connect the transport to a remote peer
+    pub fn connect(&self, addr: &str) -> Result<(), BridgeError> {
+        self.transport.connect(addr)
+    }
+
+    /// Register payload type `M` with the bridge.
+    ///
+    /// Messages of type `M` received from peers are injected into the router, and messages of type `M`
+    /// dispatched in the local router are sent over the transport until the returned [`Endpoint`] is dropped.
+    pub fn register<M>(&self) -> Endpoint<'static, M, R>
+    where
+        M: BridgePayload,
+    {
+        let outbound = self.outbound.clone();
+        let endpoint = register_forward(&self.router, &self.decoders, move |frame| {
+            outbound.send(frame)
+        });
+
+        let outbound = self.outbound.clone();
+        let decoders = self.decoders.clone();
+        let discovery = self.discovery.clone();
+        self.router.add_remote_route(
+            TypeId::of::<M>(),
+            endpoint.addr(),
+            Arc::new(move |message| Self::route::<M>(message, &outbound, &decoders, &discovery)),
+        );
+
+        endpoint
+    }
+
+    /// Send a unicast message to a remote node which advertised its payload type, or a message destined to
+    /// a remote endpoint to its node, returning false if the bridge has no live advertisement from such a node
+    fn route<M: BridgePayload>(
+        message: &Message,
+        outbound: &Outbound<T, C>,
+        decoders: &Decoders<C>,
+        discovery: &OnceLock<Discovery>,
+    ) -> bool {
+        let registry = decoders.registry();
+
+        let (Some(discovery), Some(id), Some(payload)) =
+            (discovery.get(), registry.id::<M>(), message.inner::<M>())
+        else {
+            return false;
+        };
+
+        let dest = match message.dest() {
+            Destination::Remote(node, addr) if discovery.is_live(node) => WireDest::Endpoint {
+                node: node.id(),
+                addr,
+            },
+            Destination::Remote(node, _) => {
+                trace!("No live advertisement from {node:?}");
+                return false;
+            }
+            _ => match discovery.select(&id) {
+                Some(node) => WireDest::Node(node),
+                None => {
+                    trace!("No remote node advertised {id}");
+                    return false;
+                }
+            },
+        };
+
+        let sent = registry.encode(payload).and_then(|mut wire| {
+            wire.dest = dest;
+            outbound.send(&wire.to_frame(registry.codec())?)
+        });
+
+        match sent {
+            Ok(()) => true,
+            Err(e) => {
+                error!("Failed to send {} to a remote node: {e}", type_name::<M>());
+                false
+            }
+        }
+    }
+
+    /// Send a payload directly to all peers, without dispatching it in the local router
+    pub fn send<M>(&self, payload: &M) -> Result<(), BridgeError>
+    where
+        M: BridgePayload,
+    {
+        self.outbound.send(&self.decoders.encode_frame(payload)?)
+    }
+
+    /// Receive frames until shutdown, and dispatch them into the router
+    fn receive(
+        transport: Arc<T>,
+        outbound: Arc<Outbound<T, C>>,
+        decoders: Arc<Decoders<C>>,
+        discovery: Arc<OnceLock<Discovery>>,
+        router: MessageRouter<'static, R>,
+        shutdown: Arc<AtomicBool>,
+    ) {
+        debug!("Bridge receiver started for {}", std::any::type_name::<T>());
+
+        if let Err(e) = outbound.announce() {
+            error!("Failed to announce capabilities: {e}");
+        }
+
+        while !shutdown.load(Ordering::Relaxed) {
+            if let Some(stalled) = outbound.take_stall() {
+                warn!("Bridge stalled with a window of {} frames", stalled.window);
+                router.handle_message(Message::broadcast(stalled));
+            }
+
+            if let Err(e) = outbound.heartbeat() {
+                debug!("Failed to send heartbeat: {e}");
+            }
+
+            for unreachable in outbound.take_unreachable() {
+                warn!(
+                    "Peer {:#x} sent no heartbeat for {:?}",
+                    unreachable.peer, unreachable.silent_for
+                );
+                router.handle_message(Message::broadcast(unreachable));
+            }
+
+            if let Some(discovery) = discovery.get() {
+                if discovery.advertisement_due() {
+                    Self::advertise(&transport, &decoders, discovery, &router);
+                }
+            }
+
+            let frame = match transport.recv_frame(POLL_INTERVAL) {
+                Ok(Some(frame)) => frame,
+                Ok(None) => continue,
+                Err(e) => {
+                    error!("Bridge receive failed: {e}");
+                    std::thread::sleep(POLL_INTERVAL);
+                    continue;
+                }
+            };
+
+            let frames = match Self::unpack(frame, &decoders) {
+                Ok(frames) => frames,
+                Err(e) => {
+                    warn!("Dropping frame: {e}");
+                    continue;
+                }
+            };
+
+            for frame in frames {
+                match Self::decode(&frame, &outbound, &decoders, &discovery) {
+                    Ok(Some(message)) => {
+                        trace!("Received {message:?}");
+                        router.handle_message(message);
+                    }
+                    Ok(None) => {}
+                    Err(e) => warn!("Dropping frame: {e}"),
+                }
+            }
+        }
+    }
+
+    /// Decompress a compressed frame, and split a batch frame into the frames it carries.
+    /// Other frames are returned as is.
+    fn unpack(frame: Vec<u8>, decoders: &Decoders<C>) -> Result<Vec<Vec<u8>>, BridgeError> {
+        let codec = decoders.registry().codec();
+        let wire = WireMessage::from_frame(codec, &frame)?;
+
+        match wire.type_name.as_str() {
+            COMPRESSED_ID => Self::unpack(compression::decompress(codec, &wire.payload)?, decoders),
+            BATCH_ID => codec.decode(&wire.payload),
+            _ => Ok(vec![frame]),
+        }
+    }
+
+    /// Decode a frame, returning the message it carries for the local router, if any.
+    /// Control frames are handled, and messages for other nodes are ignored.
+    fn decode(
+        frame: &[u8],
+        outbound: &Outbound<T, C>,
+        decoders: &Decoders<C>,
+        discovery: &OnceLock<Discovery>,
+    ) -> Result<Option<Message>, BridgeError> {
+        let codec = decoders.registry().codec();
+        let wire = WireMessage::from_frame(codec, frame)?;
+
+        if wire.type_name == CAPABILITIES_ID {
+            outbound.update_capabilities(&wire.payload)?;
+            return Ok(None);
+        }
+
+        if wire.type_name == FLOW_ID {
+            outbound.update_flow(&wire.payload)?;
+            return Ok(None);
+        }
+
+        if wire.type_name == HEARTBEAT_ID {
+            return Ok(outbound
+                .update_heartbeat(&wire.payload)?
+                .map(Message::broadcast));
+        }
+
+        if wire.type_name == ADVERTISEMENT_ID {
+            if let Some(discovery) = discovery.get() {
+                discovery.update(codec.decode::<Advertisement>(&wire.payload)?);
+            }
+            return Ok(None);
+        }
+
+        match &wire.dest {
+            WireDest::Broadcast => decoders.decode_wire(&wire).map(Some),
+            WireDest::Node(node) if discovery.get().is_some_and(|d| d.node() == node) => {
+                let message = decoders.decode_wire(&wire)?;
+                Ok(Some(message.with_dest(Destination::any())))
+            }
+            WireDest::Node(node) => {
+                trace!("Ignoring {} for node {node}", wire.type_name);
+                Ok(None)
+            }
+            WireDest::Endpoint { node, addr }
+                if discovery.get().is_some_and(|d| d.id().id() == *node) =>
+            {
+                let message = decoders.decode_wire(&wire)?;
+                Ok(Some(message.with_dest(Destination::endpoint(*addr))))
+            }
+            WireDest::Endpoint { node, .. } => {
+                trace!("Ignoring {} for node {node:#x}", wire.type_name);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Advertise the registered payload types which have endpoints in the local router
+    fn advertise(
+        transport: &T,
+        decoders: &Decoders<C>,
+        discovery: &Discovery,
+        router: &MessageRouter<'static, R>,
+    ) {
+        let types = decoders
+            .types()
+            .into_iter()
+            .filter(|type_id| router.has_local_handlers(*type_id))
+            .filter_map(|type_id| decoders.registry().id_of(type_id))
+            .collect();
+
+        if let Err(e) = Self::advertisement_frame(decoders, discovery.advertisement(types))
+            .and_then(|frame| transport.send_frame(&frame))
+        {
+            error!("Failed to send advertisement: {e}");
+        }
+    }
+
+    /// Encode an advertisement into a frame
+    fn advertisement_frame(
+        decoders: &Decoders<C>,
+        advertisement: Advertisement,
+    ) -> Result<Vec<u8>, BridgeError> {
+        let codec = decoders.registry().codec();
+        let wire = WireMessage {
+            type_name: ADVERTISEMENT_ID.into(),
+            payload: codec.encode(&advertisement)?,
+            dest: WireDest::Broadcast,
+            traceparent: None,
+        };
+
+        wire.to_frame(codec)
+    }
+}
+
+impl<T, R, C> Drop for Bridge<T, R, C>
+where
+    T: Transport,
+{
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+        if let Some(receiver) = self.receiver.take() {
+            let _ = receiver.join();
+        }
+
+        // The batch thread sends any queued frames before it exits
+        if let Some(batcher) = self.batcher.take() {
+            self.outbound.wake();
+            let _ = batcher.join();
+        }
+
+        // Withdraw the advertisement of this node, so peers stop routing to it
+        if let Some(withdrawal) = &self.withdrawal {
+            if let Err(e) = self.transport.send_frame(withdrawal) {
+                debug!("Failed to withdraw advertisement: {e}");
+            }
+        }
+    }
+}
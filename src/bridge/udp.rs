@@ -0,0 +1,204 @@
+//! UDP datagram transport
+//!
+//! Each message is sent as a single datagram to every configured peer, so encoded messages
+//! must fit within [`MAX_DATAGRAM`] bytes. Delivery is unreliable and unordered, which suits
+//! small periodic messages such as sensor readings and telemetry.
+//!
+//! Messages received from peers are broadcast into the local router, with the sender [`SocketAddr`]
+//! as the message source.
+
+use std::{
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use anylock::{AnyLock, ParkingLotRwLock};
+use tracing::{debug, error, trace, warn};
+
+use crate::{endpoint::Endpoint, router::MessageRouter};
+
+use super::{register_forward, Bincode, BridgeError, BridgePayload, Codec, Decoders, TypeRegistry};
+
+/// Maximum payload size of a UDP datagram over IPv4
+pub const MAX_DATAGRAM: usize = 65507;
+
+/// Interval at which the receiver thread checks for shutdown
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Bridges registered payload types to peers over UDP, encoding frames with codec `C`
+pub struct UdpTransport<R, C = Bincode> {
+    socket: UdpSocket,
+    peers: Arc<ParkingLotRwLock<Vec<SocketAddr>>>,
+    decoders: Arc<Decoders<C>>,
+    router: MessageRouter<'static, R>,
+    shutdown: Arc<AtomicBool>,
+    receiver: Option<JoinHandle<()>>,
+}
+
+impl<R, C> std::fmt::Debug for UdpTransport<R, C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UdpTransport")
+            .field("local_addr", &self.socket.local_addr().ok())
+            .field("peers", &*self.peers.read())
+            .field("types", &self.decoders)
+            .finish()
+    }
+}
+
+impl<R> UdpTransport<R>
+where
+    R: Default + Send + 'static,
+{
+    /// Bind a UDP socket, and spawn a thread injecting received messages into `router`
+    pub fn bind(
+        addr: impl ToSocketAddrs,
+        router: &MessageRouter<'static, R>,
+    ) -> Result<Self, BridgeError> {
+        Self::bind_with_registry(addr, router, Arc::new(TypeRegistry::new()))
+    }
+}
+
+impl<R, C> UdpTransport<R, C>
+where
+    R: Default + Send + 'static,
+    C: Codec,
+{
+    /// Bind a UDP socket which identifies and encodes payloads with `registry`,
+    /// and spawn a thread injecting received messages into `router`
+    pub fn bind_with_registry(
+        addr: impl ToSocketAddrs,
+        router: &MessageRouter<'static, R>,
+        registry: Arc<TypeRegistry<C>>,
+    ) -> Result<Self, BridgeError> {
+        let socket = UdpSocket::bind(addr)?;
+        let decoders = Arc::new(Decoders::new(registry));
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let receiver = {
+            let socket = socket.try_clone()?;
+            socket.set_read_timeout(Some(POLL_INTERVAL))?;
+
+            let decoders = decoders.clone();
+            let shutdown = shutdown.clone();
+            let router = router.clone();
+
+            std::thread::Builder::new()
+                .name("salish-udp".into())
+                .spawn(move || Self::receive(socket, decoders, router, shutdown))?
+        };
+
+        debug!("UDP transport bound to {:?}", socket.local_addr());
+
+        Ok(Self {
+            socket,
+            peers: Arc::new(ParkingLotRwLock::new(Vec::new())),
+            decoders,
+            router: router.clone(),
+            shutdown,
+            receiver: Some(receiver),
+        })
+    }
+
+    /// Get the local address the socket is bound to
+    pub fn local_addr(&self) -> Result<SocketAddr, BridgeError> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    /// Add a peer which will receive forwarded messages
+    pub fn add_peer(&self, peer: impl ToSocketAddrs) -> Result<(), BridgeError> {
+        self.peers.write().extend(peer.to_socket_addrs()?);
+        Ok(())
+    }
+
+    /// Register payload type `M` with the transport.
+    ///
+    /// Messages of type `M` received from peers are injected into the router, and messages of type `M`
+    /// dispatched in the local router are forwarded to all peers until the returned [`Endpoint`] is dropped.
+    pub fn register<M>(&self) -> Endpoint<'static, M, R>
+    where
+        M: BridgePayload,
+    {
+        let socket = self.socket.try_clone();
+        let peers = self.peers.clone();
+
+        register_forward(&self.router, &self.decoders, move |frame| match &socket {
+            Ok(socket) => Self::send_frame(socket, &peers.read(), frame),
+            Err(e) => Err(BridgeError::Io(e.kind().into())),
+        })
+    }
+
+    /// Send a payload directly to all peers, without dispatching it in the local router
+    pub fn send<M>(&self, payload: &M) -> Result<(), BridgeError>
+    where
+        M: BridgePayload,
+    {
+        let frame = self.decoders.encode_frame(payload)?;
+        Self::send_frame(&self.socket, &self.peers.read(), &frame)
+    }
+
+    fn send_frame(
+        socket: &UdpSocket,
+        peers: &[SocketAddr],
+        frame: &[u8],
+    ) -> Result<(), BridgeError> {
+        if frame.len() > MAX_DATAGRAM {
+            return Err(BridgeError::FrameTooLarge(frame.len()));
+        }
+
+        for peer in peers {
+            trace!("Sending {} bytes to {peer}", frame.len());
+            socket.send_to(frame, peer)?;
+        }
+        Ok(())
+    }
+
+    /// Receive datagrams until shutdown, and dispatch them into the router
+    fn receive(
+        socket: UdpSocket,
+        decoders: Arc<Decoders<C>>,
+        router: MessageRouter<'static, R>,
+        shutdown: Arc<AtomicBool>,
+    ) {
+        let mut buf = vec![0u8; MAX_DATAGRAM];
+
+        while !shutdown.load(Ordering::Relaxed) {
+            let (len, addr) = match socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    continue
+                }
+                Err(e) => {
+                    error!("UDP receive failed: {e}");
+                    continue;
+                }
+            };
+
+            match decoders.decode_frame(&buf[..len]) {
+                Ok(message) => {
+                    trace!("Received {message:?} from {addr}");
+                    router.handle_message(message.with_source(addr));
+                }
+                Err(e) => warn!("Dropping datagram from {addr}: {e}"),
+            }
+        }
+    }
+}
+
+impl<R, C> Drop for UdpTransport<R, C> {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+        if let Some(receiver) = self.receiver.take() {
+            let _ = receiver.join();
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! ZeroMQ [`Transport`] using PUB/SUB sockets
+//!
+//! Each [`ZmqTransport`] binds a PUB socket which frames are published on, and connects a SUB socket
+//! to the PUB sockets of its peers. Peers can join and leave at any time, and ZeroMQ reconnects
+//! automatically, but frames published before a subscriber has connected are not delivered.
+
+use std::time::Duration;
+
+use anylock::{AnyLock, ParkingLotMutex};
+use tracing::debug;
+use zmq::{Context, Socket, SocketType};
+
+use super::{transport::Transport, BridgeError};
+
+/// [`Transport`] publishing frames to peers over ZeroMQ
+pub struct ZmqTransport {
+    context: Context,
+    publisher: ParkingLotMutex<Socket>,
+    subscriber: ParkingLotMutex<Socket>,
+}
+
+impl std::fmt::Debug for ZmqTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ZmqTransport")
+            .field("endpoint", &self.endpoint().ok())
+            .finish()
+    }
+}
+
+impl ZmqTransport {
+    /// Bind the publishing socket to a ZeroMQ endpoint such as `tcp://127.0.0.1:5555`
+    pub fn bind(endpoint: &str) -> Result<Self, BridgeError> {
+        Self::bind_with_context(&Context::new(), endpoint)
+    }
+
+    /// Bind the publishing socket using an existing [`Context`], which allows `inproc://` endpoints
+    /// to be shared with other sockets of the application
+    pub fn bind_with_context(context: &Context, endpoint: &str) -> Result<Self, BridgeError> {
+        let publisher = context.socket(SocketType::PUB)?;
+        publisher.set_linger(0)?;
+        publisher.bind(endpoint)?;
+
+        let subscriber = context.socket(SocketType::SUB)?;
+        subscriber.set_linger(0)?;
+        subscriber.set_subscribe(b"")?;
+
+        let transport = Self {
+            context: context.clone(),
+            publisher: ParkingLotMutex::new(publisher),
+            subscriber: ParkingLotMutex::new(subscriber),
+        };
+
+        debug!("ZeroMQ transport bound to {:?}", transport.endpoint());
+        Ok(transport)
+    }
+
+    /// Get the endpoint the publishing socket is bound to, with any wildcard port resolved
+    pub fn endpoint(&self) -> Result<String, BridgeError> {
+        self.publisher
+            .write()
+            .get_last_endpoint()?
+            .map_err(|_| BridgeError::Transport("endpoint is not valid UTF-8".into()))
+    }
+
+    /// Get the [`Context`] of the transport
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+}
+
+impl Transport for ZmqTransport {
+    fn connect(&self, addr: &str) -> Result<(), BridgeError> {
+        self.subscriber.write().connect(addr)?;
+        debug!("ZeroMQ transport subscribed to {addr}");
+        Ok(())
+    }
+
+    fn send_frame(&self, frame: &[u8]) -> Result<(), BridgeError> {
+        Ok(self.publisher.write().send(frame, 0)?)
+    }
+
+    fn recv_frame(&self, timeout: Duration) -> Result<Option<Vec<u8>>, BridgeError> {
+        let subscriber = self.subscriber.write();
+        if subscriber.poll(zmq::POLLIN, timeout.as_millis() as i64)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(subscriber.recv_bytes(0)?))
+    }
+}
+
+impl From<zmq::Error> for BridgeError {
+    fn from(e: zmq::Error) -> Self {
+        BridgeError::Transport(e.to_string())
+    }
+}
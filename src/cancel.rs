@@ -0,0 +1,33 @@
+//! Cancellation of in-flight dispatch
+//!
+//! A [`CancellationToken`] passed to
+//! [`MessageRouter::handle_message_cancellable()`](crate::router::MessageRouter::handle_message_cancellable) stops a
+//! broadcast between handlers once it is cancelled, such as during shutdown. Clones of the token share its state,
+//! so handlers and other threads can hold a clone to cancel the dispatch, or to stop their own work early.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// Token shared by its clones, which can be cancelled once
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the token, and all of its clones
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Release)
+    }
+
+    /// Check if the token was cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+}
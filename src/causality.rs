@@ -0,0 +1,167 @@
+//! Links between messages and the messages which caused them
+//!
+//! Each [`Message`] has a [`MessageId`]. Messages created while a handler is running, including messages queued
+//! with a [`RouterSender`](crate::queue::RouterSender) and the messages of handlers on worker threads, record
+//! the message being handled as their [`Message::caused_by()`], so chains of messages can be traced back to the
+//! message which triggered them.
+//!
+//! Routers configured with [`RouterConfig::causality_log()`](crate::config::RouterConfig::causality_log) keep
+//! the links of the messages they dispatched, which are walked with
+//! [`MessageRouter::causal_chain()`](crate::router::MessageRouter::causal_chain).
+//!
+//! ```
+//! use salish::{router::MessageRouter, Message, RouterConfig};
+//!
+//! let router = MessageRouter::<()>::with_config(RouterConfig::default().causality_log(64));
+//!
+//! let sender = router.sender();
+//! let _button = router.create_endpoint::<&'static str>().message(move |_src, _msg| {
+//!     sender.send(Message::unicast(1u32));
+//! });
+//! let _counter = router.create_endpoint::<u32>().message(|_src, _msg| {});
+//!
+//! let clicked = Message::unicast("clicked");
+//! let id = clicked.id();
+//! router.handle_message(clicked);
+//! router.drain();
+//!
+//! let last = router.causal_links().last().unwrap().id;
+//! let chain = router.causal_chain(last);
+//! assert_eq!(chain.len(), 2);
+//! assert_eq!(chain[1].id, id);
+//! ```
+
+use std::{
+    cell::Cell,
+    collections::{HashMap, VecDeque},
+};
+
+use anylock::{AnyLock, ParkingLotMutex};
+
+use crate::{message::MessageId, Message};
+
+thread_local! {
+    /// Message whose handler is running on this thread
+    static CURRENT: Cell<Option<MessageId>> = const { Cell::new(None) };
+}
+
+/// Get the message whose handler is running on this thread
+pub(crate) fn current() -> Option<MessageId> {
+    CURRENT.get()
+}
+
+/// Restores the message of the outer handler when a handler returns or panics
+pub(crate) struct Entered(Option<MessageId>);
+
+impl Drop for Entered {
+    fn drop(&mut self) {
+        CURRENT.set(self.0);
+    }
+}
+
+/// Mark `id` as the message whose handler is running on this thread, until the guard is dropped
+pub(crate) fn enter(id: Option<MessageId>) -> Entered {
+    Entered(CURRENT.replace(id))
+}
+
+/// Link of a dispatched message to the message which caused it, returned by
+/// [`MessageRouter::causal_chain()`](crate::router::MessageRouter::causal_chain)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CausalLink {
+    pub id: MessageId,
+
+    /// Rust type name of the payload
+    pub type_name: &'static str,
+
+    /// Message whose handler created this message
+    pub caused_by: Option<MessageId>,
+}
+
+/// Links of the last dispatched messages, shared by all clones of a router
+pub(crate) struct CausalityLog {
+    capacity: usize,
+    links: ParkingLotMutex<Links>,
+}
+
+/// Links by message, with the order they were recorded in to discard the oldest
+#[derive(Default)]
+struct Links {
+    by_id: HashMap<MessageId, CausalLink>,
+    order: VecDeque<MessageId>,
+}
+
+impl std::fmt::Debug for CausalityLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CausalityLog")
+            .field("capacity", &self.capacity)
+            .field("links", &self.links.read().order.len())
+            .finish()
+    }
+}
+
+impl CausalityLog {
+    /// Create a log keeping the links of up to `capacity` messages
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            links: ParkingLotMutex::new(Links::default()),
+        }
+    }
+
+    /// Record the link of a message being dispatched, discarding the oldest link if the log is full
+    pub(crate) fn record(&self, message: &Message) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut links = self.links.write();
+        if links.by_id.contains_key(&message.id()) {
+            return;
+        }
+
+        if links.order.len() == self.capacity {
+            if let Some(oldest) = links.order.pop_front() {
+                links.by_id.remove(&oldest);
+            }
+        }
+
+        links.order.push_back(message.id());
+        links.by_id.insert(
+            message.id(),
+            CausalLink {
+                id: message.id(),
+                type_name: message.type_name(),
+                caused_by: message.caused_by(),
+            },
+        );
+    }
+
+    /// Walk from `id` to the messages which caused it, until a message without a recorded cause
+    pub(crate) fn chain(&self, id: MessageId) -> Vec<CausalLink> {
+        let links = self.links.read();
+        let mut chain = Vec::new();
+        let mut next = Some(id);
+
+        // Causes are created before the messages they cause, but they can be set explicitly
+        while let Some(link) = next.and_then(|id| links.by_id.get(&id)) {
+            if chain.len() == links.order.len() {
+                break;
+            }
+
+            chain.push(*link);
+            next = link.caused_by;
+        }
+
+        chain
+    }
+
+    /// Links of the recorded messages, oldest first
+    pub(crate) fn links(&self) -> Vec<CausalLink> {
+        let links = self.links.read();
+        links
+            .order
+            .iter()
+            .filter_map(|id| links.by_id.get(id).copied())
+            .collect()
+    }
+}
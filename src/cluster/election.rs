@@ -0,0 +1,157 @@
+//! Leader election
+//!
+//! An [`Election`] elects a single leader among the live members of a [`Cluster`], bully style: the node with
+//! the highest identity among the local node and the remote nodes which are up leads. As membership is gossiped,
+//! all nodes converge on the same leader without exchanging further messages. When the leader fails, it is
+//! replaced by the next highest node once the cluster detects the failure.
+//!
+//! Elections wait for one failure timeout after starting, so existing members are known before the first
+//! leader is elected. Each change of leader is broadcast into the local router as a [`LeadershipChanged`]
+//! message.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::Instant,
+};
+
+use anylock::{AnyLock, ParkingLotMutex, ParkingLotRwLock};
+use tracing::{debug, info};
+
+use crate::{router::MessageRouter, Message};
+
+use super::{Cluster, Membership};
+
+/// Broadcast into the local router when the leader of the cluster changes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeadershipChanged {
+    /// Identity of the new leader
+    pub leader: String,
+
+    /// Identity of the previous leader, or `None` for the first election
+    pub previous: Option<String>,
+
+    /// True if the local node is the new leader
+    pub is_local: bool,
+}
+
+/// Leader election among the members of a [`Cluster`].
+///
+/// The leader is tracked on a background thread, which is stopped when the election is dropped.
+pub struct Election {
+    node: String,
+    leader: Arc<ParkingLotRwLock<Option<String>>>,
+    shutdown: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl std::fmt::Debug for Election {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Election")
+            .field("node", &self.node)
+            .field("leader", &*self.leader.read())
+            .finish()
+    }
+}
+
+impl Election {
+    /// Start electing a leader among the members of `cluster`,
+    /// broadcasting [`LeadershipChanged`] messages into `router`
+    pub fn new<R>(cluster: &Cluster<R>, router: &MessageRouter<'static, R>) -> std::io::Result<Self>
+    where
+        R: Default + Send + 'static,
+    {
+        let leader = Arc::new(ParkingLotRwLock::new(None));
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let thread = {
+            let membership = cluster.membership().clone();
+            let config = cluster.config();
+            let leader = leader.clone();
+            let shutdown = shutdown.clone();
+            let router = router.clone();
+
+            std::thread::Builder::new()
+                .name("salish-election".into())
+                .spawn(move || {
+                    // Learn of existing members before the first election
+                    let start = Instant::now() + config.timeout;
+                    while !shutdown.load(Ordering::Relaxed) && Instant::now() < start {
+                        std::thread::park_timeout(start.saturating_duration_since(Instant::now()));
+                    }
+
+                    while !shutdown.load(Ordering::Relaxed) {
+                        Self::elect(&membership, &leader, &router);
+                        std::thread::park_timeout(config.interval);
+                    }
+                })?
+        };
+
+        Ok(Self {
+            node: cluster.node(),
+            leader,
+            shutdown,
+            thread: Some(thread),
+        })
+    }
+
+    /// Get the identity of the current leader, or `None` before the first election
+    pub fn leader(&self) -> Option<String> {
+        self.leader.read().clone()
+    }
+
+    /// Check if the local node is the current leader
+    pub fn is_leader(&self) -> bool {
+        self.leader.read().as_deref() == Some(self.node.as_str())
+    }
+
+    /// Elect the leader from the current membership, broadcasting a [`LeadershipChanged`] if it changed
+    fn elect<R>(
+        membership: &ParkingLotMutex<Membership>,
+        leader: &ParkingLotRwLock<Option<String>>,
+        router: &MessageRouter<'static, R>,
+    ) where
+        R: Send,
+    {
+        let (elected, is_local) = {
+            let membership = membership.write();
+            let elected = membership.leader().to_string();
+            let is_local = elected == membership.node;
+            (elected, is_local)
+        };
+
+        let previous = {
+            let mut leader = leader.write();
+            if leader.as_ref() == Some(&elected) {
+                return;
+            }
+            leader.replace(elected.clone())
+        };
+
+        if is_local {
+            info!("Elected as leader");
+        } else {
+            debug!("Elected {elected} as leader");
+        }
+
+        router.handle_message(Message::broadcast(LeadershipChanged {
+            leader: elected,
+            previous,
+            is_local,
+        }));
+    }
+}
+
+impl Drop for Election {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+
+        if let Some(thread) = self.thread.take() {
+            thread.thread().unpark();
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,404 @@
+//! Cluster membership and failure detection
+//!
+//! A [`Cluster`] tracks the nodes of a cluster by gossiping [`Heartbeat`] messages through the local router.
+//! Heartbeats are carried to peers by registering the [`Heartbeat`] payload type with a bridge, so membership
+//! works over any of the existing transports.
+//!
+//! Each heartbeat carries a counter for every live node known to the sender, so nodes learn of peers they are
+//! not directly bridged to. A node whose counter has not advanced within the failure timeout is considered down.
+//! [`NodeUp`] and [`NodeDown`] messages are broadcast into the local router as nodes join and fail, so
+//! applications can react to membership changes with normal endpoints.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime},
+};
+
+use anylock::{AnyLock, ParkingLotMutex};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use crate::{endpoint::Endpoint, router::MessageRouter, Message};
+
+pub mod election;
+
+pub use election::{Election, LeadershipChanged};
+
+/// Heartbeat interval and failure timeout of a [`Cluster`]
+#[derive(Debug, Clone, Copy)]
+pub struct ClusterConfig {
+    /// Interval between heartbeats
+    pub interval: Duration,
+
+    /// Time without a newer heartbeat after which a node is considered down
+    pub timeout: Duration,
+}
+
+impl Default for ClusterConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Latest heartbeat of a node, as known to the sender of a [`Heartbeat`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Beat {
+    /// Identity of the node
+    pub node: String,
+
+    /// Start time of the node in milliseconds since the unix epoch, which distinguishes restarts of a node
+    pub incarnation: u64,
+
+    /// Heartbeat counter of the node
+    pub counter: u64,
+}
+
+impl Beat {
+    /// Check if this beat is newer than a beat of the same node
+    fn is_newer(&self, incarnation: u64, counter: u64) -> bool {
+        (self.incarnation, self.counter) > (incarnation, counter)
+    }
+}
+
+/// Heartbeat gossiped between the nodes of a cluster
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Heartbeat {
+    /// Identity of the sending node
+    pub from: String,
+
+    /// Beats of the sending node and of all nodes it considers up
+    pub beats: Vec<Beat>,
+}
+
+/// Broadcast into the local router when a node joins the cluster, or recovers after being down
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeUp {
+    pub node: String,
+}
+
+/// Broadcast into the local router when a node misses heartbeats for the failure timeout
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeDown {
+    pub node: String,
+}
+
+/// Membership change waiting to be broadcast into the router
+#[derive(Debug)]
+enum Event {
+    Up(String),
+    Down(String),
+}
+
+/// Remote node known to the local node
+#[derive(Debug)]
+struct Member {
+    incarnation: u64,
+    counter: u64,
+    last_seen: Instant,
+    up: bool,
+}
+
+/// Membership list of the local node
+#[derive(Debug)]
+pub(crate) struct Membership {
+    node: String,
+    incarnation: u64,
+    counter: u64,
+    members: HashMap<String, Member>,
+    events: Vec<Event>,
+}
+
+impl Membership {
+    fn new(node: String) -> Self {
+        let incarnation = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or_default();
+
+        Self {
+            node,
+            incarnation,
+            counter: 0,
+            members: HashMap::new(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Advance the local counter, and create a heartbeat gossiping all live nodes
+    fn heartbeat(&mut self) -> Heartbeat {
+        self.counter += 1;
+
+        let mut beats = vec![Beat {
+            node: self.node.clone(),
+            incarnation: self.incarnation,
+            counter: self.counter,
+        }];
+
+        beats.extend(
+            self.members
+                .iter()
+                .filter(|(_, member)| member.up)
+                .map(|(node, member)| Beat {
+                    node: node.clone(),
+                    incarnation: member.incarnation,
+                    counter: member.counter,
+                }),
+        );
+
+        Heartbeat {
+            from: self.node.clone(),
+            beats,
+        }
+    }
+
+    /// Merge the beats of a received heartbeat, returning true if any node came up
+    fn merge(&mut self, heartbeat: Heartbeat) -> bool {
+        let now = Instant::now();
+        let mut changed = false;
+
+        for beat in heartbeat.beats {
+            if beat.node == self.node {
+                continue;
+            }
+
+            match self.members.get_mut(&beat.node) {
+                Some(member) if beat.is_newer(member.incarnation, member.counter) => {
+                    member.incarnation = beat.incarnation;
+                    member.counter = beat.counter;
+                    member.last_seen = now;
+
+                    if !member.up {
+                        member.up = true;
+                        self.events.push(Event::Up(beat.node));
+                        changed = true;
+                    }
+                }
+                Some(_) => {}
+                None => {
+                    self.members.insert(
+                        beat.node.clone(),
+                        Member {
+                            incarnation: beat.incarnation,
+                            counter: beat.counter,
+                            last_seen: now,
+                            up: true,
+                        },
+                    );
+                    self.events.push(Event::Up(beat.node));
+                    changed = true;
+                }
+            }
+        }
+
+        changed
+    }
+
+    /// Get the node with the highest identity among the local node and the remote nodes which are up
+    pub(crate) fn leader(&self) -> &str {
+        self.members
+            .iter()
+            .filter(|(_, member)| member.up)
+            .map(|(node, _)| node.as_str())
+            .fold(self.node.as_str(), |leader, node| leader.max(node))
+    }
+
+    /// Mark nodes without a newer beat within `timeout` as down.
+    /// Down nodes are remembered, so stale gossip about them doesn't bring them back up.
+    fn expire(&mut self, timeout: Duration) {
+        let now = Instant::now();
+
+        for (node, member) in &mut self.members {
+            if member.up && now.duration_since(member.last_seen) > timeout {
+                member.up = false;
+                self.events.push(Event::Down(node.clone()));
+            }
+        }
+    }
+}
+
+/// Membership of the local node in a cluster.
+///
+/// Heartbeats are sent and membership changes are broadcast into the router from a background thread,
+/// which is stopped when the cluster is dropped.
+pub struct Cluster<R>
+where
+    R: Send + 'static,
+{
+    membership: Arc<ParkingLotMutex<Membership>>,
+    config: ClusterConfig,
+    shutdown: Arc<AtomicBool>,
+    wake: mpsc::Sender<()>,
+    thread: Option<JoinHandle<()>>,
+    _endpoint: Endpoint<'static, Heartbeat, R>,
+}
+
+impl<R> std::fmt::Debug for Cluster<R>
+where
+    R: Send + 'static,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Cluster")
+            .field("membership", &self.membership.read())
+            .finish()
+    }
+}
+
+impl<R> Cluster<R>
+where
+    R: Default + Send + 'static,
+{
+    /// Join a cluster as `node`, gossiping heartbeats through `router`.
+    ///
+    /// The [`Heartbeat`] payload type must be registered with the bridges of the router
+    /// for heartbeats to reach other nodes. Node identities must be unique within the cluster.
+    pub fn new(
+        node: impl Into<String>,
+        router: &MessageRouter<'static, R>,
+        config: ClusterConfig,
+    ) -> std::io::Result<Self> {
+        let membership = Arc::new(ParkingLotMutex::new(Membership::new(node.into())));
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let (wake, woken) = mpsc::channel();
+
+        let endpoint = {
+            let membership = membership.clone();
+            let wake = wake.clone();
+
+            router
+                .create_endpoint::<Heartbeat>()
+                .message(move |_src, heartbeat| {
+                    // Membership changes are broadcast from the cluster thread, as handlers can't dispatch
+                    if membership.write().merge(heartbeat) {
+                        let _ = wake.send(());
+                    }
+                    R::default()
+                })
+        };
+
+        let thread = {
+            let membership = membership.clone();
+            let shutdown = shutdown.clone();
+            let router = router.clone();
+
+            std::thread::Builder::new()
+                .name("salish-cluster".into())
+                .spawn(move || Self::run(membership, router, config, woken, shutdown))?
+        };
+
+        Ok(Self {
+            membership,
+            config,
+            shutdown,
+            wake,
+            thread: Some(thread),
+            _endpoint: endpoint,
+        })
+    }
+
+    /// Get the identity of the local node
+    pub fn node(&self) -> String {
+        self.membership.read().node.clone()
+    }
+
+    /// Get the heartbeat interval and failure timeout of the cluster
+    pub fn config(&self) -> ClusterConfig {
+        self.config
+    }
+
+    /// Get the membership list of the local node
+    pub(crate) fn membership(&self) -> &Arc<ParkingLotMutex<Membership>> {
+        &self.membership
+    }
+
+    /// Get the remote nodes which are up, sorted by identity
+    pub fn members(&self) -> Vec<String> {
+        let mut members: Vec<String> = self
+            .membership
+            .read()
+            .members
+            .iter()
+            .filter(|(_, member)| member.up)
+            .map(|(node, _)| node.clone())
+            .collect();
+
+        members.sort();
+        members
+    }
+
+    /// Check if a remote node is up
+    pub fn is_up(&self, node: &str) -> bool {
+        self.membership
+            .read()
+            .members
+            .get(node)
+            .is_some_and(|member| member.up)
+    }
+
+    /// Send heartbeats, detect failed nodes, and broadcast membership changes until shutdown
+    fn run(
+        membership: Arc<ParkingLotMutex<Membership>>,
+        router: MessageRouter<'static, R>,
+        config: ClusterConfig,
+        woken: mpsc::Receiver<()>,
+        shutdown: Arc<AtomicBool>,
+    ) {
+        debug!("Cluster started as {}", membership.read().node);
+
+        let mut next_heartbeat = Instant::now();
+
+        while !shutdown.load(Ordering::Relaxed) {
+            if Instant::now() >= next_heartbeat {
+                let heartbeat = membership.write().heartbeat();
+                router.handle_message(Message::broadcast(heartbeat));
+                next_heartbeat += config.interval;
+            }
+
+            let events = {
+                let mut membership = membership.write();
+                membership.expire(config.timeout);
+                std::mem::take(&mut membership.events)
+            };
+
+            for event in events {
+                match event {
+                    Event::Up(node) => {
+                        info!("Node {node} is up");
+                        router.handle_message(Message::broadcast(NodeUp { node }));
+                    }
+                    Event::Down(node) => {
+                        warn!("Node {node} is down");
+                        router.handle_message(Message::broadcast(NodeDown { node }));
+                    }
+                }
+            }
+
+            let timeout = next_heartbeat.saturating_duration_since(Instant::now());
+            if let Err(RecvTimeoutError::Disconnected) = woken.recv_timeout(timeout) {
+                break;
+            }
+        }
+    }
+}
+
+impl<R> Drop for Cluster<R>
+where
+    R: Send + 'static,
+{
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+        let _ = self.wake.send(());
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,155 @@
+//! Router configuration
+
+use std::time::Duration;
+
+/// Handling of unroutable messages, which have a payload type without endpoints,
+/// or are addressed to an endpoint or remote node which doesn't exist
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Unroutable {
+    /// Drop the message, logging an error
+    Error,
+
+    /// Panic, so unroutable messages are caught during development
+    Panic,
+
+    /// Pass the message to the dead-letter sink of the router with [`DropReason::Unroutable`]
+    ///
+    /// [`DropReason::Unroutable`]: crate::middleware::DropReason::Unroutable
+    DeadLetter,
+
+    /// Drop the message, logging a warning
+    #[default]
+    Ignore,
+}
+
+/// Configuration of a [`MessageRouter`](crate::router::MessageRouter)
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RouterConfig {
+    unroutable: Unroutable,
+    dead_letter_buffer: usize,
+    unhandled_capacity: usize,
+    unhandled_ttl: Duration,
+    high_watermark: usize,
+    low_watermark: usize,
+    shed_after: Option<Duration>,
+    causality_log: usize,
+}
+
+impl RouterConfig {
+    /// Set the handling of unroutable messages
+    pub fn unroutable(mut self, unroutable: Unroutable) -> Self {
+        self.unroutable = unroutable;
+        self
+    }
+
+    /// Get the handling of unroutable messages
+    pub fn unroutable_policy(&self) -> Unroutable {
+        self.unroutable
+    }
+
+    /// Keep up to `capacity` dropped messages in a buffer, so they can be redelivered with
+    /// [`MessageRouter::redeliver_dead_letters()`](crate::router::MessageRouter::redeliver_dead_letters).
+    /// Buffered messages are passed to the dead-letter sink when they are discarded to make room for newer messages.
+    /// Messages are not buffered by default.
+    ///
+    /// Unroutable messages are only buffered when handled with [`Unroutable::DeadLetter`].
+    pub fn dead_letter_buffer(mut self, capacity: usize) -> Self {
+        self.dead_letter_buffer = capacity;
+        self
+    }
+
+    /// Get the capacity of the dead-letter buffer
+    pub fn dead_letter_capacity(&self) -> usize {
+        self.dead_letter_buffer
+    }
+
+    /// Hold up to `capacity` messages of each payload type without endpoints, for up to `ttl`,
+    /// and dispatch them when an endpoint for the payload type is registered.
+    /// This avoids losing messages sent by producers before their consumers are registered during startup.
+    ///
+    /// Only messages sent to [`Destination::Any`] or [`Destination::Broadcast`] are held.
+    /// Messages which expire, or are discarded to make room for newer messages, are handled as [`Unroutable`].
+    /// Messages are not held by default.
+    ///
+    /// [`Destination::Any`]: crate::message::Destination::Any
+    /// [`Destination::Broadcast`]: crate::message::Destination::Broadcast
+    pub fn buffer_unhandled(mut self, capacity: usize, ttl: Duration) -> Self {
+        self.unhandled_capacity = capacity;
+        self.unhandled_ttl = ttl;
+        self
+    }
+
+    /// Get the number of messages of each payload type without endpoints which are held
+    pub fn unhandled_capacity(&self) -> usize {
+        self.unhandled_capacity
+    }
+
+    /// Get the time messages of payload types without endpoints are held for
+    pub fn unhandled_ttl(&self) -> Duration {
+        self.unhandled_ttl
+    }
+
+    /// Publish a [`QueueHighWatermark`] when the inbound queue of the router, or the queue of an endpoint assigned
+    /// to a worker thread, holds `high` messages or more, and a [`QueueRecovered`] once it has drained to `low`
+    /// messages or fewer. Applications can subscribe to them to shed load before queues grow unbounded.
+    /// Watermarks are disabled by default.
+    ///
+    /// # Panics
+    /// Panics if `low` is not less than `high`
+    ///
+    /// [`QueueHighWatermark`]: crate::queue::QueueHighWatermark
+    /// [`QueueRecovered`]: crate::queue::QueueRecovered
+    pub fn queue_watermarks(mut self, high: usize, low: usize) -> Self {
+        assert!(
+            low < high,
+            "Low watermark must be less than the high watermark"
+        );
+        self.high_watermark = high;
+        self.low_watermark = low;
+        self
+    }
+
+    /// Get the queue depth at which a [`QueueHighWatermark`](crate::queue::QueueHighWatermark) is published,
+    /// or zero if watermarks are disabled
+    pub fn queue_high_watermark(&self) -> usize {
+        self.high_watermark
+    }
+
+    /// Get the queue depth at which a [`QueueRecovered`](crate::queue::QueueRecovered) is published
+    pub fn queue_low_watermark(&self) -> usize {
+        self.low_watermark
+    }
+
+    /// Shed load once the inbound queue of the router has stayed at or above the high watermark configured with
+    /// [`queue_watermarks()`](Self::queue_watermarks) for `after`, until it recovers to the low watermark.
+    /// While shedding, messages of the payload types marked with
+    /// [`MessageRouter::sheddable()`](crate::router::MessageRouter::sheddable) are dropped instead of being
+    /// queued or dispatched, unless they have [`Priority::Critical`](crate::message::Priority::Critical).
+    /// Load is not shed by default.
+    pub fn shed_load(mut self, after: Duration) -> Self {
+        self.shed_after = Some(after);
+        self
+    }
+
+    /// Get the time the inbound queue stays above its high watermark before load is shed, if load shedding is
+    /// enabled
+    pub fn shed_after(&self) -> Option<Duration> {
+        self.shed_after
+    }
+
+    /// Keep the [`CausalLink`]s of up to `capacity` dispatched messages, so the messages which caused a message
+    /// can be found with [`MessageRouter::causal_chain()`]. The oldest links are discarded to make room for newer
+    /// ones. Links are not kept by default.
+    ///
+    /// [`CausalLink`]: crate::causality::CausalLink
+    /// [`MessageRouter::causal_chain()`]: crate::router::MessageRouter::causal_chain
+    pub fn causality_log(mut self, capacity: usize) -> Self {
+        self.causality_log = capacity;
+        self
+    }
+
+    /// Get the number of causal links kept
+    pub fn causality_capacity(&self) -> usize {
+        self.causality_log
+    }
+}
@@ -0,0 +1,133 @@
+//! Shared resources injected into handlers
+//!
+//! Resources such as database pools or configuration are attached to a router with
+//! [`MessageRouter::provide()`](crate::router::MessageRouter::provide), and shared by all clones of the router.
+//! Handlers registered with [`Endpoint::message_ctx()`](crate::endpoint::Endpoint::message_ctx) receive a [`Ctx`]
+//! granting typed access to them, instead of capturing an [`Arc`] of each resource into every closure.
+//!
+//! ```
+//! use salish::{router::MessageRouter, Message};
+//!
+//! #[derive(Debug)]
+//! struct Config {
+//!     scale: u32,
+//! }
+//!
+//! let router = MessageRouter::<u32>::new();
+//! router.provide(Config { scale: 10 });
+//!
+//! let _endpoint = router
+//!     .create_endpoint::<u32>()
+//!     .message_ctx(|ctx, msg| ctx.expect::<Config>().scale * msg);
+//!
+//! let result = router.handle_message(Message::unicast(4u32));
+//! assert_eq!(result.results().unwrap(), [40]);
+//! ```
+
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    sync::Arc,
+};
+
+use anylock::{AnyLock, ParkingLotRwLock};
+
+use crate::message::{MessageId, SourceRef};
+
+/// Resources by type, shared by all clones of a router
+pub(crate) struct Resources {
+    resources: ParkingLotRwLock<HashMap<TypeId, Arc<dyn Any + Send + Sync>>>,
+}
+
+impl std::fmt::Debug for Resources {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Resources")
+            .field("types", &self.resources.read().len())
+            .finish()
+    }
+}
+
+impl Default for Resources {
+    fn default() -> Self {
+        Self {
+            resources: ParkingLotRwLock::new(HashMap::new()),
+        }
+    }
+}
+
+impl Resources {
+    /// Add a resource, returning the resource of the same type it replaced
+    pub(crate) fn insert<T: Send + Sync + 'static>(&self, resource: Arc<T>) -> Option<Arc<T>> {
+        self.resources
+            .write()
+            .insert(TypeId::of::<T>(), resource)
+            .and_then(|previous| previous.downcast().ok())
+    }
+
+    /// Remove the resource of type `T`
+    pub(crate) fn remove<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.resources
+            .write()
+            .remove(&TypeId::of::<T>())
+            .and_then(|previous| previous.downcast().ok())
+    }
+
+    pub(crate) fn get<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.resources
+            .read()
+            .get(&TypeId::of::<T>())?
+            .clone()
+            .downcast()
+            .ok()
+    }
+}
+
+/// Context of a message passed to handlers, granting access to the source of the message and the resources
+/// provided to the router
+#[derive(Debug, Clone)]
+pub struct Ctx {
+    source: Option<SourceRef>,
+    message: Option<MessageId>,
+    resources: Arc<Resources>,
+}
+
+impl Ctx {
+    pub(crate) fn new(source: Option<SourceRef>, resources: Arc<Resources>) -> Self {
+        Self {
+            source,
+            message: crate::causality::current(),
+            resources,
+        }
+    }
+
+    /// Get the source of the message
+    pub fn source(&self) -> Option<&SourceRef> {
+        self.source.as_ref()
+    }
+
+    /// Get the [`MessageId`] of the message, which the messages created by the handler record as their
+    /// [`Message::caused_by()`](crate::message::Message::caused_by). Payloads passed without a message, with
+    /// [`MessageRouter::send_direct()`](crate::router::MessageRouter::send_direct), get the message whose handler
+    /// sent them, if any.
+    pub fn message_id(&self) -> Option<MessageId> {
+        self.message
+    }
+
+    /// Get the resource of type `T`, if one was provided to the router
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.resources.get::<T>()
+    }
+
+    /// Get the resource of type `T`
+    ///
+    /// # Panics
+    /// If no resource of type `T` was provided to the router
+    pub fn expect<T: Send + Sync + 'static>(&self) -> Arc<T> {
+        self.get::<T>().unwrap_or_else(|| {
+            panic!(
+                "No resource of type {} provided to the router",
+                std::any::type_name::<T>()
+            )
+        })
+    }
+}
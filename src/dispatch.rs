@@ -0,0 +1,107 @@
+//! Outcome of dispatching a [`Message`](crate::Message) in a [`MessageRouter`](crate::router::MessageRouter)
+
+use smallvec::{smallvec, SmallVec};
+
+use crate::{
+    endpoint::{handle::Undelivered, EndpointId},
+    middleware::DropReason,
+    validate::ValidationError,
+};
+
+/// Results of the handlers a message was delivered to. Most messages are delivered to a single handler,
+/// so a single result is stored inline without allocating.
+pub type Results<R> = SmallVec<[R; 1]>;
+
+/// Outcome of [`MessageRouter::handle_message()`](crate::router::MessageRouter::handle_message).
+///
+/// Distinguishes messages which were delivered from the reasons a message can be dropped, so callers can react
+/// differently to configuration bugs and normal drops.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DispatchResult<R> {
+    /// Delivered to at least one endpoint, with the results of the handlers which were called.
+    /// Messages forwarded to remote routers are delivered without results.
+    Delivered(Results<R>),
+
+    /// No endpoint could receive the message, because none is registered for its payload type and destination,
+    /// the endpoints declined it, or the only endpoint is the origin of the message
+    NoHandler,
+
+    /// Endpoints are registered for the message, but the filters of every endpoint which could receive it
+    /// rejected it
+    Filtered,
+
+    /// Rejected by [`Middleware`](crate::middleware::Middleware), or dropped because its time to live elapsed,
+    /// with the reason it was dropped
+    Rejected(DropReason),
+
+    /// Rejected by a validator of the payload type registered with
+    /// [`MessageRouter::add_validator()`](crate::router::MessageRouter::add_validator)
+    Invalid(ValidationError),
+
+    /// Endpoints were passed a message of a payload type they are not registered for, and couldn't handle it
+    TypeMismatch,
+
+    /// Held until an endpoint for the payload type is registered, as configured with
+    /// [`RouterConfig::buffer_unhandled()`](crate::config::RouterConfig::buffer_unhandled)
+    Pending,
+
+    /// Not delivered by [`MessageRouter::try_handle_message()`](crate::router::MessageRouter::try_handle_message),
+    /// because the endpoints which could receive the message were busy on other threads
+    WouldBlock,
+
+    /// Cancelled with a [`CancellationToken`](crate::cancel::CancellationToken) before all handlers were called,
+    /// with the results of the handlers which were called
+    Cancelled(Results<R>),
+
+    /// A message sent to several endpoints with [`Destination::Multi`] couldn't be delivered to some of them.
+    /// Has the results of the handlers which were called, and the outcome for each endpoint which failed.
+    ///
+    /// [`Destination::Multi`]: crate::message::Destination::Multi
+    Partial(Results<R>, Vec<(EndpointId, DispatchResult<R>)>),
+}
+
+impl<R> DispatchResult<R> {
+    /// Create the result of calling a single handler, which may not have taken the message
+    pub(crate) fn single<T>(result: Result<R, Undelivered<T>>) -> Self {
+        match result {
+            Ok(result) => DispatchResult::Delivered(smallvec![result]),
+            Err(Undelivered::Declined(_)) => DispatchResult::NoHandler,
+            Err(Undelivered::TypeMismatch) => DispatchResult::TypeMismatch,
+        }
+    }
+
+    /// Check if the message was delivered
+    pub fn is_delivered(&self) -> bool {
+        matches!(self, DispatchResult::Delivered(_))
+    }
+
+    /// Get the endpoints a multi-destination message couldn't be delivered to, with the outcome for each
+    pub fn failures(&self) -> &[(EndpointId, DispatchResult<R>)] {
+        match self {
+            DispatchResult::Partial(_, failed) => failed,
+            _ => &[],
+        }
+    }
+
+    /// Get the results of the handlers, or `None` if the message was not delivered.
+    /// Cancelled and partially delivered messages have the results of the handlers which were called.
+    pub fn results(&self) -> Option<&[R]> {
+        match self {
+            DispatchResult::Delivered(results)
+            | DispatchResult::Cancelled(results)
+            | DispatchResult::Partial(results, _) => Some(results),
+            _ => None,
+        }
+    }
+
+    /// Take the results of the handlers, or `None` if the message was not delivered.
+    /// Cancelled and partially delivered messages have the results of the handlers which were called.
+    pub fn into_results(self) -> Option<Results<R>> {
+        match self {
+            DispatchResult::Delivered(results)
+            | DispatchResult::Cancelled(results)
+            | DispatchResult::Partial(results, _) => Some(results),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! Return type adapters
+//!
+//! Handlers of a [`MessageRouter`](crate::router::MessageRouter) return the router's return type `R`, which is
+//! usually an application wide enum. [`Endpoint::map_return()`] registers a conversion from the natural return type
+//! of a handler into `R`, so the handler doesn't need to construct `R` itself.
+
+use crate::{message::SourceRef, traits::Payload};
+
+use super::Endpoint;
+
+/// Endpoint waiting for a handler returning `T`, which is converted into the return type `R` of the router.
+/// Created with [`Endpoint::map_return()`].
+pub struct ReturnAdapter<'a, M, R, T, F>
+where
+    M: Payload + 'static,
+    R: Send + 'a,
+    F: FnMut(T) -> R + Send + Sync + 'a,
+{
+    endpoint: Endpoint<'a, M, R>,
+    map: F,
+    _return: std::marker::PhantomData<fn() -> T>,
+}
+
+impl<'a, M, R, T, F> std::fmt::Debug for ReturnAdapter<'a, M, R, T, F>
+where
+    M: Payload + 'static,
+    R: Send + 'a,
+    F: FnMut(T) -> R + Send + Sync + 'a,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReturnAdapter")
+            .field("endpoint", &self.endpoint)
+            .field("from", &std::any::type_name::<T>())
+            .finish()
+    }
+}
+
+impl<'a, M, R, T, F> ReturnAdapter<'a, M, R, T, F>
+where
+    M: Payload + 'static,
+    R: Send + 'a,
+    F: FnMut(T) -> R + Send + Sync + 'a,
+{
+    pub(crate) fn new(endpoint: Endpoint<'a, M, R>, map: F) -> Self {
+        Self {
+            endpoint,
+            map,
+            _return: std::marker::PhantomData,
+        }
+    }
+
+    /// Register a message callback returning `T`, whose results are converted into `R`
+    pub fn message<H>(self, mut handler: H) -> Endpoint<'a, M, R>
+    where
+        H: FnMut(Option<SourceRef>, M) -> T + Send + Sync + 'a,
+    {
+        let mut map = self.map;
+        self.endpoint
+            .message(move |src, msg| map(handler(src, msg)))
+    }
+}
+
+impl<'a, M, R> Endpoint<'a, M, R>
+where
+    M: Payload + 'static,
+    R: Send + 'a,
+{
+    /// Convert the results of the handler from `T` into the return type `R` of the router with `map`.
+    /// The handler is registered with [`ReturnAdapter::message()`].
+    pub fn map_return<T, F>(self, map: F) -> ReturnAdapter<'a, M, R, T, F>
+    where
+        F: FnMut(T) -> R + Send + Sync + 'a,
+    {
+        ReturnAdapter::new(self, map)
+    }
+
+    /// Register a message callback returning any type which converts into the return type `R` of the router
+    pub fn message_into<H, T>(self, mut handler: H) -> Self
+    where
+        H: FnMut(Option<SourceRef>, M) -> T + Send + Sync + 'a,
+        T: Into<R>,
+    {
+        self.message(move |src, msg| handler(src, msg).into())
+    }
+}
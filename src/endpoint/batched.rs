@@ -0,0 +1,164 @@
+//! Endpoints receiving messages in batches
+//!
+//! [`MessageRouter::batched()`](crate::router::MessageRouter::batched) registers an endpoint which accumulates
+//! messages, and passes them to its handler together once the batch is full, or the first message of the batch
+//! has waited for the maximum delay. Handlers writing to a database or a socket can then do one write per batch.
+//!
+//! The delay is checked when a message arrives. A batch which stops receiving messages is only flushed by
+//! [`Batched::flush_expired()`], which should be called periodically such as from a timer of the application,
+//! by [`Batched::flush()`], or when the [`Batched`] endpoint is dropped.
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anylock::{AnyLock, ParkingLotMutex};
+use tracing::trace;
+
+use crate::traits::Payload;
+
+use super::Endpoint;
+
+/// Handler receiving the messages of a batch
+type BatchHandler<'a, M, R> = Box<dyn FnMut(Vec<M>) -> R + Send + 'a>;
+
+struct BatchState<'a, M, R> {
+    batch: Vec<M>,
+    started: Instant,
+    max: usize,
+    max_delay: Duration,
+    handler: BatchHandler<'a, M, R>,
+}
+
+impl<'a, M, R> BatchState<'a, M, R> {
+    /// Pass the accumulated messages to the handler, if there are any
+    fn flush(&mut self) -> Option<R> {
+        if self.batch.is_empty() {
+            return None;
+        }
+
+        let batch = std::mem::replace(&mut self.batch, Vec::with_capacity(self.max));
+        trace!("Flushing batch of {}", batch.len());
+        Some((self.handler)(batch))
+    }
+
+    fn is_expired(&self) -> bool {
+        !self.batch.is_empty() && self.started.elapsed() >= self.max_delay
+    }
+}
+
+/// Endpoint delivering messages to its handler in batches.
+/// Messages which don't complete a batch return `R::default()`, and the message completing a batch returns the
+/// result of the handler. The endpoint is deregistered, and the remaining messages flushed, when this is dropped.
+pub struct Batched<'a, M, R>
+where
+    M: Payload + 'static,
+    R: Send + 'a,
+{
+    endpoint: Endpoint<'a, M, R>,
+    state: Arc<ParkingLotMutex<BatchState<'a, M, R>>>,
+}
+
+impl<'a, M, R> std::fmt::Debug for Batched<'a, M, R>
+where
+    M: Payload + 'static,
+    R: Send + 'a,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = self.state.read();
+        f.debug_struct("Batched")
+            .field("endpoint", &self.endpoint)
+            .field("pending", &state.batch.len())
+            .field("max", &state.max)
+            .field("max_delay", &state.max_delay)
+            .finish()
+    }
+}
+
+impl<'a, M, R> Batched<'a, M, R>
+where
+    M: Payload + 'static,
+    R: Default + Send + 'a,
+{
+    /// Deliver the messages of `endpoint` to `handler` in batches of up to `max` messages,
+    /// flushing a batch once its first message waited for `max_delay`
+    pub(crate) fn new(
+        endpoint: Endpoint<'a, M, R>,
+        max: usize,
+        max_delay: Duration,
+        handler: impl FnMut(Vec<M>) -> R + Send + 'a,
+    ) -> Self {
+        let max = max.max(1);
+        let state = Arc::new(ParkingLotMutex::new(BatchState {
+            batch: Vec::with_capacity(max),
+            started: Instant::now(),
+            max,
+            max_delay,
+            handler: Box::new(handler),
+        }));
+
+        let endpoint = endpoint.message({
+            let state = state.clone();
+
+            move |_src, msg| {
+                let state = &mut *state.write();
+                if state.batch.is_empty() {
+                    state.started = Instant::now();
+                }
+
+                state.batch.push(msg);
+
+                if state.batch.len() >= state.max || state.is_expired() {
+                    state.flush().unwrap_or_default()
+                } else {
+                    R::default()
+                }
+            }
+        });
+
+        Self { endpoint, state }
+    }
+}
+
+impl<'a, M, R> Batched<'a, M, R>
+where
+    M: Payload + 'static,
+    R: Send + 'a,
+{
+    /// Get the [`Endpoint`] receiving messages for the batches
+    pub fn endpoint(&self) -> &Endpoint<'a, M, R> {
+        &self.endpoint
+    }
+
+    /// Get the number of messages waiting in the current batch
+    pub fn pending(&self) -> usize {
+        self.state.read().batch.len()
+    }
+
+    /// Pass the current batch to the handler, returning its result if the batch wasn't empty
+    pub fn flush(&self) -> Option<R> {
+        self.state.write().flush()
+    }
+
+    /// Pass the current batch to the handler if its first message waited for the maximum delay
+    pub fn flush_expired(&self) -> Option<R> {
+        let mut state = self.state.write();
+        if state.is_expired() {
+            state.flush()
+        } else {
+            None
+        }
+    }
+}
+
+/// Flush the messages which didn't complete a batch
+impl<'a, M, R> Drop for Batched<'a, M, R>
+where
+    M: Payload + 'static,
+    R: Send + 'a,
+{
+    fn drop(&mut self) {
+        self.state.write().flush();
+    }
+}
@@ -0,0 +1,73 @@
+//! Serialization of the calls to the handler of an endpoint
+//!
+//! Each endpoint has a [`Gate`] which dispatch enters before locking the endpoint. Blocking dispatch waits for a
+//! handler running on another thread to return, while dispatch with
+//! [`MessageRouter::try_handle_message()`](crate::router::MessageRouter::try_handle_message) skips the busy
+//! endpoint and records that it would have blocked.
+
+use std::{
+    cell::Cell,
+    sync::{Mutex, MutexGuard, PoisonError, TryLockError},
+};
+
+thread_local! {
+    /// `None` for blocking dispatch on this thread. With non-blocking dispatch, whether an endpoint was skipped
+    /// because it was busy.
+    static NON_BLOCKING: Cell<Option<bool>> = const { Cell::new(None) };
+}
+
+/// Gate entered by dispatch while calling the filters or the handler of an endpoint
+#[derive(Debug, Default)]
+pub(crate) struct Gate(Mutex<()>);
+
+impl Gate {
+    /// Enter the gate, waiting for a call on another thread to return. With non-blocking dispatch, returns `None`
+    /// instead of waiting.
+    pub(crate) fn enter(&self) -> Option<MutexGuard<'_, ()>> {
+        if NON_BLOCKING.get().is_none() {
+            // A handler which panicked doesn't leave the endpoint in an inconsistent state
+            return Some(self.0.lock().unwrap_or_else(PoisonError::into_inner));
+        }
+
+        match self.0.try_lock() {
+            Ok(guard) => Some(guard),
+            Err(TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner()),
+            Err(TryLockError::WouldBlock) => {
+                NON_BLOCKING.set(Some(true));
+                None
+            }
+        }
+    }
+}
+
+/// Restores the dispatch mode of the thread when non-blocking dispatch returns or panics
+struct Restore(Option<bool>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        NON_BLOCKING.set(self.0);
+    }
+}
+
+/// Run `f` with non-blocking dispatch on this thread, including the messages dispatched by the handlers it calls
+pub(crate) fn non_blocking<T>(f: impl FnOnce() -> T) -> T {
+    let _restore = Restore(NON_BLOCKING.replace(Some(false)));
+    f()
+}
+
+/// Check if non-blocking dispatch skipped a busy endpoint, since the innermost [`track_busy()`] started
+pub(crate) fn skipped_busy() -> bool {
+    NON_BLOCKING.get() == Some(true)
+}
+
+/// Run `f`, returning whether non-blocking dispatch skipped a busy endpoint while it ran
+pub(crate) fn track_busy<T>(f: impl FnOnce() -> T) -> (T, bool) {
+    let Some(outer) = NON_BLOCKING.get() else {
+        return (f(), false);
+    };
+
+    NON_BLOCKING.set(Some(false));
+    let result = f();
+    let busy = NON_BLOCKING.replace(Some(outer)) == Some(true);
+    (result, busy)
+}
@@ -1,100 +1,249 @@
 //! Endpoint type erased handle
 
-use std::{any::TypeId, ops::Deref};
+use std::{
+    any::{Any, TypeId},
+    ops::Deref,
+    sync::Arc,
+    time::Instant,
+};
 
 use anylock::AnyLock;
 use tracing::{error, warn};
 
 use crate::{
-    handler::MessageHandler as _,
-    message::{Message, MessageSource},
+    filter::{Filter, FilterId},
+    message::{Destination, Message, SourceRef},
+    metrics::EndpointCounters,
     traits::{internal::SalishMessageInternal as _, Payload},
 };
 
-use super::{Endpoint, EndpointId, EndpointInner};
+use super::{Endpoint, EndpointId, EndpointInner, TakeableMessage};
 
 /// Endpoint callback to the inner dispatch closure which downcasts to concrete message type
-/// and forwards to [`Endpoint::on_message()`]
-pub type EndpointCallbackOwned<'a, Ret, Source> =
-    Box<dyn Fn(Option<Source>, crate::message::Message) -> Option<Ret> + Send + Sync + 'a>;
+/// and offers it to [`EndpointInner::offer()`]
+pub type EndpointCallbackOwned<'a, Ret> = Box<
+    dyn Fn(Option<SourceRef>, crate::message::Message) -> Result<Ret, Undelivered<Message>>
+        + Send
+        + Sync
+        + 'a,
+>;
+
+/// Endpoint callback taking the concrete payload out of an `Option<M>` slot, without boxing it into a [`Message`].
+/// A declined payload is left in the slot.
+pub type EndpointCallbackDirect<'a, Ret> =
+    Box<dyn Fn(Option<SourceRef>, &mut dyn Any) -> Result<Ret, Undelivered<()>> + Send + Sync + 'a>;
 
 #[allow(unused)]
 pub type EndpointCallbackRef<'a, Ret> =
     Box<dyn for<'b> Fn(&'b crate::message::Message) -> Option<Ret> + Send + Sync + 'a>;
 
-pub type FilterCallback<'a> = Box<dyn for<'b> Fn(&'b crate::Message) -> bool + Send + Sync + 'a>;
+pub type FilterCallback<'a> =
+    Box<dyn for<'b> Fn(&'b crate::Message) -> FilterMatch + Send + Sync + 'a>;
+
+/// Result of matching a message against the filters of an endpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterMatch {
+    /// The endpoint has no filters
+    Unfiltered,
+
+    /// A filter of the endpoint matched the message
+    Matched,
+
+    /// None of the filters of the endpoint matched the message
+    Rejected,
+}
+
+/// Reason an endpoint callback didn't deliver a message
+#[derive(Debug)]
+pub enum Undelivered<T> {
+    /// The endpoint can't receive the payload type of the message, or is a one-shot endpoint which already
+    /// received its message
+    TypeMismatch,
+
+    /// The handler declined the message without taking its payload, or the endpoint was busy on another thread
+    /// with non-blocking dispatch. The message is handed back to be offered to another endpoint, boxed so the
+    /// result of a callback stays small.
+    Declined(Box<T>),
+}
 
 /// Type erased endpoint handle. Contains a callback to the message handler
-pub struct EndpointHandle<'a, Ret, Source>
-where
-    Source: MessageSource,
-{
+pub struct EndpointHandle<'a, Ret> {
     pub endpoint_id: EndpointId,
-    pub callback: EndpointCallbackOwned<'a, Ret, Source>,
+    /// [`TypeId`] of the payload type the endpoint receives
+    pub payload_type: TypeId,
+    /// Name of the payload type the endpoint receives
+    pub type_name: &'static str,
+    pub callback: EndpointCallbackOwned<'a, Ret>,
+    pub direct: EndpointCallbackDirect<'a, Ret>,
     pub filter: FilterCallback<'a>,
+    /// Describe the filters of the endpoint
+    pub(crate) describe_filters: Box<dyn Fn() -> Vec<String> + Send + Sync + 'a>,
+    /// Add a filter to the endpoint
+    pub(crate) add_filter: Box<dyn Fn(Box<dyn Filter>) -> FilterId + Send + Sync + 'a>,
+    /// Counters of the endpoint, updated by the callbacks
+    pub(crate) stats: Arc<EndpointCounters>,
 }
 
-impl<'a, Ret, Source> std::fmt::Debug for EndpointHandle<'a, Ret, Source>
-where
-    Source: MessageSource,
-{
+impl<'a, Ret> std::fmt::Debug for EndpointHandle<'a, Ret> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("EndpointHandle")
             .field("endpoint_id", &self.endpoint_id)
+            .field("type_name", &self.type_name)
+            .field("payload_type", &self.payload_type)
             .finish()
     }
 }
 
-impl<'a, Ret, Source> EndpointHandle<'a, Ret, Source>
-where
-    Source: MessageSource + Copy,
-{
+impl<'a, Ret> EndpointHandle<'a, Ret> {
     /// Create a new [`EndpointHandle`] for an [`Endpoint`]
-    pub fn new<M, Lock, Ref>(endpoint: &Endpoint<'a, M, Ret, Source, Lock, Ref>) -> Self
+    pub fn new<M, Lock, Ref>(endpoint: &Endpoint<'a, M, Ret, Lock, Ref>) -> Self
     where
         M: Payload + 'static,
-        Ref: Deref<Target: AnyLock<EndpointInner<'a, M, Ret, Source>>>
+        Ref: Deref<Target: AnyLock<EndpointInner<'a, M, Ret>>>
             + From<Lock>
             + Clone
             + Send
             + Sync
             + 'a,
-        Lock: AnyLock<EndpointInner<'a, M, Ret, Source>> + Send + Sync + 'a,
+        Lock: AnyLock<EndpointInner<'a, M, Ret>> + Send + Sync + 'a,
         Ret: Send,
     {
+        let stats = endpoint.stats.clone();
+
         // Get a clone of the [`EndpointInner`] handler, which can be held longer than the [`Endpoint`] itself
         let inner = endpoint.inner.clone();
+        let gate = endpoint.gate.clone();
+        let counters = stats.clone();
 
-        let dispatch = move |source: Option<Source>, message: Message| {
+        let dispatch = move |source: Option<SourceRef>, message: Message| {
             if TypeId::of::<M>() != message.payload_type() {
                 warn!(
-                    "EndpointHandle message payload type {:?} != endpoint type {:?}",
-                    message.payload_type(),
-                    TypeId::of::<M>()
+                    "EndpointHandle message payload type {} != endpoint type {}",
+                    message.type_name(),
+                    std::any::type_name::<M>()
                 );
-                return None;
+                counters.error();
+                return Err(Undelivered::TypeMismatch);
             }
 
+            // Continue the trace of the message in the handler
+            #[cfg(feature = "otel")]
+            let _span = crate::otel::handler_span(&message).entered();
+
+            // Messages created by the handler are caused by this message
+            let _cause = crate::causality::enter(Some(message.id()));
+
+            // A busy endpoint declines the message with non-blocking dispatch
+            let Some(_gate) = gate.enter() else {
+                return Err(Undelivered::Declined(Box::new(message)));
+            };
+
+            // A one-shot endpoint which already received its message hands it back
             let mut guard = inner.write();
-            // Get the downcast inner concrete message of type [`MessageHandler::Message`]
-            if let Some(payload) = message.into_inner::<M>() {
-                Some(guard.on_message(source, payload))
-            } else {
-                error!("Endpoint closure failed to downcast message");
-                None
+            if guard.is_spent() {
+                return Err(Undelivered::Declined(Box::new(message)));
+            }
+
+            // The payload stays in the message until the handler takes it
+            let start = Instant::now();
+            match guard.offer(source, TakeableMessage::from_message(message)) {
+                Ok(ret) => {
+                    counters.received(start, start.elapsed());
+                    Ok(ret)
+                }
+                Err(declined) => match declined.into_message() {
+                    Some(message) => Err(Undelivered::Declined(Box::new(message))),
+                    None => {
+                        error!("Endpoint handler declined a message it wasn't offered");
+                        counters.error();
+                        Err(Undelivered::TypeMismatch)
+                    }
+                },
+            }
+        };
+
+        let inner = endpoint.inner.clone();
+        let gate = endpoint.gate.clone();
+        let counters = stats.clone();
+        let direct = move |source: Option<SourceRef>, slot: &mut dyn Any| {
+            // Leave the payload in the slot if the endpoint can't receive it
+            let Some(_gate) = gate.enter() else {
+                return Err(Undelivered::Declined(Box::new(())));
+            };
+
+            let mut guard = inner.write();
+            if guard.is_spent() {
+                return Err(Undelivered::Declined(Box::new(())));
+            }
+
+            let Some(slot) = slot.downcast_mut::<Option<M>>() else {
+                counters.error();
+                return Err(Undelivered::TypeMismatch);
+            };
+
+            let Some(payload) = slot.take() else {
+                counters.error();
+                return Err(Undelivered::TypeMismatch);
+            };
+
+            let start = Instant::now();
+            match guard.offer(source, TakeableMessage::from_payload(payload)) {
+                Ok(ret) => {
+                    counters.received(start, start.elapsed());
+                    Ok(ret)
+                }
+                Err(declined) => {
+                    *slot = Some(declined.take());
+                    Err(Undelivered::Declined(Box::new(())))
+                }
             }
         };
 
         let inner = endpoint.inner.clone();
+        let gate = endpoint.gate.clone();
+        let counters = stats.clone();
         let filter = move |message: &crate::Message| {
-            let guard = inner.write();
-            guard.filter(message)
+            let Some(_gate) = gate.enter() else {
+                return FilterMatch::Rejected;
+            };
+
+            let mut guard = inner.write();
+            let broadcast = matches!(message.dest(), Destination::Broadcast(_));
+
+            if guard.is_spent() {
+                FilterMatch::Rejected
+            } else if !guard.has_filters() || (broadcast && !guard.filters_broadcasts()) {
+                FilterMatch::Unfiltered
+            } else if guard.filter_cached(message) {
+                FilterMatch::Matched
+            } else {
+                counters.filtered();
+                FilterMatch::Rejected
+            }
         };
 
+        let inner = endpoint.inner.clone();
+        let describe_filters = move || {
+            inner
+                .read()
+                .filters()
+                .map(|filter| format!("{filter:?}"))
+                .collect()
+        };
+
+        let inner = endpoint.inner.clone();
+        let add_filter = move |filter: Box<dyn Filter>| inner.write().add_filter(filter);
+
         EndpointHandle {
             endpoint_id: endpoint.id,
+            payload_type: TypeId::of::<M>(),
+            type_name: std::any::type_name::<M>(),
             callback: Box::new(dispatch),
+            direct: Box::new(direct),
             filter: Box::new(filter),
+            describe_filters: Box::new(describe_filters),
+            add_filter: Box::new(add_filter),
+            stats,
         }
     }
 }
@@ -0,0 +1,142 @@
+//! Allocation of endpoint IDs
+//!
+//! Each router allocates the IDs of its endpoints in creation order starting from zero, so a program creating its
+//! endpoints in the same order gets the same IDs on every run, regardless of other routers in the process. Child
+//! routers share the allocator of their parent, as messages they can't deliver are passed to the parent by ID.
+//!
+//! Endpoints can also be created with an explicit ID from configuration with
+//! [`MessageRouter::create_endpoint_with_id()`](crate::router::MessageRouter::create_endpoint_with_id). IDs which
+//! will be claimed later can be reserved with
+//! [`MessageRouter::reserve_endpoint_id()`](crate::router::MessageRouter::reserve_endpoint_id), so automatic
+//! allocation skips them in the meantime.
+//!
+//! With the `wide-ids` feature, IDs are 128 bits wide and each router allocates them from a random 64 bit
+//! prefix, so endpoints of routers in different processes have distinct addresses without coordination.
+
+use std::collections::HashSet;
+
+use anylock::{AnyLock, ParkingLotMutex};
+use tracing::trace;
+
+use super::EndpointId;
+
+/// Error claiming or reserving an endpoint ID
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EndpointIdError {
+    /// The ID is used by a registered endpoint
+    InUse(EndpointId),
+
+    /// The ID was already reserved
+    Reserved(EndpointId),
+}
+
+impl std::fmt::Display for EndpointIdError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InUse(id) => write!(f, "endpoint ID {id} is in use"),
+            Self::Reserved(id) => write!(f, "endpoint ID {id} is already reserved"),
+        }
+    }
+}
+
+impl std::error::Error for EndpointIdError {}
+
+#[derive(Debug, Default)]
+struct IdState {
+    /// Next ID to try when allocating
+    next: EndpointId,
+
+    /// IDs of registered endpoints
+    used: HashSet<EndpointId>,
+
+    /// IDs reserved for endpoints created with an explicit ID
+    reserved: HashSet<EndpointId>,
+}
+
+/// Endpoint IDs of a router, shared by all clones of the router and its children
+pub(crate) struct EndpointIds {
+    state: ParkingLotMutex<IdState>,
+}
+
+impl std::fmt::Debug for EndpointIds {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = self.state.read();
+        f.debug_struct("EndpointIds")
+            .field("next", &state.next)
+            .field("used", &state.used.len())
+            .field("reserved", &state.reserved.len())
+            .finish()
+    }
+}
+
+impl Default for EndpointIds {
+    fn default() -> Self {
+        Self {
+            state: ParkingLotMutex::new(IdState {
+                next: first_id(),
+                ..Default::default()
+            }),
+        }
+    }
+}
+
+/// First ID allocated by a router
+#[cfg(not(feature = "wide-ids"))]
+fn first_id() -> EndpointId {
+    0
+}
+
+/// First ID allocated by a router, with a random prefix in the upper 64 bits
+#[cfg(feature = "wide-ids")]
+fn first_id() -> EndpointId {
+    EndpointId::from(rand::random::<u64>()) << 64
+}
+
+impl EndpointIds {
+    /// Allocate the next ID which is neither used nor reserved
+    pub(crate) fn allocate(&self) -> EndpointId {
+        let mut state = self.state.write();
+
+        let mut id = state.next;
+        while state.used.contains(&id) || state.reserved.contains(&id) {
+            id += 1;
+        }
+
+        state.next = id + 1;
+        state.used.insert(id);
+        id
+    }
+
+    /// Claim an explicit ID, which may have been reserved
+    pub(crate) fn claim(&self, id: EndpointId) -> Result<(), EndpointIdError> {
+        let mut state = self.state.write();
+
+        if !state.used.insert(id) {
+            return Err(EndpointIdError::InUse(id));
+        }
+
+        state.reserved.remove(&id);
+        trace!("Claimed endpoint ID {id}");
+        Ok(())
+    }
+
+    /// Reserve an ID to be claimed later, so it isn't allocated in the meantime
+    pub(crate) fn reserve(&self, id: EndpointId) -> Result<(), EndpointIdError> {
+        let mut state = self.state.write();
+
+        if state.used.contains(&id) {
+            return Err(EndpointIdError::InUse(id));
+        }
+
+        if !state.reserved.insert(id) {
+            return Err(EndpointIdError::Reserved(id));
+        }
+
+        Ok(())
+    }
+
+    /// Release the ID of a deregistered endpoint, so it can be claimed again
+    pub(crate) fn release(&self, id: EndpointId) {
+        self.state.write().used.remove(&id);
+    }
+}
@@ -0,0 +1,102 @@
+//! Endpoints holding a handler per key
+//!
+//! [`MessageRouter::keyed()`](crate::router::MessageRouter::keyed) registers an endpoint which extracts a key from
+//! each message, such as the ID of the sensor a reading came from. A handler is built for each new key, and all
+//! messages with the same key are delivered to the same handler, which can keep the state of that entity.
+
+use std::{collections::HashMap, hash::Hash, sync::Arc};
+
+use anylock::{AnyLock, ParkingLotMutex};
+use tracing::debug;
+
+use crate::{message::SourceRef, traits::Payload};
+
+use super::Endpoint;
+
+/// Handler built for a key of a [`Keyed`] endpoint
+type KeyedHandler<'a, M, R> = Box<dyn FnMut(Option<SourceRef>, M) -> R + Send + 'a>;
+
+/// Handlers of a [`Keyed`] endpoint by key, shared with the callback of the endpoint
+type KeyedHandlers<'a, M, K, R> = Arc<ParkingLotMutex<HashMap<K, KeyedHandler<'a, M, R>>>>;
+
+/// Endpoint delivering messages to a handler per key.
+/// The endpoint is deregistered when this is dropped.
+pub struct Keyed<'a, M, K, R>
+where
+    M: Payload + 'static,
+    R: Send + 'a,
+{
+    endpoint: Endpoint<'a, M, R>,
+    handlers: KeyedHandlers<'a, M, K, R>,
+}
+
+impl<'a, M, K, R> std::fmt::Debug for Keyed<'a, M, K, R>
+where
+    M: Payload + 'static,
+    R: Send + 'a,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Keyed")
+            .field("endpoint", &self.endpoint)
+            .field("instances", &self.handlers.read().len())
+            .finish()
+    }
+}
+
+impl<'a, M, K, R> Keyed<'a, M, K, R>
+where
+    M: Payload + 'static,
+    K: Eq + Hash + Send + 'a,
+    R: Send + 'a,
+{
+    /// Deliver the messages of `endpoint` to the handler of the key extracted by `key`,
+    /// building the handler with `factory` for each new key
+    pub(crate) fn new<H>(
+        endpoint: Endpoint<'a, M, R>,
+        key: impl Fn(&M) -> K + Send + Sync + 'a,
+        factory: impl Fn(&K) -> H + Send + Sync + 'a,
+    ) -> Self
+    where
+        H: FnMut(Option<SourceRef>, M) -> R + Send + 'a,
+    {
+        let handlers: KeyedHandlers<'a, M, K, R> = Arc::new(ParkingLotMutex::new(HashMap::new()));
+
+        let endpoint = endpoint.message({
+            let handlers = handlers.clone();
+
+            move |src, msg| {
+                let mut handlers = handlers.write();
+                let handler = handlers.entry(key(&msg)).or_insert_with_key(|key| {
+                    debug!("Building handler for {}", std::any::type_name::<M>());
+                    Box::new(factory(key)) as KeyedHandler<'a, M, R>
+                });
+
+                handler(src, msg)
+            }
+        });
+
+        Self { endpoint, handlers }
+    }
+
+    /// Get the [`Endpoint`] receiving messages for the handlers
+    pub fn endpoint(&self) -> &Endpoint<'a, M, R> {
+        &self.endpoint
+    }
+
+    /// Get the number of keys a handler has been built for
+    pub fn num_instances(&self) -> usize {
+        self.handlers.read().len()
+    }
+
+    /// Check if a handler has been built for `key`
+    pub fn contains(&self, key: &K) -> bool {
+        self.handlers.read().contains_key(key)
+    }
+
+    /// Drop the handler of `key`, returning true if it existed.
+    /// A new handler is built by the next message with the key.
+    pub fn remove(&self, key: &K) -> bool {
+        let handler = self.handlers.write().remove(key);
+        handler.is_some()
+    }
+}
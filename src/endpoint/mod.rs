@@ -2,29 +2,67 @@
 
 use std::{
     any::TypeId,
+    collections::{HashMap, VecDeque},
     marker::PhantomData,
     ops::Deref,
-    sync::{atomic::AtomicU64, Arc, LazyLock},
+    sync::{atomic::AtomicU64, Arc, LazyLock, Weak},
 };
 
-use anylock::AnyLock;
+use anylock::{AnyLock, ParkingLotMutex};
+use gate::Gate;
 use handle::EndpointHandle;
-use tracing::debug;
+use tracing::{debug, trace, warn};
 
 use crate::{
-    filter::Filter,
+    causality,
+    context::Ctx,
+    filter::{Filter, FilterId},
     handler::MessageHandler,
-    message::MessageSource,
+    message::{MessageId, SourceRef},
+    metrics::{EndpointCounters, EndpointStats},
+    queue::{Backlog, QueueId, Watermark},
     router::MessageRouter,
+    spawn::Spawner,
     traits::{EndpointAddress, Payload},
 };
 
+mod adapter;
+mod batched;
+pub(crate) mod gate;
 pub(crate) mod handle;
+pub(crate) mod ids;
+mod keyed;
+mod on_demand;
+mod oneshot;
+mod select;
+mod state_machine;
+mod takeable;
 
+pub use adapter::ReturnAdapter;
+pub use batched::Batched;
+pub use ids::EndpointIdError;
+pub use keyed::Keyed;
+pub use on_demand::OnDemand;
+pub use oneshot::{Elapsed, OneShot, Timeout};
+pub use select::Select;
+pub use state_machine::{StateMachine, Transition};
+pub use takeable::TakeableMessage;
+
+/// Maximum number of sources the outcome of the filters of an endpoint is cached for
+const FILTER_CACHE_CAPACITY: usize = 1024;
+
+/// IDs of endpoints created without a router. Endpoints created with a router get their ID from the router.
 static ENDPOINT_ID: LazyLock<Arc<AtomicU64>> = LazyLock::new(|| Arc::new(AtomicU64::new(0)));
 
+/// Address of an endpoint in a router
+#[cfg(not(feature = "wide-ids"))]
 pub type EndpointId = u64;
 
+/// Address of an endpoint in a router. IDs are 128 bits wide with the `wide-ids` feature, so addresses can be
+/// globally unique across processes without coordination.
+#[cfg(feature = "wide-ids")]
+pub type EndpointId = u128;
+
 /// Message Endpoint
 ///
 /// This is split into an outer Endpoint, and [`EndpointInner`] which implements [`MessageHandler`]
@@ -34,19 +72,17 @@ pub struct Endpoint<
     'a,
     Message,
     Return,
-    Source,
     // Default to ParkingLotMutex lock
-    Lock = anylock::ParkingLotMutex<EndpointInner<'a, Message, Return, Source>>,
+    Lock = anylock::ParkingLotMutex<EndpointInner<'a, Message, Return>>,
     // Default to Arc reference
     Ref = std::sync::Arc<Lock>,
 > where
     Self: Send + Sync,
     Return: Send + 'a,
     Message: Payload,
-    Source: MessageSource + Copy,
 
     // Ref can be anything that Derefs to AnyLock wrapping [`EndpointInner`]
-    Ref: Deref<Target: AnyLock<EndpointInner<'a, Message, Return, Source>>>
+    Ref: Deref<Target: AnyLock<EndpointInner<'a, Message, Return>>>
         // From<Lock> allows using .into() on the lock to obtain a reference
         + From<Lock>
         + Send
@@ -54,28 +90,29 @@ pub struct Endpoint<
         // Inner Ref must be cloneable
         + Clone
         + 'a,
-    Lock: AnyLock<EndpointInner<'a, Message, Return, Source>> + Send + 'a,
+    Lock: AnyLock<EndpointInner<'a, Message, Return>> + Send + 'a,
 {
     id: EndpointId,
-    router: Option<MessageRouter<'a, Return, Source>>,
+    router: Option<MessageRouter<'a, Return>>,
+    stats: Arc<EndpointCounters>,
+    /// Entered by dispatch before locking [`EndpointInner`], so non-blocking dispatch can skip a busy endpoint
+    gate: Arc<Gate>,
     inner: Ref,
-    _phantom: (PhantomData<Message>, PhantomData<Source>, PhantomData<Lock>),
+    _phantom: (PhantomData<Message>, PhantomData<Lock>),
 }
 
-impl<'a, Message, Return, Source, Lock, Ref> EndpointAddress
-    for Endpoint<'a, Message, Return, Source, Lock, Ref>
+impl<'a, Message, Return, Lock, Ref> EndpointAddress for Endpoint<'a, Message, Return, Lock, Ref>
 where
     Self: Send + Sync,
     Return: Send + 'a,
     Message: Payload,
-    Source: MessageSource + Copy,
-    Ref: Deref<Target: AnyLock<EndpointInner<'a, Message, Return, Source>>>
+    Ref: Deref<Target: AnyLock<EndpointInner<'a, Message, Return>>>
         + From<Lock>
         + Send
         + Sync
         + Clone
         + 'a,
-    Lock: AnyLock<EndpointInner<'a, Message, Return, Source>> + Send + 'a,
+    Lock: AnyLock<EndpointInner<'a, Message, Return>> + Send + 'a,
 {
     type Addr = EndpointId;
 
@@ -84,32 +121,30 @@ where
     }
 }
 
-impl<'a, M, R, S, Lock, Ref> std::fmt::Debug for Endpoint<'a, M, R, S, Lock, Ref>
+impl<'a, M, R, Lock, Ref> std::fmt::Debug for Endpoint<'a, M, R, Lock, Ref>
 where
     Self: Send + Sync,
     R: Send + 'a,
     M: Payload,
-    S: MessageSource + Copy,
-    Ref: Deref<Target: AnyLock<EndpointInner<'a, M, R, S>>> + From<Lock> + Clone + Send + Sync + 'a,
-    Lock: AnyLock<EndpointInner<'a, M, R, S>> + Send + 'a,
+    Ref: Deref<Target: AnyLock<EndpointInner<'a, M, R>>> + From<Lock> + Clone + Send + Sync + 'a,
+    Lock: AnyLock<EndpointInner<'a, M, R>> + Send + 'a,
 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Endpoint")
             .field("id", &self.id)
-            .field("filters", &self.inner.read().filters())
+            .field("filters", &self.inner.read().filters().collect::<Vec<_>>())
             .finish()
     }
 }
 
 /// Automatically deregister ourselves from the [`MessageRouter`] on Drop
-impl<'a, M, R, S, Lock, Ref> Drop for Endpoint<'a, M, R, S, Lock, Ref>
+impl<'a, M, R, Lock, Ref> Drop for Endpoint<'a, M, R, Lock, Ref>
 where
     Self: Send + Sync,
     R: Send,
     M: Payload,
-    S: MessageSource + Copy,
-    Ref: Deref<Target: AnyLock<EndpointInner<'a, M, R, S>>> + From<Lock> + Clone + Send + Sync + 'a,
-    Lock: AnyLock<EndpointInner<'a, M, R, S>> + Send + 'a,
+    Ref: Deref<Target: AnyLock<EndpointInner<'a, M, R>>> + From<Lock> + Clone + Send + Sync + 'a,
+    Lock: AnyLock<EndpointInner<'a, M, R>> + Send + 'a,
 {
     fn drop(&mut self) {
         if let Some(router) = &self.router {
@@ -119,24 +154,38 @@ where
 }
 
 /// [`Endpoint`] implementation
-impl<'a, M, R, S, Lock, Ref> Endpoint<'a, M, R, S, Lock, Ref>
+impl<'a, M, R, Lock, Ref> Endpoint<'a, M, R, Lock, Ref>
 where
     Self: Send + Sync,
     R: Send + 'a,
     M: Payload + 'static,
-    S: MessageSource + Copy,
-    Ref: Deref<Target: AnyLock<EndpointInner<'a, M, R, S>>> + From<Lock> + Clone + Send + Sync + 'a,
-    Lock: AnyLock<EndpointInner<'a, M, R, S>> + Send + Sync + 'a,
+    Ref: Deref<Target: AnyLock<EndpointInner<'a, M, R>>> + From<Lock> + Clone + Send + Sync + 'a,
+    Lock: AnyLock<EndpointInner<'a, M, R>> + Send + Sync + 'a,
 {
-    pub fn new(router: Option<MessageRouter<'a, R, S>>) -> Self
+    pub fn new(router: Option<MessageRouter<'a, R>>) -> Self
+    where
+        R: 'a,
+    {
+        let id = match &router {
+            Some(router) => router.allocate_endpoint_id(),
+            None => ENDPOINT_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed) as EndpointId,
+        };
+
+        Self::with_id(router, id)
+    }
+
+    /// Create an endpoint with an ID which was already allocated or claimed from the router
+    pub(crate) fn with_id(router: Option<MessageRouter<'a, R>>, id: EndpointId) -> Self
     where
         R: 'a,
     {
         let endpoint = Self {
-            id: ENDPOINT_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed),
+            id,
+            stats: Arc::default(),
+            gate: Arc::default(),
             inner: Lock::new(EndpointInner::new()).into(),
             router,
-            _phantom: (PhantomData, PhantomData, PhantomData),
+            _phantom: (PhantomData, PhantomData),
         };
 
         // Register this endpoint with the router
@@ -144,23 +193,27 @@ where
             router.add_endpoint(&endpoint);
         }
 
-        let type_id = TypeId::of::<M>();
-
         debug!(
-            "Created {endpoint:?} Addr: {:?} for {type_id:?}",
-            endpoint.addr()
+            "Created {endpoint:?} Addr: {:?} for {}",
+            endpoint.addr(),
+            std::any::type_name::<M>()
         );
 
         endpoint
     }
 
     /// Get a new [`EndpointHandle`] for this endpoint which erases the payload type
-    pub fn handle(&self) -> EndpointHandle<'a, R, S> {
+    pub fn handle(&self) -> EndpointHandle<'a, R> {
         EndpointHandle::new(self)
     }
 
+    /// Get the statistics of this endpoint
+    pub fn stats(&self) -> EndpointStats {
+        self.stats.snapshot()
+    }
+
     /// Get a reference to the [`MessageRouter`] which was cloned into this endpoint
-    pub fn router(&self) -> Option<&MessageRouter<'a, R, S>> {
+    pub fn router(&self) -> Option<&MessageRouter<'a, R>> {
         self.router.as_ref()
     }
 
@@ -177,38 +230,358 @@ where
         self
     }
 
-    // Register a message callback with [`EndpointInner`]
-    pub fn message<F>(self, f: F) -> Self
+    /// Add a filter to the endpoint while it's live, returning its ID to remove it. The filter applies to
+    /// messages dispatched after it's added.
+    pub fn add_filter(&self, filter: impl Filter + 'static) -> FilterId {
+        self.inner.write().add_filter(filter)
+    }
+
+    /// Remove the filter `id` from the endpoint, returning whether the endpoint had the filter
+    pub fn remove_filter(&self, id: FilterId) -> bool {
+        self.inner.write().remove_filter(id)
+    }
+
+    /// Remove all filters of the endpoint, so it receives all messages of its payload type
+    pub fn clear_filters(&self) {
+        self.inner.write().clear_filters()
+    }
+
+    /// Receive all broadcasts of the payload type, applying filters only to unicast messages
+    pub fn unfiltered_broadcasts(self) -> Self {
+        self.inner.write().filter_broadcasts = false;
+        self
+    }
+
+    /// Register the endpoint under `name` with its router, so it can be found with
+    /// [`MessageRouter::endpoint_by_name()`] and addressed with [`Destination::Named`](crate::message::Destination::Named).
+    /// The name is released when the endpoint is deregistered.
+    pub fn named(self, name: &str) -> Self {
+        match &self.router {
+            Some(router) => router.name_endpoint(self.id, name),
+            None => warn!("Endpoint {} has no router to be named {name} in", self.id),
+        }
+        self
+    }
+
+    /// Claim `key` in its router, so messages sent to
+    /// [`Destination::hashed()`](crate::message::Destination::hashed) with the key reach this endpoint.
+    /// An endpoint can claim several keys, such as the IDs of the sensors it's responsible for.
+    pub fn claim_hash<K>(self, key: &K) -> Self
+    where
+        K: std::fmt::Debug + std::hash::Hash + ?Sized,
+    {
+        match &self.router {
+            Some(router) => router.claim_hash(self.id, key),
+            None => warn!("Endpoint {} has no router to claim {key:?} in", self.id),
+        }
+        self
+    }
+
+    /// Join the group `group` of its router, to receive messages sent to
+    /// [`Destination::Group`](crate::message::Destination::Group). The endpoint can join several groups.
+    pub fn join(self, group: &str) -> Self {
+        match &self.router {
+            Some(router) => router.join_group(self.id, group),
+            None => warn!(
+                "Endpoint {} has no router to join group {group} in",
+                self.id
+            ),
+        }
+        self
+    }
+
+    // Register a message callback with [`EndpointInner`], and receive messages held or retained by the router
+    pub fn message<F>(self, mut f: F) -> Self
     where
-        F: FnMut(Option<S>, M) -> R + Send + Sync + 'a,
+        F: FnMut(Option<SourceRef>, M) -> R + Send + Sync + 'a,
+    {
+        self.message_takeable(move |src, msg| Ok(f(src, msg.take())))
+    }
+
+    /// Register a message callback which is offered each message as a [`TakeableMessage`]. The callback can
+    /// inspect the payload and take it, or return the message to decline it, so the router offers it to the next
+    /// endpoint which can receive it.
+    pub fn message_takeable<F>(self, f: F) -> Self
+    where
+        F: FnMut(Option<SourceRef>, TakeableMessage<M>) -> Result<R, TakeableMessage<M>>
+            + Send
+            + Sync
+            + 'a,
     {
         self.inner.write().callback = Some(Box::new(f));
+
+        // Dispatch messages which were held until an endpoint could receive them, or the retained message
+        if let Some(router) = &self.router {
+            router.flush_pending(TypeId::of::<M>(), self.id);
+        }
+
         self
     }
+
+    /// Replace the message callback, keeping the ID, filters and registrations of the endpoint. A message being
+    /// handled finishes with the previous callback, and subsequent dispatches call `f`.
+    pub fn replace_handler<F>(&self, mut f: F)
+    where
+        F: FnMut(Option<SourceRef>, M) -> R + Send + Sync + 'a,
+    {
+        self.inner.write().callback = Some(Box::new(move |src, msg| Ok(f(src, msg.take()))));
+        trace!("Replaced the handler of endpoint {}", self.id);
+    }
+
+    /// Register a message callback receiving a [`Ctx`], which grants access to the source of the message and the
+    /// resources provided to the router
+    pub fn message_ctx<F>(self, mut f: F) -> Self
+    where
+        F: FnMut(&Ctx, M) -> R + Send + Sync + 'a,
+    {
+        let resources = self
+            .router
+            .as_ref()
+            .map(|router| router.resources().clone())
+            .unwrap_or_default();
+
+        self.message(move |src, msg| f(&Ctx::new(src, resources.clone()), msg))
+    }
+
+    /// Register a message callback which isn't [`Sync`]. Calls are serialized by the lock of [`EndpointInner`],
+    /// so the callback is never shared between threads.
+    pub fn message_mut<F>(self, f: F) -> Self
+    where
+        F: FnMut(Option<SourceRef>, M) -> R + Send + 'a,
+    {
+        let mut f = Exclusive(f);
+        self.message(move |src, msg| (f.get_mut())(src, msg))
+    }
+
+    /// Register a callback for a single message. The endpoint deregisters itself from the router when it
+    /// receives its first message, and rejects any message dispatched concurrently with it.
+    pub fn message_once<F>(self, f: F) -> Self
+    where
+        F: FnOnce(Option<SourceRef>, M) -> R + Send + 'a,
+    {
+        self.inner.write().once = true;
+
+        let router = self.router.clone();
+        let id = self.id;
+        let mut f = Some(f);
+
+        self.message_mut(move |src, msg| {
+            if let Some(router) = &router {
+                router.remove_endpoint(id);
+            }
+
+            // The callback is removed from [`EndpointInner`] after the first call, so it's never called again
+            let f = f.take().expect("One-shot endpoint callback called twice");
+            f(src, msg)
+        })
+    }
+}
+
+/// Assignment of endpoints to worker threads and blocking pools, for endpoints with the default [`Arc`]
+/// reference to their [`EndpointInner`]
+impl<M, R, Lock> Endpoint<'static, M, R, Lock, Arc<Lock>>
+where
+    Self: Send + Sync,
+    R: Default + Send + 'static,
+    M: Payload + 'static,
+    Lock: AnyLock<EndpointInner<'static, M, R>> + Send + Sync + 'static,
+{
+    /// Assign the endpoint to the worker thread `name` of its router, spawning the worker if it doesn't exist yet.
+    /// Dispatch enqueues messages to the worker and returns `R::default()` without waiting, and the worker calls
+    /// the endpoint's callback in the order messages were enqueued, so handlers holding thread-affine resources
+    /// always run on the same thread. Messages declined by a [`TakeableMessage`] callback on the worker are
+    /// dropped, as they were already delivered.
+    ///
+    /// With [`RouterConfig::queue_watermarks()`](crate::config::RouterConfig::queue_watermarks), the messages
+    /// waiting for the endpoint on the worker are counted, and the watermark events of
+    /// [`QueueId::Endpoint`](crate::queue::QueueId::Endpoint) are published into the router.
+    pub fn on_worker(self, name: &str) -> Self {
+        let Some(router) = &self.router else {
+            warn!("Endpoint {} has no router to run worker {name}", self.id);
+            return self;
+        };
+
+        let worker = router.worker(name);
+
+        // The worker only holds a weak reference, so the endpoint isn't kept alive by its own callback
+        let inner = Arc::downgrade(&self.inner);
+        let gate = self.gate.clone();
+        let id = self.id;
+
+        // Messages waiting on the worker are counted if watermarks are configured
+        let backlog = Watermark::new(QueueId::Endpoint(id), &router.config()).map(|watermark| {
+            let backlog = Arc::new(ParkingLotMutex::new(Backlog::new(watermark)));
+            (router.clone(), backlog)
+        });
+
+        self.inner.write().worker = Some(Box::new(move |src, msg| {
+            let inner = inner.clone();
+            let gate = gate.clone();
+            let backlog = backlog.clone();
+            let cause = causality::current();
+            let payload = msg.take();
+
+            if let Some((router, backlog)) = &backlog {
+                if let Some(event) = backlog.write().enqueued() {
+                    router.handle_message(event);
+                }
+            }
+
+            worker.execute(move || {
+                if let Some((router, backlog)) = &backlog {
+                    if let Some(event) = backlog.write().dequeued() {
+                        router.handle_message(event);
+                    }
+                }
+
+                Self::call_offloaded(&inner, &gate, id, cause, src, payload);
+            });
+
+            Ok(R::default())
+        }));
+
+        self
+    }
+
+    /// Call the endpoint's callback on the blocking pool of `spawner`, such as the `spawn_blocking` threads of
+    /// tokio, so handlers doing database or file IO don't stall the async tasks dispatching to the endpoint.
+    /// Dispatch returns `R::default()` without waiting, and the messages are handled one at a time, in the order
+    /// they were dispatched. A pool thread is only held while messages are waiting. Messages declined by a
+    /// [`TakeableMessage`] callback are dropped, as they were already delivered.
+    pub fn blocking(self, spawner: impl Spawner + 'static) -> Self {
+        let inner = Arc::downgrade(&self.inner);
+        let gate = self.gate.clone();
+        let id = self.id;
+
+        let pending = Arc::new(ParkingLotMutex::new(Pending {
+            messages: VecDeque::new(),
+            draining: false,
+        }));
+
+        self.inner.write().worker = Some(Box::new(move |src, msg| {
+            let start = {
+                let mut pending = pending.write();
+                pending
+                    .messages
+                    .push_back((causality::current(), src, msg.take()));
+                !std::mem::replace(&mut pending.draining, true)
+            };
+
+            if start {
+                let inner = inner.clone();
+                let gate = gate.clone();
+                let pending = pending.clone();
+
+                spawner.spawn_blocking(Box::new(move || loop {
+                    let next = {
+                        let mut pending = pending.write();
+                        let next = pending.messages.pop_front();
+                        pending.draining = next.is_some();
+                        next
+                    };
+
+                    let Some((cause, src, payload)) = next else {
+                        break;
+                    };
+
+                    if !Self::call_offloaded(&inner, &gate, id, cause, src, payload) {
+                        break;
+                    }
+                }));
+            }
+
+            Ok(R::default())
+        }));
+
+        self
+    }
+
+    /// Call the callback of the endpoint with a message offloaded from the dispatching thread, which was caused
+    /// by `cause`. Returns `false` if the endpoint was dropped or spent, and can't receive further messages.
+    fn call_offloaded(
+        inner: &Weak<Lock>,
+        gate: &Gate,
+        id: EndpointId,
+        cause: Option<MessageId>,
+        src: Option<SourceRef>,
+        payload: M,
+    ) -> bool {
+        let Some(inner) = inner.upgrade() else {
+            trace!("Endpoint {id} was dropped before it received an offloaded message");
+            return false;
+        };
+
+        let _gate = gate.enter();
+        let mut inner = inner.write();
+        if inner.is_spent() {
+            return false;
+        }
+
+        let _cause = causality::enter(cause);
+
+        if inner
+            .call(src, TakeableMessage::from_payload(payload))
+            .is_err()
+        {
+            debug!("Endpoint {id} declined an offloaded message");
+        }
+
+        true
+    }
+}
+
+/// Messages waiting for an endpoint on a blocking pool, with the messages which caused them
+struct Pending<M> {
+    messages: VecDeque<(Option<MessageId>, Option<SourceRef>, M)>,
+
+    /// Whether a job on the pool is handling the messages
+    draining: bool,
+}
+
+/// Wrapper making a callback [`Sync`], for callbacks which are only called through `&mut`
+struct Exclusive<F>(F);
+
+// SAFETY: Exclusive doesn't allow any access through a shared reference
+unsafe impl<F: Send> Sync for Exclusive<F> {}
+
+impl<F> Exclusive<F> {
+    /// Get the callback. Closures calling through this capture the whole wrapper, rather than only its field.
+    fn get_mut(&mut self) -> &mut F {
+        &mut self.0
+    }
 }
 
 /// Inner Endpoint. Clones of this can be held alive and not prevent [`Endpoint`] [`Drop`] impl from deregistering
 /// the endpoint from the [`MessageRouter`].
-pub struct EndpointInner<'a, M, R, S>
+pub struct EndpointInner<'a, M, R>
 where
     Self: MessageHandler + Send + Sync,
 {
-    filters: Vec<Box<dyn Filter>>,
-    callback: Option<
-        Box<
-            dyn FnMut(
-                    Option<S>,
-                    <Self as MessageHandler>::Message,
-                ) -> <Self as MessageHandler>::Return
-                + Send
-                + Sync
-                + 'a,
-        >,
-    >,
+    filters: Vec<(FilterId, Box<dyn Filter>)>,
+    /// ID of the next filter added to the endpoint
+    next_filter: u64,
+    /// Outcome of the filters by source type and hash, if they all depend only on the source of the message.
+    /// Cleared when the filters change.
+    filter_cache: Option<HashMap<(Option<TypeId>, Option<u64>), bool>>,
+    filter_broadcasts: bool,
+    callback: Option<InnerCallback<'a, M, R>>,
+    /// Enqueues messages to the worker thread or blocking pool the endpoint is assigned to, instead of calling the
+    /// callback
+    worker: Option<InnerCallback<'a, M, R>>,
+    /// The callback is removed after its first call
+    once: bool,
     _phantom: PhantomData<M>,
 }
 
-impl<'a, M, R, S> std::fmt::Debug for EndpointInner<'a, M, R, S>
+/// Boxed message callback held by [`EndpointInner`], which can decline the message by returning it
+type InnerCallback<'a, M, R> = Box<
+    dyn FnMut(Option<SourceRef>, TakeableMessage<M>) -> Result<R, TakeableMessage<M>>
+        + Send
+        + Sync
+        + 'a,
+>;
+
+impl<'a, M, R> std::fmt::Debug for EndpointInner<'a, M, R>
 where
     Self: MessageHandler,
     M: std::fmt::Debug,
@@ -218,7 +591,7 @@ where
     }
 }
 
-impl<'a, M, R, S> Drop for EndpointInner<'a, M, R, S>
+impl<'a, M, R> Drop for EndpointInner<'a, M, R>
 where
     Self: MessageHandler,
 {
@@ -228,7 +601,18 @@ where
     }
 }
 
-impl<'a, M, R, S> EndpointInner<'a, M, R, S>
+impl<'a, M, R> Default for EndpointInner<'a, M, R>
+where
+    Self: MessageHandler,
+    M: Payload,
+    R: 'a,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, M, R> EndpointInner<'a, M, R>
 where
     Self: MessageHandler,
     M: Payload,
@@ -237,46 +621,157 @@ where
     pub fn new() -> Self {
         Self {
             filters: Vec::new(),
+            next_filter: 0,
+            filter_cache: Some(HashMap::new()),
+            filter_broadcasts: true,
             callback: None,
+            worker: None,
+            once: false,
             _phantom: PhantomData,
         }
     }
 
-    pub fn add_filter(&mut self, filter: impl Filter + 'static) {
-        self.filters.push(Box::new(filter))
+    /// Add a filter, returning its ID to remove it
+    pub fn add_filter(&mut self, filter: impl Filter + 'static) -> FilterId {
+        let id = FilterId(self.next_filter);
+        self.next_filter += 1;
+        self.filters.push((id, Box::new(filter)));
+        self.invalidate_filter_cache();
+        id
+    }
+
+    /// Remove the filter `id`, returning whether it was assigned to this inner endpoint
+    pub fn remove_filter(&mut self, id: FilterId) -> bool {
+        let len = self.filters.len();
+        self.filters.retain(|(filter_id, _)| *filter_id != id);
+        self.invalidate_filter_cache();
+        self.filters.len() != len
+    }
+
+    /// Remove all filters, so the endpoint receives all messages of its payload type
+    pub fn clear_filters(&mut self) {
+        self.filters.clear();
+        self.invalidate_filter_cache();
+    }
+
+    /// Clear the cached outcomes of the filters after they changed, and only cache them again if they all depend
+    /// only on the source of the message
+    fn invalidate_filter_cache(&mut self) {
+        self.filter_cache = self
+            .filters
+            .iter()
+            .all(|(_, filter)| filter.by_source())
+            .then(HashMap::new);
     }
 
     /// Get the filters assigned to this inner endpoint
-    pub fn filters(&self) -> &Vec<Box<dyn Filter>> {
-        &self.filters
+    pub fn filters(&self) -> impl Iterator<Item = &dyn Filter> {
+        self.filters.iter().map(|(_, filter)| &**filter)
+    }
+
+    /// Check if any filters are assigned to this inner endpoint
+    pub fn has_filters(&self) -> bool {
+        !self.filters.is_empty()
     }
 
+    /// Check if the filters assigned to this inner endpoint apply to broadcasts
+    pub fn filters_broadcasts(&self) -> bool {
+        self.filter_broadcasts
+    }
+
+    /// Check if this is a one-shot endpoint which already received its message
+    pub fn is_spent(&self) -> bool {
+        self.once && self.callback.is_none()
+    }
+
+    /// Check if any filter assigned to this inner endpoint matches the message
     pub fn filter(&self, message: &crate::Message) -> bool {
-        for filter in &self.filters {
-            let res = filter.filter(message);
-            if res == true {
-                println!("ENDPOINT FILTER MATCH {filter:?}");
+        for (_, filter) in &self.filters {
+            if filter.filter(message) {
+                trace!("Endpoint filter match {filter:?}");
                 return true;
             }
         }
         false
     }
+
+    /// Check if any filter matches the message, caching the outcome per source if the filters depend only on the
+    /// source of the message
+    pub(crate) fn filter_cached(&mut self, message: &crate::Message) -> bool {
+        let Some(cache) = &self.filter_cache else {
+            return self.filter(message);
+        };
+
+        // Filters downcasting the source can reject sources of another type with an equal hash
+        let source = (message.source_type(), message.source_hash());
+        if let Some(matched) = cache.get(&source) {
+            return *matched;
+        }
+
+        let matched = self.filter(message);
+        if let Some(cache) = &mut self.filter_cache {
+            // Bound the cache of endpoints receiving messages from many sources
+            if cache.len() >= FILTER_CACHE_CAPACITY {
+                cache.clear();
+            }
+            cache.insert(source, matched);
+        }
+        matched
+    }
+
+    /// Offer a message to the callback, getting the message back if the callback declined it. Messages of an
+    /// endpoint assigned to a worker thread or blocking pool are enqueued to it instead.
+    // Declined payloads are handed back by value, so the direct path can put them back in their slot without allocating
+    #[allow(clippy::result_large_err)]
+    pub fn offer(
+        &mut self,
+        source: Option<SourceRef>,
+        message: TakeableMessage<M>,
+    ) -> Result<R, TakeableMessage<M>> {
+        match &mut self.worker {
+            Some(worker) => (worker)(source, message),
+            None => self.call(source, message),
+        }
+    }
+
+    /// Call the callback with a message on the current thread
+    #[allow(clippy::result_large_err)]
+    fn call(
+        &mut self,
+        source: Option<SourceRef>,
+        message: TakeableMessage<M>,
+    ) -> Result<R, TakeableMessage<M>> {
+        if self.once {
+            if let Some(mut callback) = self.callback.take() {
+                // A declined message doesn't spend a one-shot endpoint
+                let result = (callback)(source, message);
+                if result.is_err() {
+                    self.callback = Some(callback);
+                }
+                return result;
+            }
+        }
+
+        if let Some(callback) = &mut self.callback {
+            (callback)(source, message)
+        } else {
+            panic!("No message handler defined in Endpoint. Ensure you've registered a closure with Endpoint::message()")
+        }
+    }
 }
 
-impl<'a, M, R, S> MessageHandler for EndpointInner<'a, M, R, S>
+impl<'a, M, R> MessageHandler for EndpointInner<'a, M, R>
 where
     M: Payload,
-    S: MessageSource + Copy,
+    R: 'a,
 {
     type Message = M;
     type Return = R;
-    type Source = S;
 
-    fn on_message(&mut self, source: Option<Self::Source>, message: Self::Message) -> Self::Return {
-        if let Some(callback) = &mut self.callback {
-            (callback)(source, message)
-        } else {
-            panic!("No message handler defined in Endpoint. Ensure you've registered a closure with Endpoint::message()")
+    fn on_message(&mut self, source: Option<SourceRef>, message: Self::Message) -> Self::Return {
+        match self.offer(source, TakeableMessage::from_payload(message)) {
+            Ok(result) => result,
+            Err(_) => panic!("Endpoint declined a message passed to MessageHandler::on_message()"),
         }
     }
 }
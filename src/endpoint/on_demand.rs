@@ -0,0 +1,130 @@
+//! Endpoints instantiated on demand
+//!
+//! [`MessageRouter::on_demand()`](crate::router::MessageRouter::on_demand) registers an endpoint whose handler is
+//! built by a factory when the first message arrives, so rarely used handlers and their resources are only
+//! constructed once they are needed. With an idle timeout, the handler can be released after a period without
+//! messages by calling [`OnDemand::release_idle()`], and is built again by the next message.
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anylock::{AnyLock, ParkingLotMutex};
+use tracing::debug;
+
+use crate::{message::SourceRef, traits::Payload};
+
+use super::Endpoint;
+
+/// Handler built by the factory of an [`OnDemand`] endpoint
+type OnDemandHandler<'a, M, R> = Box<dyn FnMut(Option<SourceRef>, M) -> R + Send + 'a>;
+
+struct OnDemandState<'a, M, R> {
+    handler: Option<OnDemandHandler<'a, M, R>>,
+    last_activity: Instant,
+    idle_timeout: Option<Duration>,
+}
+
+/// Endpoint whose handler is built when the first message arrives.
+/// The endpoint is deregistered when this is dropped.
+pub struct OnDemand<'a, M, R>
+where
+    M: Payload + 'static,
+    R: Send + 'a,
+{
+    endpoint: Endpoint<'a, M, R>,
+    state: Arc<ParkingLotMutex<OnDemandState<'a, M, R>>>,
+}
+
+impl<'a, M, R> std::fmt::Debug for OnDemand<'a, M, R>
+where
+    M: Payload + 'static,
+    R: Send + 'a,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OnDemand")
+            .field("endpoint", &self.endpoint)
+            .field("instantiated", &self.is_instantiated())
+            .finish()
+    }
+}
+
+impl<'a, M, R> OnDemand<'a, M, R>
+where
+    M: Payload + 'static,
+    R: Send + 'a,
+{
+    /// Build the handler of `endpoint` with `factory` when the first message arrives
+    pub(crate) fn new<F, H>(endpoint: Endpoint<'a, M, R>, factory: F) -> Self
+    where
+        F: Fn() -> H + Send + Sync + 'a,
+        H: FnMut(Option<SourceRef>, M) -> R + Send + 'a,
+    {
+        let state = Arc::new(ParkingLotMutex::new(OnDemandState {
+            handler: None,
+            last_activity: Instant::now(),
+            idle_timeout: None,
+        }));
+
+        let endpoint = endpoint.message({
+            let state = state.clone();
+
+            move |src, msg| {
+                let state = &mut *state.write();
+                state.last_activity = Instant::now();
+
+                let handler = state.handler.get_or_insert_with(|| {
+                    debug!("Building handler for {}", std::any::type_name::<M>());
+                    Box::new(factory())
+                });
+
+                handler(src, msg)
+            }
+        });
+
+        Self { endpoint, state }
+    }
+
+    /// Allow the handler to be released by [`OnDemand::release_idle()`] when no message arrived for `timeout`
+    pub fn idle_timeout(self, timeout: Duration) -> Self {
+        self.state.write().idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Get the [`Endpoint`] receiving messages for the handler
+    pub fn endpoint(&self) -> &Endpoint<'a, M, R> {
+        &self.endpoint
+    }
+
+    /// Check if the handler has been built
+    pub fn is_instantiated(&self) -> bool {
+        self.state.read().handler.is_some()
+    }
+
+    /// Release the handler if no message arrived within the idle timeout, returning true if it was released.
+    /// This should be called periodically, such as from a timer of the application.
+    pub fn release_idle(&self) -> bool {
+        let handler = {
+            let mut state = self.state.write();
+            match state.idle_timeout {
+                Some(timeout) if state.last_activity.elapsed() >= timeout => state.handler.take(),
+                _ => None,
+            }
+        };
+
+        // Drop the handler and its resources without holding the lock
+        let released = handler.is_some();
+        if released {
+            debug!("Released idle handler for {}", std::any::type_name::<M>());
+        }
+
+        released
+    }
+
+    /// Release the handler, which is built again by the next message
+    pub fn release(&self) {
+        let handler = self.state.write().handler.take();
+        drop(handler);
+    }
+}
@@ -0,0 +1,309 @@
+//! Endpoints resolving a future with the next message
+//!
+//! [`MessageRouter::oneshot()`](crate::router::MessageRouter::oneshot) registers a temporary endpoint, and returns
+//! a [`OneShot`] future which resolves to the next message of the payload type. This suits flows waiting for a
+//! response or a ready signal, without registering and dropping an endpoint by hand. The future works with any
+//! executor, and messages can be dispatched from any thread while it's pending.
+//!
+//! [`MessageRouter::await_message()`](crate::router::MessageRouter::await_message) adds a timeout, returning a
+//! [`Timeout`] which can be awaited, or waited for by blocking the thread with [`Timeout::wait()`].
+//! The endpoint is registered when the [`Timeout`] is created, so messages dispatched before waiting are received.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Condvar, Mutex},
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+use crate::traits::Payload;
+
+use super::Endpoint;
+
+/// Error returned when no message was received before the timeout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Elapsed {
+    /// Time waited for the message
+    pub timeout: Duration,
+}
+
+impl std::fmt::Display for Elapsed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "no message received within {:?}", self.timeout)
+    }
+}
+
+impl std::error::Error for Elapsed {}
+
+struct Slot<T> {
+    value: Option<T>,
+    waker: Option<Waker>,
+}
+
+/// Value received by an endpoint, shared between its callback and the task or thread waiting for it
+pub(super) struct Shared<T> {
+    slot: Mutex<Slot<T>>,
+
+    /// Notified when the value is received, for threads blocked waiting for it
+    received: Condvar,
+}
+
+impl<T> Shared<T> {
+    pub(super) fn new() -> Self {
+        Self {
+            slot: Mutex::new(Slot {
+                value: None,
+                waker: None,
+            }),
+            received: Condvar::new(),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Slot<T>> {
+        self.slot.lock().unwrap()
+    }
+
+    /// Store the value, and wake the task or threads waiting for it
+    pub(super) fn set(&self, value: T) {
+        let waker = {
+            let mut slot = self.lock();
+            slot.value = Some(value);
+            slot.waker.take()
+        };
+
+        self.received.notify_all();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+
+    pub(super) fn is_set(&self) -> bool {
+        self.lock().value.is_some()
+    }
+
+    pub(super) fn take(&self) -> Option<T> {
+        self.lock().value.take()
+    }
+
+    /// Take the value, or register the task to be woken when it's set
+    pub(super) fn poll(&self, cx: &mut Context<'_>) -> Poll<T> {
+        let mut slot = self.lock();
+        match slot.value.take() {
+            Some(value) => Poll::Ready(value),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    /// Block the thread until the value is set, or `timeout` has passed
+    pub(super) fn wait_timeout(&self, timeout: Duration) -> Option<T> {
+        let slot = self.lock();
+        let (mut slot, _) = self
+            .received
+            .wait_timeout_while(slot, timeout, |slot| slot.value.is_none())
+            .unwrap();
+
+        slot.value.take()
+    }
+
+    /// Start a thread waking the task after `timeout`, without depending on the timer of a runtime.
+    /// The thread stops early when the value is set, which wakes the task itself.
+    pub(super) fn wake_after(self: &Arc<Self>, timeout: Duration)
+    where
+        T: Send + 'static,
+    {
+        let shared = self.clone();
+
+        std::thread::spawn(move || {
+            let slot = shared.lock();
+            let (mut slot, result) = shared
+                .received
+                .wait_timeout_while(slot, timeout, |slot| slot.value.is_none())
+                .unwrap();
+
+            if result.timed_out() {
+                if let Some(waker) = slot.waker.take() {
+                    waker.wake();
+                }
+            }
+        });
+    }
+}
+
+/// Future resolving to the next message received by a temporary endpoint.
+/// The endpoint deregisters itself when it receives the message, or when this is dropped.
+pub struct OneShot<'a, M, R>
+where
+    M: Payload + 'static,
+    R: Send + 'a,
+{
+    endpoint: Endpoint<'a, M, R>,
+    shared: Arc<Shared<M>>,
+}
+
+impl<'a, M, R> std::fmt::Debug for OneShot<'a, M, R>
+where
+    M: Payload + 'static,
+    R: Send + 'a,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OneShot")
+            .field("endpoint", &self.endpoint)
+            .field("received", &self.is_received())
+            .finish()
+    }
+}
+
+impl<'a, M, R> OneShot<'a, M, R>
+where
+    M: Payload + 'static,
+    R: Default + Send + 'a,
+{
+    /// Resolve with the first message received by `endpoint`, which returns `R::default()` to the dispatcher
+    pub(crate) fn new(endpoint: Endpoint<'a, M, R>) -> Self {
+        let shared = Arc::new(Shared::new());
+
+        let endpoint = endpoint.message_once({
+            let shared = shared.clone();
+
+            move |_src, msg| {
+                shared.set(msg);
+                R::default()
+            }
+        });
+
+        Self { endpoint, shared }
+    }
+}
+
+impl<'a, M, R> OneShot<'a, M, R>
+where
+    M: Payload + 'static,
+    R: Send + 'a,
+{
+    /// Get the temporary [`Endpoint`] receiving the message
+    pub fn endpoint(&self) -> &Endpoint<'a, M, R> {
+        &self.endpoint
+    }
+
+    /// Check if the message was received, without taking it
+    pub fn is_received(&self) -> bool {
+        self.shared.is_set()
+    }
+
+    /// Take the message if it was received, without waiting for it
+    pub fn try_take(&self) -> Option<M> {
+        self.shared.take()
+    }
+
+    /// Block the thread until the message is received, or `timeout` has passed.
+    /// The message must be dispatched from another thread.
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<M, Elapsed> {
+        self.shared.wait_timeout(timeout).ok_or(Elapsed { timeout })
+    }
+
+    /// Resolve to an error if the message isn't received within `timeout`
+    pub fn timeout(self, timeout: Duration) -> Timeout<'a, M, R> {
+        Timeout {
+            oneshot: self,
+            timeout,
+            deadline: Instant::now() + timeout,
+            timer: false,
+        }
+    }
+}
+
+// The future isn't self-referential, and the message is held in shared state rather than pinned
+impl<'a, M, R> Unpin for OneShot<'a, M, R>
+where
+    M: Payload + 'static,
+    R: Send + 'a,
+{
+}
+
+impl<'a, M, R> Future for OneShot<'a, M, R>
+where
+    M: Payload + 'static,
+    R: Send + 'a,
+{
+    type Output = M;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.shared.poll(cx)
+    }
+}
+
+/// [`OneShot`] with a timeout, which can be awaited, or waited for by blocking with [`Timeout::wait()`]
+pub struct Timeout<'a, M, R>
+where
+    M: Payload + 'static,
+    R: Send + 'a,
+{
+    oneshot: OneShot<'a, M, R>,
+    timeout: Duration,
+    deadline: Instant,
+
+    /// Whether a thread was started to wake the task at the deadline
+    timer: bool,
+}
+
+impl<'a, M, R> std::fmt::Debug for Timeout<'a, M, R>
+where
+    M: Payload + 'static,
+    R: Send + 'a,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Timeout")
+            .field("oneshot", &self.oneshot)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+impl<'a, M, R> Timeout<'a, M, R>
+where
+    M: Payload + 'static,
+    R: Send + 'a,
+{
+    /// Block the thread until the message is received, or the timeout has passed since this was created.
+    /// The message must be dispatched from another thread.
+    pub fn wait(self) -> Result<M, Elapsed> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        self.oneshot.wait_timeout(remaining).map_err(|_| Elapsed {
+            timeout: self.timeout,
+        })
+    }
+}
+
+impl<'a, M, R> Future for Timeout<'a, M, R>
+where
+    M: Payload + 'static,
+    R: Send + 'a,
+{
+    type Output = Result<M, Elapsed>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+
+        if let Poll::Ready(message) = Pin::new(&mut this.oneshot).poll(cx) {
+            return Poll::Ready(Ok(message));
+        }
+
+        let remaining = this.deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Poll::Ready(Err(Elapsed {
+                timeout: this.timeout,
+            }));
+        }
+
+        if !this.timer {
+            this.timer = true;
+            this.oneshot.shared.wake_after(remaining);
+        }
+
+        Poll::Pending
+    }
+}
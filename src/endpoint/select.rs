@@ -0,0 +1,164 @@
+//! Waiting for the first of several payload types
+//!
+//! [`MessageRouter::select()`](crate::router::MessageRouter::select) returns a [`Select`] builder, which registers
+//! a temporary endpoint for each payload type added with [`Select::on()`]. The first message received by any of
+//! them is passed to the branch of its payload type, and the [`Select`] future resolves to the result of the
+//! branch. The endpoints of all branches are then deregistered, so protocol state machines can branch on whichever
+//! event happens first.
+//!
+//! ```
+//! use std::time::Duration;
+//! use salish::{router::MessageRouter, Message};
+//!
+//! #[derive(Debug, Clone)]
+//! struct Connected(u32);
+//!
+//! #[derive(Debug, Clone)]
+//! struct Failed(&'static str);
+//!
+//! let router = MessageRouter::<()>::new();
+//! let select = router
+//!     .select::<Result<u32, &str>>()
+//!     .on(|connected: Connected| Ok(connected.0))
+//!     .on(|failed: Failed| Err(failed.0));
+//!
+//! router.handle_message(Message::broadcast(Failed("refused")));
+//! assert_eq!(select.wait_timeout(Duration::ZERO), Ok(Err("refused")));
+//! ```
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tracing::debug;
+
+use crate::{router::MessageRouter, traits::Payload};
+
+use super::{
+    oneshot::{Elapsed, Shared},
+    EndpointId,
+};
+
+struct SelectState<T> {
+    result: Shared<T>,
+
+    /// Set by the first message received by a branch
+    selected: AtomicBool,
+
+    /// Endpoints of the branches, deregistered once a branch is selected
+    endpoints: Mutex<Vec<EndpointId>>,
+}
+
+/// Future resolving to the result of the branch of the first message received for any of its payload types.
+/// The endpoints of the branches are deregistered when a branch is selected, or when this is dropped.
+pub struct Select<'a, T, R> {
+    router: MessageRouter<'a, R>,
+    state: Arc<SelectState<T>>,
+
+    /// Type erased endpoints of the branches
+    branches: Vec<Box<dyn Send + Sync + 'a>>,
+}
+
+impl<'a, T, R> std::fmt::Debug for Select<'a, T, R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Select")
+            .field("branches", &self.branches.len())
+            .field("selected", &self.is_selected())
+            .finish()
+    }
+}
+
+impl<'a, T, R> Select<'a, T, R>
+where
+    T: Send + 'a,
+    R: Default + Send + 'a,
+{
+    pub(crate) fn new(router: MessageRouter<'a, R>) -> Self {
+        Self {
+            router,
+            state: Arc::new(SelectState {
+                result: Shared::new(),
+                selected: AtomicBool::new(false),
+                endpoints: Mutex::new(Vec::new()),
+            }),
+            branches: Vec::new(),
+        }
+    }
+
+    /// Add a branch for payload type `M`, whose result resolves the [`Select`] if a message of type `M` is the
+    /// first received by any branch. The endpoint of the branch returns `R::default()` to the dispatcher.
+    pub fn on<M>(mut self, branch: impl FnOnce(M) -> T + Send + 'a) -> Self
+    where
+        M: Payload + 'static,
+    {
+        if self.is_selected() {
+            debug!(
+                "Not adding branch for {}, a branch was already selected",
+                std::any::type_name::<M>()
+            );
+            return self;
+        }
+
+        let endpoint = self.router.create_endpoint::<M>();
+        self.state.endpoints.lock().unwrap().push(endpoint.id);
+
+        let router = self.router.clone();
+        let state = self.state.clone();
+        let mut branch = Some(branch);
+
+        let endpoint = endpoint.message_mut(move |_src, msg| {
+            if !state.selected.swap(true, Ordering::AcqRel) {
+                debug!("Selected branch for {}", std::any::type_name::<M>());
+
+                for id in state.endpoints.lock().unwrap().drain(..) {
+                    router.remove_endpoint(id);
+                }
+
+                if let Some(branch) = branch.take() {
+                    state.result.set(branch(msg));
+                }
+            }
+
+            R::default()
+        });
+
+        self.branches.push(Box::new(endpoint));
+        self
+    }
+}
+
+impl<'a, T, R> Select<'a, T, R> {
+    /// Check if a branch was selected
+    pub fn is_selected(&self) -> bool {
+        self.state.selected.load(Ordering::Acquire)
+    }
+
+    /// Take the result of the selected branch, without waiting for it
+    pub fn try_take(&self) -> Option<T> {
+        self.state.result.take()
+    }
+
+    /// Block the thread until a branch is selected, or `timeout` has passed.
+    /// The message must be dispatched from another thread, or before waiting.
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<T, Elapsed> {
+        self.state
+            .result
+            .wait_timeout(timeout)
+            .ok_or(Elapsed { timeout })
+    }
+}
+
+impl<'a, T, R> Future for Select<'a, T, R> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.state.result.poll(cx)
+    }
+}
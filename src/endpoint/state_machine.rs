@@ -0,0 +1,222 @@
+//! State machines driven by messages
+//!
+//! [`MessageRouter::state_machine()`](crate::router::MessageRouter::state_machine) returns a [`StateMachine`]
+//! builder, which registers an endpoint for each payload type with a handler. Handlers receive the current state
+//! and the message, and return a [`Transition`] to the next state. Handlers added with [`StateMachine::on_in()`]
+//! only handle messages in one state, and take precedence over handlers added with [`StateMachine::on()`], which
+//! handle messages in any state. Messages without a handler in the current state are ignored.
+//!
+//! ```
+//! use salish::{endpoint::Transition, router::MessageRouter, Message};
+//!
+//! #[derive(Debug, Clone, PartialEq)]
+//! enum Link {
+//!     Disconnected,
+//!     Connected,
+//! }
+//!
+//! #[derive(Debug, Clone)]
+//! struct Connect;
+//!
+//! #[derive(Debug, Clone)]
+//! struct Disconnect;
+//!
+//! let router = MessageRouter::<()>::new();
+//! let link = router
+//!     .state_machine(Link::Disconnected)
+//!     .on_in(Link::Disconnected, |_, _: Connect| Transition::To(Link::Connected))
+//!     .on(|_, _: Disconnect| Transition::To(Link::Disconnected));
+//!
+//! router.handle_message(Message::broadcast(Connect));
+//! assert_eq!(link.state(), Link::Connected);
+//! ```
+//!
+//! Handlers run while the state machine is locked, so they must not dispatch messages handled by the same state
+//! machine synchronously. Such messages can be sent through the queue of the router instead.
+
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    sync::Arc,
+};
+
+use anylock::{AnyLock, ParkingLotMutex};
+use tracing::{debug, trace};
+
+use crate::{router::MessageRouter, traits::Payload};
+
+/// Result of a state machine handler
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transition<S> {
+    /// Stay in the current state
+    Stay,
+
+    /// Transition to a state
+    To(S),
+}
+
+/// Handler taking the payload out of an `Option<M>` slot, so handlers of all payload types are stored together
+type ErasedHandler<'a, S> = Box<dyn FnMut(&S, &mut dyn Any) -> Option<Transition<S>> + Send + 'a>;
+
+/// Handlers of a payload type, with the state they are restricted to
+type StateHandlers<'a, S> = Vec<(Option<S>, ErasedHandler<'a, S>)>;
+
+/// Callback called with the previous and next state of each transition
+type TransitionCallback<'a, S> = Box<dyn FnMut(&S, &S) + Send + 'a>;
+
+struct Machine<'a, S> {
+    state: S,
+
+    /// Handlers by payload type, with the state they are restricted to
+    handlers: HashMap<TypeId, StateHandlers<'a, S>>,
+
+    on_transition: Option<TransitionCallback<'a, S>>,
+}
+
+impl<'a, S: PartialEq + std::fmt::Debug> Machine<'a, S> {
+    /// Pass the payload in `slot` to the handler of its type for the current state, and apply the transition
+    fn handle(&mut self, type_id: TypeId, slot: &mut dyn Any) {
+        let Some(handlers) = self.handlers.get_mut(&type_id) else {
+            return;
+        };
+
+        let state = &self.state;
+        let handler = handlers
+            .iter()
+            .position(|(only, _)| only.as_ref() == Some(state))
+            .or_else(|| handlers.iter().position(|(only, _)| only.is_none()));
+
+        let Some(index) = handler else {
+            trace!("No handler in state {state:?}");
+            return;
+        };
+
+        if let Some(Transition::To(next)) = (handlers[index].1)(state, slot) {
+            if next != self.state {
+                debug!("Transition from {:?} to {next:?}", self.state);
+                if let Some(on_transition) = &mut self.on_transition {
+                    on_transition(&self.state, &next);
+                }
+            }
+
+            self.state = next;
+        }
+    }
+}
+
+/// State machine receiving messages from a router.
+/// The endpoints of the state machine are deregistered when this is dropped.
+pub struct StateMachine<'a, S, R> {
+    router: MessageRouter<'a, R>,
+    machine: Arc<ParkingLotMutex<Machine<'a, S>>>,
+
+    /// Type erased endpoints of the payload types with handlers
+    endpoints: Vec<Box<dyn Send + Sync + 'a>>,
+}
+
+impl<'a, S: std::fmt::Debug, R> std::fmt::Debug for StateMachine<'a, S, R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let machine = self.machine.read();
+        f.debug_struct("StateMachine")
+            .field("state", &machine.state)
+            .field("types", &machine.handlers.len())
+            .finish()
+    }
+}
+
+impl<'a, S, R> StateMachine<'a, S, R>
+where
+    S: PartialEq + std::fmt::Debug + Send + 'a,
+    R: Default + Send + 'a,
+{
+    pub(crate) fn new(router: MessageRouter<'a, R>, initial: S) -> Self {
+        Self {
+            router,
+            machine: Arc::new(ParkingLotMutex::new(Machine {
+                state: initial,
+                handlers: HashMap::new(),
+                on_transition: None,
+            })),
+            endpoints: Vec::new(),
+        }
+    }
+
+    /// Handle messages of payload type `M` in any state without a handler for `M` added with
+    /// [`StateMachine::on_in()`]
+    pub fn on<M>(self, handler: impl FnMut(&S, M) -> Transition<S> + Send + 'a) -> Self
+    where
+        M: Payload + 'static,
+    {
+        self.add_handler(None, handler)
+    }
+
+    /// Handle messages of payload type `M` in `state`
+    pub fn on_in<M>(self, state: S, handler: impl FnMut(&S, M) -> Transition<S> + Send + 'a) -> Self
+    where
+        M: Payload + 'static,
+    {
+        self.add_handler(Some(state), handler)
+    }
+
+    /// Call `f` with the previous and next state when the state machine transitions to a different state
+    pub fn on_transition(self, f: impl FnMut(&S, &S) + Send + 'a) -> Self {
+        self.machine.write().on_transition = Some(Box::new(f));
+        self
+    }
+
+    fn add_handler<M>(
+        mut self,
+        state: Option<S>,
+        mut handler: impl FnMut(&S, M) -> Transition<S> + Send + 'a,
+    ) -> Self
+    where
+        M: Payload + 'static,
+    {
+        let type_id = TypeId::of::<M>();
+        let erased: ErasedHandler<'a, S> = Box::new(move |current, slot| {
+            let msg = slot.downcast_mut::<Option<M>>()?.take()?;
+            Some(handler(current, msg))
+        });
+
+        let first = {
+            let mut machine = self.machine.write();
+            let handlers = machine.handlers.entry(type_id).or_default();
+            handlers.push((state, erased));
+            handlers.len() == 1
+        };
+
+        // Register a single endpoint per payload type, so unicast messages reach the state machine once
+        if first {
+            let machine = self.machine.clone();
+            let endpoint = self
+                .router
+                .create_endpoint::<M>()
+                .message(move |_src, msg| {
+                    machine.write().handle(type_id, &mut Some(msg));
+                    R::default()
+                });
+
+            self.endpoints.push(Box::new(endpoint));
+        }
+
+        self
+    }
+}
+
+impl<'a, S, R> StateMachine<'a, S, R> {
+    /// Get a clone of the current state
+    pub fn state(&self) -> S
+    where
+        S: Clone,
+    {
+        self.machine.read().state.clone()
+    }
+
+    /// Check if the state machine is in `state`
+    pub fn is_in(&self, state: &S) -> bool
+    where
+        S: PartialEq,
+    {
+        self.machine.read().state == *state
+    }
+}
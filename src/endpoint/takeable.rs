@@ -0,0 +1,74 @@
+//! Messages offered to a handler, which can inspect the payload before deciding to take it
+
+use crate::{
+    message::Message,
+    traits::{internal::SalishMessageInternal as _, Payload},
+};
+
+/// A message offered to an endpoint registered with [`Endpoint::message_takeable()`](super::Endpoint::message_takeable).
+///
+/// The handler can inspect the payload with [`peek()`](Self::peek), and either consume it with
+/// [`take()`](Self::take) or decline the message by returning it. The router then offers a declined message to
+/// the next endpoint which can receive it, without cloning the payload, so unicast messages can be declined too.
+pub struct TakeableMessage<M> {
+    slot: Slot<M>,
+}
+
+/// Payload of a [`TakeableMessage`], which stays boxed in its [`Message`] until it's taken
+enum Slot<M> {
+    Message(Message),
+    Payload(M),
+}
+
+impl<M> TakeableMessage<M> {
+    /// Offer a payload which was already unwrapped
+    pub(crate) fn from_payload(payload: M) -> Self {
+        Self {
+            slot: Slot::Payload(payload),
+        }
+    }
+
+    /// Get back the [`Message`] of a declined offer, or `None` if the payload was offered without one
+    pub(crate) fn into_message(self) -> Option<Message> {
+        match self.slot {
+            Slot::Message(message) => Some(message),
+            Slot::Payload(_) => None,
+        }
+    }
+}
+
+impl<M: Payload + 'static> TakeableMessage<M> {
+    /// Offer a message, which must have a payload of type `M`
+    pub(crate) fn from_message(message: Message) -> Self {
+        debug_assert!(message.is_type::<M>());
+        Self {
+            slot: Slot::Message(message),
+        }
+    }
+
+    /// Get a reference to the payload, without taking it
+    pub fn peek(&self) -> &M {
+        match &self.slot {
+            Slot::Message(message) => message
+                .inner::<M>()
+                .expect("Takeable message payload type is checked when it's offered"),
+            Slot::Payload(payload) => payload,
+        }
+    }
+
+    /// Take the payload, accepting the message
+    pub fn take(self) -> M {
+        match self.slot {
+            Slot::Message(message) => message
+                .into_inner::<M>()
+                .expect("Takeable message payload type is checked when it's offered"),
+            Slot::Payload(payload) => payload,
+        }
+    }
+}
+
+impl<M: Payload + 'static> std::fmt::Debug for TakeableMessage<M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("TakeableMessage").field(self.peek()).finish()
+    }
+}
@@ -0,0 +1,55 @@
+//! Routers with erased return types
+//!
+//! All handlers of a [`MessageRouter`] return the same type `R`. An [`ErasedRouter`] returns [`AnyReturn`] from its
+//! handlers instead, so handlers returning different types can be registered with one router. Handlers are
+//! registered with [`Endpoint::reply()`], which erases the type they return, and callers get typed replies with
+//! [`MessageRouter::request()`].
+//!
+//! ```
+//! use salish::{erased::ErasedRouter, Message};
+//!
+//! let router = ErasedRouter::new();
+//! let _len = router.create_endpoint::<String>().reply(|_src, msg| msg.len());
+//! let _upper = router.create_endpoint::<char>().reply(|_src, msg| msg.to_ascii_uppercase());
+//!
+//! assert_eq!(router.request::<usize>(Message::unicast(String::from("salish"))), Some(6));
+//! assert_eq!(router.request::<char>(Message::unicast('s')), Some('S'));
+//! ```
+
+use std::any::Any;
+
+use crate::{
+    endpoint::Endpoint, message::SourceRef, router::MessageRouter, traits::Payload, Message,
+};
+
+/// Type erased result of a handler
+pub type AnyReturn = Box<dyn Any + Send>;
+
+/// Router whose handlers return [`AnyReturn`], so they can return different types
+pub type ErasedRouter<'a> = MessageRouter<'a, AnyReturn>;
+
+impl<'a> MessageRouter<'a, AnyReturn> {
+    /// Handle a message, and get the first result of its handlers of type `T`.
+    /// Returns `None` if the message was not delivered, or no handler returned a `T`.
+    pub fn request<T: 'static>(&self, message: Message) -> Option<T> {
+        self.handle_message(message)
+            .into_results()?
+            .into_iter()
+            .find_map(|result| result.downcast::<T>().ok())
+            .map(|result| *result)
+    }
+}
+
+impl<'a, M> Endpoint<'a, M, AnyReturn>
+where
+    M: Payload + 'static,
+{
+    /// Register a message callback returning any type, which is erased into an [`AnyReturn`]
+    pub fn reply<F, T>(self, mut f: F) -> Self
+    where
+        F: FnMut(Option<SourceRef>, M) -> T + Send + Sync + 'a,
+        T: Send + 'static,
+    {
+        self.message(move |src, msg| Box::new(f(src, msg)) as AnyReturn)
+    }
+}
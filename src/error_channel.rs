@@ -0,0 +1,155 @@
+//! Handler errors republished as messages
+//!
+//! When the handler result type of a router is `Result<T, E>`,
+//! [`MessageRouter::publish_errors()`](crate::router::MessageRouter::publish_errors) broadcasts each error returned
+//! by a handler as a [`HandlerError<E>`] message. Dedicated endpoints can then subscribe to errors, instead of
+//! errors being lost in the results of dispatches which callers ignore. Errors returned by endpoints of
+//! [`HandlerError<E>`] are not republished, so an error handler failing doesn't loop.
+//!
+//! ```
+//! use std::sync::{Arc, Mutex};
+//! use salish::{error_channel::HandlerError, router::MessageRouter, Message};
+//!
+//! let router = MessageRouter::<Result<(), String>>::new();
+//! router.publish_errors();
+//!
+//! let _endpoint = router
+//!     .create_endpoint::<u32>()
+//!     .message(|_src, msg| Err(format!("can't handle {msg}")));
+//!
+//! let errors = Arc::new(Mutex::new(Vec::new()));
+//! let _errors = router.create_endpoint::<HandlerError<String>>().message({
+//!     let errors = errors.clone();
+//!     move |_src, e| {
+//!         errors.lock().unwrap().push(e.error);
+//!         Ok(())
+//!     }
+//! });
+//!
+//! router.handle_message(Message::unicast(7u32));
+//! assert_eq!(*errors.lock().unwrap(), ["can't handle 7"]);
+//! ```
+
+use std::any::TypeId;
+
+use arc_swap::ArcSwapOption;
+use tracing::trace;
+
+use crate::{dispatch::DispatchResult, Message};
+
+/// Broadcast when a handler returns an error, if the router publishes errors
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HandlerError<E> {
+    /// Error returned by the handler
+    pub error: E,
+
+    /// Rust type name of the payload the handler failed to handle
+    pub payload: &'static str,
+}
+
+/// Creates the [`HandlerError`] message of a handler result, if it's an error
+type Publish<R> = Box<dyn Fn(&R, &'static str) -> Option<Message> + Send + Sync>;
+
+struct Publisher<R> {
+    /// [`TypeId`] of the [`HandlerError`] messages, whose handler errors are not republished
+    error_type: TypeId,
+    publish: Publish<R>,
+}
+
+/// Publisher of handler errors, shared by all clones of a router
+pub(crate) struct ErrorChannel<R> {
+    publisher: ArcSwapOption<Publisher<R>>,
+}
+
+impl<R> std::fmt::Debug for ErrorChannel<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ErrorChannel")
+            .field("enabled", &self.publisher.load().is_some())
+            .finish()
+    }
+}
+
+impl<R> Default for ErrorChannel<R> {
+    fn default() -> Self {
+        Self {
+            publisher: ArcSwapOption::empty(),
+        }
+    }
+}
+
+impl<T, E> ErrorChannel<Result<T, E>>
+where
+    T: 'static,
+    E: Clone + std::fmt::Debug + Send + Sync + 'static,
+{
+    /// Publish the errors of handler results as [`HandlerError<E>`] messages
+    pub(crate) fn enable(&self) {
+        self.publisher.store(Some(
+            Publisher {
+                error_type: TypeId::of::<HandlerError<E>>(),
+                publish: Box::new(|result: &Result<T, E>, payload| {
+                    let error = result.as_ref().err()?.clone();
+                    Some(Message::broadcast(HandlerError { error, payload }))
+                }),
+            }
+            .into(),
+        ));
+    }
+}
+
+impl<R> ErrorChannel<R> {
+    /// Stop publishing errors
+    pub(crate) fn disable(&self) {
+        self.publisher.store(None);
+    }
+
+    /// Get the [`HandlerError`] message of the result of a single handler, if it's an error
+    pub(crate) fn error(
+        &self,
+        type_id: TypeId,
+        type_name: &'static str,
+        result: &R,
+    ) -> Option<Message> {
+        let publisher = self.publisher.load();
+        let publisher = publisher.as_ref()?;
+
+        if type_id == publisher.error_type {
+            return None;
+        }
+
+        (publisher.publish)(result, type_name)
+    }
+
+    /// Get the [`HandlerError`] messages of the errors in the results of a dispatch
+    pub(crate) fn errors(
+        &self,
+        type_id: TypeId,
+        type_name: &'static str,
+        results: &DispatchResult<R>,
+    ) -> Vec<Message> {
+        let publisher = self.publisher.load();
+        let Some(publisher) = publisher.as_ref() else {
+            return Vec::new();
+        };
+
+        if type_id == publisher.error_type {
+            return Vec::new();
+        }
+
+        let errors: Vec<Message> = results
+            .results()
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|result| (publisher.publish)(result, type_name))
+            .collect();
+
+        if !errors.is_empty() {
+            trace!(
+                "Publishing {} errors of handlers of {type_name}",
+                errors.len()
+            );
+        }
+
+        errors
+    }
+}
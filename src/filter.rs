@@ -1,6 +1,7 @@
 use std::{
     collections::HashSet,
     hash::{DefaultHasher, Hasher as _},
+    ops::{Bound, RangeBounds},
 };
 
 use crate::{message::MessageSource, Message};
@@ -8,9 +9,30 @@ use crate::{message::MessageSource, Message};
 /// Filter trait for implementing specific filter types
 pub trait Filter: std::fmt::Debug + Send + Sync {
     fn filter(&self, message: &Message) -> bool;
+
+    /// Whether the outcome of the filter only depends on the source of the message. Endpoints whose filters all
+    /// depend only on the source cache their outcome per source, instead of evaluating them for every message.
+    fn by_source(&self) -> bool {
+        false
+    }
+}
+
+/// ID of a filter added to a live endpoint with [`Endpoint::add_filter()`](crate::endpoint::Endpoint::add_filter)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FilterId(pub(crate) u64);
+
+/// Boxed filters, such as filters built from configuration
+impl Filter for Box<dyn Filter> {
+    fn filter(&self, message: &Message) -> bool {
+        (**self).filter(message)
+    }
+
+    fn by_source(&self) -> bool {
+        (**self).by_source()
+    }
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FilterOp {
     /// Match any
     #[default]
@@ -29,7 +51,16 @@ pub struct SourceFilter {
 }
 
 impl SourceFilter {
+    /// Create an empty filter matching sources with `op`
+    pub fn new(op: FilterOp) -> Self {
+        Self {
+            op,
+            hashes: HashSet::new(),
+        }
+    }
+
     /// Hash a MessageSource, and add it to the filter set
+    #[allow(clippy::should_implement_trait)]
     pub fn add<S: MessageSource>(mut self, source: S) -> Self {
         let mut hasher = DefaultHasher::new();
         source.hash(&mut hasher);
@@ -41,14 +72,208 @@ impl SourceFilter {
 
 impl Filter for SourceFilter {
     fn filter(&self, message: &Message) -> bool {
-        if let Some(hash) = message.source_hash() {
-            match self.op {
-                FilterOp::Any => self.hashes.contains(&hash),
-                FilterOp::All => todo!(),
-                FilterOp::Negative => todo!(),
+        match (self.op, message.source_hash()) {
+            (FilterOp::Any, Some(hash)) => self.hashes.contains(&hash),
+            // A message has a single source, which can only match all sources of a filter holding one source
+            (FilterOp::All, Some(hash)) => {
+                !self.hashes.is_empty() && self.hashes.iter().all(|added| *added == hash)
             }
-        } else {
-            false
+            (FilterOp::Negative, Some(hash)) => !self.hashes.contains(&hash),
+            // Messages without a source match none of the sources
+            (FilterOp::Negative, None) => true,
+            (_, None) => false,
         }
     }
+
+    fn by_source(&self) -> bool {
+        true
+    }
+}
+
+/// Filter matching sources of a numeric type, or any ordered type, within a range. Complements the set-based
+/// [`SourceFilter`] for sources such as sensor IDs, which are compared instead of hashed:
+///
+/// ```
+/// use salish::{filter::{Filter as _, RangeFilter}, Message};
+///
+/// let filter = RangeFilter::new(100u32..200);
+/// assert!(filter.filter(&Message::unicast(()).with_source(150u32)));
+/// assert!(!filter.filter(&Message::unicast(()).with_source(200u32)));
+/// ```
+///
+/// Messages without a source of type `T` don't match.
+#[derive(Debug, Clone)]
+pub struct RangeFilter<T> {
+    start: Bound<T>,
+    end: Bound<T>,
+}
+
+impl<T> RangeFilter<T>
+where
+    T: PartialOrd + Copy + std::fmt::Debug + Send + Sync + 'static,
+{
+    /// Match sources within `range`, which can have inclusive, exclusive or unbounded ends such as `100..=199`
+    /// or `..200`
+    pub fn new(range: impl RangeBounds<T>) -> Self {
+        Self {
+            start: range.start_bound().cloned(),
+            end: range.end_bound().cloned(),
+        }
+    }
+}
+
+impl<T> Filter for RangeFilter<T>
+where
+    T: PartialOrd + Copy + std::fmt::Debug + Send + Sync + 'static,
+{
+    fn filter(&self, message: &Message) -> bool {
+        message
+            .source_ref()
+            .and_then(|source| source.get::<T>())
+            .is_some_and(|source| (self.start, self.end).contains(&source))
+    }
+
+    fn by_source(&self) -> bool {
+        true
+    }
+}
+
+/// Part of a message matched by a [`PatternFilter`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatternTarget {
+    /// A source of type `&'static str`
+    Source,
+
+    /// The value of a header
+    Header(String),
+}
+
+/// Pattern a [`PatternFilter`] matches text against
+#[derive(Debug, Clone)]
+enum Pattern {
+    /// Glob pattern, where `*` matches any sequence of characters and `?` matches any single character
+    Glob(String),
+
+    #[cfg(feature = "regex")]
+    Regex(regex::Regex),
+}
+
+impl Pattern {
+    fn matches(&self, text: &str) -> bool {
+        match self {
+            Pattern::Glob(glob) => glob_match(glob, text),
+            #[cfg(feature = "regex")]
+            Pattern::Regex(regex) => regex.is_match(text),
+        }
+    }
+}
+
+/// Filter matching string sources or header values against a glob pattern, or a regular expression with the
+/// `regex` feature. Unlike a [`SourceFilter`], which matches hashes of exact sources, a pattern can match a family
+/// of sources such as `sensor/*`.
+///
+/// Messages without a string source, or without the header, don't match.
+#[derive(Debug, Clone)]
+pub struct PatternFilter {
+    target: PatternTarget,
+    pattern: Pattern,
+}
+
+impl PatternFilter {
+    /// Match `&'static str` sources against a glob pattern
+    pub fn source(glob: impl Into<String>) -> Self {
+        Self {
+            target: PatternTarget::Source,
+            pattern: Pattern::Glob(glob.into()),
+        }
+    }
+
+    /// Match the value of the header `name` against a glob pattern
+    pub fn header(name: impl Into<String>, glob: impl Into<String>) -> Self {
+        Self {
+            target: PatternTarget::Header(name.into()),
+            pattern: Pattern::Glob(glob.into()),
+        }
+    }
+
+    /// Match `&'static str` sources against a regular expression
+    #[cfg(feature = "regex")]
+    pub fn source_regex(regex: &str) -> Result<Self, regex::Error> {
+        Ok(Self {
+            target: PatternTarget::Source,
+            pattern: Pattern::Regex(regex::Regex::new(regex)?),
+        })
+    }
+
+    /// Match the value of the header `name` against a regular expression
+    #[cfg(feature = "regex")]
+    pub fn header_regex(name: impl Into<String>, regex: &str) -> Result<Self, regex::Error> {
+        Ok(Self {
+            target: PatternTarget::Header(name.into()),
+            pattern: Pattern::Regex(regex::Regex::new(regex)?),
+        })
+    }
+
+    /// Get the part of messages the filter matches
+    pub fn target(&self) -> &PatternTarget {
+        &self.target
+    }
+}
+
+impl Filter for PatternFilter {
+    fn filter(&self, message: &Message) -> bool {
+        let text = match &self.target {
+            PatternTarget::Source => message
+                .source_ref()
+                .and_then(|source| source.get::<&'static str>()),
+            PatternTarget::Header(name) => message.header(name),
+        };
+
+        text.is_some_and(|text| self.pattern.matches(text))
+    }
+
+    fn by_source(&self) -> bool {
+        self.target == PatternTarget::Source
+    }
+}
+
+/// Match `text` against a glob pattern, where `*` matches any sequence of characters and `?` matches any single
+/// character
+fn glob_match(glob: &str, text: &str) -> bool {
+    let glob: Vec<char> = glob.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut g, mut t) = (0, 0);
+
+    // Position of the last `*` in the glob, and of the text it was matched from
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        match glob.get(g) {
+            Some('*') => {
+                star = Some((g, t));
+                g += 1;
+            }
+            Some('?') => {
+                g += 1;
+                t += 1;
+            }
+            Some(c) if *c == text[t] => {
+                g += 1;
+                t += 1;
+            }
+            _ => match star {
+                // Let the last `*` match one more character
+                Some((star_g, star_t)) => {
+                    star = Some((star_g, star_t + 1));
+                    g = star_g + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    // Trailing `*`s match the empty remainder of the text
+    glob[g..].iter().all(|c| *c == '*')
 }
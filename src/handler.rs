@@ -1,5 +1,7 @@
+use std::future::Future;
+
 use crate::{
-    message::{DynMessageSource, MessageSource},
+    message::SourceRef,
     traits::Payload,
 };
 
@@ -8,11 +10,25 @@ pub trait MessageHandler: std::fmt::Debug + Send + Sync {
     /// Payload type this handler is receiving
     type Message: Payload;
 
-    type Source: MessageSource + Copy;
-
     /// The return type of the message handler
     type Return;
 
     /// Called when a message is received
-    fn on_message(&mut self, source: Option<Self::Source>, message: Self::Message) -> Self::Return;
+    fn on_message(&mut self, source: Option<SourceRef>, message: Self::Message) -> Self::Return;
+}
+
+/// Async Message Handler Trait
+///
+/// Messages are passed to the handler one at a time, from a task which awaits each message before receiving the
+/// next. Since the router doesn't wait for the task, the handler doesn't return a result to the dispatcher.
+pub trait AsyncMessageHandler: std::fmt::Debug + Send {
+    /// Payload type this handler is receiving
+    type Message: Payload;
+
+    /// Called when a message is received
+    fn on_message(
+        &mut self,
+        source: Option<SourceRef>,
+        message: Self::Message,
+    ) -> impl Future<Output = ()> + Send;
 }
@@ -0,0 +1,222 @@
+//! Runtime inspection of a [`MessageRouter`] over HTTP
+//!
+//! An [`Inspector`] serves the state of a running router as JSON, for debugging long lived services:
+//!
+//! - `/endpoints` lists the registered endpoints with their filters and [`EndpointStats`]
+//! - `/types` lists the payload types endpoints are registered for, and the number of endpoints for each
+//! - `/stats` reports the router totals and the [`TypeMetrics`] of each payload type
+//! - `/deadletter` reports the number of dropped messages, and the most recently dropped messages
+//!
+//! The server only implements enough of HTTP/1.1 to answer `GET` requests, closing the connection after each
+//! response. It is intended for local diagnostics, and should not be exposed to untrusted networks.
+//!
+//! [`EndpointStats`]: crate::metrics::EndpointStats
+//! [`TypeMetrics`]: crate::metrics::TypeMetrics
+
+use std::{net::SocketAddr, time::Instant};
+
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncReadExt as _, AsyncWriteExt as _},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    task::{JoinHandle, JoinSet},
+};
+use tracing::{debug, error, trace};
+
+use crate::router::MessageRouter;
+
+/// Maximum size of a request head
+const MAX_REQUEST: usize = 8 * 1024;
+
+/// HTTP server exposing the state of a [`MessageRouter`] as JSON. The server stops when the inspector is dropped.
+pub struct Inspector {
+    local_addr: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl std::fmt::Debug for Inspector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Inspector")
+            .field("local_addr", &self.local_addr)
+            .finish()
+    }
+}
+
+impl Inspector {
+    /// Serve the state of `router` over HTTP on `addr`
+    pub async fn serve<R>(
+        addr: impl ToSocketAddrs,
+        router: &MessageRouter<'static, R>,
+    ) -> std::io::Result<Self>
+    where
+        R: Send + 'static,
+    {
+        let listener = TcpListener::bind(addr).await?;
+        let local_addr = listener.local_addr()?;
+        debug!("Inspector listening on {local_addr}");
+
+        let router = router.clone();
+        let task = tokio::spawn(async move {
+            // Connection tasks are aborted along with the listener task
+            let mut connections = JoinSet::new();
+
+            loop {
+                match listener.accept().await {
+                    Ok((stream, peer)) => {
+                        trace!("Inspector connection from {peer}");
+                        let router = router.clone();
+
+                        connections.spawn(async move {
+                            if let Err(e) = Self::respond(stream, &router).await {
+                                debug!("Inspector connection from {peer} failed: {e}");
+                            }
+                        });
+                    }
+                    Err(e) => error!("Inspector accept failed: {e}"),
+                }
+
+                while connections.try_join_next().is_some() {}
+            }
+        });
+
+        Ok(Self { local_addr, task })
+    }
+
+    /// Get the address the inspector is listening on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Read a request from `stream`, and write the response
+    async fn respond<R>(
+        mut stream: TcpStream,
+        router: &MessageRouter<'static, R>,
+    ) -> std::io::Result<()> {
+        let mut request = Vec::new();
+        let mut buf = [0u8; 1024];
+
+        while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+            let len = stream.read(&mut buf).await?;
+            if len == 0 {
+                return Ok(());
+            }
+
+            request.extend_from_slice(&buf[..len]);
+            if request.len() > MAX_REQUEST {
+                return Self::write(&mut stream, "431 Request Header Fields Too Large", None).await;
+            }
+        }
+
+        let request = String::from_utf8_lossy(&request);
+        let mut parts = request.split_whitespace();
+
+        match (parts.next(), parts.next()) {
+            (Some("GET"), Some(target)) => {
+                // Ignore any query string
+                let path = target.split('?').next().unwrap_or_default();
+
+                match Self::route(path, router) {
+                    Some(body) => Self::write(&mut stream, "200 OK", Some(body)).await,
+                    None => Self::write(&mut stream, "404 Not Found", None).await,
+                }
+            }
+            (Some(_), Some(_)) => Self::write(&mut stream, "405 Method Not Allowed", None).await,
+            _ => Self::write(&mut stream, "400 Bad Request", None).await,
+        }
+    }
+
+    /// Write a response with an optional JSON body, and close the connection
+    async fn write(
+        stream: &mut TcpStream,
+        status: &str,
+        body: Option<Value>,
+    ) -> std::io::Result<()> {
+        let body = body.map(|body| body.to_string()).unwrap_or_default();
+        let response = format!(
+            "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+
+        stream.write_all(response.as_bytes()).await?;
+        stream.shutdown().await
+    }
+
+    /// Get the JSON document for a path, or `None` if the path doesn't exist
+    fn route<R>(path: &str, router: &MessageRouter<'static, R>) -> Option<Value> {
+        let now = Instant::now();
+
+        let body = match path.trim_end_matches('/') {
+            "/endpoints" => router
+                .endpoints()
+                .into_iter()
+                .map(|info| {
+                    // 128 bit IDs don't fit in JSON numbers
+                    #[cfg(feature = "wide-ids")]
+                    let id = info.id.to_string();
+                    #[cfg(not(feature = "wide-ids"))]
+                    let id = info.id;
+
+                    json!({
+                        "id": id,
+                        "type": info.type_name,
+                        "filters": info.filters,
+                        "forwarder": info.forwarder,
+                        "received": info.stats.received,
+                        "filtered": info.stats.filtered,
+                        "errors": info.stats.errors,
+                        "idle_ms": info.stats.last_activity
+                            .map(|last| now.duration_since(last).as_millis() as u64),
+                        "mean_duration_us": info.stats.mean_duration.as_micros() as u64,
+                    })
+                })
+                .collect(),
+            "/types" => router
+                .handler_types()
+                .into_iter()
+                .map(|(type_name, endpoints)| json!({ "type": type_name, "endpoints": endpoints }))
+                .collect(),
+            "/stats" => json!({
+                "endpoints": router.num_endpoints(),
+                "handlers": router.num_handlers(),
+                "queued": router.queued(),
+                "dead_letters": router.dead_letters(),
+                "types": router
+                    .metrics()
+                    .snapshot()
+                    .into_iter()
+                    .map(|metrics| {
+                        json!({
+                            "type": metrics.type_name,
+                            "dispatched": metrics.dispatched,
+                            "delivered": metrics.delivered,
+                            "dropped": metrics.dropped,
+                        })
+                    })
+                    .collect::<Vec<_>>(),
+            }),
+            "/deadletter" => json!({
+                "total": router.dead_letters(),
+                "recent": router
+                    .recent_dead_letters()
+                    .into_iter()
+                    .map(|record| {
+                        json!({
+                            "type": record.type_name,
+                            "reason": record.reason.to_string(),
+                            "age_ms": now.duration_since(record.time).as_millis() as u64,
+                        })
+                    })
+                    .collect::<Vec<_>>(),
+            }),
+            _ => return None,
+        };
+
+        Some(body)
+    }
+}
+
+impl Drop for Inspector {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
@@ -0,0 +1,133 @@
+//! [Bevy](https://bevyengine.org) plugin exposing a [`MessageRouter`] as a [`Resource`]
+//!
+//! [`SalishPlugin`] inserts a [`SalishRouter`] resource, and drains the inbound queue of the router once per frame in [`PreUpdate`].
+//! The time spent draining each frame can be bounded with [`SalishPlugin::budget()`].
+//! Handler results are written as [`RouterResult`] events, which can be read by systems with an `EventReader`.
+//!
+//! Startup systems can register endpoints through `Res<SalishRouter<R>>` with [`MessageRouter::static_endpoint()`],
+//! and any system can queue messages with a [`RouterSender`](crate::queue::RouterSender) obtained from the resource.
+
+use std::{
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+    time::Duration,
+};
+
+use bevy_app::{App, Plugin, PreUpdate};
+use bevy_ecs::{
+    event::{Event, EventWriter},
+    system::{Res, Resource},
+};
+
+use crate::router::MessageRouter;
+
+/// Resource wrapping the [`MessageRouter`] of the app
+#[derive(Resource)]
+pub struct SalishRouter<R>
+where
+    R: Send + Sync + 'static,
+{
+    router: MessageRouter<'static, R>,
+}
+
+impl<R> std::fmt::Debug for SalishRouter<R>
+where
+    R: Send + Sync + 'static,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SalishRouter")
+            .field("router", &self.router)
+            .finish()
+    }
+}
+
+impl<R> Default for SalishRouter<R>
+where
+    R: Send + Sync + 'static,
+{
+    fn default() -> Self {
+        Self {
+            router: MessageRouter::new(),
+        }
+    }
+}
+
+impl<R> Deref for SalishRouter<R>
+where
+    R: Send + Sync + 'static,
+{
+    type Target = MessageRouter<'static, R>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.router
+    }
+}
+
+impl<R> DerefMut for SalishRouter<R>
+where
+    R: Send + Sync + 'static,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.router
+    }
+}
+
+/// Event carrying a result returned by a handler while draining the router
+#[derive(Event, Debug)]
+pub struct RouterResult<R: Send + Sync + 'static>(pub R);
+
+/// Plugin inserting a [`SalishRouter`] resource, and draining it each frame
+pub struct SalishPlugin<R> {
+    budget: Option<Duration>,
+    _phantom: PhantomData<fn() -> R>,
+}
+
+impl<R> Default for SalishPlugin<R> {
+    fn default() -> Self {
+        Self {
+            budget: None,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<R> SalishPlugin<R> {
+    /// Stop draining the router each frame once `budget` has elapsed, leaving the remaining messages for the
+    /// next frame. See [`MessageRouter::run_for()`].
+    pub fn budget(mut self, budget: Duration) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+}
+
+/// Time budget for draining the router each frame
+#[derive(Resource)]
+struct DrainBudget(Option<Duration>);
+
+impl<R> Plugin for SalishPlugin<R>
+where
+    R: Send + Sync + 'static,
+{
+    fn build(&self, app: &mut App) {
+        app.insert_resource(SalishRouter::<R>::default())
+            .insert_resource(DrainBudget(self.budget))
+            .add_event::<RouterResult<R>>()
+            .add_systems(PreUpdate, drain_router::<R>);
+    }
+}
+
+/// System dispatching the queued messages of the router within the budget, and writing the results as events
+fn drain_router<R>(
+    router: Res<SalishRouter<R>>,
+    budget: Res<DrainBudget>,
+    mut results: EventWriter<RouterResult<R>>,
+) where
+    R: Send + Sync + 'static,
+{
+    let drained = match budget.0 {
+        Some(budget) => router.run_for(budget),
+        None => router.drain(),
+    };
+
+    results.send_batch(drained.into_iter().map(RouterResult));
+}
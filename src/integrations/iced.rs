@@ -0,0 +1,117 @@
+//! Helpers for pumping router messages from an [iced](https://iced.rs) application
+//!
+//! A [`UiRouter`] wraps a [`MessageRouter`] whose handlers return the application's own message type.
+//! Results of dispatching can be returned from `update` as a [`Task`], or streamed into the application
+//! by [`UiRouter::subscription()`], which drains the inbound queue whenever a [`RouterSender`] queues a message.
+//!
+//! [`RouterSender`] is cheaply cloneable, and can be moved into widget callbacks.
+
+use std::sync::Arc;
+
+use anylock::{AnyLock, ParkingLotMutex};
+use iced_runtime::{
+    futures::{
+        futures::{channel::mpsc, stream, StreamExt as _},
+        Subscription,
+    },
+    Task,
+};
+
+use crate::{queue::RouterSender, router::MessageRouter, Message};
+
+/// Router for GUI applications, yielding iced [`Task`] and [`Subscription`] values
+pub struct UiRouter<R> {
+    router: MessageRouter<'static, R>,
+
+    /// Wakeups sent each time a message is queued. Taken by the first subscription.
+    wake: Arc<ParkingLotMutex<Option<mpsc::UnboundedReceiver<()>>>>,
+}
+
+impl<R> std::fmt::Debug for UiRouter<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UiRouter")
+            .field("router", &self.router)
+            .finish()
+    }
+}
+
+impl<R> Default for UiRouter<R>
+where
+    R: Send + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<R> UiRouter<R>
+where
+    R: Send + 'static,
+{
+    pub fn new() -> Self {
+        let router = MessageRouter::new();
+
+        let (tx, rx) = mpsc::unbounded();
+        router.on_queued(move || {
+            // Only fails once the subscription has been dropped
+            let _ = tx.unbounded_send(());
+        });
+
+        Self {
+            router,
+            wake: Arc::new(ParkingLotMutex::new(Some(rx))),
+        }
+    }
+
+    /// Get a reference to the inner [`MessageRouter`], for creating endpoints
+    pub fn router(&self) -> &MessageRouter<'static, R> {
+        &self.router
+    }
+
+    /// Get a mutable reference to the inner [`MessageRouter`]
+    pub fn router_mut(&mut self) -> &mut MessageRouter<'static, R> {
+        &mut self.router
+    }
+
+    /// Get a [`RouterSender`] which can be cloned into widget callbacks
+    pub fn sender(&self) -> RouterSender {
+        self.router.sender()
+    }
+
+    /// Dispatch a message immediately, and return the handler results as a [`Task`]
+    pub fn handle_message(&self, message: Message) -> Task<R> {
+        match self.router.handle_message(message).into_results() {
+            Some(results) => Self::into_task(results),
+            None => Task::none(),
+        }
+    }
+
+    /// Dispatch all queued messages, and return the handler results as a [`Task`].
+    /// Use this when driving the router from `update` rather than [`UiRouter::subscription()`].
+    pub fn drain(&self) -> Task<R> {
+        Self::into_task(self.router.drain())
+    }
+
+    /// Create a [`Subscription`] which drains the inbound queue each time a message is queued,
+    /// producing handler results as application messages.
+    ///
+    /// Only one subscription per [`UiRouter`] receives wakeups.
+    pub fn subscription(&self) -> Subscription<R> {
+        let router = self.router.clone();
+        let wake = self.wake.clone();
+
+        // Identify the subscription by the wake channel, so iced keeps a single running stream per router
+        let id = Arc::as_ptr(&self.wake) as usize;
+
+        let results = stream::once(async move { wake.write().take() })
+            .filter_map(|rx| async move { rx })
+            .flatten()
+            .flat_map(move |()| stream::iter(router.drain()));
+
+        Subscription::run_with_id(id, results)
+    }
+
+    fn into_task(results: impl IntoIterator<Item = R>) -> Task<R> {
+        Task::batch(results.into_iter().map(Task::done))
+    }
+}
@@ -0,0 +1,13 @@
+//! Integrations with external runtimes and frameworks
+
+#[cfg(feature = "bevy")]
+pub mod bevy;
+
+#[cfg(feature = "iced")]
+pub mod iced;
+
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+
+#[cfg(feature = "tokio")]
+pub mod tokio;
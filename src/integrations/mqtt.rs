@@ -0,0 +1,296 @@
+//! MQTT gateway using [`rumqttc`]
+//!
+//! An [`MqttGateway`] publishes messages dispatched in a [`MessageRouter`] to MQTT topics, and injects
+//! messages published to subscribed MQTT topics into the router as typed payloads.
+//!
+//! Payload types are mapped to MQTT topics when registered. Outbound payloads are converted to bytes by a
+//! user supplied encode function, and inbound MQTT payloads are converted to typed messages by a user supplied
+//! decode function, so any wire format used by existing devices can be bridged.
+//!
+//! Inbound messages are broadcast, and are not delivered back to the publishing endpoint of the same type.
+//! Brokers still echo messages back to the gateway when it subscribes to a topic it also publishes to,
+//! so avoid registering the same type in both directions on overlapping topics.
+
+use std::{
+    any::TypeId,
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use anylock::{AnyLock, ParkingLotRwLock};
+use rumqttc::{
+    Client, ClientError, Connection, Event, MqttOptions, Packet, Publish, QoS, RecvTimeoutError,
+};
+use tracing::{debug, error, trace, warn};
+
+use crate::{
+    endpoint::{Endpoint, EndpointId},
+    router::MessageRouter,
+    traits::{BroadcastPayload, EndpointAddress as _, Payload},
+    Message,
+};
+
+/// Capacity of the request channel between the [`Client`] and the connection
+const REQUEST_CAPACITY: usize = 64;
+
+/// Interval at which the connection thread checks for shutdown
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Delay before polling the connection again after an error, which reconnects to the broker
+const RECONNECT_DELAY: Duration = Duration::from_millis(500);
+
+/// Function decoding an MQTT publish into a [`Message`]
+type DecodeFn = Box<dyn Fn(&str, &[u8]) -> Option<Message> + Send + Sync>;
+
+/// Subscribed topic filter, and the decoder for messages published to it
+struct Subscription {
+    filter: String,
+    qos: QoS,
+    type_id: TypeId,
+    decode: DecodeFn,
+}
+
+/// State shared with the connection thread
+#[derive(Default)]
+struct Inbound {
+    subscriptions: Vec<Subscription>,
+
+    /// Publishing endpoint of each payload type, which inbound messages of the type are not delivered to
+    publishers: HashMap<TypeId, EndpointId>,
+}
+
+type SharedInbound = Arc<ParkingLotRwLock<Inbound>>;
+
+/// Gateway between a [`MessageRouter`] and an MQTT broker
+pub struct MqttGateway<R> {
+    client: Client,
+    inbound: SharedInbound,
+    router: MessageRouter<'static, R>,
+    shutdown: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<R> std::fmt::Debug for MqttGateway<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let inbound = self.inbound.read();
+        f.debug_struct("MqttGateway")
+            .field(
+                "subscriptions",
+                &inbound
+                    .subscriptions
+                    .iter()
+                    .map(|subscription| &subscription.filter)
+                    .collect::<Vec<_>>(),
+            )
+            .field("publishers", &inbound.publishers.len())
+            .finish()
+    }
+}
+
+impl<R> MqttGateway<R>
+where
+    R: Default + Send + 'static,
+{
+    /// Connect to the broker described by `options`, and spawn a thread driving the connection
+    /// and injecting received messages into `router`.
+    pub fn new(
+        options: MqttOptions,
+        router: &MessageRouter<'static, R>,
+    ) -> Result<Self, std::io::Error> {
+        let (client, connection) = Client::new(options, REQUEST_CAPACITY);
+        let mut gateway = Self::with_client(client, router);
+
+        let inbound = gateway.inbound.clone();
+        let shutdown = gateway.shutdown.clone();
+        let client = gateway.client.clone();
+        let router = router.clone();
+
+        gateway.thread = Some(
+            std::thread::Builder::new()
+                .name("salish-mqtt".into())
+                .spawn(move || Self::run(connection, client, inbound, router, shutdown))?,
+        );
+
+        Ok(gateway)
+    }
+
+    /// Create a gateway from an existing [`Client`], without spawning a connection thread.
+    ///
+    /// The caller is responsible for polling the connection of the client, and passing received
+    /// publishes to [`MqttGateway::inject()`].
+    pub fn with_client(client: Client, router: &MessageRouter<'static, R>) -> Self {
+        Self {
+            client,
+            inbound: Arc::new(ParkingLotRwLock::new(Inbound::default())),
+            router: router.clone(),
+            shutdown: Arc::new(AtomicBool::new(false)),
+            thread: None,
+        }
+    }
+
+    /// Get the MQTT [`Client`] of the gateway
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Publish messages of type `M` dispatched in the router to `topic`, until the returned [`Endpoint`] is dropped.
+    /// Payloads are converted to bytes with `encode`.
+    pub fn publish<M>(
+        &self,
+        topic: impl Into<String>,
+        qos: QoS,
+        encode: impl Fn(&M) -> Vec<u8> + Send + Sync + 'static,
+    ) -> Endpoint<'static, M, R>
+    where
+        M: Payload + 'static,
+    {
+        let topic = topic.into();
+        let client = self.client.clone();
+
+        let endpoint = self
+            .router
+            .create_endpoint::<M>()
+            .message(move |_src, msg| {
+                trace!("Publishing {} to {topic}", std::any::type_name::<M>());
+                if let Err(e) = client.try_publish(topic.as_str(), qos, false, encode(&msg)) {
+                    error!("Failed to publish to {topic}: {e}");
+                }
+                R::default()
+            });
+
+        self.inbound
+            .write()
+            .publishers
+            .insert(TypeId::of::<M>(), endpoint.addr());
+
+        endpoint
+    }
+
+    /// Subscribe to an MQTT topic filter, which may contain `+` and `#` wildcards.
+    ///
+    /// Messages published to matching topics are converted to `M` with `decode`, which receives the topic and payload,
+    /// and broadcast into the router. Publishes which `decode` returns `None` for are dropped.
+    pub fn subscribe<M>(
+        &self,
+        filter: impl Into<String>,
+        qos: QoS,
+        decode: impl Fn(&str, &[u8]) -> Option<M> + Send + Sync + 'static,
+    ) -> Result<(), ClientError>
+    where
+        M: BroadcastPayload + 'static,
+    {
+        let filter = filter.into();
+        self.client.try_subscribe(filter.as_str(), qos)?;
+
+        let decode =
+            move |topic: &str, payload: &[u8]| Some(Message::broadcast(decode(topic, payload)?));
+
+        self.inbound.write().subscriptions.push(Subscription {
+            filter,
+            qos,
+            type_id: TypeId::of::<M>(),
+            decode: Box::new(decode),
+        });
+
+        Ok(())
+    }
+
+    /// Dispatch a publish received from the broker into the router, decoded by every subscription with a
+    /// matching topic filter. Returns the number of messages dispatched.
+    pub fn inject(&self, publish: &Publish) -> usize {
+        Self::dispatch(&self.inbound, &self.router, publish)
+    }
+
+    fn dispatch(
+        inbound: &SharedInbound,
+        router: &MessageRouter<'static, R>,
+        publish: &Publish,
+    ) -> usize {
+        // Decode while holding the lock, but release it before dispatching so handlers can register types
+        let inbound = inbound.read();
+        let messages: Vec<Message> = inbound
+            .subscriptions
+            .iter()
+            .filter(|subscription| rumqttc::matches(&publish.topic, &subscription.filter))
+            .filter_map(|subscription| {
+                let message = (subscription.decode)(&publish.topic, &publish.payload);
+                if message.is_none() {
+                    warn!(
+                        "Dropping publish to {} which failed to decode for {}",
+                        publish.topic, subscription.filter
+                    );
+                }
+
+                // Avoid sending the message straight back out of the gateway
+                match inbound.publishers.get(&subscription.type_id) {
+                    Some(origin) => message.map(|message| message.with_origin(*origin)),
+                    None => message,
+                }
+            })
+            .collect();
+        drop(inbound);
+
+        let count = messages.len();
+        for message in messages {
+            trace!("Received {message:?} from {}", publish.topic);
+            router.handle_message(message);
+        }
+        count
+    }
+
+    /// Drive the connection until shutdown, dispatching received publishes
+    fn run(
+        mut connection: Connection,
+        client: Client,
+        inbound: SharedInbound,
+        router: MessageRouter<'static, R>,
+        shutdown: Arc<AtomicBool>,
+    ) {
+        while !shutdown.load(Ordering::Relaxed) {
+            match connection.recv_timeout(POLL_INTERVAL) {
+                Ok(Ok(Event::Incoming(Packet::Publish(publish)))) => {
+                    Self::dispatch(&inbound, &router, &publish);
+                }
+                Ok(Ok(Event::Incoming(Packet::ConnAck(ack)))) => {
+                    debug!("Connected to MQTT broker");
+
+                    // Subscriptions are lost when reconnecting without a persistent session
+                    if !ack.session_present {
+                        for subscription in inbound.read().subscriptions.iter() {
+                            if let Err(e) =
+                                client.try_subscribe(subscription.filter.as_str(), subscription.qos)
+                            {
+                                error!("Failed to subscribe to {}: {e}", subscription.filter);
+                            }
+                        }
+                    }
+                }
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => {
+                    warn!("MQTT connection error: {e}");
+                    if !shutdown.load(Ordering::Relaxed) {
+                        std::thread::sleep(RECONNECT_DELAY);
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+    }
+}
+
+impl<R> Drop for MqttGateway<R> {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::Relaxed);
+
+        if let Some(thread) = self.thread.take() {
+            let _ = self.client.try_disconnect();
+            let _ = thread.join();
+        }
+    }
+}
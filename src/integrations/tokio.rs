@@ -0,0 +1,107 @@
+//! Adapters between the [`MessageRouter`] and [`tokio::sync`] channels
+//!
+//! Messages of a payload type can be forwarded from the router into a [`broadcast`] or [`watch`] channel,
+//! and messages received from a channel can be injected into the router from a spawned task.
+//! An [`AsyncMessageHandler`] can be registered as an endpoint, which passes messages to a spawned task awaiting
+//! the handler. Handlers can run on other executors with
+//! [`MessageRouter::spawn_handler_on()`](crate::router::MessageRouter::spawn_handler_on).
+//!
+//! Bridging the same channel in both directions will echo messages back into the router indefinitely.
+
+use tokio::{
+    sync::{broadcast, watch},
+    task::JoinHandle,
+};
+use tracing::{debug, warn};
+
+use crate::{
+    endpoint::Endpoint, handler::AsyncMessageHandler, router::MessageRouter, spawn::TokioSpawner,
+    traits::Payload, Message,
+};
+
+impl<R> MessageRouter<'static, R>
+where
+    R: Default + Send + 'static,
+{
+    /// Forward messages of type `M` received by the router into a [`broadcast::Sender`].
+    /// Forwarding stops when the returned [`Endpoint`] is dropped.
+    pub fn bridge_broadcast<M>(&self, tx: broadcast::Sender<M>) -> Endpoint<'static, M, R>
+    where
+        M: Payload + 'static,
+    {
+        self.create_endpoint::<M>().message(move |_src, msg| {
+            // Sending only fails when there are no active receivers
+            if tx.send(msg).is_err() {
+                debug!("No broadcast receivers for {}", std::any::type_name::<M>());
+            }
+            R::default()
+        })
+    }
+
+    /// Forward messages of type `M` received by the router into a [`watch::Sender`].
+    /// Forwarding stops when the returned [`Endpoint`] is dropped.
+    pub fn bridge_watch<M>(&self, tx: watch::Sender<M>) -> Endpoint<'static, M, R>
+    where
+        M: Payload + 'static,
+    {
+        self.create_endpoint::<M>().message(move |_src, msg| {
+            tx.send_replace(msg);
+            R::default()
+        })
+    }
+
+    /// Spawn a task which broadcasts every message received from a [`broadcast::Receiver`] into the router.
+    /// The task ends when the channel is closed.
+    pub fn inject_broadcast<M>(&self, mut rx: broadcast::Receiver<M>) -> JoinHandle<()>
+    where
+        M: Payload + Clone + 'static,
+    {
+        let router = self.clone();
+
+        tokio::spawn(async move {
+            loop {
+                match rx.recv().await {
+                    Ok(msg) => {
+                        router.handle_message(Message::broadcast(msg));
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!(
+                            "Broadcast injector for {} lagged, skipped {skipped} messages",
+                            std::any::type_name::<M>()
+                        );
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+
+    /// Spawn a task which broadcasts the value of a [`watch::Receiver`] into the router each time it changes.
+    /// The task ends when the [`watch::Sender`] is dropped.
+    pub fn inject_watch<M>(&self, mut rx: watch::Receiver<M>) -> JoinHandle<()>
+    where
+        M: Payload + Clone + 'static,
+    {
+        let router = self.clone();
+
+        tokio::spawn(async move {
+            while rx.changed().await.is_ok() {
+                let msg = rx.borrow_and_update().clone();
+                router.handle_message(Message::broadcast(msg));
+            }
+        })
+    }
+
+    /// Register an endpoint which passes messages to an [`AsyncMessageHandler`] awaited by a task spawned on the
+    /// tokio runtime of the calling thread, as with [`spawn_handler_on()`](Self::spawn_handler_on).
+    /// The endpoint returns `R::default()` without waiting for the handler, and messages are handled in the order
+    /// they were received. The task ends when the returned [`Endpoint`] is dropped, once the messages it
+    /// received are handled.
+    pub fn spawn_handler<H>(&self, handler: H) -> Endpoint<'static, H::Message, R>
+    where
+        H: AsyncMessageHandler + 'static,
+        H::Message: 'static,
+    {
+        self.spawn_handler_on(&TokioSpawner, handler)
+    }
+}
@@ -0,0 +1,87 @@
+//! Cache of the most recent payload of opted-in payload types
+//!
+//! Payload types registered with [`MessageRouter::cache_last()`](crate::router::MessageRouter::cache_last) keep a
+//! clone of the payload of the last message the router dispatched, whether unicast or broadcast. Code without an
+//! endpoint, such as a UI refresh or a metrics exporter, can then poll the current value with
+//! [`MessageRouter::last()`](crate::router::MessageRouter::last).
+
+use std::{any::TypeId, collections::HashMap};
+
+use anylock::{AnyLock, ParkingLotRwLock};
+
+use crate::{
+    traits::{internal::SalishMessageInternal as _, BroadcastPayload},
+    Message,
+};
+
+/// Clones the payload of a message of a cached type
+type Extract = fn(&Message) -> Option<Box<dyn BroadcastPayload>>;
+
+struct LastValue {
+    extract: Extract,
+    value: Option<Box<dyn BroadcastPayload>>,
+}
+
+/// Last payloads by payload type, shared by all clones of a router
+pub(crate) struct LastValues {
+    values: ParkingLotRwLock<HashMap<TypeId, LastValue>>,
+}
+
+impl std::fmt::Debug for LastValues {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LastValues")
+            .field("types", &self.values.read().len())
+            .finish()
+    }
+}
+
+impl Default for LastValues {
+    fn default() -> Self {
+        Self {
+            values: ParkingLotRwLock::new(HashMap::new()),
+        }
+    }
+}
+
+impl LastValues {
+    /// Cache the last payload of type `M`
+    pub(crate) fn cache<M: BroadcastPayload + 'static>(&self) {
+        self.values
+            .write()
+            .entry(TypeId::of::<M>())
+            .or_insert(LastValue {
+                extract: |message| message.inner::<M>().map(M::clone_payload),
+                value: None,
+            });
+    }
+
+    /// Stop caching payloads of a type
+    pub(crate) fn remove(&self, type_id: TypeId) {
+        self.values.write().remove(&type_id);
+    }
+
+    /// Keep a clone of the payload of a message, if its payload type is cached
+    pub(crate) fn update(&self, message: &Message) {
+        let type_id = message.payload_type();
+        let Some(extract) = self.values.read().get(&type_id).map(|last| last.extract) else {
+            return;
+        };
+
+        let value = extract(message);
+        if let Some(last) = self.values.write().get_mut(&type_id) {
+            last.value = value;
+        }
+    }
+
+    /// Get a clone of the last payload of type `M`
+    pub(crate) fn get<M: Clone + 'static>(&self) -> Option<M> {
+        self.values
+            .read()
+            .get(&TypeId::of::<M>())?
+            .value
+            .as_ref()?
+            .as_any()
+            .downcast_ref::<M>()
+            .cloned()
+    }
+}
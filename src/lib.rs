@@ -1,15 +1,62 @@
 //! Salish Application Messaging
 
+#[cfg(feature = "bridge")]
+pub mod bridge;
+#[cfg(feature = "bridge")]
+pub mod cluster;
+pub mod cancel;
+pub mod causality;
+pub mod config;
+pub mod context;
+pub mod dispatch;
 pub mod endpoint;
+pub mod erased;
+pub mod error_channel;
 pub mod filter;
 pub mod handler;
+#[cfg(feature = "inspect-http")]
+pub mod inspect;
+pub mod integrations;
+mod last_value;
+pub mod local;
 pub mod message;
+pub mod metrics;
+pub mod middleware;
+#[cfg(feature = "otel")]
+pub mod otel;
+mod pending;
 pub mod policy;
+pub mod queue;
+#[cfg(feature = "registration")]
+pub mod registration;
+mod remote;
+mod retained;
+pub mod ring;
 pub mod router;
+#[cfg(feature = "routes")]
+pub mod routes;
+pub mod spawn;
+pub mod static_router;
+pub mod tagged;
+pub mod template;
 pub mod traits;
+pub mod transaction;
+pub mod validate;
+pub mod view;
+mod worker;
+pub mod workflow;
 
+pub use config::{RouterConfig, Unroutable};
+pub use dispatch::{DispatchResult, Results};
 pub use message::Message;
 pub use traits::EndpointAddress;
 
+#[cfg(feature = "registration")]
+pub use salish_macros::handler;
+
+// Allow the handler attribute to refer to this crate as `salish` in its own tests
+#[cfg(feature = "registration")]
+extern crate self as salish;
+
 #[cfg(test)]
 mod test;
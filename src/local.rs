@@ -0,0 +1,154 @@
+//! Handlers which aren't [`Send`], drained on the thread which owns them
+//!
+//! Endpoints of a [`MessageRouter`] must be [`Send`] and [`Sync`], as messages can be dispatched from any thread.
+//! Handlers holding state which can't leave its thread, such as `Rc` or GUI handles, are registered with a
+//! [`LocalRouter`] instead. The local router registers a [`Send`] endpoint with the shared router for each handler,
+//! which passes the messages it receives over a channel. The owner thread then calls the handlers by draining the
+//! local router, for example once per frame of its event loop.
+
+use std::{
+    any::Any,
+    collections::HashMap,
+    sync::mpsc::{self, Receiver, Sender},
+};
+
+use tracing::{debug, trace};
+
+use crate::{
+    endpoint::{Endpoint, EndpointId},
+    message::SourceRef,
+    router::MessageRouter,
+    traits::{EndpointAddress as _, Payload},
+};
+
+/// Message received by the endpoint of a local handler, waiting to be drained
+struct Delivery {
+    id: EndpointId,
+    source: Option<SourceRef>,
+    payload: Box<dyn Any + Send>,
+}
+
+/// Callback of a local handler, downcasting the payload to the type of the handler
+type LocalCallback<R> = Box<dyn FnMut(Option<SourceRef>, Box<dyn Any + Send>) -> R>;
+
+/// Handler registered with a [`LocalRouter`], with the endpoint passing messages to it
+struct LocalHandler<R> {
+    /// Endpoint registered with the shared router, which is deregistered when the handler is removed
+    _endpoint: Box<dyn Any>,
+    callback: LocalCallback<R>,
+}
+
+/// Router of handlers which aren't [`Send`], receiving the messages of a shared [`MessageRouter`].
+///
+/// The endpoints of the handlers return `R::default()` to the dispatcher without waiting, and the handlers are
+/// called on the thread calling [`drain()`](Self::drain), in the order their messages were dispatched.
+pub struct LocalRouter<R> {
+    router: MessageRouter<'static, R>,
+    tx: Sender<Delivery>,
+    rx: Receiver<Delivery>,
+    handlers: HashMap<EndpointId, LocalHandler<R>>,
+}
+
+impl<R> std::fmt::Debug for LocalRouter<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LocalRouter")
+            .field("handlers", &self.handlers.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl<R> LocalRouter<R>
+where
+    R: Default + Send + 'static,
+{
+    /// Create a local router receiving messages from `router`
+    pub fn new(router: &MessageRouter<'static, R>) -> Self {
+        let (tx, rx) = mpsc::channel();
+
+        Self {
+            router: router.clone(),
+            tx,
+            rx,
+            handlers: HashMap::new(),
+        }
+    }
+
+    /// Get the shared [`MessageRouter`] the handlers receive messages from
+    pub fn router(&self) -> &MessageRouter<'static, R> {
+        &self.router
+    }
+
+    /// Register a handler of payload type `M`, returning the address of its endpoint in the shared router.
+    /// The handler receives messages until it's removed with [`remove()`](Self::remove) or the local router is
+    /// dropped.
+    pub fn message<M, F>(&mut self, mut f: F) -> EndpointId
+    where
+        M: Payload + 'static,
+        F: FnMut(Option<SourceRef>, M) -> R + 'static,
+    {
+        let endpoint: Endpoint<'static, M, R> = self.router.create_endpoint::<M>();
+        let id = endpoint.addr();
+
+        let tx = self.tx.clone();
+        let endpoint = endpoint.message(move |source, msg| {
+            // Sending only fails once the local router is dropped, which deregisters the endpoint
+            let _ = tx.send(Delivery {
+                id,
+                source,
+                payload: Box::new(msg),
+            });
+            R::default()
+        });
+
+        let callback = move |source, payload: Box<dyn Any + Send>| {
+            let payload = payload
+                .downcast::<M>()
+                .expect("Local handler received a payload of another type");
+            f(source, *payload)
+        };
+
+        self.handlers.insert(
+            id,
+            LocalHandler {
+                _endpoint: Box::new(endpoint),
+                callback: Box::new(callback),
+            },
+        );
+
+        debug!(
+            "Registered local handler {id} for {}",
+            std::any::type_name::<M>()
+        );
+
+        id
+    }
+
+    /// Remove the handler registered at `id`, deregistering its endpoint. Messages it received which were not
+    /// drained yet are dropped.
+    pub fn remove(&mut self, id: EndpointId) -> bool {
+        self.handlers.remove(&id).is_some()
+    }
+
+    /// Number of handlers registered with the local router
+    pub fn num_handlers(&self) -> usize {
+        self.handlers.len()
+    }
+
+    /// Call the handlers with the messages they received since the last drain, returning their results.
+    /// Messages received during the drain are left for the next drain.
+    pub fn drain(&mut self) -> Vec<R> {
+        let deliveries: Vec<Delivery> = self.rx.try_iter().collect();
+        let mut results = Vec::with_capacity(deliveries.len());
+
+        for delivery in deliveries {
+            let Some(handler) = self.handlers.get_mut(&delivery.id) else {
+                trace!("Dropped message for removed local handler {}", delivery.id);
+                continue;
+            };
+
+            results.push((handler.callback)(delivery.source, delivery.payload));
+        }
+
+        results
+    }
+}
@@ -2,13 +2,19 @@
 
 use std::{
     any::{Any, TypeId},
-    hash::{DefaultHasher, Hasher},
-    marker::PhantomData,
-    sync::Arc,
+    collections::BTreeMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 
 use crate::{
+    endpoint::EndpointId,
     policy::Policy,
+    tagged::Tagged,
     traits::{
         internal::SalishMessageInternal as _, BroadcastPayload, EndpointAddress, MessagePayload,
         Payload, SalishMessage, UnicastPayload,
@@ -23,6 +29,28 @@ pub trait MessageSource: Any + std::fmt::Debug + Send + Sync + 'static {
     fn hash(&self, state: &mut DefaultHasher);
 }
 
+/// Source of a message passed to handlers, which can be downcast to its concrete type.
+/// Sources of any type implementing [`MessageSource`] can be passed through the same router.
+#[derive(Debug, Clone)]
+pub struct SourceRef(DynMessageSource);
+
+impl SourceRef {
+    /// Get a reference to the source, if it is of type `T`
+    pub fn downcast_ref<T: 'static>(&self) -> Option<&T> {
+        (*self.0).as_any().downcast_ref::<T>()
+    }
+
+    /// Get a copy of the source, if it is of type `T`
+    pub fn get<T: Copy + 'static>(&self) -> Option<T> {
+        self.downcast_ref::<T>().copied()
+    }
+
+    /// Check if the source is of type `T`
+    pub fn is<T: 'static>(&self) -> bool {
+        (*self.0).as_any().is::<T>()
+    }
+}
+
 /// Blanket implementation of MessageSource on supported trait bounds
 impl<T> MessageSource for T
 where
@@ -46,25 +74,70 @@ pub struct Message {
     source: Option<Arc<dyn MessageSource>>,
     dest: Destination<<<Self as SalishMessage>::Endpoint as EndpointAddress>::Addr>,
     payload: MessagePayload,
+    origin: Option<EndpointId>,
     is_clone: bool,
+    headers: BTreeMap<String, String>,
+    priority: Priority,
+    /// Time after which the message is dropped instead of dispatched
+    expires: Option<Instant>,
+    /// OpenTelemetry context the message was created in
+    #[cfg(feature = "otel")]
+    trace_context: Option<opentelemetry::Context>,
+    id: MessageId,
+    /// Message whose handler created this message
+    caused_by: Option<MessageId>,
 }
 
-impl Clone for Message {
-    fn clone(&self) -> Self {
-        match &self.payload {
-            MessagePayload::Unicast(_) => {
-                panic!("Cannot clone messages with Unicast payload");
-            }
-            MessagePayload::Broadcast(_) => Message {
-                source: self.source.clone(),
-                dest: self.dest,
-                payload: self.payload.clone(),
-                is_clone: true,
-            },
-        }
+/// Identifier of a [`Message`], unique within the process. Clones of a broadcast share the identifier of the
+/// message they were cloned from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MessageId(u64);
+
+impl MessageId {
+    /// Get the next identifier
+    fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(1);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Get the raw identifier
+    pub fn id(&self) -> u64 {
+        self.0
     }
 }
 
+impl std::fmt::Display for MessageId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// Priority of a [`Message`]. Messages queued with a [`RouterSender`](crate::queue::RouterSender) are dispatched
+/// in order of priority, and in the order they were queued within a priority.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+    Critical,
+}
+
+/// Error cloning a [`Message`] with a unicast payload, which can only be delivered once
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CloneError {
+    /// Rust type name of the payload
+    pub type_name: &'static str,
+}
+
+impl std::fmt::Display for CloneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "cannot clone unicast payload {}", self.type_name)
+    }
+}
+
+impl std::error::Error for CloneError {}
+
 impl std::fmt::Debug for Message {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut debug = &mut f.debug_struct("Message");
@@ -74,15 +147,41 @@ impl std::fmt::Debug for Message {
             .field("payload_id", &self.payload_type())
             .field("payload", &self.payload);
 
+        if let Some(origin) = &self.origin {
+            debug = debug.field("origin", origin)
+        }
+
         if self.is_clone {
             debug = debug.field("cloned", &self.is_clone)
         }
 
+        if !self.headers.is_empty() {
+            debug = debug.field("headers", &self.headers)
+        }
+
+        if self.priority != Priority::Normal {
+            debug = debug.field("priority", &self.priority)
+        }
+
+        if let Some(expires) = &self.expires {
+            debug = debug.field("expires", expires)
+        }
+
+        debug = debug.field("id", &self.id);
+        if let Some(caused_by) = &self.caused_by {
+            debug = debug.field("caused_by", caused_by)
+        }
+
         debug.finish()
     }
 }
 
 impl Message {
+    /// Create a [`MessageBuilder`], to set the payload and properties of a message in one place
+    pub fn builder() -> MessageBuilder {
+        MessageBuilder::default()
+    }
+
     /// Create a new message with destination set to [`Destination::Any`].
     /// This will route the message to any registered receiver for this message type
     pub fn unicast<P: UnicastPayload + 'static>(payload: P) -> Self {
@@ -97,6 +196,20 @@ impl Message {
         )
     }
 
+    /// Create a new message with destination set to [`Destination::Any`], and the payload wrapped in
+    /// [`Tagged`] with the marker type `Tag`, such as `Message::unicast_tagged::<Temperature>(21u64)`
+    pub fn unicast_tagged<Tag: 'static>(payload: impl UnicastPayload + 'static) -> Self {
+        Self::unicast(Tagged::<_, Tag>::new(payload))
+    }
+
+    /// Create a new message with destination set to [`Destination::Broadcast`], and the payload wrapped in
+    /// [`Tagged`] with the marker type `Tag`
+    pub fn broadcast_tagged<Tag: 'static>(
+        payload: impl BroadcastPayload + Clone + 'static,
+    ) -> Self {
+        Self::broadcast(Tagged::<_, Tag>::new(payload))
+    }
+
     /// Create a new message with destination specified by `dest`
     pub fn new_to(
         dest: Destination<<<Self as SalishMessage>::Endpoint as EndpointAddress>::Addr>,
@@ -106,7 +219,15 @@ impl Message {
             source: None,
             dest,
             payload,
+            origin: None,
             is_clone: false,
+            headers: BTreeMap::new(),
+            priority: Priority::default(),
+            expires: None,
+            #[cfg(feature = "otel")]
+            trace_context: crate::otel::current(),
+            id: MessageId::next(),
+            caused_by: crate::causality::current(),
         }
     }
 
@@ -125,11 +246,146 @@ impl Message {
         self
     }
 
+    /// Set the destination of this [`Message`] to an [`Address`]
+    pub fn to(self, address: impl Into<Address>) -> Self {
+        let address: Address = address.into();
+        self.with_dest(address.into())
+    }
+
+    /// Set the destination of this [`Message`] to each of `endpoints`
+    #[allow(clippy::wrong_self_convention)]
+    pub fn to_many(self, endpoints: impl IntoIterator<Item = EndpointId>) -> Self {
+        self.with_dest(Destination::multi(endpoints))
+    }
+
+    /// Set the [`EndpointId`] this [`Message`] originated from.
+    /// The origin endpoint will not receive the message when it is dispatched by payload type,
+    /// which allows bridges to inject messages without echoing them back to their forwarding endpoint.
+    pub fn with_origin(mut self, origin: EndpointId) -> Self {
+        self.origin = Some(origin);
+        self
+    }
+
+    /// Get the [`EndpointId`] this [`Message`] originated from
+    pub fn origin(&self) -> Option<EndpointId> {
+        self.origin
+    }
+
+    /// Set the header `name` of this [`Message`] to `value`, replacing any previous value
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.insert(name.into(), value.into());
+        self
+    }
+
+    /// Get the value of the header `name`
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+
+    /// Get the headers of this [`Message`], ordered by name
+    pub fn headers(&self) -> &BTreeMap<String, String> {
+        &self.headers
+    }
+
+    /// Set the [`Priority`] of this [`Message`]
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Get the [`Priority`] of this [`Message`]
+    pub fn priority(&self) -> Priority {
+        self.priority
+    }
+
+    /// Set the time to live of this [`Message`] from now. The router drops the message with
+    /// [`DropReason::Expired`](crate::middleware::DropReason::Expired) if it's dispatched after it expired,
+    /// such as when it waited too long in a queue.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.expires = Some(Instant::now() + ttl);
+        self
+    }
+
+    /// Get the time this [`Message`] expires, if it has a time to live
+    pub fn expires(&self) -> Option<Instant> {
+        self.expires
+    }
+
+    /// Check if the time to live of this [`Message`] elapsed
+    pub fn is_expired(&self) -> bool {
+        self.expires
+            .is_some_and(|expires| Instant::now() >= expires)
+    }
+
+    /// Set the OpenTelemetry context handlers of this [`Message`] continue the trace of.
+    /// Messages capture the context of the current span when they are created.
+    #[cfg(feature = "otel")]
+    pub fn with_trace_context(mut self, context: opentelemetry::Context) -> Self {
+        self.trace_context = Some(context);
+        self
+    }
+
+    /// Get the OpenTelemetry context this [`Message`] was created in
+    #[cfg(feature = "otel")]
+    pub fn trace_context(&self) -> Option<&opentelemetry::Context> {
+        self.trace_context.as_ref()
+    }
+
+    /// Get the [`MessageId`] of this [`Message`]
+    pub fn id(&self) -> MessageId {
+        self.id
+    }
+
+    /// Set the message which caused this [`Message`].
+    /// Messages record the message being handled on the current thread when they are created.
+    pub fn with_caused_by(mut self, caused_by: MessageId) -> Self {
+        self.caused_by = Some(caused_by);
+        self
+    }
+
+    /// Get the message whose handler created this [`Message`], if it was created in a handler
+    pub fn caused_by(&self) -> Option<MessageId> {
+        self.caused_by
+    }
+
     /// Check if the payload is of type T
     pub fn is_type<T: 'static>(&self) -> bool {
         TypeId::of::<T>() == self.payload_type()
     }
 
+    /// Check if the payload can be cloned, to deliver the message to several endpoints
+    pub fn is_cloneable(&self) -> bool {
+        matches!(self.payload, MessagePayload::Broadcast(_))
+    }
+
+    /// Clone a message with a broadcast payload. Unicast payloads can't be cloned, as they are delivered to a
+    /// single endpoint.
+    pub fn try_clone(&self) -> Result<Self, CloneError> {
+        let payload = self.payload.try_clone().ok_or(CloneError {
+            type_name: self.type_name(),
+        })?;
+
+        Ok(Message {
+            source: self.source.clone(),
+            dest: self.dest.clone(),
+            payload,
+            origin: self.origin,
+            is_clone: true,
+            headers: self.headers.clone(),
+            priority: self.priority,
+            expires: self.expires,
+            #[cfg(feature = "otel")]
+            trace_context: self.trace_context.clone(),
+            id: self.id,
+            caused_by: self.caused_by,
+        })
+    }
+
+    /// Get the Rust type name of the payload
+    pub fn type_name(&self) -> &'static str {
+        self.payload.type_name()
+    }
+
     /// Get the hash of the source via trait object
     pub fn source_hash(&self) -> Option<u64> {
         if let Some(source) = &self.source {
@@ -141,6 +397,18 @@ impl Message {
         }
     }
 
+    /// Get the type of the source, which tells apart sources of different types with equal hashes
+    pub(crate) fn source_type(&self) -> Option<TypeId> {
+        self.source
+            .as_ref()
+            .map(|source| (**source).as_any().type_id())
+    }
+
+    /// Get the source of this message, which can be downcast to its concrete type
+    pub fn source_ref(&self) -> Option<SourceRef> {
+        self.source.clone().map(SourceRef)
+    }
+
     /// Get the source of this message, downcast to the provided type
     pub fn source<T: Copy + 'static>(&self) -> Option<T> {
         if let Some(source) = &self.source {
@@ -158,14 +426,14 @@ impl Message {
     pub fn dest(
         &self,
     ) -> Destination<<<Self as SalishMessage>::Endpoint as EndpointAddress>::Addr> {
-        self.dest
+        self.dest.clone()
     }
 }
 
 impl SalishMessage for Message {
-    type Endpoint = u64;
+    type Endpoint = EndpointId;
 
-    fn payload<'b>(&'b self) -> &'b MessagePayload {
+    fn payload(&self) -> &MessagePayload {
         &self.payload
     }
 
@@ -174,15 +442,141 @@ impl SalishMessage for Message {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+/// Error building a [`Message`] with a [`MessageBuilder`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildError {
+    /// No payload was set
+    MissingPayload,
+}
+
+impl std::fmt::Display for BuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingPayload => write!(f, "message has no payload"),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+/// Builder of a [`Message`], created with [`Message::builder()`].
+///
+/// Messages are sent to [`Destination::Any`] by default, or [`Destination::Broadcast`] if the payload was set with
+/// [`broadcast()`](Self::broadcast).
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use salish::message::{Message, Priority};
+///
+/// let message = Message::builder()
+///     .broadcast(21u64)
+///     .header("unit", "celsius")
+///     .priority(Priority::High)
+///     .ttl(Duration::from_secs(5))
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(message.header("unit"), Some("celsius"));
+/// ```
+#[derive(Debug, Default)]
+pub struct MessageBuilder {
+    payload: Option<MessagePayload>,
+    dest: Option<Destination<EndpointId>>,
+    source: Option<DynMessageSource>,
+    headers: BTreeMap<String, String>,
+    priority: Priority,
+    ttl: Option<Duration>,
+}
+
+impl MessageBuilder {
+    /// Set a unicast payload, which is delivered to a single endpoint
+    pub fn unicast<P: UnicastPayload + 'static>(mut self, payload: P) -> Self {
+        self.payload = Some(payload.into_payload());
+        self
+    }
+
+    /// Set a broadcast payload, and broadcast the message unless a destination is set
+    pub fn broadcast<P: BroadcastPayload + 'static>(mut self, payload: P) -> Self {
+        self.payload = Some(payload.into_payload());
+        self.dest = self
+            .dest
+            .or(Some(Destination::Broadcast(Policy::default())));
+        self
+    }
+
+    /// Set a payload which was already wrapped in a [`MessagePayload`]
+    pub fn payload(mut self, payload: MessagePayload) -> Self {
+        self.payload = Some(payload);
+        self
+    }
+
+    /// Set the destination of the message
+    pub fn dest(mut self, dest: Destination<EndpointId>) -> Self {
+        self.dest = Some(dest);
+        self
+    }
+
+    /// Set the destination of the message to an [`Address`]
+    pub fn to(self, address: impl Into<Address>) -> Self {
+        let address: Address = address.into();
+        self.dest(address.into())
+    }
+
+    /// Set the source address of the message
+    pub fn source(mut self, source: impl MessageSource + Copy) -> Self {
+        self.source = Some(Arc::new(source));
+        self
+    }
+
+    /// Set the header `name` to `value`, replacing any previous value
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.insert(name.into(), value.into());
+        self
+    }
+
+    /// Set the [`Priority`] of the message
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Set the time to live of the message, counted from when it's built
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Build the [`Message`]. Payloads are checked by the validators of a router when the message is dispatched,
+    /// or with [`MessageRouter::validate()`](crate::router::MessageRouter::validate).
+    pub fn build(self) -> Result<Message, BuildError> {
+        let payload = self.payload.ok_or(BuildError::MissingPayload)?;
+        let mut message = Message::new_to(self.dest.unwrap_or_else(Destination::any), payload);
+
+        message.source = self.source;
+        message.headers = self.headers;
+        message.priority = self.priority;
+        message.expires = self.ttl.map(|ttl| Instant::now() + ttl);
+
+        Ok(message)
+    }
+}
+
+#[derive(Clone, Debug)]
 pub enum Destination<Addr> {
     /// Message destined to any endpoint listening to a message type.
     /// It will be delivered to a single endpoint only. Use broadcast
     /// to send clones to all endpoints listening to a message type.
+    ///
+    /// The [`Policy`] selects among the endpoints with a [`Filter`](crate::filter::Filter) matching the message,
+    /// or if there are none, among the endpoints without filters. Endpoints whose filters all reject the
+    /// message never receive it.
     Any(Policy),
 
     /// Broadcast clones of the message to all endpoints registered for
-    /// the payload [`TypeId`] of the message
+    /// the payload [`TypeId`] of the message, except endpoints whose filters reject it.
+    /// Endpoints can opt out of filtering broadcasts with
+    /// [`Endpoint::unfiltered_broadcasts()`](crate::endpoint::Endpoint::unfiltered_broadcasts)
     Broadcast(Policy),
 
     /// Publish a message to subscribers of a [`Topic`]
@@ -191,6 +585,27 @@ pub enum Destination<Addr> {
     /// Message destined to a specific endpoint
     //Endpoint(Arc<dyn EndpointAddress<Addr = Addr>>),
     Endpoint(Addr),
+
+    /// Message destined to a specific endpoint in the router of a remote node.
+    /// Remote nodes are resolved by bridges, and the message is dropped if no bridge can reach the node.
+    Remote(NodeId, Addr),
+
+    /// Message destined to the endpoint registered under a name with
+    /// [`Endpoint::named()`](crate::endpoint::Endpoint::named).
+    /// The name is resolved to an endpoint when the message is routed.
+    Named(EndpointName),
+
+    /// Message destined to the endpoints which joined a group with
+    /// [`Endpoint::join()`](crate::endpoint::Endpoint::join), and receive the payload type of the message.
+    /// Broadcasts are delivered to all of them, and unicast messages to the first member accepting the message.
+    Group(GroupName),
+
+    /// Message destined to each of a list of endpoints. Broadcasts are delivered as clones to each endpoint,
+    /// reporting the endpoints they couldn't be delivered to with [`DispatchResult::Partial`]. Unicast payloads
+    /// can't be cloned, so they are delivered to the first endpoint in the list which can receive them.
+    ///
+    /// [`DispatchResult::Partial`]: crate::dispatch::DispatchResult::Partial
+    Multi(Vec<Addr>),
 }
 
 impl<Addr: 'static> Destination<Addr> {
@@ -201,26 +616,254 @@ impl<Addr: 'static> Destination<Addr> {
     pub fn endpoint(addr: Addr) -> Self {
         Self::Endpoint(addr)
     }
+
+    pub fn remote(node: impl Into<NodeId>, addr: Addr) -> Self {
+        Self::Remote(node.into(), addr)
+    }
+
+    pub fn named(name: impl Into<EndpointName>) -> Self {
+        Self::Named(name.into())
+    }
+
+    /// Destination of the endpoint which claimed `key` with
+    /// [`Endpoint::claim_hash()`](crate::endpoint::Endpoint::claim_hash)
+    pub fn hashed<K: std::fmt::Debug + Hash + ?Sized>(key: &K) -> Self {
+        Self::Named(HashEndpoint::new(key).addr())
+    }
+
+    pub fn group(group: impl Into<GroupName>) -> Self {
+        Self::Group(group.into())
+    }
+
+    pub fn multi(addrs: impl IntoIterator<Item = Addr>) -> Self {
+        Self::Multi(addrs.into_iter().collect())
+    }
+}
+
+/// Address of an endpoint or group of endpoints, in each of the ways they can be addressed.
+///
+/// Addresses convert into the [`Destination`] of a message, and can be built from an [`EndpointId`],
+/// an [`EndpointName`] or a [`GroupName`].
+///
+/// ```
+/// use salish::message::{Address, Destination, EndpointName};
+///
+/// let address = Address::from(EndpointName::new("temperature-logger"));
+/// assert!(matches!(Destination::from(address), Destination::Named(_)));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Address {
+    /// Endpoint of the local router
+    Local(EndpointId),
+
+    /// Endpoint registered under a name in the local router
+    Named(EndpointName),
+
+    /// Endpoint in the router of a remote node
+    Remote(NodeId, EndpointId),
+
+    /// Endpoints which joined a group in the local router
+    Group(GroupName),
 }
 
+impl EndpointAddress for Address {
+    type Addr = Address;
+
+    fn addr(&self) -> Self::Addr {
+        *self
+    }
+}
+
+impl From<EndpointId> for Address {
+    fn from(id: EndpointId) -> Self {
+        Self::Local(id)
+    }
+}
+
+impl From<EndpointName> for Address {
+    fn from(name: EndpointName) -> Self {
+        Self::Named(name)
+    }
+}
+
+impl From<GroupName> for Address {
+    fn from(group: GroupName) -> Self {
+        Self::Group(group)
+    }
+}
+
+impl From<Address> for Destination<EndpointId> {
+    fn from(address: Address) -> Self {
+        match address {
+            Address::Local(id) => Self::Endpoint(id),
+            Address::Named(name) => Self::Named(name),
+            Address::Remote(node, id) => Self::Remote(node, id),
+            Address::Group(group) => Self::Group(group),
+        }
+    }
+}
+
+impl Destination<EndpointId> {
+    /// Get the address of the destination, if it's addressed to an endpoint or group rather than by payload type
+    pub fn address(&self) -> Option<Address> {
+        match *self {
+            Self::Any(_) | Self::Broadcast(_) | Self::Multi(_) => None,
+            Self::Endpoint(id) => Some(Address::Local(id)),
+            Self::Remote(node, id) => Some(Address::Remote(node, id)),
+            Self::Named(name) => Some(Address::Named(name)),
+            Self::Group(group) => Some(Address::Group(group)),
+        }
+    }
+}
+
+/// FNV-1a [`Hasher`], so names hashed from keys are stable across processes and builds
+struct Fnv1aHasher(u64);
+
+impl Default for Fnv1aHasher {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+
+impl Hasher for Fnv1aHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+/// 64 bit FNV-1a hash of a string, stable across processes and builds
+const fn fnv1a(s: &str) -> u64 {
+    let bytes = s.as_bytes();
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        hash ^= bytes[i] as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+        i += 1;
+    }
+
+    hash
+}
+
+/// Identifier of a remote node, derived from the identity the node was given by its bridges.
+///
+/// Identifiers are a 64 bit FNV-1a hash of the identity, so they are stable across processes and builds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId(u64);
+
+impl NodeId {
+    /// Get the identifier of the node with identity `node`
+    pub const fn new(node: &str) -> Self {
+        Self(fnv1a(node))
+    }
+
+    /// Get the raw identifier
+    pub fn id(&self) -> u64 {
+        self.0
+    }
+}
+
+impl From<&str> for NodeId {
+    fn from(node: &str) -> Self {
+        Self::new(node)
+    }
+}
+
+/// Name of an endpoint, given with [`Endpoint::named()`](crate::endpoint::Endpoint::named).
+///
+/// Names are a 64 bit FNV-1a hash of the name, so they are cheap to compare and can be referenced from
+/// configuration before the endpoint registers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct EndpointName(u64);
+
+impl EndpointName {
+    /// Get the endpoint name of `name`
+    pub const fn new(name: &str) -> Self {
+        Self(fnv1a(name))
+    }
+
+    /// Get the endpoint name hashed from a key.
+    /// This differs from the name of the key formatted as a string.
+    pub fn hashed<K: Hash + ?Sized>(key: &K) -> Self {
+        let mut hasher = Fnv1aHasher::default();
+        key.hash(&mut hasher);
+        Self(hasher.finish())
+    }
+
+    /// Get the raw hash of the name
+    pub fn id(&self) -> u64 {
+        self.0
+    }
+}
+
+impl From<&str> for EndpointName {
+    fn from(name: &str) -> Self {
+        Self::new(name)
+    }
+}
+
+/// Name of a group of endpoints, joined with [`Endpoint::join()`](crate::endpoint::Endpoint::join).
+///
+/// Like [`EndpointName`], group names are a 64 bit FNV-1a hash of the name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct GroupName(u64);
+
+impl GroupName {
+    /// Get the group name of `name`
+    pub const fn new(name: &str) -> Self {
+        Self(fnv1a(name))
+    }
+
+    /// Get the raw hash of the name
+    pub fn id(&self) -> u64 {
+        self.0
+    }
+}
+
+impl From<&str> for GroupName {
+    fn from(name: &str) -> Self {
+        Self::new(name)
+    }
+}
+
+/// Address of the endpoint which claimed a hashable key with
+/// [`Endpoint::claim_hash()`](crate::endpoint::Endpoint::claim_hash), such as a sensor ID.
+///
+/// The address is the [`EndpointName`] hashed from the key, so senders can address the endpoint responsible for a
+/// key without knowing its [`EndpointId`].
 #[derive(Debug, Clone)]
-struct HashEndpoint<'a, T>
+pub struct HashEndpoint<'a, T>
 where
-    T: std::fmt::Debug + std::hash::Hash + Send + Sync,
+    T: std::fmt::Debug + std::hash::Hash + ?Sized,
 {
-    h: &'a T,
-    _phantom: PhantomData<T>,
+    key: &'a T,
 }
 
-impl<'a, T: std::hash::Hash> EndpointAddress for HashEndpoint<'a, T>
+impl<'a, T> HashEndpoint<'a, T>
 where
-    T: std::fmt::Debug + std::hash::Hash + Send + Sync,
+    T: std::fmt::Debug + std::hash::Hash + ?Sized,
 {
-    type Addr = u64;
+    pub fn new(key: &'a T) -> Self {
+        Self { key }
+    }
+}
+
+impl<'a, T> EndpointAddress for HashEndpoint<'a, T>
+where
+    T: std::fmt::Debug + std::hash::Hash + ?Sized,
+{
+    type Addr = EndpointName;
+
     fn addr(&self) -> Self::Addr {
-        let mut hasher = DefaultHasher::new();
-        self.h.hash(&mut hasher);
-        hasher.finish()
+        EndpointName::hashed(self.key)
     }
 }
 
@@ -232,6 +875,14 @@ impl EndpointAddress for u64 {
     }
 }
 
+impl EndpointAddress for u128 {
+    type Addr = u128;
+
+    fn addr(&self) -> Self::Addr {
+        *self
+    }
+}
+
 /// Convert a Result with a [`Payload`] type into a Message
 impl<P, E> From<Result<P, E>> for Message
 where
@@ -0,0 +1,411 @@
+//! Router metrics
+//!
+//! Counters are tracked per payload [`TypeId`] and labelled with the Rust type name of the payload. Dropped
+//! messages are also counted by their [`DropReason`].
+//! With the `prometheus` feature enabled, the counters can be exported in the Prometheus text format.
+//!
+//! Each endpoint also tracks [`EndpointStats`], which are available from
+//! [`MessageRouter::endpoint_stats()`](crate::router::MessageRouter::endpoint_stats).
+
+use std::{
+    any::TypeId,
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use anylock::{AnyLock, ParkingLotMutex, ParkingLotRwLock};
+
+use crate::middleware::DropReason;
+
+/// Live counters for a single payload type
+#[derive(Debug)]
+struct TypeCounters {
+    type_name: &'static str,
+    dispatched: AtomicU64,
+    delivered: AtomicU64,
+    dropped: AtomicU64,
+    shed: AtomicU64,
+
+    /// Dropped messages by [`DropReason::label()`]
+    drop_reasons: ParkingLotMutex<BTreeMap<&'static str, u64>>,
+}
+
+impl TypeCounters {
+    fn new(type_name: &'static str) -> Self {
+        Self {
+            type_name,
+            dispatched: AtomicU64::new(0),
+            delivered: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            shed: AtomicU64::new(0),
+            drop_reasons: ParkingLotMutex::new(BTreeMap::new()),
+        }
+    }
+
+    fn drop_reason(&self, reason: &DropReason) {
+        *self.drop_reasons.write().entry(reason.label()).or_default() += 1;
+    }
+}
+
+/// Point in time copy of the counters for a payload type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeMetrics {
+    /// Rust type name of the payload
+    pub type_name: &'static str,
+
+    /// Number of messages of this type passed to the router
+    pub dispatched: u64,
+
+    /// Number of handler results returned for messages of this type
+    pub delivered: u64,
+
+    /// Number of messages of this type which produced no results
+    pub dropped: u64,
+
+    /// Number of messages of this type dropped by load shedding, which are not counted as dispatched
+    pub shed: u64,
+
+    /// Number of messages of this type dropped for each reason, by [`DropReason::label()`]
+    pub drop_reasons: BTreeMap<&'static str, u64>,
+}
+
+/// Live counters of a single endpoint, shared by the handles of the endpoint
+#[derive(Debug)]
+pub(crate) struct EndpointCounters {
+    created: Instant,
+    received: AtomicU64,
+    filtered: AtomicU64,
+    errors: AtomicU64,
+
+    /// Nanoseconds from `created` to the last received message, plus one, or zero if none was received
+    last_activity: AtomicU64,
+
+    /// Total nanoseconds spent in the handler
+    handler_nanos: AtomicU64,
+}
+
+impl Default for EndpointCounters {
+    fn default() -> Self {
+        Self {
+            created: Instant::now(),
+            received: AtomicU64::new(0),
+            filtered: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            last_activity: AtomicU64::new(0),
+            handler_nanos: AtomicU64::new(0),
+        }
+    }
+}
+
+impl EndpointCounters {
+    /// Record a message received by the handler at `start`, which returned after `duration`
+    pub(crate) fn received(&self, start: Instant, duration: Duration) {
+        let since = start.duration_since(self.created).as_nanos() as u64;
+
+        self.received.fetch_add(1, Ordering::Relaxed);
+        self.last_activity.fetch_max(since + 1, Ordering::Relaxed);
+        self.handler_nanos
+            .fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// Record a message rejected by the filters of the endpoint
+    pub(crate) fn filtered(&self) {
+        self.filtered.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record a message which couldn't be passed to the handler
+    pub(crate) fn error(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> EndpointStats {
+        let received = self.received.load(Ordering::Relaxed);
+        let handler_nanos = self.handler_nanos.load(Ordering::Relaxed);
+
+        EndpointStats {
+            received,
+            filtered: self.filtered.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            last_activity: match self.last_activity.load(Ordering::Relaxed) {
+                0 => None,
+                since => Some(self.created + Duration::from_nanos(since - 1)),
+            },
+            mean_duration: Duration::from_nanos(handler_nanos.checked_div(received).unwrap_or(0)),
+        }
+    }
+}
+
+/// Point in time copy of the counters of an endpoint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EndpointStats {
+    /// Number of messages passed to the handler
+    pub received: u64,
+
+    /// Number of messages rejected by the filters of the endpoint
+    pub filtered: u64,
+
+    /// Number of messages addressed to the endpoint which couldn't be passed to the handler,
+    /// such as messages of another payload type
+    pub errors: u64,
+
+    /// Time the last message was passed to the handler
+    pub last_activity: Option<Instant>,
+
+    /// Mean time spent in the handler
+    pub mean_duration: Duration,
+}
+
+/// Message counters of a [`MessageRouter`](crate::router::MessageRouter), shared by all clones of the router
+#[derive(Debug)]
+pub struct RouterMetrics {
+    types: ParkingLotRwLock<HashMap<TypeId, Arc<TypeCounters>>>,
+}
+
+impl Default for RouterMetrics {
+    fn default() -> Self {
+        Self {
+            types: ParkingLotRwLock::new(HashMap::new()),
+        }
+    }
+}
+
+impl RouterMetrics {
+    /// Get the counters for a payload type, creating them on first use
+    fn counters(&self, type_id: TypeId, type_name: &'static str) -> Arc<TypeCounters> {
+        if let Some(counters) = self.types.read().get(&type_id) {
+            return counters.clone();
+        }
+
+        self.types
+            .write()
+            .entry(type_id)
+            .or_insert_with(|| Arc::new(TypeCounters::new(type_name)))
+            .clone()
+    }
+
+    /// Record the outcome of dispatching a message.
+    /// `delivered` is the number of results returned by handlers, or `None` if the message was dropped.
+    pub(crate) fn record(
+        &self,
+        type_id: TypeId,
+        type_name: &'static str,
+        delivered: Option<usize>,
+    ) {
+        let counters = self.counters(type_id, type_name);
+        counters.dispatched.fetch_add(1, Ordering::Relaxed);
+
+        match delivered {
+            Some(count) => counters
+                .delivered
+                .fetch_add(count as u64, Ordering::Relaxed),
+            None => counters.dropped.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+
+    /// Record the reason a message was dropped. The message itself is counted as dropped by
+    /// [`record()`](Self::record) once it was dispatched.
+    pub(crate) fn drop_reason(
+        &self,
+        type_id: TypeId,
+        type_name: &'static str,
+        reason: &DropReason,
+    ) {
+        self.counters(type_id, type_name).drop_reason(reason);
+    }
+
+    /// Record a message dropped by load shedding before it was dispatched
+    pub(crate) fn shed(&self, type_id: TypeId, type_name: &'static str) {
+        let counters = self.counters(type_id, type_name);
+        counters.shed.fetch_add(1, Ordering::Relaxed);
+        counters.drop_reason(&DropReason::Shed);
+    }
+
+    /// Get the metrics for a payload type
+    pub fn get<T: 'static>(&self) -> Option<TypeMetrics> {
+        self.types
+            .read()
+            .get(&TypeId::of::<T>())
+            .map(|counters| Self::snapshot_counters(counters))
+    }
+
+    /// Get the metrics of all payload types seen by the router, sorted by type name
+    pub fn snapshot(&self) -> Vec<TypeMetrics> {
+        let mut metrics: Vec<_> = self
+            .types
+            .read()
+            .values()
+            .map(|counters| Self::snapshot_counters(counters))
+            .collect();
+
+        metrics.sort_by(|a, b| a.type_name.cmp(b.type_name));
+        metrics
+    }
+
+    /// Get the number of messages of all payload types dropped for each reason, by [`DropReason::label()`]
+    pub fn drop_reasons(&self) -> BTreeMap<&'static str, u64> {
+        let mut reasons = BTreeMap::new();
+        for counters in self.types.read().values() {
+            for (label, count) in counters.drop_reasons.read().iter() {
+                *reasons.entry(*label).or_default() += count;
+            }
+        }
+        reasons
+    }
+
+    fn snapshot_counters(counters: &TypeCounters) -> TypeMetrics {
+        TypeMetrics {
+            type_name: counters.type_name,
+            dispatched: counters.dispatched.load(Ordering::Relaxed),
+            delivered: counters.delivered.load(Ordering::Relaxed),
+            dropped: counters.dropped.load(Ordering::Relaxed),
+            shed: counters.shed.load(Ordering::Relaxed),
+            drop_reasons: counters.drop_reasons.read().clone(),
+        }
+    }
+}
+
+#[cfg(feature = "prometheus")]
+pub use exporter::RouterCollector;
+
+#[cfg(feature = "prometheus")]
+mod exporter {
+    use std::sync::Arc;
+
+    use prometheus::{
+        core::{Collector, Desc},
+        proto::MetricFamily,
+        Encoder as _, IntCounterVec, Opts, Registry, TextEncoder,
+    };
+
+    use super::RouterMetrics;
+
+    const TYPE_LABEL: &str = "payload_type";
+    const REASON_LABEL: &str = "reason";
+
+    /// Counter vectors exported for each payload type
+    struct CounterVecs {
+        dispatched: IntCounterVec,
+        delivered: IntCounterVec,
+        dropped: IntCounterVec,
+        shed: IntCounterVec,
+        drop_reasons: IntCounterVec,
+    }
+
+    impl CounterVecs {
+        fn new() -> Self {
+            let vec = |name: &str, help: &str| {
+                IntCounterVec::new(Opts::new(name, help).namespace("salish"), &[TYPE_LABEL])
+                    .expect("Invalid metric options")
+            };
+
+            Self {
+                dispatched: vec("messages_dispatched_total", "Messages passed to the router"),
+                delivered: vec(
+                    "messages_delivered_total",
+                    "Handler results returned by endpoints",
+                ),
+                dropped: vec(
+                    "messages_dropped_total",
+                    "Messages which produced no handler results",
+                ),
+                shed: vec("messages_shed_total", "Messages dropped by load shedding"),
+                drop_reasons: IntCounterVec::new(
+                    Opts::new(
+                        "messages_dropped_by_reason_total",
+                        "Messages dropped for each reason",
+                    )
+                    .namespace("salish"),
+                    &[TYPE_LABEL, REASON_LABEL],
+                )
+                .expect("Invalid metric options"),
+            }
+        }
+
+        /// Populate a fresh set of counter vectors from the current router metrics
+        fn from_metrics(metrics: &RouterMetrics) -> Self {
+            let vecs = Self::new();
+            for type_metrics in metrics.snapshot() {
+                let labels = [type_metrics.type_name];
+                vecs.dispatched
+                    .with_label_values(&labels)
+                    .inc_by(type_metrics.dispatched);
+                vecs.delivered
+                    .with_label_values(&labels)
+                    .inc_by(type_metrics.delivered);
+                vecs.dropped
+                    .with_label_values(&labels)
+                    .inc_by(type_metrics.dropped);
+                vecs.shed
+                    .with_label_values(&labels)
+                    .inc_by(type_metrics.shed);
+                for (reason, count) in &type_metrics.drop_reasons {
+                    vecs.drop_reasons
+                        .with_label_values(&[type_metrics.type_name, *reason])
+                        .inc_by(*count);
+                }
+            }
+            vecs
+        }
+
+        fn collect(&self) -> Vec<MetricFamily> {
+            let mut families = self.dispatched.collect();
+            families.extend(self.delivered.collect());
+            families.extend(self.dropped.collect());
+            families.extend(self.shed.collect());
+            families.extend(self.drop_reasons.collect());
+            families
+        }
+    }
+
+    /// Prometheus [`Collector`] reading live values from [`RouterMetrics`] on each scrape
+    pub struct RouterCollector {
+        metrics: Arc<RouterMetrics>,
+        descs: CounterVecs,
+    }
+
+    impl Collector for RouterCollector {
+        fn desc(&self) -> Vec<&Desc> {
+            let mut descs = self.descs.dispatched.desc();
+            descs.extend(self.descs.delivered.desc());
+            descs.extend(self.descs.dropped.desc());
+            descs.extend(self.descs.shed.desc());
+            descs.extend(self.descs.drop_reasons.desc());
+            descs
+        }
+
+        fn collect(&self) -> Vec<MetricFamily> {
+            CounterVecs::from_metrics(&self.metrics).collect()
+        }
+    }
+
+    impl RouterMetrics {
+        /// Create a Prometheus [`Collector`] for these metrics, which can be registered with an existing [`Registry`]
+        pub fn collector(self: &Arc<Self>) -> RouterCollector {
+            RouterCollector {
+                metrics: self.clone(),
+                descs: CounterVecs::new(),
+            }
+        }
+
+        /// Create a new Prometheus [`Registry`] with a [`RouterCollector`] registered
+        pub fn registry(self: &Arc<Self>) -> prometheus::Result<Registry> {
+            let registry = Registry::new();
+            registry.register(Box::new(self.collector()))?;
+            Ok(registry)
+        }
+
+        /// Encode the current metrics in the Prometheus text exposition format
+        pub fn encode(&self) -> prometheus::Result<String> {
+            let families = CounterVecs::from_metrics(self).collect();
+
+            let mut buffer = Vec::new();
+            TextEncoder::new().encode(&families, &mut buffer)?;
+
+            String::from_utf8(buffer).map_err(|e| prometheus::Error::Msg(e.to_string()))
+        }
+    }
+}
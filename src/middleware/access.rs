@@ -0,0 +1,70 @@
+//! Access control of payload types by message source
+
+use std::{
+    any::{type_name, TypeId},
+    collections::{HashMap, HashSet},
+    hash::{DefaultHasher, Hasher as _},
+};
+
+use tracing::warn;
+
+use crate::{message::MessageSource, traits::internal::SalishMessageInternal as _, Message};
+
+use super::{DropReason, Middleware};
+
+/// Sources allowed to send a payload type
+#[derive(Debug)]
+struct Rule {
+    type_name: &'static str,
+    sources: HashSet<u64>,
+}
+
+/// [`Middleware`] restricting payload types to allowlists of message sources.
+///
+/// Payload types without any rules are accepted from any source. Once a source is allowed for a payload type,
+/// messages of that type from other sources, or without a source, are rejected with [`DropReason::AccessDenied`].
+#[derive(Debug, Default)]
+pub struct AccessControl {
+    rules: HashMap<TypeId, Rule>,
+}
+
+impl AccessControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow messages with payload type `M` from `source`
+    pub fn allow<M: 'static>(mut self, source: impl MessageSource) -> Self {
+        let mut hasher = DefaultHasher::new();
+        source.hash(&mut hasher);
+
+        self.rules
+            .entry(TypeId::of::<M>())
+            .or_insert_with(|| Rule {
+                type_name: type_name::<M>(),
+                sources: HashSet::new(),
+            })
+            .sources
+            .insert(hasher.finish());
+        self
+    }
+}
+
+impl Middleware for AccessControl {
+    fn check(&self, message: &Message) -> Result<(), DropReason> {
+        let Some(rule) = self.rules.get(&message.payload_type()) else {
+            return Ok(());
+        };
+
+        match message.source_hash() {
+            Some(hash) if rule.sources.contains(&hash) => Ok(()),
+            _ => {
+                warn!(
+                    "Denied {} from a source not in its allowlist",
+                    rule.type_name
+                );
+                Err(DropReason::AccessDenied)
+            }
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! Audit log of dispatched messages, written as JSON lines
+
+use std::{fmt::Write as _, io::Write, sync::mpsc::Sender, time::UNIX_EPOCH};
+
+use anylock::{AnyLock, ParkingLotMutex};
+use tracing::warn;
+
+use crate::{message::Destination, Message};
+
+use super::{DispatchRecord, DropReason, Middleware, Outcome};
+
+/// Destination of the audit records
+enum Sink {
+    Writer(ParkingLotMutex<Box<dyn Write + Send>>),
+    Channel(Sender<String>),
+}
+
+/// [`Middleware`] writing a JSON line for every message dispatched by the router.
+///
+/// Records have the fields `time` (milliseconds since the Unix epoch), `type`, `source` (hash of the source),
+/// `dest`, `endpoint` (the addressed endpoint), `origin`, `outcome`, and either `handlers` with the number of
+/// handlers called, or `reason` for dropped messages. Absent values are written as `null`.
+///
+/// ```json
+/// {"time":1700000000000,"type":"u32","source":null,"dest":"broadcast","endpoint":null,"origin":null,"outcome":"delivered","handlers":2}
+/// ```
+///
+/// The audit log never rejects messages.
+pub struct AuditLog {
+    sink: Sink,
+}
+
+impl std::fmt::Debug for AuditLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sink = match self.sink {
+            Sink::Writer(_) => "writer",
+            Sink::Channel(_) => "channel",
+        };
+
+        f.debug_struct("AuditLog").field("sink", &sink).finish()
+    }
+}
+
+impl AuditLog {
+    /// Write records to `writer`, one line each
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            sink: Sink::Writer(ParkingLotMutex::new(Box::new(writer))),
+        }
+    }
+
+    /// Send records to `channel`, without a trailing newline
+    pub fn channel(channel: Sender<String>) -> Self {
+        Self {
+            sink: Sink::Channel(channel),
+        }
+    }
+
+    /// Format a record as a single line of JSON
+    pub fn format(record: &DispatchRecord) -> String {
+        let time = record
+            .time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+
+        let dest = match record.dest {
+            Destination::Any(_) => "any",
+            Destination::Broadcast(_) => "broadcast",
+            Destination::Endpoint(_) => "endpoint",
+            Destination::Remote(..) => "remote",
+            Destination::Named(_) => "named",
+            Destination::Group(_) => "group",
+            Destination::Multi(_) => "multi",
+        };
+
+        let mut line = format!(
+            "{{\"time\":{time},\"type\":\"{}\",\"source\":{},\"dest\":\"{dest}\",\"endpoint\":{},\"origin\":{},\"outcome\":\"{}\"",
+            escape(record.type_name),
+            json_number(record.source_hash),
+            json_number(record.endpoint()),
+            json_number(record.origin),
+            record.outcome.name(),
+        );
+
+        match &record.outcome {
+            Outcome::Delivered(handlers) | Outcome::Cancelled(handlers) => {
+                let _ = write!(line, ",\"handlers\":{handlers}");
+            }
+            Outcome::Dropped(reason) => {
+                let _ = write!(line, ",\"reason\":\"{}\"", escape(&reason.to_string()));
+            }
+            Outcome::Partial { delivered, failed } => {
+                let _ = write!(line, ",\"handlers\":{delivered},\"failed\":{failed}");
+            }
+            _ => {}
+        }
+
+        line.push('}');
+        line
+    }
+}
+
+impl Middleware for AuditLog {
+    fn check(&self, _message: &Message) -> Result<(), DropReason> {
+        Ok(())
+    }
+
+    fn dispatched(&self, record: &DispatchRecord) {
+        let line = Self::format(record);
+
+        match &self.sink {
+            Sink::Writer(writer) => {
+                if let Err(e) = writeln!(writer.write(), "{line}") {
+                    warn!("Failed to write audit record: {e}");
+                }
+            }
+            Sink::Channel(channel) => {
+                if channel.send(line).is_err() {
+                    warn!("Audit record receiver was dropped");
+                }
+            }
+        }
+    }
+}
+
+fn json_number(value: Option<impl ToString>) -> String {
+    value.map_or_else(|| "null".into(), |value| value.to_string())
+}
+
+/// Escape a string for a JSON string literal
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
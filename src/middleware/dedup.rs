@@ -0,0 +1,141 @@
+//! Dropping duplicate messages within a time window
+
+use std::{
+    any::TypeId,
+    collections::{HashMap, VecDeque},
+    hash::{DefaultHasher, Hash, Hasher as _},
+    time::{Duration, Instant},
+};
+
+use anylock::{AnyLock, ParkingLotMutex};
+use tracing::debug;
+
+use crate::{traits::internal::SalishMessageInternal as _, Message};
+
+use super::{DropReason, Middleware};
+
+/// Hashes the payload of a message, or returns `None` if it's not of the expected type
+type HashFn = Box<dyn Fn(&Message) -> Option<u64> + Send + Sync>;
+
+/// Payload type, source hash, and payload hash of a message
+type Key = (TypeId, Option<u64>, u64);
+
+/// Messages seen within the window, oldest first
+#[derive(Debug, Default)]
+struct Seen {
+    times: HashMap<Key, Instant>,
+    order: VecDeque<(Instant, Key)>,
+}
+
+impl Seen {
+    /// Forget messages seen before `since`
+    fn expire(&mut self, since: Instant) {
+        while let Some((time, key)) = self.order.front() {
+            if *time >= since {
+                break;
+            }
+
+            self.times.remove(key);
+            self.order.pop_front();
+        }
+    }
+}
+
+/// [`Middleware`] dropping messages whose payload is identical to a message from the same source within a window.
+///
+/// Only payload types registered with [`hashed()`](Dedup::hashed) or [`hash_with()`](Dedup::hash_with) are
+/// checked. The window starts when a payload is first delivered, and repeats are not delivered until it ends,
+/// so a payload repeated continuously is delivered once per window. Duplicates are dropped with
+/// [`DropReason::Discarded`], without passing them to the dead-letter sink.
+///
+/// ```
+/// use std::time::Duration;
+/// use salish::middleware::Dedup;
+///
+/// #[derive(Hash)]
+/// struct Reading {
+///     sensor: u16,
+///     value: i32,
+/// }
+///
+/// let dedup = Dedup::new(Duration::from_secs(5))
+///     .hashed::<Reading>()
+///     .hash_with::<f64>(|value| value.to_bits());
+/// ```
+pub struct Dedup {
+    window: Duration,
+    hashers: HashMap<TypeId, HashFn>,
+    seen: ParkingLotMutex<Seen>,
+}
+
+impl std::fmt::Debug for Dedup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Dedup")
+            .field("window", &self.window)
+            .field("types", &self.hashers.len())
+            .field("seen", &self.seen.read().order.len())
+            .finish()
+    }
+}
+
+impl Dedup {
+    /// Create a deduplicator dropping repeats within `window`
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            hashers: HashMap::new(),
+            seen: ParkingLotMutex::new(Seen::default()),
+        }
+    }
+
+    /// Deduplicate payloads of type `M` by their [`Hash`] implementation
+    pub fn hashed<M: Hash + 'static>(self) -> Self {
+        self.hash_with::<M>(|payload| {
+            let mut hasher = DefaultHasher::new();
+            payload.hash(&mut hasher);
+            hasher.finish()
+        })
+    }
+
+    /// Deduplicate payloads of type `M` by the hash returned by `hash`
+    pub fn hash_with<M: 'static>(
+        mut self,
+        hash: impl Fn(&M) -> u64 + Send + Sync + 'static,
+    ) -> Self {
+        self.hashers.insert(
+            TypeId::of::<M>(),
+            Box::new(move |message| message.inner::<M>().map(&hash)),
+        );
+        self
+    }
+}
+
+impl Middleware for Dedup {
+    fn check(&self, message: &Message) -> Result<(), DropReason> {
+        let type_id = message.payload_type();
+        let Some(hash) = self
+            .hashers
+            .get(&type_id)
+            .and_then(|hasher| hasher(message))
+        else {
+            return Ok(());
+        };
+
+        let key = (type_id, message.source_hash(), hash);
+        let now = Instant::now();
+
+        let mut seen = self.seen.write();
+        if let Some(since) = now.checked_sub(self.window) {
+            seen.expire(since);
+        }
+
+        if seen.times.contains_key(&key) {
+            debug!("Dropped duplicate {}", message.type_name());
+            return Err(DropReason::Discarded);
+        }
+
+        seen.times.insert(key, now);
+        seen.order.push_back((now, key));
+        Ok(())
+    }
+}
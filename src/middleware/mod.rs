@@ -0,0 +1,427 @@
+//! Middleware inspecting messages before they are dispatched
+//!
+//! [`Middleware`] registered with [`MessageRouter::add_middleware()`](crate::router::MessageRouter::add_middleware)
+//! is run in order for every message handled by the router. A message rejected by any middleware is not dispatched,
+//! and is passed to the dead-letter sink of the router along with the [`DropReason`]. Messages addressed to an
+//! endpoint of a different payload type, or rejected by the filters of every endpoint, are also passed to the
+//! dead-letter sink, as are unroutable messages if the router is configured with
+//! [`Unroutable::DeadLetter`](crate::config::Unroutable::DeadLetter).
+//!
+//! Every dropped message is counted by its [`DropReason::label()`] in the
+//! [`TypeMetrics::drop_reasons`](crate::metrics::TypeMetrics::drop_reasons) of its payload type, including the
+//! messages which are not passed to the dead-letter sink.
+//!
+//! The router keeps a [`DeadLetterRecord`] of the most recently dropped messages, which is available from
+//! [`MessageRouter::recent_dead_letters()`](crate::router::MessageRouter::recent_dead_letters).
+//!
+//! Once a message was dispatched, every middleware is passed a [`DispatchRecord`] with the [`Outcome`] through
+//! [`Middleware::dispatched()`]. The [`AuditLog`] middleware writes these records as JSON lines.
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Instant, SystemTime},
+};
+
+use anylock::{AnyLock, ParkingLotRwLock};
+use tracing::debug;
+
+use crate::{
+    dispatch::DispatchResult,
+    endpoint::EndpointId,
+    message::{Destination, Message},
+    queue::RouterSender,
+};
+
+pub mod access;
+pub mod audit;
+pub mod dedup;
+pub mod rate;
+
+pub use access::AccessControl;
+pub use audit::AuditLog;
+pub use dedup::Dedup;
+pub use rate::{Excess, Rate, RateLimiter};
+
+/// Reason a message was dropped without being dispatched
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DropReason {
+    /// The source of the message is not allowed to send its payload type
+    AccessDenied,
+
+    /// Rejected by middleware for another reason
+    Rejected(String),
+
+    /// Addressed to an endpoint registered for a different payload type
+    TypeMismatch,
+
+    /// No endpoint is registered for the payload type, or the destination doesn't exist
+    Unroutable,
+
+    /// The source or payload type exceeded its rate limit
+    RateLimited,
+
+    /// Discarded or requeued by middleware, without passing it to the dead-letter sink
+    Discarded,
+
+    /// Rejected by a validator of the payload type, with the error of the validator
+    Invalid(String),
+
+    /// The time to live of the message elapsed before it was dispatched
+    Expired,
+
+    /// Rejected by the filters of every endpoint which could receive it
+    Filtered,
+
+    /// Discarded to make room in a full buffer, such as the messages held until an endpoint is registered
+    Overflow,
+
+    /// Dropped from the inbound queue by load shedding. Shed messages are counted, but not passed to the
+    /// dead-letter sink.
+    Shed,
+
+    /// Handed back by middleware to be queued with the sender, such as messages exceeding a rate limit with
+    /// [`Excess::Queue`]. Requeued messages are counted, but not passed to the dead-letter sink.
+    Requeue(RouterSender),
+}
+
+impl DropReason {
+    /// Name of the reason without its details, labelling the drop counters of
+    /// [`RouterMetrics`](crate::metrics::RouterMetrics)
+    pub fn label(&self) -> &'static str {
+        match self {
+            DropReason::AccessDenied => "access_denied",
+            DropReason::Rejected(_) => "rejected",
+            DropReason::TypeMismatch => "type_mismatch",
+            DropReason::Unroutable => "unroutable",
+            DropReason::RateLimited => "rate_limited",
+            DropReason::Discarded => "discarded",
+            DropReason::Invalid(_) => "invalid",
+            DropReason::Expired => "expired",
+            DropReason::Filtered => "filtered",
+            DropReason::Overflow => "overflow",
+            DropReason::Shed => "shed",
+            DropReason::Requeue(_) => "requeued",
+        }
+    }
+}
+
+impl std::fmt::Display for DropReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DropReason::AccessDenied => write!(f, "access denied"),
+            DropReason::Rejected(reason) => write!(f, "rejected: {reason}"),
+            DropReason::TypeMismatch => write!(f, "payload type does not match the endpoint"),
+            DropReason::Unroutable => write!(f, "unroutable"),
+            DropReason::RateLimited => write!(f, "rate limited"),
+            DropReason::Discarded => write!(f, "discarded"),
+            DropReason::Invalid(error) => write!(f, "invalid: {error}"),
+            DropReason::Expired => write!(f, "expired"),
+            DropReason::Filtered => write!(f, "rejected by the filters of all endpoints"),
+            DropReason::Overflow => write!(f, "buffer full"),
+            DropReason::Shed => write!(f, "shed"),
+            DropReason::Requeue(_) => write!(f, "requeued"),
+        }
+    }
+}
+
+/// Message which was dropped, and the reason it was dropped
+#[derive(Debug)]
+pub struct DeadLetter {
+    pub message: Message,
+    pub reason: DropReason,
+}
+
+/// Summary of a dropped message, kept by the router for diagnostics
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeadLetterRecord {
+    /// Rust type name of the payload
+    pub type_name: &'static str,
+
+    /// Reason the message was dropped
+    pub reason: DropReason,
+
+    /// Time the message was dropped
+    pub time: Instant,
+}
+
+/// Outcome of dispatching a message, without the results of the handlers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// Delivered to this number of handlers
+    Delivered(usize),
+
+    /// No endpoint could receive the message
+    NoHandler,
+
+    /// Rejected by middleware
+    Dropped(DropReason),
+
+    /// Addressed to an endpoint registered for a different payload type
+    TypeMismatch,
+
+    /// Held until an endpoint for the payload type is registered
+    Pending,
+
+    /// Not delivered by non-blocking dispatch, as the endpoints were busy
+    WouldBlock,
+
+    /// Cancelled after being delivered to this number of handlers
+    Cancelled(usize),
+
+    /// Delivered to some endpoints of a multi-destination message
+    Partial {
+        /// Number of handlers the message was delivered to
+        delivered: usize,
+
+        /// Number of endpoints the message couldn't be delivered to
+        failed: usize,
+    },
+}
+
+impl Outcome {
+    /// Name of the outcome, as written by [`AuditLog`]
+    pub fn name(&self) -> &'static str {
+        match self {
+            Outcome::Delivered(_) => "delivered",
+            Outcome::NoHandler => "no_handler",
+            Outcome::Dropped(_) => "dropped",
+            Outcome::TypeMismatch => "type_mismatch",
+            Outcome::Pending => "pending",
+            Outcome::WouldBlock => "would_block",
+            Outcome::Cancelled(_) => "cancelled",
+            Outcome::Partial { .. } => "partial",
+        }
+    }
+}
+
+impl<R> From<&DispatchResult<R>> for Outcome {
+    fn from(result: &DispatchResult<R>) -> Self {
+        match result {
+            DispatchResult::Delivered(results) => Outcome::Delivered(results.len()),
+            DispatchResult::NoHandler => Outcome::NoHandler,
+            DispatchResult::Filtered => Outcome::Dropped(DropReason::Filtered),
+            DispatchResult::Rejected(reason) => Outcome::Dropped(reason.clone()),
+            DispatchResult::Invalid(error) => {
+                Outcome::Dropped(DropReason::Invalid(error.error().to_string()))
+            }
+            DispatchResult::TypeMismatch => Outcome::TypeMismatch,
+            DispatchResult::Pending => Outcome::Pending,
+            DispatchResult::WouldBlock => Outcome::WouldBlock,
+            DispatchResult::Cancelled(results) => Outcome::Cancelled(results.len()),
+            DispatchResult::Partial(results, failed) => Outcome::Partial {
+                delivered: results.len(),
+                failed: failed.len(),
+            },
+        }
+    }
+}
+
+/// Summary of a dispatched message, passed to [`Middleware::dispatched()`]
+#[derive(Debug, Clone)]
+pub struct DispatchRecord {
+    /// Time the message was dispatched
+    pub time: SystemTime,
+
+    /// Rust type name of the payload
+    pub type_name: &'static str,
+
+    /// Hash of the message source, if it has one
+    pub source_hash: Option<u64>,
+
+    /// Destination of the message
+    pub dest: Destination<EndpointId>,
+
+    /// Endpoint which sent the message, if it was sent by an endpoint
+    pub origin: Option<EndpointId>,
+
+    /// Outcome of dispatching the message
+    pub outcome: Outcome,
+}
+
+impl DispatchRecord {
+    /// Start a record of a message which is about to be dispatched
+    pub(crate) fn new(message: &Message) -> Self {
+        Self {
+            time: SystemTime::now(),
+            type_name: message.type_name(),
+            source_hash: message.source_hash(),
+            dest: message.dest(),
+            origin: message.origin(),
+            outcome: Outcome::NoHandler,
+        }
+    }
+
+    /// Get the endpoint the message was addressed to, if it was addressed to a single endpoint
+    pub fn endpoint(&self) -> Option<EndpointId> {
+        match self.dest {
+            Destination::Endpoint(addr) | Destination::Remote(_, addr) => Some(addr),
+            _ => None,
+        }
+    }
+}
+
+/// Inspects messages before dispatch, and rejects those which should not be delivered
+pub trait Middleware: Send + Sync {
+    /// Check a message before it is dispatched. Returning an error drops the message.
+    fn check(&self, message: &Message) -> Result<(), DropReason>;
+
+    /// Observe the outcome of dispatching a message. Called for every message handled by the router,
+    /// including those rejected by other middleware.
+    fn dispatched(&self, _record: &DispatchRecord) {}
+}
+
+/// Callback receiving dropped messages
+pub(crate) type DeadLetterSink = Box<dyn Fn(DeadLetter) + Send + Sync>;
+
+/// Number of [`DeadLetterRecord`]s kept by a router
+const RECENT_DEAD_LETTERS: usize = 32;
+
+/// Middleware and dead-letter sink shared by all clones of a router
+pub(crate) struct MiddlewareChain {
+    middleware: ParkingLotRwLock<Vec<Arc<dyn Middleware>>>,
+    dead_letter: ParkingLotRwLock<Option<DeadLetterSink>>,
+    dropped: AtomicU64,
+    recent: ParkingLotRwLock<VecDeque<DeadLetterRecord>>,
+
+    /// Dropped messages kept for redelivery, up to `capacity`
+    buffer: ParkingLotRwLock<VecDeque<DeadLetter>>,
+    capacity: usize,
+}
+
+impl std::fmt::Debug for MiddlewareChain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MiddlewareChain")
+            .field("middleware", &self.middleware.read().len())
+            .field("dead_letter", &self.dead_letter.read().is_some())
+            .field("dropped", &self.dropped())
+            .field("buffered", &self.buffer.read().len())
+            .finish()
+    }
+}
+
+impl MiddlewareChain {
+    /// Create a chain buffering up to `capacity` dropped messages
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            middleware: ParkingLotRwLock::new(Vec::new()),
+            dead_letter: ParkingLotRwLock::new(None),
+            dropped: AtomicU64::new(0),
+            recent: ParkingLotRwLock::new(VecDeque::with_capacity(RECENT_DEAD_LETTERS)),
+            buffer: ParkingLotRwLock::new(VecDeque::new()),
+            capacity,
+        }
+    }
+
+    pub(crate) fn push(&self, middleware: Arc<dyn Middleware>) {
+        self.middleware.write().push(middleware);
+    }
+
+    pub(crate) fn set_dead_letter(&self, sink: Option<DeadLetterSink>) {
+        *self.dead_letter.write() = sink;
+    }
+
+    /// Run a message through all middleware, stopping at the first rejection
+    pub(crate) fn check(&self, message: &Message) -> Result<(), DropReason> {
+        self.middleware
+            .read()
+            .iter()
+            .try_for_each(|middleware| middleware.check(message))
+    }
+
+    /// Start a [`DispatchRecord`] of a message, if any middleware is registered to observe it
+    pub(crate) fn record(&self, message: &Message) -> Option<DispatchRecord> {
+        (!self.middleware.read().is_empty()).then(|| DispatchRecord::new(message))
+    }
+
+    /// Pass the outcome of dispatching a message to all middleware
+    pub(crate) fn dispatched(&self, record: Option<DispatchRecord>, outcome: Outcome) {
+        if let Some(mut record) = record {
+            record.outcome = outcome;
+            self.middleware
+                .read()
+                .iter()
+                .for_each(|middleware| middleware.dispatched(&record));
+        }
+    }
+
+    /// Pass a dropped message to the dead-letter sink. Messages with [`DropReason::Discarded`] are dropped, and
+    /// messages with [`DropReason::Requeue`] are queued with their sender.
+    pub(crate) fn dead_letter(&self, message: Message, reason: DropReason) {
+        match &reason {
+            DropReason::Discarded => return,
+            DropReason::Requeue(sender) => {
+                sender.send(message);
+                return;
+            }
+            _ => {}
+        }
+
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+
+        {
+            let mut recent = self.recent.write();
+            if recent.len() == RECENT_DEAD_LETTERS {
+                recent.pop_front();
+            }
+            recent.push_back(DeadLetterRecord {
+                type_name: message.type_name(),
+                reason: reason.clone(),
+                time: Instant::now(),
+            });
+        }
+
+        let mut dead_letter = DeadLetter { message, reason };
+
+        // Buffered messages only reach the sink when they are discarded from a full buffer
+        if self.capacity > 0 {
+            let mut buffer = self.buffer.write();
+            buffer.push_back(dead_letter);
+
+            if buffer.len() <= self.capacity {
+                return;
+            }
+
+            dead_letter = buffer.pop_front().expect("Dead-letter buffer is empty");
+        }
+
+        match &*self.dead_letter.read() {
+            Some(sink) => sink(dead_letter),
+            None => debug!("Dropped {:?}: {}", dead_letter.message, dead_letter.reason),
+        }
+    }
+
+    /// Get the number of messages passed to the dead-letter sink
+    pub(crate) fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Get the most recently dropped messages, oldest first
+    pub(crate) fn recent(&self) -> Vec<DeadLetterRecord> {
+        self.recent.read().iter().cloned().collect()
+    }
+
+    /// Get the number of dropped messages in the buffer
+    pub(crate) fn buffered(&self) -> usize {
+        self.buffer.read().len()
+    }
+
+    /// Remove the buffered messages matching `filter`, oldest first
+    pub(crate) fn take_buffered(&self, filter: impl Fn(&DeadLetter) -> bool) -> Vec<DeadLetter> {
+        let mut buffer = self.buffer.write();
+        let mut taken = Vec::new();
+
+        for dead_letter in std::mem::take(&mut *buffer) {
+            if filter(&dead_letter) {
+                taken.push(dead_letter);
+            } else {
+                buffer.push_back(dead_letter);
+            }
+        }
+
+        taken
+    }
+}
@@ -0,0 +1,224 @@
+//! Token bucket rate limiting by payload type and message source
+
+use std::{
+    any::TypeId,
+    collections::HashMap,
+    hash::{DefaultHasher, Hasher as _},
+    time::Instant,
+};
+
+use anylock::{AnyLock, ParkingLotMutex};
+use tracing::debug;
+
+use crate::{
+    message::MessageSource, queue::RouterSender, traits::internal::SalishMessageInternal as _,
+    Message,
+};
+
+use super::{DropReason, Middleware};
+
+/// Sustained rate and burst size of a token bucket
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rate {
+    per_second: f64,
+    burst: f64,
+}
+
+impl Rate {
+    /// Allow `messages` per second, with a burst of the same size
+    pub fn per_second(messages: f64) -> Self {
+        Self {
+            per_second: messages,
+            burst: messages.max(1.0),
+        }
+    }
+
+    /// Set the number of messages which can be sent at once after the bucket has filled up
+    pub fn burst(mut self, messages: u32) -> Self {
+        self.burst = messages as f64;
+        self
+    }
+}
+
+/// What happens to messages exceeding a rate limit
+#[derive(Debug, Clone, Default)]
+pub enum Excess {
+    /// Drop the message without passing it to the dead-letter sink
+    Drop,
+
+    /// Pass the message to the dead-letter sink with [`DropReason::RateLimited`]
+    #[default]
+    DeadLetter,
+
+    /// Hand the message back to be queued with the sender with [`DropReason::Requeue`], so it's retried by the
+    /// next [`MessageRouter::drain()`](crate::router::MessageRouter::drain)
+    Queue(RouterSender),
+}
+
+/// Messages a bucket applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Key {
+    Type(TypeId),
+    Source(u64),
+    TypeSource(TypeId, u64),
+}
+
+#[derive(Debug)]
+struct Bucket {
+    rate: Rate,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(rate: Rate) -> Self {
+        Self {
+            rate,
+            tokens: rate.burst,
+            updated: Instant::now(),
+        }
+    }
+
+    /// Refill the bucket for the time since it was last updated, and check if it has a token
+    fn refill(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate.per_second).min(self.rate.burst);
+        self.updated = now;
+        self.tokens >= 1.0
+    }
+}
+
+/// [`Middleware`] limiting the rate of messages with token buckets.
+///
+/// Limits can be set for a payload type across all sources, for all messages from a source, or for each source
+/// of a payload type separately. A message must be within every limit which applies to it, and only takes a
+/// token from the buckets if it is. Messages without a source are only limited by the payload type limits.
+///
+/// ```
+/// use salish::middleware::{Rate, RateLimiter};
+///
+/// let limiter = RateLimiter::new()
+///     .limit::<u32>(Rate::per_second(100.0))
+///     .limit_each_source::<String>(Rate::per_second(1.0).burst(5));
+/// ```
+#[derive(Debug)]
+pub struct RateLimiter {
+    type_limits: HashMap<TypeId, Rate>,
+    source_limits: HashMap<u64, Rate>,
+    each_source_limits: HashMap<TypeId, Rate>,
+    buckets: ParkingLotMutex<HashMap<Key, Bucket>>,
+    excess: Excess,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self {
+            type_limits: HashMap::new(),
+            source_limits: HashMap::new(),
+            each_source_limits: HashMap::new(),
+            buckets: ParkingLotMutex::new(HashMap::new()),
+            excess: Excess::default(),
+        }
+    }
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit messages with payload type `M` from all sources together
+    pub fn limit<M: 'static>(mut self, rate: Rate) -> Self {
+        self.type_limits.insert(TypeId::of::<M>(), rate);
+        self
+    }
+
+    /// Limit all messages from `source`
+    pub fn limit_source(mut self, source: impl MessageSource, rate: Rate) -> Self {
+        let mut hasher = DefaultHasher::new();
+        source.hash(&mut hasher);
+
+        self.source_limits.insert(hasher.finish(), rate);
+        self
+    }
+
+    /// Limit messages with payload type `M` from each source separately
+    pub fn limit_each_source<M: 'static>(mut self, rate: Rate) -> Self {
+        self.each_source_limits.insert(TypeId::of::<M>(), rate);
+        self
+    }
+
+    /// Set what happens to messages exceeding a limit. Defaults to [`Excess::DeadLetter`].
+    pub fn excess(mut self, excess: Excess) -> Self {
+        self.excess = excess;
+        self
+    }
+
+    /// Get the buckets which apply to a message, and the rate of each
+    fn limits(
+        &self,
+        type_id: TypeId,
+        source: Option<u64>,
+    ) -> impl Iterator<Item = (Key, Rate)> + '_ {
+        let type_limit = self
+            .type_limits
+            .get(&type_id)
+            .map(|rate| (Key::Type(type_id), *rate));
+
+        let source_limits = source.into_iter().flat_map(move |source| {
+            let source_limit = self
+                .source_limits
+                .get(&source)
+                .map(|rate| (Key::Source(source), *rate));
+
+            let each_source_limit = self
+                .each_source_limits
+                .get(&type_id)
+                .map(|rate| (Key::TypeSource(type_id, source), *rate));
+
+            source_limit.into_iter().chain(each_source_limit)
+        });
+
+        type_limit.into_iter().chain(source_limits)
+    }
+}
+
+impl Middleware for RateLimiter {
+    fn check(&self, message: &Message) -> Result<(), DropReason> {
+        let type_id = message.payload_type();
+        let limits: Vec<_> = self.limits(type_id, message.source_hash()).collect();
+
+        if limits.is_empty() {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let mut buckets = self.buckets.write();
+
+        let allowed = limits.iter().all(|(key, rate)| {
+            buckets
+                .entry(*key)
+                .or_insert_with(|| Bucket::new(*rate))
+                .refill(now)
+        });
+
+        if allowed {
+            for (key, _) in &limits {
+                if let Some(bucket) = buckets.get_mut(key) {
+                    bucket.tokens -= 1.0;
+                }
+            }
+
+            return Ok(());
+        }
+
+        debug!("Rate limited {}", message.type_name());
+
+        match &self.excess {
+            Excess::Drop => Err(DropReason::Discarded),
+            Excess::DeadLetter => Err(DropReason::RateLimited),
+            // The router owns the message, and queues it once the middleware returns
+            Excess::Queue(sender) => Err(DropReason::Requeue(sender.clone())),
+        }
+    }
+}
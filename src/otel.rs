@@ -0,0 +1,83 @@
+//! OpenTelemetry context propagation
+//!
+//! Messages capture the OpenTelemetry context of the current [`tracing`] span when they are created, and handlers
+//! are called in a span whose parent is that context, so traces follow messages through the router. Bridges send
+//! the context to peers as a W3C `traceparent` in the [`WireMessage`](crate::bridge::WireMessage) envelope, which
+//! continues the trace in the receiving router.
+//!
+//! Spans are exported by the `tracing_opentelemetry` layer installed by the application. Without the layer,
+//! messages carry no context.
+
+use opentelemetry::{
+    trace::{SpanContext, SpanId, TraceContextExt as _, TraceFlags, TraceId, TraceState},
+    Context,
+};
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt as _;
+
+use crate::Message;
+
+/// Get the OpenTelemetry context of the current span, if it belongs to a trace
+pub(crate) fn current() -> Option<Context> {
+    let context = Span::current().context();
+    let valid = context.span().span_context().is_valid();
+    valid.then_some(context)
+}
+
+/// Create the span a handler of `message` is called in, continuing the trace of the message
+pub(crate) fn handler_span(message: &Message) -> Span {
+    let span = tracing::info_span!("salish.handle", payload = message.type_name());
+
+    if let Some(context) = message.trace_context() {
+        span.set_parent(context.clone());
+    }
+
+    span
+}
+
+/// Format the span of `context` as a W3C `traceparent`, if it belongs to a trace
+pub fn traceparent(context: &Context) -> Option<String> {
+    let span = context.span();
+    let span_context = span.span_context();
+
+    span_context.is_valid().then(|| {
+        format!(
+            "00-{:032x}-{:016x}-{:02x}",
+            span_context.trace_id(),
+            span_context.span_id(),
+            span_context.trace_flags().to_u8()
+        )
+    })
+}
+
+/// Parse a W3C `traceparent` into a context with a remote parent span
+pub fn from_traceparent(traceparent: &str) -> Option<Context> {
+    let mut parts = traceparent.split('-');
+
+    let (Some("00"), Some(trace_id), Some(span_id), Some(flags), None) = (
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+        parts.next(),
+    ) else {
+        return None;
+    };
+
+    let span_context = SpanContext::new(
+        TraceId::from_hex(trace_id).ok()?,
+        SpanId::from_hex(span_id).ok()?,
+        TraceFlags::new(u8::from_str_radix(flags, 16).ok()?),
+        true,
+        TraceState::default(),
+    );
+
+    span_context
+        .is_valid()
+        .then(|| Context::new().with_remote_span_context(span_context))
+}
+
+/// Get the `traceparent` of the current span, sent with payloads encoded for bridges
+pub(crate) fn current_traceparent() -> Option<String> {
+    current().as_ref().and_then(traceparent)
+}
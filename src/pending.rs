@@ -0,0 +1,97 @@
+//! Messages held until an endpoint for their payload type is registered
+//!
+//! A router configured with [`RouterConfig::buffer_unhandled()`](crate::config::RouterConfig::buffer_unhandled)
+//! holds [`Destination::Any`](crate::message::Destination::Any) and
+//! [`Destination::Broadcast`](crate::message::Destination::Broadcast) messages of payload types without endpoints,
+//! instead of dropping them. The held messages are dispatched once an endpoint for their payload type is ready to
+//! receive them. Each payload type holds a bounded number of messages, which expire after a time to live.
+
+use std::{
+    any::TypeId,
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use anylock::{AnyLock, ParkingLotMutex};
+
+use crate::{middleware::DropReason, traits::internal::SalishMessageInternal as _, Message};
+
+/// Messages held per payload type, shared by all clones of a router
+pub(crate) struct PendingMessages {
+    messages: ParkingLotMutex<HashMap<TypeId, VecDeque<(Instant, Message)>>>,
+    capacity: usize,
+    ttl: Duration,
+}
+
+impl std::fmt::Debug for PendingMessages {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PendingMessages")
+            .field("len", &self.len())
+            .field("capacity", &self.capacity)
+            .field("ttl", &self.ttl)
+            .finish()
+    }
+}
+
+impl PendingMessages {
+    /// Create a store holding up to `capacity` messages of each payload type, for up to `ttl`
+    pub(crate) fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            messages: ParkingLotMutex::new(HashMap::new()),
+            capacity,
+            ttl,
+        }
+    }
+
+    /// Check if messages are held
+    pub(crate) fn enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Hold a message until an endpoint for its payload type is registered.
+    /// Returns the messages of the payload type which expired, or were discarded to make room, with the reason
+    /// they were dropped.
+    pub(crate) fn push(&self, message: Message) -> Vec<(Message, DropReason)> {
+        let now = Instant::now();
+        let mut messages = self.messages.write();
+        let held = messages.entry(message.payload_type()).or_default();
+
+        let mut discarded = Vec::new();
+        while let Some((time, _)) = held.front() {
+            let reason = if now.duration_since(*time) >= self.ttl {
+                DropReason::Expired
+            } else if held.len() >= self.capacity {
+                DropReason::Overflow
+            } else {
+                break;
+            };
+
+            discarded.extend(held.pop_front().map(|(_, message)| (message, reason)));
+        }
+
+        held.push_back((now, message));
+        discarded
+    }
+
+    /// Take the held messages of a payload type, oldest first, split into live and expired messages
+    pub(crate) fn take(&self, type_id: TypeId) -> (Vec<Message>, Vec<Message>) {
+        let Some(held) = self.messages.write().remove(&type_id) else {
+            return Default::default();
+        };
+
+        let now = Instant::now();
+        let (live, expired): (Vec<_>, Vec<_>) = held
+            .into_iter()
+            .partition(|(time, _)| now.duration_since(*time) < self.ttl);
+
+        (
+            live.into_iter().map(|(_, message)| message).collect(),
+            expired.into_iter().map(|(_, message)| message).collect(),
+        )
+    }
+
+    /// Get the number of held messages
+    pub(crate) fn len(&self) -> usize {
+        self.messages.read().values().map(VecDeque::len).sum()
+    }
+}
@@ -0,0 +1,559 @@
+//! Inbound message queue
+//!
+//! Messages can be queued into a [`MessageRouter`](crate::router::MessageRouter) from anywhere using a [`RouterSender`],
+//! and are dispatched when the owner of the router calls [`MessageRouter::drain()`](crate::router::MessageRouter::drain).
+//! Messages of a higher [`Priority`](crate::message::Priority) are dispatched first.
+//!
+//! A [`ScopedSender`] restricts which payload types can be queued to a fixed set, checked at compile time.
+//!
+//! The messages waiting for an endpoint can be inspected with
+//! [`MessageRouter::peek_queue()`](crate::router::MessageRouter::peek_queue), to find which consumer is backing up.
+//! Routers configured with [`RouterConfig::queue_watermarks()`] publish a [`QueueHighWatermark`] when a queue
+//! backs up, and a [`QueueRecovered`] once it has drained. With [`RouterConfig::shed_load()`], messages of
+//! payload types marked with [`MessageRouter::sheddable()`] are dropped while the inbound queue stays backed up,
+//! so other messages are dispatched sooner.
+//!
+//! [`RouterConfig::queue_watermarks()`]: crate::config::RouterConfig::queue_watermarks
+//! [`RouterConfig::shed_load()`]: crate::config::RouterConfig::shed_load
+//! [`MessageRouter::sheddable()`]: crate::router::MessageRouter::sheddable
+
+use std::{
+    any::TypeId,
+    collections::{BTreeMap, HashSet, VecDeque},
+    marker::PhantomData,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anylock::{AnyLock, ParkingLotMutex, ParkingLotRwLock};
+use tracing::{debug, trace, warn};
+
+use crate::{
+    config::RouterConfig,
+    endpoint::EndpointId,
+    message::Priority,
+    metrics::RouterMetrics,
+    traits::{internal::SalishMessageInternal as _, BroadcastPayload, UnicastPayload},
+    Message,
+};
+
+/// Callback invoked each time a message is queued
+pub(crate) type NotifyCallback = Box<dyn Fn() + Send + Sync>;
+
+/// Summary of a message waiting in the queue of a router
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueuedMessage {
+    /// Rust type name of the payload
+    pub type_name: &'static str,
+
+    /// Time the message has been waiting in the queue
+    pub age: Duration,
+
+    pub priority: Priority,
+}
+
+/// Summary of the messages waiting in the queue of a router for an endpoint, returned by
+/// [`MessageRouter::peek_queue()`](crate::router::MessageRouter::peek_queue)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueueSummary {
+    /// Messages in the order they will be dispatched
+    pub messages: Vec<QueuedMessage>,
+
+    /// Number of messages by payload type name
+    pub counts: BTreeMap<&'static str, usize>,
+
+    /// Time the oldest message has been waiting
+    pub oldest: Option<Duration>,
+}
+
+impl QueueSummary {
+    /// Number of messages waiting
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    /// Check if no messages are waiting
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+}
+
+/// Queue whose depth crossed a watermark
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QueueId {
+    /// Inbound queue of the router, which [`RouterSender`]s queue messages into
+    Ingest,
+
+    /// Messages enqueued to the worker thread of an endpoint assigned with
+    /// [`Endpoint::on_worker()`](crate::endpoint::Endpoint::on_worker)
+    Endpoint(EndpointId),
+}
+
+/// Published into the router when a queue holds at least the high watermark configured with
+/// [`RouterConfig::queue_watermarks()`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueueHighWatermark {
+    pub queue: QueueId,
+
+    /// Number of messages in the queue
+    pub depth: usize,
+
+    /// High watermark which was reached
+    pub threshold: usize,
+}
+
+/// Published into the router when a queue which reached its high watermark has drained to the low watermark
+/// configured with [`RouterConfig::queue_watermarks()`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueueRecovered {
+    pub queue: QueueId,
+
+    /// Number of messages in the queue
+    pub depth: usize,
+
+    /// Low watermark which was reached
+    pub threshold: usize,
+}
+
+/// Watermarks of a queue, raised when the queue reaches the high watermark and lowered when it drains to the
+/// low watermark, so each crossing is published once
+#[derive(Debug)]
+pub(crate) struct Watermark {
+    queue: QueueId,
+    high: usize,
+    low: usize,
+
+    /// Time the high watermark was reached, until the queue recovers
+    raised: Option<Instant>,
+}
+
+impl Watermark {
+    /// Watermarks of `queue` configured in `config`, or `None` if they are disabled
+    pub(crate) fn new(queue: QueueId, config: &RouterConfig) -> Option<Self> {
+        let high = config.queue_high_watermark();
+
+        (high > 0).then(|| Self {
+            queue,
+            high,
+            low: config.queue_low_watermark(),
+            raised: None,
+        })
+    }
+
+    /// Check the depth of the queue after it changed, returning the event to publish if a watermark was crossed
+    pub(crate) fn check(&mut self, depth: usize) -> Option<Message> {
+        if self.raised.is_none() && depth >= self.high {
+            self.raised = Some(Instant::now());
+            warn!("Queue {:?} reached {depth} messages", self.queue);
+
+            Some(Message::broadcast(QueueHighWatermark {
+                queue: self.queue,
+                depth,
+                threshold: self.high,
+            }))
+        } else if self.raised.is_some() && depth <= self.low {
+            self.raised = None;
+            debug!("Queue {:?} recovered at {depth} messages", self.queue);
+
+            Some(Message::broadcast(QueueRecovered {
+                queue: self.queue,
+                depth,
+                threshold: self.low,
+            }))
+        } else {
+            None
+        }
+    }
+
+    /// Time the queue has been at or above the high watermark without recovering
+    pub(crate) fn raised_for(&self) -> Option<Duration> {
+        self.raised.map(|raised| raised.elapsed())
+    }
+}
+
+/// Number of messages waiting in a queue outside the router, such as the queue of a worker thread
+#[derive(Debug)]
+pub(crate) struct Backlog {
+    depth: usize,
+    watermark: Watermark,
+}
+
+impl Backlog {
+    pub(crate) fn new(watermark: Watermark) -> Self {
+        Self {
+            depth: 0,
+            watermark,
+        }
+    }
+
+    /// Count a message entering the queue, returning the event to publish if a watermark was crossed
+    pub(crate) fn enqueued(&mut self) -> Option<Message> {
+        self.depth += 1;
+        self.watermark.check(self.depth)
+    }
+
+    /// Count a message leaving the queue, returning the event to publish if a watermark was crossed
+    pub(crate) fn dequeued(&mut self) -> Option<Message> {
+        self.depth = self.depth.saturating_sub(1);
+        self.watermark.check(self.depth)
+    }
+}
+
+/// Message in the queue, with the time it was queued
+struct Queued {
+    message: Message,
+    queued: Instant,
+}
+
+/// Queue of messages waiting to be dispatched by the router
+pub(crate) struct MessageQueue {
+    messages: ParkingLotMutex<VecDeque<Queued>>,
+    notify: ParkingLotRwLock<Option<NotifyCallback>>,
+
+    /// Watermarks of the queue, if configured. Only checked while the messages are locked.
+    watermark: Option<ParkingLotMutex<Watermark>>,
+
+    /// Time the queue stays above the high watermark before load is shed, if load shedding is enabled
+    shed_after: Option<Duration>,
+
+    /// Payload types which are dropped while load is shed
+    sheddable: ParkingLotRwLock<HashSet<TypeId>>,
+
+    /// Counters of the router, which count the messages which are shed
+    metrics: Arc<RouterMetrics>,
+}
+
+impl std::fmt::Debug for MessageQueue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MessageQueue")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl Default for MessageQueue {
+    fn default() -> Self {
+        Self {
+            messages: ParkingLotMutex::new(VecDeque::new()),
+            notify: ParkingLotRwLock::new(None),
+            watermark: None,
+            shed_after: None,
+            sheddable: ParkingLotRwLock::new(HashSet::new()),
+            metrics: Arc::default(),
+        }
+    }
+}
+
+impl MessageQueue {
+    /// Create a queue with the watermarks and load shedding configured in `config`, counting the messages which
+    /// are shed in `metrics`
+    pub(crate) fn new(config: &RouterConfig, metrics: Arc<RouterMetrics>) -> Self {
+        Self {
+            watermark: Watermark::new(QueueId::Ingest, config).map(ParkingLotMutex::new),
+            shed_after: config.shed_after(),
+            metrics,
+            ..Self::default()
+        }
+    }
+
+    /// Queue a message behind the messages of the same or higher priority.
+    ///
+    /// Senders have no router to publish through, so a [`QueueHighWatermark`] is queued ahead of all other
+    /// messages, to be dispatched first by the next drain. Messages of sheddable payload types are dropped while
+    /// load is shed.
+    pub(crate) fn push(&self, message: Message) {
+        {
+            let mut messages = self.messages.write();
+            if self.shedding() && self.shed(&message) {
+                return;
+            }
+
+            let priority = message.priority();
+
+            // Most messages have the same priority as the last queued message, and are appended
+            let index = messages
+                .iter()
+                .rposition(|queued| queued.message.priority() >= priority)
+                .map_or(0, |index| index + 1);
+            messages.insert(
+                index,
+                Queued {
+                    message,
+                    queued: Instant::now(),
+                },
+            );
+
+            if let Some(event) = self.check_watermark(messages.len()) {
+                messages.push_front(Queued {
+                    message: event.with_priority(Priority::Critical),
+                    queued: Instant::now(),
+                });
+            }
+        }
+
+        if let Some(notify) = &*self.notify.read() {
+            (notify)()
+        }
+    }
+
+    /// Take the next message to dispatch, dropping the messages of sheddable payload types ahead of it while
+    /// load is shed
+    pub(crate) fn pop(&self) -> Option<Message> {
+        let mut messages = self.messages.write();
+        let shedding = self.shedding();
+
+        while let Some(queued) = messages.pop_front() {
+            if !(shedding && self.shed(&queued.message)) {
+                return Some(queued.message);
+            }
+        }
+
+        None
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.messages.read().len()
+    }
+
+    /// Check whether the queue has drained to its low watermark, returning the [`QueueRecovered`] to publish
+    pub(crate) fn recovered(&self) -> Option<Message> {
+        let messages = self.messages.read();
+        self.check_watermark(messages.len())
+    }
+
+    /// Check the watermarks against the depth of the queue, while the messages are locked
+    fn check_watermark(&self, depth: usize) -> Option<Message> {
+        self.watermark.as_ref()?.write().check(depth)
+    }
+
+    /// Check if load is shed, because the queue has stayed above its high watermark for long enough
+    fn shedding(&self) -> bool {
+        let (Some(after), Some(watermark)) = (self.shed_after, &self.watermark) else {
+            return false;
+        };
+
+        watermark
+            .read()
+            .raised_for()
+            .is_some_and(|raised_for| raised_for >= after)
+    }
+
+    /// Count and drop a message while load is shed, if its payload type is sheddable.
+    /// Critical messages are never shed.
+    fn shed(&self, message: &Message) -> bool {
+        let type_id = message.payload_type();
+        if message.priority() == Priority::Critical || !self.sheddable.read().contains(&type_id) {
+            return false;
+        }
+
+        trace!("Shed {}", message.type_name());
+        self.metrics.shed(type_id, message.type_name());
+        true
+    }
+
+    /// Mark a payload type as sheddable, or not
+    pub(crate) fn set_sheddable(&self, type_id: TypeId, sheddable: bool) {
+        let mut types = self.sheddable.write();
+        if sheddable {
+            types.insert(type_id);
+        } else {
+            types.remove(&type_id);
+        }
+    }
+
+    /// Summarize the queued messages which `include` selects
+    pub(crate) fn summarize(&self, mut include: impl FnMut(&Message) -> bool) -> QueueSummary {
+        let now = Instant::now();
+        let mut summary = QueueSummary::default();
+
+        for queued in self.messages.read().iter() {
+            if !include(&queued.message) {
+                continue;
+            }
+
+            let age = now.saturating_duration_since(queued.queued);
+            let type_name = queued.message.type_name();
+
+            *summary.counts.entry(type_name).or_default() += 1;
+            summary.oldest = summary.oldest.max(Some(age));
+            summary.messages.push(QueuedMessage {
+                type_name,
+                age,
+                priority: queued.message.priority(),
+            });
+        }
+
+        summary
+    }
+
+    /// Set a callback to be invoked each time a message is queued, replacing any existing callback
+    pub(crate) fn set_notify(&self, notify: Option<NotifyCallback>) {
+        *self.notify.write() = notify;
+    }
+}
+
+/// Cheaply cloneable handle for queueing messages into a router
+#[derive(Debug, Clone)]
+pub struct RouterSender {
+    queue: Arc<MessageQueue>,
+}
+
+/// Senders are equal if they queue messages into the same router
+impl PartialEq for RouterSender {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.queue, &other.queue)
+    }
+}
+
+impl Eq for RouterSender {}
+
+impl RouterSender {
+    pub(crate) fn new(queue: Arc<MessageQueue>) -> Self {
+        Self { queue }
+    }
+
+    /// Queue a message to be dispatched on the next drain of the router
+    pub fn send(&self, message: Message) {
+        self.queue.push(message)
+    }
+
+    /// Get the number of messages waiting in the queue
+    pub fn queued(&self) -> usize {
+        self.queue.len()
+    }
+}
+
+/// Handle for queueing messages into a router, limited to the payload types in the tuple `T`.
+///
+/// ```compile_fail
+/// # use salish::{router::MessageRouter, queue::ScopedSender};
+/// let router = MessageRouter::<()>::new();
+/// let sender: ScopedSender<(u32, String)> = router.sender_for();
+///
+/// sender.send(5u32);
+/// sender.send(String::from("allowed"));
+///
+/// // u64 is not in the set of allowed payload types
+/// sender.send(5u64);
+/// ```
+pub struct ScopedSender<T> {
+    sender: RouterSender,
+    _types: PhantomData<fn() -> T>,
+}
+
+impl<T> std::fmt::Debug for ScopedSender<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScopedSender")
+            .field("types", &std::any::type_name::<T>())
+            .field("queue", &self.sender.queue)
+            .finish()
+    }
+}
+
+impl<T> Clone for ScopedSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+            _types: PhantomData,
+        }
+    }
+}
+
+impl<T: PayloadSet> ScopedSender<T> {
+    pub(crate) fn new(sender: RouterSender) -> Self {
+        Self {
+            sender,
+            _types: PhantomData,
+        }
+    }
+
+    /// Queue a payload to be dispatched to any handler of its type
+    pub fn send<M, I>(&self, payload: M)
+    where
+        T: Contains<M, I>,
+        M: UnicastPayload + 'static,
+    {
+        self.sender.send(Message::unicast(payload))
+    }
+
+    /// Queue a payload to be broadcast to all handlers of its type
+    pub fn broadcast<M, I>(&self, payload: M)
+    where
+        T: Contains<M, I>,
+        M: BroadcastPayload + 'static,
+    {
+        self.sender.send(Message::broadcast(payload))
+    }
+
+    /// Get the number of messages waiting in the queue
+    pub fn queued(&self) -> usize {
+        self.sender.queued()
+    }
+}
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Set of payload types a [`ScopedSender`] is allowed to send, implemented for tuples of up to six types
+pub trait PayloadSet: sealed::Sealed {}
+
+/// Implemented by a [`PayloadSet`] containing payload type `M` at the position marked by `I`.
+/// The position is inferred, so it never needs to be named.
+pub trait Contains<M, I>: PayloadSet {}
+
+/// Markers for the position of a payload type in a [`PayloadSet`]
+pub mod index {
+    pub struct I0;
+    pub struct I1;
+    pub struct I2;
+    pub struct I3;
+    pub struct I4;
+    pub struct I5;
+}
+
+macro_rules! payload_set {
+    ($($T:ident),+) => {
+        impl<$($T),+> sealed::Sealed for ($($T,)+) {}
+        impl<$($T),+> PayloadSet for ($($T,)+) {}
+    };
+}
+
+macro_rules! contains {
+    ($($T:ident),+ ; $M:ident => $I:ident) => {
+        impl<$($T),+> Contains<$M, index::$I> for ($($T,)+) {}
+    };
+}
+
+payload_set!(A);
+contains!(A; A => I0);
+
+payload_set!(A, B);
+contains!(A, B; A => I0);
+contains!(A, B; B => I1);
+
+payload_set!(A, B, C);
+contains!(A, B, C; A => I0);
+contains!(A, B, C; B => I1);
+contains!(A, B, C; C => I2);
+
+payload_set!(A, B, C, D);
+contains!(A, B, C, D; A => I0);
+contains!(A, B, C, D; B => I1);
+contains!(A, B, C, D; C => I2);
+contains!(A, B, C, D; D => I3);
+
+payload_set!(A, B, C, D, E);
+contains!(A, B, C, D, E; A => I0);
+contains!(A, B, C, D, E; B => I1);
+contains!(A, B, C, D, E; C => I2);
+contains!(A, B, C, D, E; D => I3);
+contains!(A, B, C, D, E; E => I4);
+
+payload_set!(A, B, C, D, E, F);
+contains!(A, B, C, D, E, F; A => I0);
+contains!(A, B, C, D, E, F; B => I1);
+contains!(A, B, C, D, E, F; C => I2);
+contains!(A, B, C, D, E, F; D => I3);
+contains!(A, B, C, D, E, F; E => I4);
+contains!(A, B, C, D, E, F; F => I5);
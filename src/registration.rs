@@ -0,0 +1,122 @@
+//! Static endpoints registered at compile time
+//!
+//! With the `registration` feature, free functions marked with the [`handler`](crate::handler) attribute are
+//! collected with [`inventory`] when the program is linked, so modules can declare handlers without access to the
+//! router. [`MessageRouter::with_registered_handlers()`] creates a router with a static endpoint for each of them,
+//! and [`MessageRouter::add_registered_handlers()`] adds them to an existing router.
+//!
+//! A router only receives the handlers returning its handler result type `R`, so an application with routers of
+//! several result types can register handlers for each of them.
+//!
+//! ```
+//! use salish::{message::SourceRef, router::MessageRouter, Message};
+//!
+//! #[derive(Debug, Clone)]
+//! struct TempMessage(f32);
+//!
+//! #[salish::handler(TempMessage)]
+//! fn double(_src: Option<SourceRef>, msg: TempMessage) -> f32 {
+//!     msg.0 * 2.0
+//! }
+//!
+//! let router = MessageRouter::<f32>::with_registered_handlers();
+//! let result = router.handle_message(Message::broadcast(TempMessage(1.5)));
+//! assert_eq!(result.results().unwrap().first(), Some(&3.0));
+//! ```
+
+use std::any::Any;
+
+use tracing::{debug, trace};
+
+use crate::{message::SourceRef, router::MessageRouter, traits::Payload};
+
+#[doc(hidden)]
+pub use inventory;
+
+/// Adds a registered handler to a router, returning false if the router has a different handler result type
+type Register = fn(&dyn Any) -> bool;
+
+/// Free function registered as a static endpoint by the [`handler`](crate::handler) attribute
+#[derive(Debug)]
+pub struct RegisteredHandler {
+    name: &'static str,
+    payload: &'static str,
+    register: Register,
+}
+
+impl RegisteredHandler {
+    #[doc(hidden)]
+    pub const fn new(name: &'static str, payload: &'static str, register: Register) -> Self {
+        Self {
+            name,
+            payload,
+            register,
+        }
+    }
+
+    /// Get the path of the handler function
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Get the payload type of the handler, as written in the attribute
+    pub fn payload(&self) -> &'static str {
+        self.payload
+    }
+}
+
+inventory::collect!(RegisteredHandler);
+
+/// Iterate over all handlers registered in the program
+pub fn registered_handlers() -> impl Iterator<Item = &'static RegisteredHandler> {
+    inventory::iter::<RegisteredHandler>.into_iter()
+}
+
+/// Add `handler` as a static endpoint of `router`, if it's a router with handler result type `R`
+#[doc(hidden)]
+pub fn register<M, R, F>(router: &dyn Any, handler: F) -> bool
+where
+    M: Payload + 'static,
+    R: Send + 'static,
+    F: Fn(Option<SourceRef>, M) -> R + Send + Sync + 'static,
+{
+    match router.downcast_ref::<MessageRouter<'static, R>>() {
+        Some(router) => {
+            router.static_endpoint(handler);
+            true
+        }
+        None => false,
+    }
+}
+
+impl<R: Send + 'static> MessageRouter<'static, R> {
+    /// Create a router with a static endpoint for each registered handler returning `R`
+    pub fn with_registered_handlers() -> Self {
+        let router = Self::new();
+        router.add_registered_handlers();
+        router
+    }
+
+    /// Add a static endpoint for each registered handler returning `R`, returning the number of handlers added
+    pub fn add_registered_handlers(&self) -> usize {
+        let mut added = 0;
+
+        for handler in registered_handlers() {
+            if (handler.register)(self) {
+                debug!(
+                    "Registered handler {} for {}",
+                    handler.name, handler.payload
+                );
+                added += 1;
+            } else {
+                trace!(
+                    "Skipping registered handler {}, which doesn't return {}",
+                    handler.name,
+                    std::any::type_name::<R>()
+                );
+            }
+        }
+
+        added
+    }
+}
@@ -0,0 +1,91 @@
+//! Routes to endpoints of remote routers
+//!
+//! Bridges register the endpoints which forward messages to remote routers, along with a route for each
+//! forwarded payload type. Messages sent to [`Destination::Any`](crate::message::Destination::Any) are never
+//! delivered to forwarding endpoints. If no local endpoint can receive such a message, the routes of its
+//! payload type are tried in order until one delivers it to a remote router. Messages sent to
+//! [`Destination::Remote`](crate::message::Destination::Remote) are always passed to the routes.
+
+use std::{
+    any::TypeId,
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use anylock::{AnyLock, ParkingLotRwLock};
+
+use crate::{endpoint::EndpointId, traits::internal::SalishMessageInternal as _, Message};
+
+/// Forwards a message to a remote router, returning false if no remote router can receive it
+pub(crate) type RemoteRoute = Arc<dyn Fn(&Message) -> bool + Send + Sync>;
+
+/// Forwarding endpoints and remote routes shared by all clones of a router
+pub(crate) struct RemoteRoutes {
+    forwarders: ParkingLotRwLock<HashSet<EndpointId>>,
+    routes: ParkingLotRwLock<HashMap<TypeId, Vec<(EndpointId, RemoteRoute)>>>,
+}
+
+impl Default for RemoteRoutes {
+    fn default() -> Self {
+        Self {
+            forwarders: ParkingLotRwLock::new(HashSet::new()),
+            routes: ParkingLotRwLock::new(HashMap::new()),
+        }
+    }
+}
+
+impl std::fmt::Debug for RemoteRoutes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RemoteRoutes")
+            .field("forwarders", &self.forwarders.read().len())
+            .field("types", &self.routes.read().len())
+            .finish()
+    }
+}
+
+impl RemoteRoutes {
+    /// Register `forwarder` as an endpoint forwarding payload type `type_id` to remote routers over `route`
+    #[cfg(feature = "bridge")]
+    pub(crate) fn add(&self, type_id: TypeId, forwarder: EndpointId, route: RemoteRoute) {
+        self.forwarders.write().insert(forwarder);
+        self.routes
+            .write()
+            .entry(type_id)
+            .or_default()
+            .push((forwarder, route));
+    }
+
+    /// Remove a forwarding endpoint and its routes
+    pub(crate) fn remove(&self, forwarder: EndpointId) {
+        if self.forwarders.write().remove(&forwarder) {
+            self.routes.write().retain(|_, routes| {
+                routes.retain(|(id, _)| *id != forwarder);
+                !routes.is_empty()
+            });
+        }
+    }
+
+    /// Check if an endpoint forwards messages to remote routers
+    pub(crate) fn is_forwarder(&self, endpoint: EndpointId) -> bool {
+        self.forwarders.read().contains(&endpoint)
+    }
+
+    /// Forward a message to a remote router, returning false if no route delivered it.
+    /// Messages received from remote routers are not forwarded again, so they can't loop between routers.
+    pub(crate) fn forward(&self, message: &Message) -> bool {
+        if message
+            .origin()
+            .is_some_and(|origin| self.is_forwarder(origin))
+        {
+            return false;
+        }
+
+        // Clone the routes so they are not called while holding the lock
+        let routes: Vec<RemoteRoute> = match self.routes.read().get(&message.payload_type()) {
+            Some(routes) => routes.iter().map(|(_, route)| route.clone()).collect(),
+            None => return false,
+        };
+
+        routes.iter().any(|route| route(message))
+    }
+}
@@ -0,0 +1,74 @@
+//! Last broadcast message of payload types marked as retained
+//!
+//! Like retained messages in MQTT, a router keeps the last broadcast of each payload type marked with
+//! [`MessageRouter::retain()`](crate::router::MessageRouter::retain), and delivers it to endpoints of the payload
+//! type once they are ready to receive messages. Endpoints registered after the state was broadcast then start
+//! from the current state, without requesting it separately.
+
+use std::{any::TypeId, collections::HashMap};
+
+use anylock::{AnyLock, ParkingLotRwLock};
+
+use crate::{message::Destination, traits::internal::SalishMessageInternal as _, Message};
+
+/// Retained messages by payload type, shared by all clones of a router
+pub(crate) struct RetainedMessages {
+    /// Last broadcast of each retained payload type, or `None` if none was broadcast yet
+    messages: ParkingLotRwLock<HashMap<TypeId, Option<Message>>>,
+}
+
+impl std::fmt::Debug for RetainedMessages {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let messages = self.messages.read();
+        f.debug_struct("RetainedMessages")
+            .field("types", &messages.len())
+            .field("retained", &messages.values().flatten().count())
+            .finish()
+    }
+}
+
+impl Default for RetainedMessages {
+    fn default() -> Self {
+        Self {
+            messages: ParkingLotRwLock::new(HashMap::new()),
+        }
+    }
+}
+
+impl RetainedMessages {
+    /// Retain the last broadcast of a payload type
+    pub(crate) fn retain(&self, type_id: TypeId) {
+        self.messages.write().entry(type_id).or_default();
+    }
+
+    /// Stop retaining broadcasts of a payload type, returning the retained message
+    pub(crate) fn release(&self, type_id: TypeId) -> Option<Message> {
+        self.messages.write().remove(&type_id).flatten()
+    }
+
+    /// Keep a clone of a broadcast, if its payload type is retained
+    pub(crate) fn update(&self, message: &Message) {
+        if !matches!(message.dest(), Destination::Broadcast(_)) || !message.is_cloneable() {
+            return;
+        }
+
+        let type_id = message.payload_type();
+        if !self.messages.read().contains_key(&type_id) {
+            return;
+        }
+
+        if let Some(retained) = self.messages.write().get_mut(&type_id) {
+            *retained = message.try_clone().ok();
+        }
+    }
+
+    /// Get a clone of the retained message of a payload type
+    pub(crate) fn get(&self, type_id: TypeId) -> Option<Message> {
+        self.messages
+            .read()
+            .get(&type_id)?
+            .as_ref()?
+            .try_clone()
+            .ok()
+    }
+}
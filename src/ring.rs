@@ -0,0 +1,188 @@
+//! Fixed-capacity single-producer single-consumer ring buffer
+//!
+//! A [`channel()`] allocates its slots once when it's created. [`RingSender::try_send()`] and
+//! [`RingReceiver::try_recv()`] never allocate, lock or block, so realtime threads such as audio callbacks can
+//! emit messages safely. The receiving thread drains them into a [`StaticRouter`] with
+//! [`StaticRouter::drain()`], which dispatches through its frozen table of handlers without allocating either.
+//!
+//! ```
+//! use salish::{
+//!     ring,
+//!     static_router::{MessageSet, StaticRouter},
+//! };
+//!
+//! enum Meter {
+//!     Peak(f32),
+//! }
+//!
+//! impl MessageSet for Meter {
+//!     const COUNT: usize = 1;
+//!
+//!     fn index(&self) -> usize {
+//!         0
+//!     }
+//! }
+//!
+//! let (mut tx, mut rx) = ring::channel::<Meter>(64);
+//!
+//! let mut router = StaticRouter::<Meter>::new();
+//! router.on(0, |Meter::Peak(level)| println!("Peak {level}"));
+//!
+//! // On the audio thread
+//! tx.try_send(Meter::Peak(0.8)).ok();
+//!
+//! // On the UI thread
+//! assert_eq!(router.drain(&mut rx), 1);
+//! ```
+//!
+//! [`StaticRouter`]: crate::static_router::StaticRouter
+//! [`StaticRouter::drain()`]: crate::static_router::StaticRouter::drain
+
+use std::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+/// Slots shared by the sender and receiver of a channel
+struct Ring<T> {
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+
+    /// Position of the next slot to read, only advanced by the receiver
+    head: AtomicUsize,
+
+    /// Position of the next slot to write, only advanced by the sender
+    tail: AtomicUsize,
+}
+
+// SAFETY: Each slot is only accessed by the sender while it's empty, and by the receiver while it's full.
+// Ownership of a slot is handed over with release stores of the positions.
+unsafe impl<T: Send> Send for Ring<T> {}
+unsafe impl<T: Send> Sync for Ring<T> {}
+
+impl<T> Ring<T> {
+    /// Slot at a position. The capacity is a power of two, so positions can wrap around.
+    fn slot(&self, position: usize) -> &UnsafeCell<MaybeUninit<T>> {
+        &self.slots[position & (self.slots.len() - 1)]
+    }
+}
+
+impl<T> Drop for Ring<T> {
+    fn drop(&mut self) {
+        let tail = *self.tail.get_mut();
+        let mut head = *self.head.get_mut();
+
+        // Drop the values which were sent but not received
+        while head != tail {
+            // SAFETY: Slots between the head and tail hold values, and both ends of the channel are dropped
+            unsafe { (*self.slot(head).get()).assume_init_drop() };
+            head = head.wrapping_add(1);
+        }
+    }
+}
+
+/// Create a channel holding up to `capacity` values, rounded up to a power of two.
+///
+/// # Panics
+/// Panics if `capacity` is zero
+pub fn channel<T>(capacity: usize) -> (RingSender<T>, RingReceiver<T>) {
+    assert!(capacity > 0, "Ring buffer capacity must not be zero");
+
+    let slots = (0..capacity.next_power_of_two())
+        .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+        .collect();
+
+    let ring = Arc::new(Ring {
+        slots,
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+    });
+
+    (RingSender { ring: ring.clone() }, RingReceiver { ring })
+}
+
+/// Sending end of a ring buffer [`channel()`]
+pub struct RingSender<T> {
+    ring: Arc<Ring<T>>,
+}
+
+impl<T> std::fmt::Debug for RingSender<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RingSender")
+            .field("capacity", &self.capacity())
+            .finish()
+    }
+}
+
+impl<T> RingSender<T> {
+    /// Send a value without blocking, or get it back if the ring is full
+    pub fn try_send(&mut self, value: T) -> Result<(), T> {
+        let tail = self.ring.tail.load(Ordering::Relaxed);
+        let head = self.ring.head.load(Ordering::Acquire);
+
+        if tail.wrapping_sub(head) == self.capacity() {
+            return Err(value);
+        }
+
+        // SAFETY: The slot at the tail is empty, and only the sender writes to it
+        unsafe { (*self.ring.slot(tail).get()).write(value) };
+        self.ring
+            .tail
+            .store(tail.wrapping_add(1), Ordering::Release);
+
+        Ok(())
+    }
+
+    /// Number of values the ring can hold
+    pub fn capacity(&self) -> usize {
+        self.ring.slots.len()
+    }
+}
+
+/// Receiving end of a ring buffer [`channel()`]
+pub struct RingReceiver<T> {
+    ring: Arc<Ring<T>>,
+}
+
+impl<T> std::fmt::Debug for RingReceiver<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RingReceiver")
+            .field("len", &self.len())
+            .field("capacity", &self.ring.slots.len())
+            .finish()
+    }
+}
+
+impl<T> RingReceiver<T> {
+    /// Receive the oldest value without blocking, or `None` if the ring is empty
+    pub fn try_recv(&mut self) -> Option<T> {
+        let head = self.ring.head.load(Ordering::Relaxed);
+        let tail = self.ring.tail.load(Ordering::Acquire);
+
+        if head == tail {
+            return None;
+        }
+
+        // SAFETY: The slot at the head was written by the sender, and only the receiver reads it
+        let value = unsafe { (*self.ring.slot(head).get()).assume_init_read() };
+        self.ring
+            .head
+            .store(head.wrapping_add(1), Ordering::Release);
+
+        Some(value)
+    }
+
+    /// Number of values waiting to be received
+    pub fn len(&self) -> usize {
+        let tail = self.ring.tail.load(Ordering::Acquire);
+        tail.wrapping_sub(self.ring.head.load(Ordering::Relaxed))
+    }
+
+    /// Check if no values are waiting to be received
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
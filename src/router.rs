@@ -5,121 +5,405 @@
 //!
 //! This module provides the implementation of the `MessageRouter`, which includes methods for creating new instances, registering endpoints, dispatching messages, and removing endpoints.
 
-use anylock::{AnyLock, ParkingLotRwLock};
+use anylock::{AnyLock, ParkingLotMutex, ParkingLotRwLock};
+use arc_swap::ArcSwap;
 use std::{
     any::{Any, TypeId},
-    collections::HashMap,
-    ops::Deref,
-    sync::Arc,
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
-use tracing::{debug, instrument, trace, trace_span, warn};
+use tracing::{debug, error, instrument, trace, trace_span, warn};
 
 //use rayon::{prelude::*, ThreadPool, ThreadPoolBuilder};
 
 use crate::{
-    endpoint::{handle::EndpointHandle, Endpoint, EndpointId, EndpointInner},
-    message::{Destination, Message, MessageSource},
+    cancel::CancellationToken,
+    causality::{CausalLink, CausalityLog},
+    config::{RouterConfig, Unroutable},
+    context::Resources,
+    dispatch::{DispatchResult, Results},
+    endpoint::{
+        gate,
+        handle::{EndpointHandle, FilterMatch, Undelivered},
+        ids::EndpointIds,
+        Batched, Endpoint, EndpointId, EndpointIdError, EndpointInner, Keyed, OnDemand, OneShot,
+        Select, StateMachine, Timeout,
+    },
+    error_channel::ErrorChannel,
+    filter::{Filter, FilterId},
+    handler::MessageHandler,
+    last_value::LastValues,
+    message::{Destination, EndpointName, GroupName, Message, MessageId, SourceRef},
+    metrics::{EndpointStats, RouterMetrics},
+    middleware::{
+        DeadLetter, DeadLetterRecord, DispatchRecord, DropReason, Middleware, MiddlewareChain,
+        Outcome,
+    },
+    pending::PendingMessages,
     policy::Policy,
-    traits::{internal::SalishMessageInternal as _, EndpointAddress as _, Payload},
+    queue::{MessageQueue, PayloadSet, QueueSummary, RouterSender, ScopedSender},
+    remote::RemoteRoutes,
+    retained::RetainedMessages,
+    traits::{
+        internal::SalishMessageInternal as _, BroadcastPayload, EndpointAddress as _, Payload,
+    },
+    transaction::Transaction,
+    validate::{ValidationError, Validators},
+    view::{Fold, View, Views},
+    worker::{Worker, Workers},
+    workflow::Workflow,
 };
 
+#[cfg(feature = "routes")]
+use crate::routes::{FilterSpec, RouteError, RoutesConfig};
+
 use rand::prelude::*;
+use smallvec::{smallvec, SmallVec};
 
-type HandlerList<'a, Ret, Source> = Vec<EndpointHandle<'a, Ret, Source>>;
+/// Handlers registered for a payload type. Most payload types have a few handlers, which are stored inline.
+type HandlerList<'a, Ret> = SmallVec<[Arc<EndpointHandle<'a, Ret>>; 4]>;
 
 //const THREADS: usize = 4;
 
+/// ID of the next child router
+static CHILD_ID: AtomicU64 = AtomicU64::new(0);
+
 #[derive(Debug)]
-struct TypeHandler<'a, R, S>
-where
-    S: MessageSource + Copy,
-{
-    handlers: HandlerList<'a, R, S>,
+struct TypeHandler<'a, R> {
+    handlers: HandlerList<'a, R>,
 
-    // Next index for round robin policy
-    next_index: usize,
+    // Next index for round robin policy, advanced atomically so concurrent and reentrant dispatch
+    // don't need a lock
+    next_index: AtomicUsize,
 }
 
-impl<'a, R, S> Default for TypeHandler<'a, R, S>
-where
-    S: MessageSource + Copy,
-{
-    fn default() -> Self {
+impl<'a, R> TypeHandler<'a, R> {
+    /// Create a type handler with a new list of handlers, continuing round robin from `next_index`
+    fn new(handlers: HandlerList<'a, R>, next_index: usize) -> Self {
         Self {
-            handlers: HandlerList::default(),
-            next_index: 0,
+            handlers,
+            next_index: AtomicUsize::new(next_index),
         }
     }
 }
 
-/// Message Router
-pub struct MessageRouter<'a, R, S>
-where
-    S: MessageSource + Copy,
-{
+/// Snapshot of the endpoints registered with a router.
+///
+/// Dispatch reads the current snapshot without locking, and registration replaces it with an updated copy.
+/// Handles are shared between snapshots, so copying a snapshot only copies the maps.
+struct Registry<'a, R> {
     /// Registered endpoints by EndpointId
-    endpoints: Arc<ParkingLotRwLock<HashMap<EndpointId, EndpointHandle<'a, R, S>>>>,
+    endpoints: HashMap<EndpointId, Arc<EndpointHandle<'a, R>>>,
 
     /// Map of [`TypeId`] of the Message that an Endpoint is registered to receive.
     /// This is used to dispatch messages to all registered endpoints for a specific type.
-    type_handlers: Arc<ParkingLotRwLock<HashMap<TypeId, TypeHandler<'a, R, S>>>>,
+    types: HashMap<TypeId, Arc<TypeHandler<'a, R>>>,
+
+    /// Endpoints registered under a name, with the name
+    names: HashMap<EndpointName, (EndpointId, Arc<str>)>,
+
+    /// Endpoints which joined each group, in the order they joined
+    groups: HashMap<GroupName, Vec<EndpointId>>,
+}
+
+impl<'a, R> Default for Registry<'a, R> {
+    fn default() -> Self {
+        Self {
+            endpoints: HashMap::new(),
+            types: HashMap::new(),
+            names: HashMap::new(),
+            groups: HashMap::new(),
+        }
+    }
+}
+
+impl<'a, R> Clone for Registry<'a, R> {
+    fn clone(&self) -> Self {
+        Self {
+            endpoints: self.endpoints.clone(),
+            types: self.types.clone(),
+            names: self.names.clone(),
+            groups: self.groups.clone(),
+        }
+    }
+}
+
+impl<'a, R> Registry<'a, R> {
+    /// Add a handle to a copy of the registry
+    fn with_handle(&self, handle: &Arc<EndpointHandle<'a, R>>) -> Self {
+        let mut registry = self.clone();
+        registry
+            .endpoints
+            .insert(handle.endpoint_id, handle.clone());
+
+        let (mut handlers, next_index) = match registry.types.get(&handle.payload_type) {
+            Some(type_handler) => (
+                type_handler.handlers.clone(),
+                type_handler.next_index.load(Ordering::Relaxed),
+            ),
+            None => (HandlerList::new(), 0),
+        };
+        handlers.push(handle.clone());
+
+        registry.types.insert(
+            handle.payload_type,
+            Arc::new(TypeHandler::new(handlers, next_index)),
+        );
+        registry
+    }
+
+    /// Register an endpoint under a name in a copy of the registry, replacing any endpoint with the same name
+    fn with_name(&self, name: EndpointName, label: &str, endpoint_id: EndpointId) -> Self {
+        let mut registry = self.clone();
+        registry.names.insert(name, (endpoint_id, label.into()));
+        registry
+    }
+
+    /// Add an endpoint to a group in a copy of the registry
+    fn with_member(&self, group: GroupName, endpoint_id: EndpointId) -> Self {
+        let mut registry = self.clone();
+        let members = registry.groups.entry(group).or_default();
+        if !members.contains(&endpoint_id) {
+            members.push(endpoint_id);
+        }
+        registry
+    }
+
+    /// Remove an endpoint from a group in a copy of the registry
+    fn without_member(&self, group: GroupName, endpoint_id: EndpointId) -> Self {
+        let mut registry = self.clone();
+        if let Some(members) = registry.groups.get_mut(&group) {
+            members.retain(|id| *id != endpoint_id);
+        }
+        registry.groups.retain(|_, members| !members.is_empty());
+        registry
+    }
+
+    /// Remove an endpoint from a copy of the registry
+    fn without_endpoint(&self, endpoint_id: EndpointId) -> Self {
+        let mut registry = self.clone();
+        registry.names.retain(|_, (id, _)| *id != endpoint_id);
+        for members in registry.groups.values_mut() {
+            members.retain(|id| *id != endpoint_id);
+        }
+        registry.groups.retain(|_, members| !members.is_empty());
+
+        if let Some(handle) = registry.endpoints.remove(&endpoint_id) {
+            if let Some(type_handler) = registry.types.remove(&handle.payload_type) {
+                let handlers: HandlerList<'a, R> = type_handler
+                    .handlers
+                    .iter()
+                    .filter(|h| h.endpoint_id != endpoint_id)
+                    .cloned()
+                    .collect();
+
+                // Keep the type only if there are remaining handlers
+                if !handlers.is_empty() {
+                    let next_index = type_handler.next_index.load(Ordering::Relaxed);
+                    registry.types.insert(
+                        handle.payload_type,
+                        Arc::new(TypeHandler::new(handlers, next_index)),
+                    );
+                }
+            }
+        }
+
+        registry
+    }
+}
+
+/// Description of an endpoint registered with a [`MessageRouter`]
+#[derive(Debug, Clone)]
+pub struct EndpointInfo {
+    pub id: EndpointId,
+
+    /// Rust type name of the payload type the endpoint receives
+    pub type_name: &'static str,
+
+    /// Descriptions of the filters of the endpoint
+    pub filters: Vec<String>,
+
+    /// Name the endpoint is registered under, if it was named
+    pub name: Option<String>,
+
+    /// Whether the endpoint forwards messages to remote routers through a bridge
+    pub forwarder: bool,
+
+    pub stats: EndpointStats,
+}
+
+/// Message Router
+///
+/// All state of the router is shared by its clones behind locks, so messages are dispatched through `&self`.
+/// A router can be shared by threads dispatching concurrently, directly or in an [`Arc`], or cloned into each of them.
+pub struct MessageRouter<'a, R> {
+    /// Snapshot of the registered endpoints, shared by all clones of the router
+    registry: Arc<ArcSwap<Registry<'a, R>>>,
+
+    /// Rust type names of the payload types seen by the router, shared by all clones of the router
+    type_names: Arc<ParkingLotRwLock<HashMap<TypeId, &'static str>>>,
 
     /// Static endpoints being held. These cannot be deregistered, and live as long as the router
-    static_endpoints: Option<Vec<Box<dyn Any + Send + Sync>>>,
+    static_endpoints: Option<ParkingLotMutex<Vec<Box<dyn Any + Send + Sync>>>>,
+
+    /// Message counters shared by all clones of the router
+    metrics: Arc<RouterMetrics>,
+
+    /// Inbound message queue shared by all clones of the router
+    queue: Arc<MessageQueue>,
+
+    /// Middleware and dead-letter sink shared by all clones of the router
+    middleware: Arc<MiddlewareChain>,
+
+    /// Validators of payload types shared by all clones of the router
+    validators: Arc<Validators>,
+
+    /// Routes to remote routers shared by all clones of the router
+    remote: Arc<RemoteRoutes>,
+
+    /// Worker threads of endpoints with thread affinity, shared by all clones of the router
+    workers: Arc<Workers>,
+
+    /// Messages held until an endpoint for their payload type is registered, shared by all clones of the router
+    pending: Arc<PendingMessages>,
+
+    /// Last broadcast of retained payload types, shared by all clones of the router
+    retained: Arc<RetainedMessages>,
+
+    /// Last payloads of cached payload types, shared by all clones of the router
+    last_values: Arc<LastValues>,
+
+    /// Materialized views of payload types, shared by all clones of the router
+    views: Arc<Views>,
+
+    /// Resources provided to handlers, shared by all clones of the router
+    resources: Arc<Resources>,
+
+    /// Causal links of the last dispatched messages, shared by all clones of the router
+    causality: Arc<CausalityLog>,
+
+    /// Publisher of handler errors, shared by all clones of the router
+    errors: Arc<ErrorChannel<R>>,
+
+    /// Endpoint IDs, shared by all clones of the router and its children
+    ids: Arc<EndpointIds>,
+
+    /// Router which receives the messages this router can't deliver, if this is a child router
+    parent: Option<Arc<MessageRouter<'a, R>>>,
+
+    /// Child routers by ID, shared by all clones of the router
+    children: Arc<ParkingLotRwLock<BTreeMap<u64, MessageRouter<'a, R>>>>,
+
+    /// Routers of the namespaces of this router by name, shared by all clones of the router
+    namespaces: Arc<ParkingLotRwLock<BTreeMap<String, MessageRouter<'a, R>>>>,
+
+    /// Configuration of the router, copied into clones of the router
+    config: RouterConfig,
     // /// Rayon thread pool
     //pool: Option<ThreadPool>,
 }
 
-impl<'a, R, S> Clone for MessageRouter<'a, R, S>
-where
-    //R: Send,
-    S: MessageSource + Copy,
-{
+impl<'a, R> Clone for MessageRouter<'a, R> {
     fn clone(&self) -> Self {
         MessageRouter {
-            endpoints: self.endpoints.clone(),
-            type_handlers: self.type_handlers.clone(),
+            registry: self.registry.clone(),
+            type_names: self.type_names.clone(),
 
             // Clones do not get a thread pool
             //pool: None,
 
             // Static endpoints do not get cloned
             static_endpoints: None,
+
+            metrics: self.metrics.clone(),
+            queue: self.queue.clone(),
+            middleware: self.middleware.clone(),
+            validators: self.validators.clone(),
+            remote: self.remote.clone(),
+            workers: self.workers.clone(),
+            pending: self.pending.clone(),
+            retained: self.retained.clone(),
+            last_values: self.last_values.clone(),
+            views: self.views.clone(),
+            resources: self.resources.clone(),
+            causality: self.causality.clone(),
+            errors: self.errors.clone(),
+            ids: self.ids.clone(),
+            parent: self.parent.clone(),
+            children: self.children.clone(),
+            namespaces: self.namespaces.clone(),
+            config: self.config,
         }
     }
 }
 
-impl<'a, R, S> std::fmt::Debug for MessageRouter<'a, R, S>
-where
-    S: MessageSource + Copy,
-{
+impl<'a, R> std::fmt::Debug for MessageRouter<'a, R> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        //let handlers = self.type_handlers.read();
-
-        // Get a Vec of endpoints and handler counts for debug
-        //let handlers_count: Vec<_> = handlers.iter().map(|(k, v)| (k, v.len())).collect();
-
         f.debug_struct("MessageRouter")
             .field("endpoints", &self.num_endpoints())
             .field("handlers", &self.num_handlers())
-            //.field("handlers", &self.type_handlers.read().keys())
+            .field("types", &self.handler_types())
+            .field("children", &self.children.read().len())
+            .field("namespaces", &self.namespaces())
             .finish()
     }
 }
 
-impl<'a, R, S> MessageRouter<'a, R, S>
-where
-    S: MessageSource + Copy,
-{
+impl<'a, R> Default for MessageRouter<'a, R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, R> MessageRouter<'a, R> {
     pub fn new() -> Self {
+        Self::with_config(RouterConfig::default())
+    }
+
+    /// Create a router with a [`RouterConfig`]
+    pub fn with_config(config: RouterConfig) -> Self {
+        let metrics = Arc::new(RouterMetrics::default());
+
         Self {
-            endpoints: Arc::new(ParkingLotRwLock::new(HashMap::new())),
-            type_handlers: Arc::new(ParkingLotRwLock::new(HashMap::new())),
-            static_endpoints: Some(Vec::new()),
+            registry: Arc::new(ArcSwap::from_pointee(Registry::default())),
+            type_names: Arc::new(ParkingLotRwLock::new(HashMap::new())),
+            static_endpoints: Some(ParkingLotMutex::new(Vec::new())),
+            metrics: metrics.clone(),
+            queue: Arc::new(MessageQueue::new(&config, metrics)),
+            middleware: Arc::new(MiddlewareChain::new(config.dead_letter_capacity())),
+            validators: Arc::default(),
+            remote: Arc::new(RemoteRoutes::default()),
+            workers: Arc::default(),
+            pending: Arc::new(PendingMessages::new(
+                config.unhandled_capacity(),
+                config.unhandled_ttl(),
+            )),
+            retained: Arc::default(),
+            last_values: Arc::default(),
+            views: Arc::default(),
+            resources: Arc::default(),
+            causality: Arc::new(CausalityLog::new(config.causality_capacity())),
+            errors: Arc::default(),
+            ids: Arc::default(),
+            parent: None,
+            children: Arc::new(ParkingLotRwLock::new(BTreeMap::new())),
+            namespaces: Arc::new(ParkingLotRwLock::new(BTreeMap::new())),
+            config,
             //pool: Some(Self::new_pool()),
         }
     }
 
+    /// Get the [`RouterConfig`] the router was created with
+    pub fn config(&self) -> RouterConfig {
+        self.config
+    }
+
     /*
     /// Create a new thread pool. Only the original MessageRouter obtains a pool.
     /// Clones of the router to keep references to endpoint lists for auto deregistration do not obtain a pool.
@@ -136,55 +420,456 @@ where
 
     /// Get the number of endpoints registered with the router
     pub fn num_endpoints(&self) -> usize {
-        // Sum the inner vec lengths for all keys
-        self.endpoints.read().len()
+        self.registry.load().endpoints.len()
     }
 
     /// Get the number of handlers registered with the router
     pub fn num_handlers(&self) -> usize {
         // Sum the inner vec lengths for all keys
-        self.type_handlers
-            .read()
-            .iter()
-            .map(|(_k, v)| v.handlers.len())
+        self.registry
+            .load()
+            .types
+            .values()
+            .map(|v| v.handlers.len())
             .sum()
     }
 
-    /// Call a [`Vec`] of handlers with a reference to a [`Message`]
+    /// Get the names of the payload types endpoints are registered for, and the number of endpoints
+    /// registered for each, sorted by name
+    pub fn handler_types(&self) -> Vec<(&'static str, usize)> {
+        let mut types: Vec<_> = self
+            .registry
+            .load()
+            .types
+            .values()
+            .filter_map(|v| v.handlers.first().map(|h| (h.type_name, v.handlers.len())))
+            .collect();
+        types.sort();
+        types
+    }
+
+    /// Get the Rust type name of a payload type which endpoints were registered for,
+    /// or messages were handled with
+    pub fn type_name(&self, type_id: TypeId) -> Option<&'static str> {
+        self.type_names.read().get(&type_id).copied()
+    }
+
+    /// Record the Rust type name of a payload type
+    fn add_type_name(&self, type_id: TypeId, type_name: &'static str) {
+        if !self.type_names.read().contains_key(&type_id) {
+            self.type_names.write().insert(type_id, type_name);
+        }
+    }
+
+    /// Describe the message topology of the router as a Graphviz DOT graph.
+    ///
+    /// Payload types are drawn as boxes with edges to the endpoints registered for them, which are labelled with
+    /// their filters. Endpoints forwarding messages to remote routers through bridges are drawn as diamonds.
+    pub fn dump_graph(&self) -> String {
+        let registry = self.registry.load();
+        let mut types: Vec<_> = registry
+            .types
+            .values()
+            .filter_map(|v| v.handlers.first().map(|h| (h.type_name, &v.handlers)))
+            .collect();
+        types.sort_by_key(|(type_name, _)| *type_name);
+
+        let mut dot = String::from("digraph salish {\n    rankdir=LR;\n");
+
+        for (index, (type_name, handlers)) in types.into_iter().enumerate() {
+            dot += &format!(
+                "    type{index} [shape=box, label=\"{}\"];\n",
+                escape_dot(type_name)
+            );
+
+            for handle in handlers {
+                let id = handle.endpoint_id;
+                let (shape, mut label) = if self.remote.is_forwarder(id) {
+                    ("diamond", format!("bridge {id}"))
+                } else {
+                    ("ellipse", format!("endpoint {id}"))
+                };
+
+                for filter in (handle.describe_filters)() {
+                    label += &format!("\\n{}", escape_dot(&filter));
+                }
+
+                dot += &format!("    endpoint{id} [shape={shape}, label=\"{label}\"];\n");
+                dot += &format!("    type{index} -> endpoint{id};\n");
+            }
+        }
+
+        dot += "}\n";
+        dot
+    }
+
+    /// Get the [`RouterMetrics`] of this router
+    pub fn metrics(&self) -> &Arc<RouterMetrics> {
+        &self.metrics
+    }
+
+    /// Get the causal links from the message `id` to the message which caused it, and so on, until a message
+    /// which wasn't caused by a handler or whose link was discarded. Returns an empty chain if the link of `id`
+    /// isn't kept, as configured with [`RouterConfig::causality_log()`].
+    pub fn causal_chain(&self, id: MessageId) -> Vec<CausalLink> {
+        self.causality.chain(id)
+    }
+
+    /// Get the causal links kept for the last dispatched messages, oldest first
+    pub fn causal_links(&self) -> Vec<CausalLink> {
+        self.causality.links()
+    }
+
+    /// Get a [`RouterSender`] for queueing messages into this router
+    pub fn sender(&self) -> RouterSender {
+        RouterSender::new(self.queue.clone())
+    }
+
+    /// Get a [`ScopedSender`] which can only queue the payload types in the tuple `T`,
+    /// such as `router.sender_for::<(TempMessage, HumidityMessage)>()`
+    pub fn sender_for<T: PayloadSet>(&self) -> ScopedSender<T> {
+        ScopedSender::new(self.sender())
+    }
+
+    /// Get the number of messages waiting in the inbound queue
+    pub fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Set a callback which is invoked each time a message is queued with a [`RouterSender`],
+    /// replacing any previous callback. This can be used to wake an event loop to drain the router.
+    pub fn on_queued(&self, notify: impl Fn() + Send + Sync + 'static) {
+        self.queue.set_notify(Some(Box::new(notify)))
+    }
+
+    /// Add [`Middleware`] which checks every message before it is dispatched.
+    /// Middleware is run in the order it was added.
+    pub fn add_middleware(&self, middleware: impl Middleware + 'static) {
+        self.middleware.push(Arc::new(middleware))
+    }
+
+    /// Set a sink receiving messages which were rejected by middleware, or addressed to an endpoint
+    /// of a different payload type, replacing any previous sink.
+    /// With a dead-letter buffer, the sink only receives messages discarded from the full buffer.
+    pub fn on_dead_letter(&self, sink: impl Fn(DeadLetter) + Send + Sync + 'static) {
+        self.middleware.set_dead_letter(Some(Box::new(sink)))
+    }
+
+    /// Create a child router with the configuration of this router. Messages which the child can't deliver, because
+    /// it has no endpoint for their payload type or destination, are passed up to this router.
+    /// The child is detached from this router, and its endpoints are removed, when the [`ChildRouter`] is dropped.
+    pub fn child(&self) -> ChildRouter<'a, R> {
+        let id = CHILD_ID.fetch_add(1, Ordering::Relaxed);
+
+        let mut router = Self::with_config(self.config);
+        router.parent = Some(Arc::new(self.clone()));
+        router.ids = self.ids.clone();
+        self.children.write().insert(id, router.clone());
+
+        debug!("Mounted child router {id}");
+        ChildRouter { id, router }
+    }
+
+    /// Get the router of the namespace `name`, creating it with the configuration of this router on first use.
+    ///
+    /// Each namespace has its own endpoints, so modules can register endpoints for the same payload type, such as
+    /// `String`, in different namespaces without receiving each other's messages. Messages are sent in a namespace
+    /// by handling or queueing them with its router, and are not passed to this router if the namespace has no
+    /// endpoint for them. Namespaces live as long as this router.
+    pub fn namespace(&self, name: &str) -> MessageRouter<'a, R> {
+        if let Some(router) = self.namespaces.read().get(name) {
+            return router.clone();
+        }
+
+        self.namespaces
+            .write()
+            .entry(name.to_string())
+            .or_insert_with(|| {
+                debug!("Created namespace {name}");
+                Self::with_config(self.config)
+            })
+            .clone()
+    }
+
+    /// Get the names of the namespaces of this router, sorted by name
+    pub fn namespaces(&self) -> Vec<String> {
+        self.namespaces.read().keys().cloned().collect()
+    }
+
+    /// Forward a message down to the child routers with endpoints registered for its payload type.
+    /// Broadcasts are delivered to all of them, and other messages to the first child router which was mounted.
+    pub fn forward_to_children(&self, message: Message) -> DispatchResult<R>
+    where
+        R: Send,
+    {
+        let type_id = message.payload_type();
+        let children: Vec<_> = self
+            .children
+            .read()
+            .values()
+            .filter(|child| child.registry.load().types.contains_key(&type_id))
+            .cloned()
+            .collect();
+
+        let Some((last, others)) = children.split_last() else {
+            trace!("No child router receives {}", message.type_name());
+            return DispatchResult::NoHandler;
+        };
+
+        if !matches!(message.dest(), Destination::Broadcast(_)) || !message.is_cloneable() {
+            return children[0].dispatch(message);
+        }
+
+        let mut results = Results::new();
+        for child in others {
+            let Ok(clone) = message.try_clone() else {
+                break;
+            };
+            results.extend(child.dispatch(clone).into_results().unwrap_or_default());
+        }
+        results.extend(last.dispatch(message).into_results().unwrap_or_default());
+
+        if results.is_empty() {
+            DispatchResult::NoHandler
+        } else {
+            DispatchResult::Delivered(results)
+        }
+    }
+
+    /// Register `forwarder` as an endpoint forwarding payload type `type_id` to remote routers.
+    /// Unicast messages are delivered over `route` instead of to the forwarder, if no local endpoint can receive them.
+    #[cfg(feature = "bridge")]
+    pub(crate) fn add_remote_route(
+        &self,
+        type_id: TypeId,
+        forwarder: EndpointId,
+        route: crate::remote::RemoteRoute,
+    ) {
+        self.remote.add(type_id, forwarder, route)
+    }
+
+    /// Check if any endpoint other than those forwarding to remote routers is registered for a payload type
+    #[cfg(feature = "bridge")]
+    pub(crate) fn has_local_handlers(&self, type_id: TypeId) -> bool {
+        self.registry
+            .load()
+            .types
+            .get(&type_id)
+            .is_some_and(|type_handler| {
+                type_handler
+                    .handlers
+                    .iter()
+                    .any(|handle| !self.remote.is_forwarder(handle.endpoint_id))
+            })
+    }
+
+    /// Dispatch the messages waiting in the inbound queue, returning the results of all handlers.
+    /// Messages queued by handlers during the drain are left for the next drain.
+    pub fn drain(&self) -> Vec<R>
+    where
+        R: Send,
+    {
+        self.drain_bounded(self.queue.len(), None)
+    }
+
+    /// Dispatch up to `messages` of the messages waiting in the inbound queue, returning the results of all
+    /// handlers. The remaining messages are left for the next drain, so a frame-based loop can bound the work
+    /// done per frame.
+    pub fn run_n(&self, messages: usize) -> Vec<R>
+    where
+        R: Send,
+    {
+        self.drain_bounded(messages.min(self.queue.len()), None)
+    }
+
+    /// Dispatch the messages waiting in the inbound queue until `budget` has elapsed, returning the results of all
+    /// handlers. The remaining messages are left for the next drain, so a frame-based loop such as a GUI or game
+    /// isn't starved of time to render.
+    ///
+    /// The budget is checked before each message is dispatched, so a slow handler can overrun it.
+    pub fn run_for(&self, budget: Duration) -> Vec<R>
+    where
+        R: Send,
+    {
+        self.drain_bounded(self.queue.len(), Some(Instant::now() + budget))
+    }
+
+    /// Dispatch up to `limit` queued messages, stopping early once `deadline` has passed
+    fn drain_bounded(&self, limit: usize, deadline: Option<Instant>) -> Vec<R>
+    where
+        R: Send,
+    {
+        let mut results = Vec::new();
+
+        for _ in 0..limit {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                trace!("Drain budget elapsed with {} queued", self.queue.len());
+                break;
+            }
+
+            let Some(message) = self.queue.pop() else {
+                break;
+            };
+
+            if let DispatchResult::Delivered(ret) = self.handle_message(message) {
+                results.extend(ret);
+            }
+
+            if let Some(event) = self.queue.recovered() {
+                if let DispatchResult::Delivered(ret) = self.handle_message(event) {
+                    results.extend(ret);
+                }
+            }
+        }
+
+        results
+    }
+
+    /// Summarize the messages waiting in the inbound queue which can be delivered to the endpoint `endpoint_id`,
+    /// to diagnose which consumer is backing up. Returns `None` if there's no such endpoint.
+    ///
+    /// Messages of the payload type of the endpoint are included if they are addressed to it by ID, name, group
+    /// or list of endpoints, or if they can be delivered to any endpoint receiving the payload type.
+    pub fn peek_queue(&self, endpoint_id: EndpointId) -> Option<QueueSummary> {
+        let registry = self.registry.load();
+        let handle = registry.endpoints.get(&endpoint_id)?;
+
+        Some(self.queue.summarize(|message| {
+            if message.payload_type() != handle.payload_type {
+                return false;
+            }
+
+            match message.dest() {
+                Destination::Any(_) | Destination::Broadcast(_) => true,
+                Destination::Endpoint(endpoint) => endpoint.addr() == endpoint_id,
+                Destination::Named(name) => registry
+                    .names
+                    .get(&name)
+                    .is_some_and(|(id, _)| *id == endpoint_id),
+                Destination::Group(group) => registry
+                    .groups
+                    .get(&group)
+                    .is_some_and(|members| members.contains(&endpoint_id)),
+                Destination::Multi(endpoints) => endpoints.contains(&endpoint_id),
+                Destination::Remote(..) => false,
+            }
+        }))
+    }
+
+    /// Run `f` with a [`Transaction`], and dispatch the messages it sent in order once it returns `Ok`.
+    /// If `f` returns an error, none of the messages are dispatched and the error is returned.
+    pub fn transaction<E>(
+        &self,
+        f: impl FnOnce(&mut Transaction) -> Result<(), E>,
+    ) -> Result<Vec<DispatchResult<R>>, E>
+    where
+        R: Send,
+    {
+        let mut transaction = Transaction::new();
+
+        if let Err(e) = f(&mut transaction) {
+            debug!("Transaction of {} messages discarded", transaction.len());
+            return Err(e);
+        }
+
+        Ok(transaction
+            .into_messages()
+            .into_iter()
+            .map(|message| self.handle_message(message))
+            .collect())
+    }
+
+    /// Call a list of handlers with a [`Message`]
     fn call_handlers<'b>(
         &self,
         message: Message,
-        handlers: &HandlerList<'b, R, S>,
+        handlers: &HandlerList<'b, R>,
         _policy: Policy,
-    ) -> Option<Vec<R>>
+    ) -> DispatchResult<R>
     where
         R: Send,
     {
-        let source = message.source::<S>();
+        let source = message.source_ref();
+        let origin = message.origin();
 
         match handlers.len() {
             0 => {
                 warn!("No handlers");
-                None
+                DispatchResult::NoHandler
+            }
+            // If we have a single handler, call it and wrap the result in a single element vec,
+            // or report a type mismatch if the handler couldn't downcast the message
+            1 if Some(handlers[0].endpoint_id) == origin => DispatchResult::NoHandler,
+            1 if (handlers[0].filter)(&message) == FilterMatch::Rejected => self.filtered(message),
+            1 => DispatchResult::single((handlers[0].callback)(source, message)),
+
+            // Unicast payloads can't be cloned, so they are offered to each endpoint accepting them in turn,
+            // until one takes the payload
+            _ if !message.is_cloneable() => {
+                let mut message = message;
+                let mut offered = false;
+                let mut rejected = false;
+
+                for handler in handlers
+                    .iter()
+                    .filter(|handler| Some(handler.endpoint_id) != origin)
+                {
+                    if (handler.filter)(&message) == FilterMatch::Rejected {
+                        rejected = true;
+                        continue;
+                    }
+
+                    offered = true;
+                    match (handler.callback)(source.clone(), message) {
+                        Ok(result) => return DispatchResult::Delivered(smallvec![result]),
+                        Err(Undelivered::Declined(declined)) => message = *declined,
+                        Err(Undelivered::TypeMismatch) => return DispatchResult::TypeMismatch,
+                    }
+                }
+
+                if rejected && !offered {
+                    self.filtered(message)
+                } else {
+                    DispatchResult::NoHandler
+                }
             }
-            // If we have a single handler, get a ref to the only handler,
-            // call the handler, and map the returned option into a single element vec,
-            // or return None if the handler returned None
-            1 => (handlers[0].callback)(source, message).map(|ret| vec![ret]),
 
             _ => {
-                let mut tasks: Vec<R> = vec![];
+                let mut tasks = Results::new();
+                let mut mismatched = false;
+                let mut offered = false;
+                let mut rejected = false;
 
-                tasks.extend(
-                    handlers
-                        .iter()
-                        .filter_map(|handler| (handler.callback)(source, message.clone())),
-                );
+                for handler in handlers
+                    .iter()
+                    .filter(|handler| Some(handler.endpoint_id) != origin)
+                {
+                    // Endpoints whose filters reject the message are skipped
+                    if (handler.filter)(&message) == FilterMatch::Rejected {
+                        rejected = true;
+                        continue;
+                    }
+
+                    let Ok(clone) = message.try_clone() else {
+                        break;
+                    };
+                    offered = true;
+
+                    match (handler.callback)(source.clone(), clone) {
+                        Ok(task) => tasks.push(task),
+                        Err(Undelivered::Declined(_)) => {}
+                        Err(Undelivered::TypeMismatch) => mismatched = true,
+                    }
+                }
 
-                if tasks.is_empty() {
-                    None
+                if !tasks.is_empty() {
+                    DispatchResult::Delivered(tasks)
+                } else if mismatched {
+                    DispatchResult::TypeMismatch
+                } else if rejected && !offered {
+                    self.filtered(message)
                 } else {
-                    Some(tasks)
+                    DispatchResult::NoHandler
                 }
             } /*
               // Otherwise, call each handler and collect the results
@@ -248,73 +933,617 @@ where
         }
     }
 
-    fn dispatch_any(&self, message: Message, policy: Policy) -> Option<Vec<R>> {
-        if let Some(type_handler) = self.type_handlers.write().get_mut(&message.payload_type()) {
-            let source = message.source::<S>();
+    /// Dispatch a message to a single endpoint selected by `policy`, among the endpoints accepting the message
+    fn dispatch_any(&self, message: Message, policy: Policy) -> DispatchResult<R>
+    where
+        R: Send,
+    {
+        let registry = self.registry.load();
+        let Some(type_handler) = registry.types.get(&message.payload_type()) else {
+            if self.remote.forward(&message) {
+                return DispatchResult::Delivered(Results::new());
+            }
+
+            return self.unhandled(message);
+        };
 
-            if let Some(_source) = source {
-                // Message has a source, traverse the type handlers and match filters
-                for handle in type_handler.handlers.iter() {
-                    if (handle.filter)(&message) {
-                        println!("MATCHED FILTER WITH HANDLER");
-                        return (handle.callback)(source, message).map(|res| vec![res]);
-                    }
+        let source = message.source_ref();
+        let origin = message.origin();
+
+        // Unicast messages are not delivered to endpoints forwarding to remote routers,
+        // which are reached through remote routes if there are no local endpoints
+        let unicast = matches!(message.dest(), Destination::Any(_));
+        let eligible = |handle: &EndpointHandle<'a, R>| {
+            Some(handle.endpoint_id) != origin
+                && !(unicast && self.remote.is_forwarder(handle.endpoint_id))
+        };
+
+        // Evaluate the filters of all eligible endpoints. Endpoints with a matching filter take precedence,
+        // then endpoints without filters, and endpoints whose filters reject the message are skipped.
+        let matches: SmallVec<[FilterMatch; 4]> = type_handler
+            .handlers
+            .iter()
+            .map(|handle| {
+                if eligible(handle) {
+                    (handle.filter)(&message)
+                } else {
+                    FilterMatch::Rejected
                 }
-            }
+            })
+            .collect();
 
-            match policy {
-                Policy::RoundRobin => {
-                    let handle = &type_handler.handlers
-                        [type_handler.next_index % type_handler.handlers.len()];
+        let level = if matches.contains(&FilterMatch::Matched) {
+            trace!("Dispatching among endpoints with matching filters");
+            FilterMatch::Matched
+        } else {
+            FilterMatch::Unfiltered
+        };
 
-                    type_handler.next_index = type_handler.next_index.wrapping_add(1);
+        // Number of handlers eligible to receive the message, excluding the origin endpoint
+        let count = matches.iter().filter(|m| **m == level).count();
 
-                    (handle.callback)(source, message).map(|res| vec![res])
+        if count == 0 {
+            trace!("No handlers other than origin {origin:?} accept the message");
+            return match self.dispatch_remote(&message) {
+                DispatchResult::NoHandler
+                    if type_handler.handlers.iter().any(|handle| eligible(handle)) =>
+                {
+                    self.filtered(message)
                 }
-                Policy::Random => {
-                    let index = ThreadRng::default().gen_range(0..type_handler.handlers.len());
-                    let handle = &type_handler.handlers[index];
-                    (handle.callback)(source, message).map(|res| vec![res])
+                results => results,
+            };
+        }
+
+        let start = match policy {
+            Policy::RoundRobin => {
+                // Advance past ineligible endpoints, such as the origin endpoint
+                loop {
+                    let index = type_handler.next_index.fetch_add(1, Ordering::Relaxed)
+                        % type_handler.handlers.len();
+
+                    if matches[index] == level {
+                        break index;
+                    }
                 }
             }
+            Policy::Random => {
+                let nth = ThreadRng::default().gen_range(0..count);
+                let (index, _) = matches
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, m)| **m == level)
+                    .nth(nth)
+                    .expect("Eligible handler index out of range");
+                index
+            }
+        };
+
+        // Offer the message to the selected endpoint, then to the following eligible endpoints if it's declined
+        let len = type_handler.handlers.len();
+        let mut message = message;
+
+        for index in (start..start + len)
+            .map(|index| index % len)
+            .filter(|index| matches[*index] == level)
+        {
+            match (type_handler.handlers[index].callback)(source.clone(), message) {
+                Ok(result) => return DispatchResult::Delivered(smallvec![result]),
+                Err(Undelivered::Declined(declined)) => message = *declined,
+                Err(Undelivered::TypeMismatch) => return DispatchResult::TypeMismatch,
+            }
+        }
+
+        trace!("All eligible endpoints declined {}", message.type_name());
+        DispatchResult::NoHandler
+    }
+
+    /// Forward a message without local endpoints, or destined to a remote node, to a remote router.
+    /// Forwarded messages are counted as delivered, without any results.
+    fn dispatch_remote(&self, message: &Message) -> DispatchResult<R> {
+        if self.remote.forward(message) {
+            trace!("Forwarded {} to a remote router", message.type_name());
+            DispatchResult::Delivered(Results::new())
         } else {
-            warn!(
-                "No handlers for type {:?} dest {:?}",
-                message.payload_type(),
-                message.dest()
-            );
-            None
+            DispatchResult::NoHandler
         }
     }
 
-    fn dispatch_broadcast(&self, message: Message, policy: Policy) -> Option<Vec<R>>
+    /// Drop a message which can't be routed for `reason`, handling it according to the [`Unroutable`] policy of
+    /// the router
+    fn unroutable(&self, message: Message, reason: DropReason) -> DispatchResult<R> {
+        let type_name = message.type_name();
+        let dest = message.dest();
+
+        self.metrics
+            .drop_reason(message.payload_type(), type_name, &reason);
+
+        match self.config.unroutable_policy() {
+            Unroutable::Ignore => warn!("No handlers for type {type_name} dest {dest:?}: {reason}"),
+            Unroutable::Error => error!("No handlers for type {type_name} dest {dest:?}: {reason}"),
+            Unroutable::Panic => panic!("No handlers for type {type_name} dest {dest:?}: {reason}"),
+            Unroutable::DeadLetter => {
+                debug!("No handlers for type {type_name} dest {dest:?}: {reason}");
+                self.middleware.dead_letter(message, reason);
+            }
+        }
+
+        DispatchResult::NoHandler
+    }
+
+    /// Drop a message rejected by the filters of every endpoint which could receive it. Messages which
+    /// non-blocking dispatch couldn't deliver to busy endpoints are not dropped, as they are reported as
+    /// [`DispatchResult::WouldBlock`].
+    fn filtered(&self, message: Message) -> DispatchResult<R> {
+        if gate::skipped_busy() {
+            return DispatchResult::NoHandler;
+        }
+
+        trace!("Filters of all endpoints rejected {}", message.type_name());
+        self.drop_message(message, DropReason::Filtered);
+        DispatchResult::Filtered
+    }
+
+    /// Count a message dropped for `reason`, and pass it to the dead-letter sink
+    fn drop_message(&self, message: Message, reason: DropReason) {
+        self.metrics
+            .drop_reason(message.payload_type(), message.type_name(), &reason);
+        self.middleware.dead_letter(message, reason);
+    }
+
+    /// Pass a message which a child router couldn't deliver to this router
+    fn bubble(&self, message: Message) -> DispatchResult<R>
+    where
+        R: Send,
+    {
+        trace!("Received {} from a child router", message.type_name());
+        self.dispatch(message)
+    }
+
+    /// Pass a message of a payload type without endpoints to the parent router, hold it if the router is
+    /// configured to, or drop it as unroutable
+    fn unhandled(&self, message: Message) -> DispatchResult<R>
+    where
+        R: Send,
+    {
+        if let Some(parent) = &self.parent {
+            return parent.bubble(message);
+        }
+
+        if !self.pending.enabled() {
+            return self.unroutable(message, DropReason::Unroutable);
+        }
+
+        trace!(
+            "Holding {} until an endpoint is registered",
+            message.type_name()
+        );
+        for (discarded, reason) in self.pending.push(message) {
+            self.discard_pending(discarded, reason);
+        }
+
+        DispatchResult::Pending
+    }
+
+    /// Dispatch the messages held for a payload type, once `endpoint` for the payload type can receive them.
+    /// If no messages were held, the retained message of the payload type is delivered to `endpoint`.
+    pub(crate) fn flush_pending(&self, type_id: TypeId, endpoint: EndpointId)
+    where
+        R: Send,
+    {
+        let (live, expired) = self.pending.take(type_id);
+
+        for message in expired {
+            self.discard_pending(message, DropReason::Expired);
+        }
+
+        if live.is_empty() {
+            if let Some(retained) = self.retained.get(type_id) {
+                debug!("Delivering retained {} to {endpoint}", retained.type_name());
+                self.route(retained.with_dest(Destination::endpoint(endpoint)));
+            }
+            return;
+        }
+
+        debug!("Dispatching {} held messages", live.len());
+
+        for message in live {
+            self.route(message);
+        }
+    }
+
+    /// Provide a resource of type `T` to handlers receiving a [`Ctx`](crate::context::Ctx), returning the
+    /// resource of the same type it replaced
+    pub fn provide<T: Send + Sync + 'static>(&self, resource: T) -> Option<Arc<T>> {
+        self.provide_arc(Arc::new(resource))
+    }
+
+    /// Provide a shared resource of type `T` to handlers receiving a [`Ctx`](crate::context::Ctx)
+    pub fn provide_arc<T: Send + Sync + 'static>(&self, resource: Arc<T>) -> Option<Arc<T>> {
+        debug!("Providing resource {}", std::any::type_name::<T>());
+        self.resources.insert(resource)
+    }
+
+    /// Remove the resource of type `T` provided to handlers
+    pub fn withdraw<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.resources.remove::<T>()
+    }
+
+    /// Get the resource of type `T` provided to handlers
+    pub fn resource<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        self.resources.get::<T>()
+    }
+
+    /// Get the resources provided to handlers
+    pub(crate) fn resources(&self) -> &Arc<Resources> {
+        &self.resources
+    }
+
+    /// Validate each message of payload type `M` with `validator` when it's dispatched, before it's passed to the
+    /// middleware. Messages rejected by a validator are not dispatched, and are handled with
+    /// [`DispatchResult::Invalid`] holding the error of the validator. Several validators of a payload type run in
+    /// the order they were added.
+    pub fn add_validator<M, E>(
+        &self,
+        validator: impl Fn(&M) -> Result<(), E> + Send + Sync + 'static,
+    ) where
+        M: Payload + 'static,
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        self.validators.add(validator)
+    }
+
+    /// Remove the validators of payload type `M`
+    pub fn remove_validators<M: 'static>(&self) {
+        self.validators.remove(TypeId::of::<M>())
+    }
+
+    /// Check a message with the validators of its payload type, without dispatching it
+    pub fn validate(&self, message: &Message) -> Result<(), ValidationError> {
+        self.validators.validate(message)
+    }
+
+    /// Get the worker thread named `name`, spawning it if it doesn't exist yet
+    pub(crate) fn worker(&self, name: &str) -> Arc<Worker> {
+        self.workers.get_or_spawn(name)
+    }
+
+    /// Names of the worker threads spawned for endpoints assigned with [`Endpoint::on_worker()`]
+    pub fn workers(&self) -> Vec<String> {
+        self.workers.names()
+    }
+
+    /// Retain the last broadcast of payload type `M`, and deliver it to each endpoint of `M` once it's ready
+    /// to receive messages, so endpoints registered later receive the current state
+    pub fn retain<M: BroadcastPayload + 'static>(&self) {
+        self.retained.retain(TypeId::of::<M>())
+    }
+
+    /// Stop retaining broadcasts of payload type `M`, returning the payload of the retained message
+    pub fn release_retained<M: 'static>(&self) -> Option<M> {
+        self.retained.release(TypeId::of::<M>())?.into_inner::<M>()
+    }
+
+    /// Get a clone of the payload of the retained message of type `M`
+    pub fn retained<M: Clone + 'static>(&self) -> Option<M> {
+        self.retained.get(TypeId::of::<M>())?.into_inner::<M>()
+    }
+
+    /// Mark payload type `M` as sheddable, so its messages are dropped while the router sheds load as configured
+    /// with [`RouterConfig::shed_load()`]. Dropped messages are counted in [`TypeMetrics::shed`].
+    ///
+    /// [`TypeMetrics::shed`]: crate::metrics::TypeMetrics::shed
+    pub fn sheddable<M: 'static>(&self) {
+        self.queue.set_sheddable(TypeId::of::<M>(), true)
+    }
+
+    /// Stop shedding the messages of payload type `M`
+    pub fn remove_sheddable<M: 'static>(&self) {
+        self.queue.set_sheddable(TypeId::of::<M>(), false)
+    }
+
+    /// Keep a clone of the payload of the last message of type `M` dispatched by the router,
+    /// which can be polled with [`MessageRouter::last()`]
+    pub fn cache_last<M: BroadcastPayload + 'static>(&self) {
+        self.last_values.cache::<M>()
+    }
+
+    /// Stop caching the last payload of type `M`
+    pub fn uncache_last<M: 'static>(&self) {
+        self.last_values.remove(TypeId::of::<M>())
+    }
+
+    /// Get a clone of the payload of the last message of type `M`, if `M` was registered with
+    /// [`MessageRouter::cache_last()`] and a message of `M` was dispatched since
+    pub fn last<M: Clone + 'static>(&self) -> Option<M> {
+        self.last_values.get::<M>()
+    }
+
+    /// Create a [`View`] folding the messages of type `M` dispatched by the router into a map.
+    /// Each message is passed to `reduce` with the value of the key extracted by `key`.
+    pub fn view<M, K, V>(
+        &self,
+        key: impl Fn(&M) -> K + Send + Sync + 'static,
+        reduce: impl Fn(&mut V, M) + Send + Sync + 'static,
+    ) -> View<K, V, M>
+    where
+        M: Clone + 'static,
+        K: Eq + Hash + Send + Sync + 'static,
+        V: Default + Send + Sync + 'static,
+    {
+        View::new(self.views.clone(), key, reduce)
+    }
+
+    /// Rebuild state from a journal of persisted payloads passed by the caller, such as messages read back from a
+    /// log by the application, then keep reducing the messages of type `M` dispatched by the router into the
+    /// returned [`Fold`] until it's dropped.
+    ///
+    /// The fold is registered before the journal is replayed, so messages dispatched during the replay are not
+    /// lost: they are reduced as they are dispatched, between the payloads of the journal. Messages which are
+    /// both in the journal and dispatched during the replay are reduced twice, so the journal should end where
+    /// the messages dispatched live start, or the reducer should ignore duplicates.
+    pub fn fold_journal_live<M, S>(
+        &self,
+        journal: impl IntoIterator<Item = M>,
+        init: S,
+        reducer: impl Fn(&mut S, M) + Send + Sync + 'static,
+    ) -> Fold<S, M>
+    where
+        M: Clone + 'static,
+        S: Send + Sync + 'static,
+    {
+        Fold::with_journal(self.views.clone(), journal, init, reducer)
+    }
+
+    /// Drop a held message which expired, or was discarded to make room for newer messages
+    fn discard_pending(&self, message: Message, reason: DropReason) {
+        self.metrics
+            .record(message.payload_type(), message.type_name(), None);
+        self.unroutable(message, reason);
+    }
+
+    /// Get the number of messages held until an endpoint for their payload type is registered
+    pub fn num_pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    fn dispatch_broadcast(&self, message: Message, policy: Policy) -> DispatchResult<R>
     where
         R: Send,
     {
         // Broadcast clones to all endpoints registered for the [`TypeId`] of the incoming message
-        let type_handlers = self.type_handlers.read();
+        let registry = self.registry.load();
+
+        let Some(type_handler) = registry.types.get(&message.payload_type()) else {
+            return self.unhandled(message);
+        };
+
+        if type_handler.handlers.len() == 1 {
+            return self.dispatch_any(message, policy);
+        }
 
-        if let Some(type_handler) = type_handlers.get(&message.payload_type()) {
-            if type_handler.handlers.len() == 1 {
-                drop(type_handlers);
-                return self.dispatch_any(message, policy);
+        self.call_handlers(message, &type_handler.handlers, policy)
+    }
+
+    /// Handle a message, and route them to registered [`MessageHandler`] implementations.
+    /// Returns the results of the handlers, or the reason the message was not delivered.
+    #[instrument(name = "router")]
+    pub fn handle_message(&self, message: Message) -> DispatchResult<R>
+    where
+        R: Send,
+    {
+        self.dispatch(message)
+    }
+
+    /// Handle a message without waiting for handlers running on other threads, for latency-critical callers such
+    /// as audio threads. Endpoints which are busy are skipped: a unicast message is offered to the next endpoint
+    /// which can receive it, and a broadcast is delivered to the endpoints which aren't busy. Returns
+    /// [`DispatchResult::WouldBlock`] if the message couldn't be delivered because the endpoints were busy.
+    ///
+    /// Messages dispatched by the handlers which are called are also non-blocking. The locks of the router
+    /// configuration are still taken, as they are only held while the configuration is changed.
+    pub fn try_handle_message(&self, message: Message) -> DispatchResult<R>
+    where
+        R: Send,
+    {
+        gate::non_blocking(|| self.dispatch(message))
+    }
+
+    /// Handle a message, returning an iterator which calls the handlers of a broadcast as the results are consumed.
+    ///
+    /// A caller which only needs some of the results, such as the first handler returning a response, can stop
+    /// iterating without calling the remaining handlers. Dropping the iterator skips the handlers which weren't
+    /// called. Messages which are not broadcasts are delivered to their single destination immediately.
+    pub fn handle_message_iter(&self, message: Message) -> DispatchIter<'a, R>
+    where
+        R: Send,
+    {
+        match self.dispatch_lazy(message) {
+            Ok(lazy) => DispatchIter {
+                lazy: Some(lazy),
+                results: Results::new().into_iter(),
+            },
+            Err(results) => DispatchIter::results(results),
+        }
+    }
+
+    /// Handle a message, stopping a broadcast between handlers once `token` is cancelled.
+    ///
+    /// A cancelled broadcast returns [`DispatchResult::Cancelled`] with the results of the handlers which were called.
+    /// Handlers can clone the token to stop long running work cooperatively. Messages which are not broadcasts are
+    /// delivered to their single destination, unless the token was cancelled before they were handled.
+    pub fn handle_message_cancellable(
+        &self,
+        message: Message,
+        token: &CancellationToken,
+    ) -> DispatchResult<R>
+    where
+        R: Send,
+    {
+        if token.is_cancelled() {
+            debug!("Dispatch of {} cancelled", message.type_name());
+            return DispatchResult::Cancelled(Results::new());
+        }
+
+        let mut lazy = match self.dispatch_lazy(message) {
+            Ok(lazy) => lazy,
+            Err(results) => return results,
+        };
+
+        let mut results = Results::new();
+        while let Some(result) = lazy.call_next() {
+            results.push(result);
+
+            if token.is_cancelled() {
+                debug!(
+                    "Broadcast of {} cancelled after {} handlers",
+                    lazy.type_name,
+                    results.len()
+                );
+                lazy.cancelled = true;
+                return DispatchResult::Cancelled(results);
             }
+        }
 
-            self.call_handlers(message, &type_handler.handlers, policy)
+        if results.is_empty() {
+            lazy.undelivered()
         } else {
-            warn!("No Handler for broadcast");
-            None
+            DispatchResult::Delivered(results)
         }
     }
 
-    /// Handle a message, and route them to registered [`MessageHandler`] implementations
-    #[instrument(name = "router")]
-    pub fn handle_message<'b>(&'b mut self, message: Message) -> Option<Vec<R>>
+    /// Run a message through the middleware, and prepare to call the handlers of a broadcast one at a time.
+    /// Other messages are dispatched immediately, returning their results as the error.
+    fn dispatch_lazy(&self, message: Message) -> Result<LazyDispatch<'a, R>, DispatchResult<R>>
+    where
+        R: Send,
+    {
+        let (message, record) = self.prepare(message)?;
+
+        // Broadcasts of unicast payloads are delivered to a single endpoint, so they are not dispatched lazily
+        let type_handler = match message.dest() {
+            Destination::Broadcast(_) if message.is_cloneable() => self
+                .registry
+                .load()
+                .types
+                .get(&message.payload_type())
+                .cloned(),
+            _ => None,
+        };
+
+        match type_handler {
+            Some(type_handler) if type_handler.handlers.len() > 1 => Ok(LazyDispatch {
+                router: self.clone(),
+                type_handler,
+                next: 0,
+                source: message.source_ref(),
+                type_id: message.payload_type(),
+                type_name: message.type_name(),
+                message: Some(message),
+                delivered: 0,
+                offered: false,
+                rejected: false,
+                mismatched: false,
+                filtered: false,
+                cancelled: false,
+                record,
+            }),
+            _ => Err(self.deliver(message, record)),
+        }
+    }
+
+    /// Run a message through the middleware, and route it to its destination
+    fn dispatch(&self, message: Message) -> DispatchResult<R>
     where
         R: Send,
     {
         trace!("{message:?}");
-        match message.dest() {
+
+        match self.prepare(message) {
+            Ok((message, record)) => self.deliver(message, record),
+            Err(result) => result,
+        }
+    }
+
+    /// Record a message and check it before it's dispatched. Returns the message with its record for the
+    /// middleware if it's admitted, or the result of the dispatch if it was dropped.
+    fn prepare(
+        &self,
+        message: Message,
+    ) -> Result<(Message, Option<DispatchRecord>), DispatchResult<R>> {
+        let type_id = message.payload_type();
+        let type_name = message.type_name();
+        self.add_type_name(type_id, type_name);
+        self.causality.record(&message);
+
+        let record = self.middleware.record(&message);
+
+        if let Err((reason, result)) = self.admit(&message) {
+            debug!("Rejected {type_name}: {reason}");
+            self.metrics.record(type_id, type_name, None);
+            self.middleware
+                .dispatched(record, Outcome::Dropped(reason.clone()));
+            self.drop_message(message, reason);
+            return Err(result);
+        }
+
+        self.retained.update(&message);
+        self.last_values.update(&message);
+        self.views.update(&message);
+
+        Ok((message, record))
+    }
+
+    /// Route a message which was admitted, pass the outcome to the middleware and republish the errors of the
+    /// handlers
+    fn deliver(&self, message: Message, record: Option<DispatchRecord>) -> DispatchResult<R>
+    where
+        R: Send,
+    {
+        let type_id = message.payload_type();
+        let type_name = message.type_name();
+
+        let results = match gate::track_busy(|| self.route(message)) {
+            (DispatchResult::NoHandler, true) => DispatchResult::WouldBlock,
+            (results, _) => results,
+        };
+        self.middleware.dispatched(record, Outcome::from(&results));
+
+        for error in self.errors.errors(type_id, type_name, &results) {
+            self.dispatch(error);
+        }
+
+        results
+    }
+
+    /// Check a message with the validators of its payload type, its time to live and the middleware before it's
+    /// dispatched, getting the reason it's dropped and the result of the dispatch if it's rejected
+    fn admit(&self, message: &Message) -> Result<(), (DropReason, DispatchResult<R>)> {
+        if let Err(error) = self.validators.validate(message) {
+            let reason = DropReason::Invalid(error.error().to_string());
+            return Err((reason, DispatchResult::Invalid(error)));
+        }
+
+        if message.is_expired() {
+            return Err((
+                DropReason::Expired,
+                DispatchResult::Rejected(DropReason::Expired),
+            ));
+        }
+
+        self.middleware
+            .check(message)
+            .map_err(|reason| (reason.clone(), DispatchResult::Rejected(reason)))
+    }
+
+    /// Route a message which passed the middleware to its destination, and record the outcome
+    fn route(&self, message: Message) -> DispatchResult<R>
+    where
+        R: Send,
+    {
+        let type_id = message.payload_type();
+        let type_name = message.type_name();
+
+        let results = match message.dest() {
             // Deliver to a single destination endpoint registered for the message type
             Destination::Any(policy) => self.dispatch_any(message, policy),
 
@@ -324,105 +1553,850 @@ where
             // Deliver to a specific [`EndpointId`]
             Destination::Endpoint(endpoint) => {
                 trace!("Sending to endpoint {}", endpoint.addr());
+                self.dispatch_endpoint(message, endpoint.addr())
+            }
 
-                if let Some(handle) = self.endpoints.read().get(&endpoint.addr()) {
-                    let source = message.source::<S>();
-                    (handle.callback)(source, message).map(|res| vec![res])
-                } else {
-                    None
+            // Deliver to the endpoint registered under a name
+            Destination::Named(name) => {
+                let endpoint = self.registry.load().names.get(&name).map(|(id, _)| *id);
+                match endpoint {
+                    Some(endpoint) => {
+                        trace!("Sending to endpoint {endpoint} named {name:?}");
+                        self.dispatch_endpoint(message, endpoint)
+                    }
+                    None => self.pass_to_parent(message),
+                }
+            }
+
+            // Deliver to the members of a group
+            Destination::Group(group) => self.dispatch_group(message, group),
+
+            // Deliver to each of a list of endpoints
+            Destination::Multi(endpoints) => self.dispatch_multi(message, endpoints),
+
+            // Deliver to a specific endpoint of a remote node through a bridge
+            Destination::Remote(node, addr) => {
+                trace!("Sending to endpoint {addr} of node {node:?}");
+                match self.dispatch_remote(&message) {
+                    DispatchResult::NoHandler => self.unroutable(message, DropReason::Unroutable),
+                    results => results,
                 }
             }
+        };
+
+        // Held messages are recorded once they are dispatched
+        if !matches!(results, DispatchResult::Pending) {
+            self.metrics
+                .record(type_id, type_name, results.results().map(<[R]>::len));
+        }
+
+        results
+    }
+
+    /// Deliver a message to the members of `group` which receive its payload type, or pass it to the parent router
+    /// if there are none
+    fn dispatch_group(&self, message: Message, group: GroupName) -> DispatchResult<R>
+    where
+        R: Send,
+    {
+        let registry = self.registry.load();
+        let members: HandlerList<'a, R> = registry
+            .groups
+            .get(&group)
+            .into_iter()
+            .flatten()
+            .filter_map(|id| registry.endpoints.get(id))
+            .filter(|handle| handle.payload_type == message.payload_type())
+            .cloned()
+            .collect();
+
+        if members.is_empty() {
+            trace!(
+                "No members of group {group:?} receive {}",
+                message.type_name()
+            );
+            return self.pass_to_parent(message);
         }
+
+        // Unicast messages are delivered to the first member accepting them
+        self.call_handlers(message, &members, Policy::default())
+    }
+
+    /// Deliver a message to the endpoint `endpoint_id`, or pass it to the parent router if there's no such endpoint
+    fn dispatch_endpoint(&self, message: Message, endpoint_id: EndpointId) -> DispatchResult<R>
+    where
+        R: Send,
+    {
+        match self.registry.load().endpoints.get(&endpoint_id) {
+            Some(handle) if handle.payload_type != message.payload_type() => {
+                handle.stats.error();
+                warn!(
+                    "Endpoint {endpoint_id} does not receive {}",
+                    message.type_name()
+                );
+                self.drop_message(message, DropReason::TypeMismatch);
+                DispatchResult::TypeMismatch
+            }
+            Some(handle) => {
+                let source = message.source_ref();
+                DispatchResult::single((handle.callback)(source, message))
+            }
+            None => self.pass_to_parent(message),
+        }
+    }
+
+    /// Deliver clones of a message to each of `endpoints`, reporting the endpoints it couldn't be delivered to
+    fn dispatch_multi(&self, message: Message, endpoints: Vec<EndpointId>) -> DispatchResult<R>
+    where
+        R: Send,
+    {
+        // Unicast payloads can't be cloned, so they are offered to each endpoint which can receive them in turn,
+        // until one takes the payload
+        if !message.is_cloneable() {
+            let registry = self.registry.load();
+            let source = message.source_ref();
+            let mut message = message;
+            let mut declined = false;
+
+            for id in endpoints {
+                let Some(handle) = registry.endpoints.get(&id) else {
+                    continue;
+                };
+
+                if handle.payload_type != message.payload_type()
+                    || (handle.filter)(&message) == FilterMatch::Rejected
+                {
+                    continue;
+                }
+
+                match (handle.callback)(source.clone(), message) {
+                    Ok(result) => return DispatchResult::Delivered(smallvec![result]),
+                    Err(Undelivered::Declined(returned)) => {
+                        message = *returned;
+                        declined = true;
+                    }
+                    Err(Undelivered::TypeMismatch) => return DispatchResult::TypeMismatch,
+                }
+            }
+
+            return if declined {
+                DispatchResult::NoHandler
+            } else {
+                self.pass_to_parent(message)
+            };
+        }
+
+        let mut results = Results::new();
+        let mut failed = Vec::new();
+
+        for endpoint in endpoints {
+            let Ok(clone) = message.try_clone() else {
+                break;
+            };
+
+            match self.dispatch_endpoint(clone, endpoint) {
+                DispatchResult::Delivered(delivered) => results.extend(delivered),
+                failure => failed.push((endpoint, failure)),
+            }
+        }
+
+        if failed.is_empty() {
+            DispatchResult::Delivered(results)
+        } else {
+            debug!(
+                "{} couldn't be delivered to {} endpoints",
+                message.type_name(),
+                failed.len()
+            );
+            DispatchResult::Partial(results, failed)
+        }
+    }
+
+    /// Pass a message addressed to an endpoint this router doesn't have to the parent router, or drop it as
+    /// unroutable
+    fn pass_to_parent(&self, message: Message) -> DispatchResult<R>
+    where
+        R: Send,
+    {
+        match &self.parent {
+            Some(parent) => parent.bubble(message),
+            None => self.unroutable(message, DropReason::Unroutable),
+        }
+    }
+
+    /// Deliver a payload straight to the endpoint `endpoint_id`, without constructing a [`Message`].
+    ///
+    /// This is a fast path for callers which know the concrete payload type and target endpoint. The payload is
+    /// not boxed or downcast, and is delivered without a source. Middleware and endpoint filters are bypassed,
+    /// so it should only be used for trusted messages.
+    pub fn send_direct<M>(&self, endpoint_id: EndpointId, payload: M) -> DispatchResult<R>
+    where
+        M: Payload + 'static,
+    {
+        let type_id = TypeId::of::<M>();
+        let type_name = std::any::type_name::<M>();
+
+        let results = match self.registry.load().endpoints.get(&endpoint_id) {
+            Some(handle) if handle.payload_type == type_id => {
+                DispatchResult::single((handle.direct)(None, &mut Some(payload)))
+            }
+            Some(handle) => {
+                handle.stats.error();
+                warn!("Endpoint {endpoint_id} does not receive {type_name}");
+                self.metrics
+                    .drop_reason(type_id, type_name, &DropReason::TypeMismatch);
+                DispatchResult::TypeMismatch
+            }
+            None => {
+                warn!("No endpoint {endpoint_id} for {type_name}");
+                self.metrics
+                    .drop_reason(type_id, type_name, &DropReason::Unroutable);
+                DispatchResult::NoHandler
+            }
+        };
+
+        self.metrics
+            .record(type_id, type_name, results.results().map(<[R]>::len));
+
+        results
+    }
+
+    /// Get the statistics of a registered endpoint
+    pub fn endpoint_stats(&self, endpoint_id: EndpointId) -> Option<EndpointStats> {
+        self.registry
+            .load()
+            .endpoints
+            .get(&endpoint_id)
+            .map(|handle| handle.stats.snapshot())
+    }
+
+    /// Get the number of dropped messages in the dead-letter buffer configured with
+    /// [`RouterConfig::dead_letter_buffer()`]
+    pub fn buffered_dead_letters(&self) -> usize {
+        self.middleware.buffered()
+    }
+
+    /// Redeliver the messages in the dead-letter buffer matching `filter`, oldest first, returning the result of
+    /// dispatching each. This can be used to retry messages which were sent before their endpoint was registered.
+    /// Messages which are dropped again are buffered again.
+    pub fn redeliver_dead_letters(
+        &self,
+        filter: impl Fn(&DeadLetter) -> bool,
+    ) -> Vec<DispatchResult<R>>
+    where
+        R: Send,
+    {
+        let dead_letters = self.middleware.take_buffered(filter);
+        debug!("Redelivering {} dead letters", dead_letters.len());
+
+        dead_letters
+            .into_iter()
+            .map(|dead_letter| self.handle_message(dead_letter.message))
+            .collect()
+    }
+
+    /// Describe the registered endpoints, sorted by [`EndpointId`]
+    pub fn endpoints(&self) -> Vec<EndpointInfo> {
+        let registry = self.registry.load();
+        let names: HashMap<EndpointId, &Arc<str>> = registry
+            .names
+            .values()
+            .map(|(id, name)| (*id, name))
+            .collect();
+
+        let mut endpoints: Vec<_> = registry
+            .endpoints
+            .values()
+            .map(|handle| EndpointInfo {
+                id: handle.endpoint_id,
+                type_name: handle.type_name,
+                filters: (handle.describe_filters)(),
+                name: names.get(&handle.endpoint_id).map(|name| name.to_string()),
+                forwarder: self.remote.is_forwarder(handle.endpoint_id),
+                stats: handle.stats.snapshot(),
+            })
+            .collect();
+
+        endpoints.sort_by_key(|info| info.id);
+        endpoints
+    }
+
+    /// Get the number of messages passed to the dead-letter sink
+    pub fn dead_letters(&self) -> u64 {
+        self.middleware.dropped()
+    }
+
+    /// Get the most recently dropped messages, oldest first
+    pub fn recent_dead_letters(&self) -> Vec<DeadLetterRecord> {
+        self.middleware.recent()
+    }
+
+    /// Register the endpoint `endpoint_id` under `name`, so messages sent to [`Destination::Named`] reach it.
+    /// An endpoint previously registered under the same name loses the name.
+    pub fn name_endpoint(&self, endpoint_id: EndpointId, name: &str) {
+        self.register_name(endpoint_id, EndpointName::new(name), name);
+    }
+
+    /// Register the endpoint `endpoint_id` as the endpoint responsible for `key`, so messages sent to
+    /// [`Destination::hashed()`] with the key reach it. The endpoint is named by the [`Debug`] format of the key.
+    pub fn claim_hash<K>(&self, endpoint_id: EndpointId, key: &K)
+    where
+        K: std::fmt::Debug + std::hash::Hash + ?Sized,
+    {
+        self.register_name(endpoint_id, EndpointName::hashed(key), &format!("{key:?}"));
+    }
+
+    /// Get the endpoint which claimed `key`
+    pub fn endpoint_by_hash<K>(&self, key: &K) -> Option<EndpointId>
+    where
+        K: std::hash::Hash + ?Sized,
+    {
+        self.registry
+            .load()
+            .names
+            .get(&EndpointName::hashed(key))
+            .map(|(id, _)| *id)
+    }
+
+    fn register_name(&self, endpoint_id: EndpointId, name: EndpointName, label: &str) {
+        let previous = self
+            .registry
+            .rcu(|registry| registry.with_name(name, label, endpoint_id));
+
+        match previous.names.get(&name) {
+            Some((previous, _)) if *previous != endpoint_id => {
+                warn!("Endpoint {endpoint_id} replaces endpoint {previous} named {label}")
+            }
+            _ => debug!("Naming endpoint {endpoint_id} {label}"),
+        }
+    }
+
+    /// Add the endpoint `endpoint_id` to `group`, so it receives messages sent to [`Destination::Group`].
+    /// Endpoints leave their groups when they are deregistered.
+    pub fn join_group(&self, endpoint_id: EndpointId, group: &str) {
+        debug!("Endpoint {endpoint_id} joining group {group}");
+        self.registry
+            .rcu(|registry| registry.with_member(GroupName::new(group), endpoint_id));
+    }
+
+    /// Add a filter to the registered endpoint `endpoint_id`, returning its ID to remove it from the endpoint,
+    /// or `None` if there's no such endpoint
+    pub fn add_filter(
+        &self,
+        endpoint_id: EndpointId,
+        filter: impl Filter + 'static,
+    ) -> Option<FilterId> {
+        let registry = self.registry.load();
+        let handle = registry.endpoints.get(&endpoint_id)?;
+        Some((handle.add_filter)(Box::new(filter)))
+    }
+
+    /// Apply the routing rules of a [`RoutesConfig`], adding filters to the named endpoints and joining them to
+    /// groups. The rules are checked before any is applied, so an invalid configuration leaves the router
+    /// unchanged.
+    #[cfg(feature = "routes")]
+    pub fn load_routes(&self, config: &RoutesConfig) -> Result<(), RouteError> {
+        let mut routes = Vec::with_capacity(config.routes.len());
+
+        for route in &config.routes {
+            let endpoint = self
+                .endpoint_by_name(&route.endpoint)
+                .ok_or_else(|| RouteError::UnknownEndpoint(route.endpoint.clone()))?;
+
+            let filters = route
+                .filters
+                .iter()
+                .map(FilterSpec::build)
+                .collect::<Result<Vec<_>, _>>()?;
+
+            routes.push((endpoint, filters, &route.groups));
+        }
+
+        for (endpoint, filters, groups) in routes {
+            for filter in filters {
+                self.add_filter(endpoint, filter);
+            }
+
+            for group in groups {
+                self.join_group(endpoint, group);
+            }
+        }
+
+        debug!("Loaded {} routes", config.routes.len());
+        Ok(())
+    }
+
+    /// Remove the endpoint `endpoint_id` from `group`
+    pub fn leave_group(&self, endpoint_id: EndpointId, group: &str) {
+        debug!("Endpoint {endpoint_id} leaving group {group}");
+        self.registry
+            .rcu(|registry| registry.without_member(GroupName::new(group), endpoint_id));
+    }
+
+    /// Get the endpoints which joined `group`, in the order they joined
+    pub fn group_members(&self, group: &str) -> Vec<EndpointId> {
+        self.registry
+            .load()
+            .groups
+            .get(&GroupName::new(group))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Get the endpoint registered under `name`
+    pub fn endpoint_by_name(&self, name: &str) -> Option<EndpointId> {
+        self.registry
+            .load()
+            .names
+            .get(&EndpointName::new(name))
+            .map(|(id, _)| *id)
     }
 
     /// Remove a registered [`Endpoint`] from the [`MessageRouter`] specified by [`EndpointId`]
     #[instrument(name = "router")]
     pub fn remove_endpoint(&self, endpoint_id: EndpointId) {
-        debug!("Removing Endpoint ID {endpoint_id}");
+        let previous = self
+            .registry
+            .rcu(|registry| registry.without_endpoint(endpoint_id));
+
+        if let Some(handle) = previous.endpoints.get(&endpoint_id) {
+            debug!(
+                "Removing Endpoint ID {endpoint_id} for {}",
+                handle.type_name
+            );
+        }
+
+        self.remote.remove(endpoint_id);
+        self.ids.release(endpoint_id);
+    }
 
-        self.endpoints.write().remove(&endpoint_id);
+    /// Allocate the ID of a new endpoint
+    pub(crate) fn allocate_endpoint_id(&self) -> EndpointId {
+        self.ids.allocate()
+    }
 
-        // Remove the EndpointId from the TypeId handler map
-        // If this was the last entry being removed from a TypeId handler, we need to remove the TypeId from the map
-        // We can do this with nested retain, one for the outer map, and one for the inner vec of EndpointHandle
-        self.type_handlers.write().retain(|_k, v| {
-            v.handlers.retain(|h| h.endpoint_id != endpoint_id);
-            !v.handlers.is_empty() // Keep only if there are remaining handlers
-        });
+    /// Reserve `id` for an endpoint to be created later with [`MessageRouter::create_endpoint_with_id()`], so
+    /// endpoints created in the meantime are not allocated the ID
+    pub fn reserve_endpoint_id(&self, id: EndpointId) -> Result<(), EndpointIdError> {
+        debug!("Reserving endpoint ID {id}");
+        self.ids.reserve(id)
     }
 
-    /// Add an [`EndpointHandle`] to the router
-    fn add_endpoint_handle(&self, handle: EndpointHandle<'a, R, S>) {
+    /// Add an [`EndpointHandle`] to the router, swapping in a new snapshot of the registry
+    fn add_endpoint_handle(&self, handle: EndpointHandle<'a, R>) {
         debug!("Adding {handle:?}");
-        self.endpoints.write().insert(handle.endpoint_id, handle);
+        self.add_type_name(handle.payload_type, handle.type_name);
+
+        let handle = Arc::new(handle);
+        self.registry.rcu(|registry| registry.with_handle(&handle));
     }
 
     /// Add an [`Endpoint`] to the router. This is handled automatically in [`Endpoint::new()`]
-    pub fn add_endpoint<M, Lock, Ref>(&self, endpoint: &Endpoint<'a, M, R, S, Lock, Ref>)
+    pub fn add_endpoint<M, Lock, Ref>(&self, endpoint: &Endpoint<'a, M, R, Lock, Ref>)
     where
         R: Send + 'a,
         M: Payload + 'static,
-        Ref: Deref<Target: AnyLock<EndpointInner<'a, M, R, S>>>
-            + From<Lock>
-            + Clone
-            + Send
-            + Sync
-            + 'a,
-        Lock: AnyLock<EndpointInner<'a, M, R, S>> + Send + Sync,
-    {
-        // Add the endpoint to the `endpoints` map
+        Ref:
+            Deref<Target: AnyLock<EndpointInner<'a, M, R>>> + From<Lock> + Clone + Send + Sync + 'a,
+        Lock: AnyLock<EndpointInner<'a, M, R>> + Send + Sync,
+    {
         self.add_endpoint_handle(endpoint.handle());
 
-        // Add the endpoint based on message TypeId to `type_handlers`
-        self.type_handlers
-            .write()
-            .entry(endpoint.message_type())
-            .or_default()
-            .handlers
-            .push(endpoint.handle());
-
         debug!("{endpoint:?} Added");
     }
 
     /// Create a new [`Endpoint`] registered with this router
     #[instrument(name = "router")]
-    pub fn create_endpoint<M>(&self) -> Endpoint<'a, M, R, S>
+    pub fn create_endpoint<M>(&self) -> Endpoint<'a, M, R>
+    where
+        M: Payload + 'static,
+        R: Send + 'a,
+    {
+        Endpoint::<'a, M, R>::new(Some(self.clone()))
+    }
+
+    /// Create a new [`Endpoint`] for payload type `M` with an explicit ID, such as one from configuration.
+    /// Fails if the ID is used by another endpoint of the router or its children.
+    pub fn create_endpoint_with_id<M>(
+        &self,
+        id: EndpointId,
+    ) -> Result<Endpoint<'a, M, R>, EndpointIdError>
+    where
+        M: Payload + 'static,
+        R: Send + 'a,
+    {
+        self.ids.claim(id)?;
+        Ok(Endpoint::<'a, M, R>::with_id(Some(self.clone()), id))
+    }
+
+    /// Register an endpoint for payload type `M`, whose handler is built by `factory` when the first message
+    /// arrives. The endpoint is deregistered when the returned [`OnDemand`] is dropped.
+    pub fn on_demand<M, F, H>(&self, factory: F) -> OnDemand<'a, M, R>
+    where
+        M: Payload + 'static,
+        R: Send + 'a,
+        F: Fn() -> H + Send + Sync + 'a,
+        H: FnMut(Option<SourceRef>, M) -> R + Send + 'a,
+    {
+        OnDemand::new(self.create_endpoint::<M>(), factory)
+    }
+
+    /// Register an endpoint for payload type `M` which holds a handler per key, extracted from each message by `key`.
+    /// A handler is built by `factory` for each new key, and receives all messages with that key.
+    /// The endpoint is deregistered when the returned [`Keyed`] is dropped.
+    pub fn keyed<M, K, H>(
+        &self,
+        key: impl Fn(&M) -> K + Send + Sync + 'a,
+        factory: impl Fn(&K) -> H + Send + Sync + 'a,
+    ) -> Keyed<'a, M, K, R>
+    where
+        M: Payload + 'static,
+        K: Eq + Hash + Send + 'a,
+        R: Send + 'a,
+        H: FnMut(Option<SourceRef>, M) -> R + Send + 'a,
+    {
+        Keyed::new(self.create_endpoint::<M>(), key, factory)
+    }
+
+    /// Register an endpoint for payload type `M` which passes messages to `handler` in batches of up to `max`,
+    /// or once the first message of a batch waited for `max_delay`.
+    /// The endpoint is deregistered when the returned [`Batched`] is dropped.
+    pub fn batched<M, H>(&self, max: usize, max_delay: Duration, handler: H) -> Batched<'a, M, R>
+    where
+        M: Payload + 'static,
+        R: Default + Send + 'a,
+        H: FnMut(Vec<M>) -> R + Send + 'a,
+    {
+        Batched::new(self.create_endpoint::<M>(), max, max_delay, handler)
+    }
+
+    /// Register a temporary endpoint for payload type `M`, returning a future which resolves to the next message.
+    /// The endpoint returns `R::default()` to the dispatcher, and deregisters itself once it received the message.
+    pub fn oneshot<M>(&self) -> OneShot<'a, M, R>
+    where
+        M: Payload + 'static,
+        R: Default + Send + 'a,
+    {
+        OneShot::new(self.create_endpoint::<M>())
+    }
+
+    /// Register a temporary endpoint for payload type `M`, returning a future which resolves to the next message
+    /// matching `filter`
+    pub fn oneshot_filtered<M>(&self, filter: impl Filter + 'static) -> OneShot<'a, M, R>
     where
         M: Payload + 'static,
+        R: Default + Send + 'a,
+    {
+        OneShot::new(self.create_endpoint::<M>().filter(filter))
+    }
+
+    /// Register a temporary endpoint for payload type `M`, waiting up to `timeout` for the next message.
+    /// The returned [`Timeout`] can be awaited, or waited for by blocking the thread with [`Timeout::wait()`].
+    pub fn await_message<M>(&self, timeout: Duration) -> Timeout<'a, M, R>
+    where
+        M: Payload + 'static,
+        R: Default + Send + 'a,
+    {
+        self.oneshot::<M>().timeout(timeout)
+    }
+
+    /// Create a [`Select`] which resolves to the result of the branch of the first message received for any of
+    /// the payload types of its branches, added with [`Select::on()`]
+    pub fn select<T>(&self) -> Select<'a, T, R>
+    where
+        T: Send + 'a,
+        R: Default + Send + 'a,
+    {
+        Select::new(self.clone())
+    }
+
+    /// Create a [`StateMachine`] in the `initial` state, driven by the messages received by the endpoints of its
+    /// handlers
+    pub fn state_machine<S>(&self, initial: S) -> StateMachine<'a, S, R>
+    where
+        S: PartialEq + std::fmt::Debug + Send + 'a,
+        R: Default + Send + 'a,
+    {
+        StateMachine::new(self.clone(), initial)
+    }
+
+    /// Create a [`Workflow`] named `name`, whose steps are triggered by messages received by this router
+    pub fn workflow(&self, name: impl Into<String>) -> Workflow<'a, R>
+    where
+        R: Default + Send + 'a,
+    {
+        Workflow::new(self.clone(), name.into())
+    }
+
+    /// Register an endpoint which passes messages to a [`MessageHandler`] implementation.
+    /// The endpoint is deregistered when the returned [`Endpoint`] is dropped.
+    pub fn register_handler<H>(&self, mut handler: H) -> Endpoint<'a, H::Message, R>
+    where
+        H: MessageHandler<Return = R> + 'a,
+        H::Message: 'static,
         R: Send + 'a,
     {
-        Endpoint::<'a, M, R, S>::new(Some(self.clone()))
+        self.create_endpoint::<H::Message>()
+            .message(move |src, msg| handler.on_message(src, msg))
     }
 
     /// Create a static endpoint that does not need to be held by the caller.
     /// It will be held in a vec of the router, and cannot be deregistered.
-    pub fn static_endpoint<M, F>(&mut self, f: F)
+    pub fn static_endpoint<M, F>(&self, f: F)
     where
         M: Payload + 'static,
         R: Send + 'static,
-        F: Fn(Option<S>, M) -> R + Send + Sync + 'static,
+        F: Fn(Option<SourceRef>, M) -> R + Send + Sync + 'static,
     {
         trace_span!("router").in_scope(|| {
-            let endpoint = Endpoint::<'static, M, R, S>::new(None).message(f);
+            let endpoint = Endpoint::<'static, M, R>::with_id(None, self.ids.allocate()).message(f);
 
-            debug!("Adding static handler for {:?}", endpoint.message_type());
+            debug!("Adding static handler for {}", std::any::type_name::<M>());
 
             self.add_endpoint_handle(endpoint.handle());
+            let addr = endpoint.addr();
 
-            // Add the endpoint based on message TypeId to `type_handlers`
-            self.type_handlers
-                .write()
-                .entry(endpoint.message_type())
-                .or_default()
-                .handlers
-                .push(endpoint.handle());
-
-            if let Some(static_endpoints) = &mut self.static_endpoints {
-                static_endpoints.push(Box::new(endpoint));
+            if let Some(static_endpoints) = &self.static_endpoints {
+                static_endpoints.write().push(Box::new(endpoint));
                 debug!("Static endpoint added");
             }
 
+            self.flush_pending(TypeId::of::<M>(), addr);
+
             debug!("{self:#?}");
         })
     }
 }
+
+impl<'a, T, E> MessageRouter<'a, Result<T, E>>
+where
+    T: 'static,
+    E: Clone + std::fmt::Debug + Send + Sync + 'static,
+{
+    /// Broadcast each error returned by a handler as a [`HandlerError<E>`](crate::error_channel::HandlerError)
+    /// message. This applies to all clones of the router, and to the results of [`MessageRouter::handle_message()`],
+    /// which are returned unchanged.
+    pub fn publish_errors(&self) {
+        debug!("Publishing handler errors");
+        self.errors.enable();
+    }
+
+    /// Stop broadcasting handler errors
+    pub fn stop_publishing_errors(&self) {
+        self.errors.disable();
+    }
+}
+
+/// Child router mounted under a parent [`MessageRouter`] with [`MessageRouter::child()`], which it dereferences to.
+/// Dropping the child router detaches it from the parent, and removes its endpoints.
+pub struct ChildRouter<'a, R> {
+    id: u64,
+    router: MessageRouter<'a, R>,
+}
+
+impl<'a, R> std::fmt::Debug for ChildRouter<'a, R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChildRouter")
+            .field("id", &self.id)
+            .field("router", &self.router)
+            .finish()
+    }
+}
+
+impl<'a, R> Deref for ChildRouter<'a, R> {
+    type Target = MessageRouter<'a, R>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.router
+    }
+}
+
+impl<'a, R> DerefMut for ChildRouter<'a, R> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.router
+    }
+}
+
+impl<'a, R> Drop for ChildRouter<'a, R> {
+    fn drop(&mut self) {
+        if let Some(parent) = &self.router.parent {
+            parent.children.write().remove(&self.id);
+        }
+
+        // Endpoints still held by their owners no longer receive messages
+        self.router.registry.store(Arc::new(Registry::default()));
+        debug!("Detached child router {}", self.id);
+    }
+}
+
+/// Iterator over the results of the handlers of a message, created with [`MessageRouter::handle_message_iter()`].
+///
+/// The handlers of a broadcast are called one at a time, as results are consumed. With the `stream` feature, this
+/// is also a [`Stream`](futures_core::Stream) which calls the next handler each time it is polled.
+pub struct DispatchIter<'a, R> {
+    lazy: Option<LazyDispatch<'a, R>>,
+    results: smallvec::IntoIter<[R; 1]>,
+}
+
+/// Broadcast whose handlers are called as the results of a [`DispatchIter`] are consumed
+struct LazyDispatch<'a, R> {
+    router: MessageRouter<'a, R>,
+    type_handler: Arc<TypeHandler<'a, R>>,
+    next: usize,
+    source: Option<SourceRef>,
+    type_id: TypeId,
+    type_name: &'static str,
+    /// Taken once the message is dropped because the filters of every endpoint rejected it
+    message: Option<Message>,
+    delivered: usize,
+    offered: bool,
+    rejected: bool,
+    mismatched: bool,
+    filtered: bool,
+    cancelled: bool,
+    record: Option<DispatchRecord>,
+}
+
+impl<'a, R> DispatchIter<'a, R> {
+    /// Iterate over the results of a message which was already dispatched
+    fn results(results: DispatchResult<R>) -> Self {
+        Self {
+            lazy: None,
+            results: results.into_results().unwrap_or_default().into_iter(),
+        }
+    }
+}
+
+impl<'a, R> std::fmt::Debug for DispatchIter<'a, R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut debug = f.debug_struct("DispatchIter");
+
+        if let Some(lazy) = &self.lazy {
+            debug
+                .field("message", &lazy.type_name)
+                .field("next", &lazy.next)
+                .field("delivered", &lazy.delivered);
+        }
+
+        debug.finish()
+    }
+}
+
+impl<'a, R: Send> Iterator for DispatchIter<'a, R> {
+    type Item = R;
+
+    fn next(&mut self) -> Option<R> {
+        match &mut self.lazy {
+            Some(lazy) => lazy.call_next(),
+            None => self.results.next(),
+        }
+    }
+}
+
+impl<'a, R: Send> LazyDispatch<'a, R> {
+    /// Call the next handler accepting the message, and get its result. Errors returned by the handler are
+    /// republished once it's called, and the message is dropped if the filters of every handler rejected it.
+    fn call_next(&mut self) -> Option<R> {
+        let message = self.message.as_ref()?;
+        let origin = message.origin();
+
+        while let Some(handler) = self.type_handler.handlers.get(self.next) {
+            self.next += 1;
+
+            if Some(handler.endpoint_id) == origin {
+                continue;
+            }
+
+            // Endpoints whose filters reject the message are skipped
+            if (handler.filter)(message) == FilterMatch::Rejected {
+                self.rejected = true;
+                continue;
+            }
+
+            let clone = match message.try_clone() {
+                Ok(clone) => clone,
+                Err(error) => {
+                    warn!(
+                        "Stopped broadcast after {} handlers: {error}",
+                        self.delivered
+                    );
+                    return None;
+                }
+            };
+            self.offered = true;
+
+            match (handler.callback)(self.source.clone(), clone) {
+                Ok(result) => {
+                    self.delivered += 1;
+                    if let Some(error) =
+                        self.router
+                            .errors
+                            .error(self.type_id, self.type_name, &result)
+                    {
+                        self.router.dispatch(error);
+                    }
+                    return Some(result);
+                }
+                Err(Undelivered::Declined(_)) => {}
+                Err(Undelivered::TypeMismatch) => self.mismatched = true,
+            }
+        }
+
+        if self.rejected && !self.offered {
+            if let Some(message) = self.message.take() {
+                self.filtered = matches!(self.router.filtered(message), DispatchResult::Filtered);
+            }
+        }
+
+        None
+    }
+
+    /// Get the result of a broadcast which wasn't delivered to any handler
+    fn undelivered(&self) -> DispatchResult<R> {
+        if self.filtered {
+            DispatchResult::Filtered
+        } else if self.mismatched {
+            DispatchResult::TypeMismatch
+        } else {
+            DispatchResult::NoHandler
+        }
+    }
+}
+
+/// Record the handlers which were called before the iterator was dropped, and pass the outcome to the middleware
+impl<'a, R> Drop for LazyDispatch<'a, R> {
+    fn drop(&mut self) {
+        let delivered = (self.delivered > 0).then_some(self.delivered);
+        self.router
+            .metrics
+            .record(self.type_id, self.type_name, delivered);
+
+        let outcome = match delivered {
+            _ if self.cancelled => Outcome::Cancelled(self.delivered),
+            Some(delivered) => Outcome::Delivered(delivered),
+            None if self.filtered => Outcome::Dropped(DropReason::Filtered),
+            None if self.mismatched => Outcome::TypeMismatch,
+            None => Outcome::NoHandler,
+        };
+        self.router
+            .middleware
+            .dispatched(self.record.take(), outcome);
+    }
+}
+
+#[cfg(feature = "stream")]
+impl<'a, R: Send + Unpin> futures_core::Stream for DispatchIter<'a, R> {
+    type Item = R;
+
+    fn poll_next(
+        self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<R>> {
+        std::task::Poll::Ready(self.get_mut().next())
+    }
+}
+
+/// Escape a label of a DOT graph
+fn escape_dot(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,124 @@
+//! Declarative routing rules loaded from configuration
+//!
+//! A [`RoutesConfig`] assigns filters and groups to named endpoints, so routing rules can be deserialized with
+//! serde from a TOML or JSON file at startup and applied with
+//! [`MessageRouter::load_routes()`](crate::router::MessageRouter::load_routes), rather than being hard-coded.
+//! Endpoints are created in code and registered under their names with
+//! [`Endpoint::named()`](crate::endpoint::Endpoint::named), while the configuration decides which messages
+//! they receive.
+//!
+//! ```json
+//! {
+//!     "routes": [
+//!         {
+//!             "endpoint": "kitchen-display",
+//!             "filters": [
+//!                 { "type": "source_pattern", "pattern": "sensor/kitchen/*" },
+//!                 { "type": "source_range", "min": 100, "max": 199 }
+//!             ],
+//!             "groups": ["displays"]
+//!         }
+//!     ]
+//! }
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+use crate::filter::{Filter, PatternFilter, RangeFilter};
+
+/// Filter of a [`RouteSpec`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FilterSpec {
+    /// Match `&'static str` sources against a glob pattern, as a [`PatternFilter::source()`]
+    SourcePattern { pattern: String },
+
+    /// Match the value of a header against a glob pattern, as a [`PatternFilter::header()`]
+    HeaderPattern { header: String, pattern: String },
+
+    /// Match `&'static str` sources against a regular expression, as a [`PatternFilter::source_regex()`]
+    #[cfg(feature = "regex")]
+    SourceRegex { regex: String },
+
+    /// Match the value of a header against a regular expression, as a [`PatternFilter::header_regex()`]
+    #[cfg(feature = "regex")]
+    HeaderRegex { header: String, regex: String },
+
+    /// Match `u64` sources between `min` and `max` inclusive, as a [`RangeFilter`]. Either bound can be omitted.
+    SourceRange {
+        #[serde(default)]
+        min: Option<u64>,
+        #[serde(default)]
+        max: Option<u64>,
+    },
+}
+
+impl FilterSpec {
+    /// Build the filter described by the spec
+    pub fn build(&self) -> Result<Box<dyn Filter>, RouteError> {
+        Ok(match self {
+            FilterSpec::SourcePattern { pattern } => Box::new(PatternFilter::source(pattern)),
+            FilterSpec::HeaderPattern { header, pattern } => {
+                Box::new(PatternFilter::header(header, pattern))
+            }
+            #[cfg(feature = "regex")]
+            FilterSpec::SourceRegex { regex } => Box::new(
+                PatternFilter::source_regex(regex)
+                    .map_err(|error| RouteError::InvalidFilter(error.to_string()))?,
+            ),
+            #[cfg(feature = "regex")]
+            FilterSpec::HeaderRegex { header, regex } => Box::new(
+                PatternFilter::header_regex(header, regex)
+                    .map_err(|error| RouteError::InvalidFilter(error.to_string()))?,
+            ),
+            FilterSpec::SourceRange { min, max } => {
+                let start = min.map_or(std::ops::Bound::Unbounded, std::ops::Bound::Included);
+                let end = max.map_or(std::ops::Bound::Unbounded, std::ops::Bound::Included);
+                Box::new(RangeFilter::<u64>::new((start, end)))
+            }
+        })
+    }
+}
+
+/// Routing rules of a named endpoint
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RouteSpec {
+    /// Name the endpoint is registered under
+    pub endpoint: String,
+
+    /// Filters added to the endpoint. The endpoint receives messages matching any of its filters.
+    #[serde(default)]
+    pub filters: Vec<FilterSpec>,
+
+    /// Groups the endpoint joins
+    #[serde(default)]
+    pub groups: Vec<String>,
+}
+
+/// Routing rules loaded with [`MessageRouter::load_routes()`](crate::router::MessageRouter::load_routes)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RoutesConfig {
+    #[serde(default)]
+    pub routes: Vec<RouteSpec>,
+}
+
+/// Error loading a [`RoutesConfig`]. No rules are applied if any of them is invalid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RouteError {
+    /// No endpoint is registered under the name
+    UnknownEndpoint(String),
+
+    /// A filter couldn't be built, such as a regular expression which doesn't compile
+    InvalidFilter(String),
+}
+
+impl std::fmt::Display for RouteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownEndpoint(name) => write!(f, "no endpoint named {name}"),
+            Self::InvalidFilter(error) => write!(f, "invalid filter: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for RouteError {}
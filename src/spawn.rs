@@ -0,0 +1,269 @@
+//! Executors running the tasks spawned by salish
+//!
+//! Async handlers are awaited by a task spawned on a [`Spawner`], so they can run on tokio, async-std, smol or
+//! an executor of the application. A spawner is implemented for any `Fn(BoxFuture)`, and for the executors of
+//! the runtimes enabled with the `tokio`, `async-std` and `smol` features.
+//!
+//! Endpoints whose handlers block, such as on database queries or file IO, can be moved onto the blocking pool
+//! of a spawner with [`Endpoint::blocking()`](crate::endpoint::Endpoint::blocking), so they don't stall the
+//! tasks dispatching to them.
+//!
+//! Timeouts of [`OneShot`](crate::endpoint::OneShot) endpoints don't depend on a runtime. The TCP bridge and the
+//! HTTP inspection server use tokio sockets, and still require a tokio runtime.
+//!
+//! ```
+//! use salish::{handler::AsyncMessageHandler, message::SourceRef, router::MessageRouter, spawn::BoxFuture};
+//!
+//! #[derive(Debug)]
+//! struct Logger;
+//!
+//! impl AsyncMessageHandler for Logger {
+//!     type Message = String;
+//!
+//!     async fn on_message(&mut self, _source: Option<SourceRef>, message: String) {
+//!         println!("{message}");
+//!     }
+//! }
+//!
+//! // Hand tasks to the executor of the application. This one drops them, so no message is handled.
+//! let spawner = |task: BoxFuture| drop(task);
+//!
+//! let router = MessageRouter::<()>::new();
+//! let _endpoint = router.spawn_handler_on(&spawner, Logger);
+//! ```
+
+use std::{
+    collections::VecDeque,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Poll, Waker},
+};
+
+use anylock::{AnyLock, ParkingLotMutex};
+use tracing::{debug, warn};
+
+use crate::{
+    endpoint::Endpoint, handler::AsyncMessageHandler, message::SourceRef, router::MessageRouter,
+};
+
+/// Task passed to a [`Spawner`]
+pub type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
+
+/// Blocking work passed to [`Spawner::spawn_blocking()`]
+pub type BlockingJob = Box<dyn FnOnce() + Send + 'static>;
+
+/// Executor running the tasks spawned by salish in the background
+pub trait Spawner: Send + Sync {
+    /// Spawn a task which runs `task` to completion, without waiting for it
+    fn spawn(&self, task: BoxFuture);
+
+    /// Run `job` on a thread where blocking doesn't stall the executor, without waiting for it.
+    /// Spawns a thread named `salish-blocking` for each job, unless the executor provides a pool for blocking work.
+    fn spawn_blocking(&self, job: BlockingJob) {
+        std::thread::Builder::new()
+            .name("salish-blocking".into())
+            .spawn(job)
+            .expect("Failed to spawn blocking thread");
+    }
+}
+
+impl<F> Spawner for F
+where
+    F: Fn(BoxFuture) + Send + Sync,
+{
+    fn spawn(&self, task: BoxFuture) {
+        self(task)
+    }
+}
+
+/// Spawns tasks on the tokio runtime of the calling thread
+///
+/// # Panics
+/// Spawning panics if the calling thread isn't in a tokio runtime
+#[cfg(feature = "tokio")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TokioSpawner;
+
+#[cfg(feature = "tokio")]
+impl Spawner for TokioSpawner {
+    fn spawn(&self, task: BoxFuture) {
+        tokio::spawn(task);
+    }
+
+    fn spawn_blocking(&self, job: BlockingJob) {
+        tokio::task::spawn_blocking(job);
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Spawner for tokio::runtime::Handle {
+    fn spawn(&self, task: BoxFuture) {
+        tokio::runtime::Handle::spawn(self, task);
+    }
+
+    fn spawn_blocking(&self, job: BlockingJob) {
+        tokio::runtime::Handle::spawn_blocking(self, job);
+    }
+}
+
+/// Spawns tasks on the global async-std executor
+#[cfg(feature = "async-std")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct AsyncStdSpawner;
+
+#[cfg(feature = "async-std")]
+impl Spawner for AsyncStdSpawner {
+    fn spawn(&self, task: BoxFuture) {
+        // Dropping the handle detaches the task
+        async_std::task::spawn(task);
+    }
+
+    fn spawn_blocking(&self, job: BlockingJob) {
+        async_std::task::spawn_blocking(job);
+    }
+}
+
+/// Spawns tasks on the global smol executor
+#[cfg(feature = "smol")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SmolSpawner;
+
+#[cfg(feature = "smol")]
+impl Spawner for SmolSpawner {
+    fn spawn(&self, task: BoxFuture) {
+        smol::spawn(task).detach();
+    }
+
+    fn spawn_blocking(&self, job: BlockingJob) {
+        smol::unblock(job).detach();
+    }
+}
+
+#[cfg(feature = "smol")]
+impl Spawner for Arc<smol::Executor<'static>> {
+    fn spawn(&self, task: BoxFuture) {
+        smol::Executor::spawn(self, task).detach();
+    }
+
+    fn spawn_blocking(&self, job: BlockingJob) {
+        smol::unblock(job).detach();
+    }
+}
+
+/// Unbounded queue between an endpoint and the task awaiting its handler
+struct Channel<T> {
+    queue: VecDeque<T>,
+    waker: Option<Waker>,
+
+    /// Set when either end is dropped
+    closed: bool,
+}
+
+/// Sending end of a [`channel()`], held by the endpoint
+struct TaskSender<T>(Arc<ParkingLotMutex<Channel<T>>>);
+
+impl<T> TaskSender<T> {
+    /// Queue a value for the task, or get it back if the task has stopped
+    fn send(&self, value: T) -> Result<(), T> {
+        let mut channel = self.0.write();
+        if channel.closed {
+            return Err(value);
+        }
+
+        channel.queue.push_back(value);
+        if let Some(waker) = channel.waker.take() {
+            waker.wake();
+        }
+
+        Ok(())
+    }
+}
+
+impl<T> Drop for TaskSender<T> {
+    fn drop(&mut self) {
+        let mut channel = self.0.write();
+        channel.closed = true;
+        if let Some(waker) = channel.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Receiving end of a [`channel()`], held by the task
+struct TaskReceiver<T>(Arc<ParkingLotMutex<Channel<T>>>);
+
+impl<T> TaskReceiver<T> {
+    /// Receive the next value, or `None` once the sender is dropped and the queued values were received
+    async fn recv(&mut self) -> Option<T> {
+        std::future::poll_fn(|cx| {
+            let mut channel = self.0.write();
+
+            if let Some(value) = channel.queue.pop_front() {
+                Poll::Ready(Some(value))
+            } else if channel.closed {
+                Poll::Ready(None)
+            } else {
+                channel.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+impl<T> Drop for TaskReceiver<T> {
+    fn drop(&mut self) {
+        self.0.write().closed = true;
+    }
+}
+
+fn channel<T>() -> (TaskSender<T>, TaskReceiver<T>) {
+    let channel = Arc::new(ParkingLotMutex::new(Channel {
+        queue: VecDeque::new(),
+        waker: None,
+        closed: false,
+    }));
+
+    (TaskSender(channel.clone()), TaskReceiver(channel))
+}
+
+impl<R> MessageRouter<'static, R>
+where
+    R: Default + Send + 'static,
+{
+    /// Register an endpoint which passes messages to an [`AsyncMessageHandler`] awaited by a task spawned on
+    /// `spawner`. The endpoint returns `R::default()` without waiting for the handler, and messages are handled
+    /// in the order they were received. The task ends when the returned [`Endpoint`] is dropped, once the
+    /// messages it received are handled.
+    pub fn spawn_handler_on<H>(
+        &self,
+        spawner: &impl Spawner,
+        mut handler: H,
+    ) -> Endpoint<'static, H::Message, R>
+    where
+        H: AsyncMessageHandler + 'static,
+        H::Message: 'static,
+    {
+        let (tx, mut rx) = channel::<(Option<SourceRef>, H::Message)>();
+
+        spawner.spawn(Box::pin(async move {
+            while let Some((src, msg)) = rx.recv().await {
+                handler.on_message(src, msg).await;
+            }
+            debug!("Async handler {handler:?} stopped");
+        }));
+
+        self.create_endpoint::<H::Message>()
+            .message(move |src, msg| {
+                // Sending only fails if the task panicked, or the executor dropped it
+                if tx.send((src, msg)).is_err() {
+                    warn!(
+                        "Async handler task for {} has stopped",
+                        std::any::type_name::<H::Message>()
+                    );
+                }
+                R::default()
+            })
+    }
+}
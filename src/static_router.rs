@@ -0,0 +1,150 @@
+//! Router for closed message sets
+//!
+//! A [`StaticRouter`] dispatches messages of a single enum implementing [`MessageSet`]. Handlers are registered
+//! for variants by index, and dispatch indexes straight into the handlers of the variant, without hashing
+//! [`TypeId`](std::any::TypeId)s, boxing payloads or downcasting. Dispatch doesn't allocate, so the router suits
+//! performance critical loops which don't need the open set of payload types of a
+//! [`MessageRouter`](crate::router::MessageRouter).
+//!
+//! Messages sent by realtime threads through a [`ring`](crate::ring) buffer are dispatched with
+//! [`StaticRouter::drain()`].
+//!
+//! ```
+//! use salish::static_router::{MessageSet, StaticRouter};
+//!
+//! enum Sensor {
+//!     Temperature(f32),
+//!     Humidity(f32),
+//! }
+//!
+//! impl Sensor {
+//!     const TEMPERATURE: usize = 0;
+//!     const HUMIDITY: usize = 1;
+//! }
+//!
+//! impl MessageSet for Sensor {
+//!     const COUNT: usize = 2;
+//!
+//!     fn index(&self) -> usize {
+//!         match self {
+//!             Sensor::Temperature(_) => Self::TEMPERATURE,
+//!             Sensor::Humidity(_) => Self::HUMIDITY,
+//!         }
+//!     }
+//! }
+//!
+//! let mut router = StaticRouter::<Sensor, f32>::new();
+//! router.on(Sensor::TEMPERATURE, |message| match message {
+//!     Sensor::Temperature(celsius) => celsius * 1.8 + 32.0,
+//!     _ => unreachable!(),
+//! });
+//!
+//! let fahrenheit: Vec<f32> = router.dispatch(&Sensor::Temperature(100.0)).collect();
+//! assert_eq!(fahrenheit, [212.0]);
+//! assert_eq!(router.dispatch(&Sensor::Humidity(0.5)).count(), 0);
+//! ```
+
+use crate::ring::RingReceiver;
+
+/// Closed set of messages, usually an enum, which can be dispatched by a [`StaticRouter`]
+pub trait MessageSet {
+    /// Number of variants in the set
+    const COUNT: usize;
+
+    /// Get the index of the variant of this message, which must be less than [`MessageSet::COUNT`]
+    fn index(&self) -> usize;
+}
+
+/// Handler registered for a variant of a [`MessageSet`]
+type StaticHandler<'a, M, R> = Box<dyn FnMut(&M) -> R + Send + 'a>;
+
+/// Router dispatching a closed [`MessageSet`] by variant index
+pub struct StaticRouter<'a, M, R = ()>
+where
+    M: MessageSet,
+{
+    handlers: Vec<Vec<StaticHandler<'a, M, R>>>,
+}
+
+impl<'a, M, R> std::fmt::Debug for StaticRouter<'a, M, R>
+where
+    M: MessageSet,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StaticRouter")
+            .field("variants", &M::COUNT)
+            .field("handlers", &self.num_handlers())
+            .finish()
+    }
+}
+
+impl<'a, M, R> Default for StaticRouter<'a, M, R>
+where
+    M: MessageSet,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, M, R> StaticRouter<'a, M, R>
+where
+    M: MessageSet,
+{
+    pub fn new() -> Self {
+        Self {
+            handlers: (0..M::COUNT).map(|_| Vec::new()).collect(),
+        }
+    }
+
+    /// Register a handler for the variant at `index`.
+    ///
+    /// # Panics
+    /// Panics if `index` is not less than [`MessageSet::COUNT`]
+    pub fn on(&mut self, index: usize, handler: impl FnMut(&M) -> R + Send + 'a) -> &mut Self {
+        assert!(
+            index < M::COUNT,
+            "Variant index {index} out of range for a set of {} variants",
+            M::COUNT
+        );
+
+        self.handlers[index].push(Box::new(handler));
+        self
+    }
+
+    /// Get the number of handlers registered with the router
+    pub fn num_handlers(&self) -> usize {
+        self.handlers.iter().map(Vec::len).sum()
+    }
+
+    /// Dispatch a message to the handlers of its variant, returning an iterator over their results.
+    /// Handlers are called lazily as the iterator is advanced.
+    ///
+    /// # Panics
+    /// Panics if the index of the message is not less than [`MessageSet::COUNT`]
+    pub fn dispatch<'b>(
+        &'b mut self,
+        message: &'b M,
+    ) -> impl Iterator<Item = R> + use<'a, 'b, M, R> {
+        self.handlers[message.index()]
+            .iter_mut()
+            .map(move |handler| handler(message))
+    }
+
+    /// Dispatch the messages waiting in a ring buffer, such as messages sent by a realtime thread, returning the
+    /// number of messages dispatched. The results of the handlers are dropped. Neither receiving nor dispatching
+    /// the messages allocates.
+    ///
+    /// # Panics
+    /// Panics if the index of a message is not less than [`MessageSet::COUNT`]
+    pub fn drain(&mut self, ingress: &mut RingReceiver<M>) -> usize {
+        let mut count = 0;
+
+        while let Some(message) = ingress.try_recv() {
+            self.dispatch(&message).for_each(drop);
+            count += 1;
+        }
+
+        count
+    }
+}
@@ -0,0 +1,101 @@
+//! Tagged payloads
+//!
+//! Messages are routed by the type of their payload, so components sending primitive payloads such as `u64` or
+//! `String` would deliver to the endpoints of unrelated components receiving the same type. Wrapping a payload in
+//! [`Tagged`] with a marker type gives each use a distinct payload type, without declaring a newtype for each.
+//!
+//! ```
+//! use salish::{router::MessageRouter, tagged::Tagged, Message};
+//!
+//! struct Temperature;
+//! struct Humidity;
+//!
+//! let router = MessageRouter::<u64>::new();
+//! let _endpoint = router
+//!     .create_endpoint::<Tagged<u64, Temperature>>()
+//!     .message(|_src, celsius| celsius.into_inner());
+//!
+//! assert!(router.handle_message(Message::unicast_tagged::<Temperature>(21u64)).is_delivered());
+//! assert!(!router.handle_message(Message::unicast_tagged::<Humidity>(40u64)).is_delivered());
+//! ```
+
+use std::{
+    hash::Hash,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+};
+
+/// Payload of type `T` distinguished by the marker type `Tag`
+pub struct Tagged<T, Tag> {
+    value: T,
+    _tag: PhantomData<fn() -> Tag>,
+}
+
+impl<T, Tag> Tagged<T, Tag> {
+    pub fn new(value: T) -> Self {
+        Self {
+            value,
+            _tag: PhantomData,
+        }
+    }
+
+    /// Take the tagged value
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T, Tag> From<T> for Tagged<T, Tag> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T, Tag> Deref for Tagged<T, Tag> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.value
+    }
+}
+
+impl<T, Tag> DerefMut for Tagged<T, Tag> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.value
+    }
+}
+
+impl<T, Tag> std::fmt::Debug for Tagged<T, Tag>
+where
+    T: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple(&format!("Tagged<{}>", std::any::type_name::<Tag>()))
+            .field(&self.value)
+            .finish()
+    }
+}
+
+// Manual impls, so the marker type doesn't need to implement these traits
+
+impl<T: Clone, Tag> Clone for Tagged<T, Tag> {
+    fn clone(&self) -> Self {
+        Self::new(self.value.clone())
+    }
+}
+
+impl<T: Copy, Tag> Copy for Tagged<T, Tag> {}
+
+impl<T: PartialEq, Tag> PartialEq for Tagged<T, Tag> {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl<T: Eq, Tag> Eq for Tagged<T, Tag> {}
+
+impl<T: Hash, Tag> Hash for Tagged<T, Tag> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.value.hash(state)
+    }
+}
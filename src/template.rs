@@ -0,0 +1,87 @@
+//! Message templates
+//!
+//! Producers sending the same broadcast at a high rate would otherwise build an identical [`Message`] for every
+//! send. A [`MessageTemplate`] captures the destination, source, and payload once, and each send clones the
+//! template. Sources are shared between the clones, so only the payload is copied.
+//!
+//! ```
+//! use salish::{router::MessageRouter, template::MessageTemplate};
+//!
+//! let router = MessageRouter::<u32>::new();
+//! let _endpoint = router.create_endpoint::<u32>().message(|_src, msg| msg * 2);
+//!
+//! let tick = MessageTemplate::broadcast(21u32).with_source(1u64);
+//! for _ in 0..3 {
+//!     assert_eq!(tick.fire(&router).results(), Some(&[42][..]));
+//! }
+//! ```
+
+use crate::{
+    dispatch::DispatchResult,
+    message::{Destination, Message, MessageSource},
+    queue::RouterSender,
+    router::MessageRouter,
+    traits::{BroadcastPayload, EndpointAddress, SalishMessage},
+};
+
+/// Message with a broadcastable payload, which is cloned each time it is sent
+#[derive(Debug)]
+pub struct MessageTemplate {
+    message: Message,
+}
+
+impl Clone for MessageTemplate {
+    fn clone(&self) -> Self {
+        Self {
+            message: self.message(),
+        }
+    }
+}
+
+impl MessageTemplate {
+    /// Create a template of a message with destination set to [`Destination::Broadcast`]
+    pub fn broadcast<P: BroadcastPayload + 'static>(payload: P) -> Self {
+        Self {
+            message: Message::broadcast(payload),
+        }
+    }
+
+    /// Create a template of a message with destination set to [`Destination::Any`]
+    pub fn unicast<P: BroadcastPayload + 'static>(payload: P) -> Self {
+        Self {
+            message: Message::new_to(Destination::any(), payload.into_payload()),
+        }
+    }
+
+    /// Set the source address of the messages sent from this template
+    pub fn with_source(mut self, source: impl MessageSource + Copy) -> Self {
+        self.message = self.message.with_source(source);
+        self
+    }
+
+    /// Set the destination address of the messages sent from this template
+    pub fn with_dest(
+        mut self,
+        dest: Destination<<<Message as SalishMessage>::Endpoint as EndpointAddress>::Addr>,
+    ) -> Self {
+        self.message = self.message.with_dest(dest);
+        self
+    }
+
+    /// Get a new [`Message`] cloned from this template
+    pub fn message(&self) -> Message {
+        self.message
+            .try_clone()
+            .expect("Templates are only created with broadcast payloads")
+    }
+
+    /// Handle a message cloned from this template with `router`
+    pub fn fire<R: Send>(&self, router: &MessageRouter<'_, R>) -> DispatchResult<R> {
+        router.handle_message(self.message())
+    }
+
+    /// Queue a message cloned from this template with `sender`
+    pub fn queue(&self, sender: &RouterSender) {
+        sender.send(self.message())
+    }
+}
@@ -0,0 +1,1219 @@
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::RecvTimeoutError,
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use smallvec::smallvec;
+use tracing_test::traced_test;
+
+use crate::{
+    bridge::{
+        udp::UdpTransport, Bincode, Bridge, BridgeConfig, BridgeError, BridgeStalled, Codec,
+        Transport, TypeRegistry, WireDest, WireMessage,
+    },
+    dispatch::DispatchResult,
+    message::{Destination, Message},
+    router::MessageRouter,
+    traits::EndpointAddress as _,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Reading {
+    sensor_id: u64,
+    value: f32,
+}
+
+/// Wait for a condition to become true, or panic after a timeout
+fn wait_for(condition: impl Fn() -> bool) {
+    let start = Instant::now();
+    while !condition() {
+        assert!(
+            start.elapsed() < Duration::from_secs(5),
+            "Timed out waiting for condition"
+        );
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[traced_test]
+#[test]
+fn udp_transport() {
+    let router_a = MessageRouter::<()>::new();
+    let router_b = MessageRouter::<()>::new();
+
+    let transport_a = UdpTransport::bind("127.0.0.1:0", &router_a).unwrap();
+    let transport_b = UdpTransport::bind("127.0.0.1:0", &router_b).unwrap();
+
+    transport_a.add_peer(transport_b.local_addr().unwrap()).unwrap();
+    transport_b.add_peer(transport_a.local_addr().unwrap()).unwrap();
+
+    let _forward_a = transport_a.register::<Reading>();
+    let _forward_b = transport_b.register::<Reading>();
+
+    let received = Arc::new(AtomicU64::new(0));
+    let sender = Arc::new(Mutex::new(None));
+
+    let count = received.clone();
+    let from = sender.clone();
+    let _endpoint = router_b
+        .create_endpoint::<Reading>()
+        .message(move |src, msg| {
+            *from.lock().unwrap() = src.and_then(|src| src.get::<SocketAddr>());
+            count.fetch_add(msg.sensor_id, Ordering::Relaxed);
+        });
+
+    router_a.handle_message(Message::broadcast(Reading {
+        sensor_id: 7,
+        value: 21.5,
+    }));
+
+    wait_for(|| received.load(Ordering::Relaxed) == 7);
+
+    // The message source is the address of the sending transport
+    assert_eq!(*sender.lock().unwrap(), Some(transport_a.local_addr().unwrap()));
+
+    // Received messages are not forwarded back to the sender, so the count remains stable
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(received.load(Ordering::Relaxed), 7);
+}
+
+#[traced_test]
+#[test]
+fn udp_unregistered_type() {
+    let router_a = MessageRouter::<()>::new();
+    let router_b = MessageRouter::<()>::new();
+
+    let transport_a = UdpTransport::bind("127.0.0.1:0", &router_a).unwrap();
+    let transport_b = UdpTransport::bind("127.0.0.1:0", &router_b).unwrap();
+    transport_a.add_peer(transport_b.local_addr().unwrap()).unwrap();
+
+    let received = Arc::new(AtomicU64::new(0));
+    let count = received.clone();
+    let _endpoint = router_b.create_endpoint::<u64>().message(move |_src, msg| {
+        count.fetch_add(msg, Ordering::Relaxed);
+    });
+
+    // Peer B has not registered u64, so the datagram is dropped
+    transport_a.send(&5u64).unwrap();
+    std::thread::sleep(Duration::from_millis(200));
+
+    // Once registered, direct sends are received
+    let _forward = transport_b.register::<u64>();
+    transport_a.send(&6u64).unwrap();
+
+    wait_for(|| received.load(Ordering::Relaxed) == 6);
+}
+
+#[cfg(unix)]
+#[traced_test]
+#[test]
+fn ipc_bridge() {
+    use crate::bridge::ipc::IpcBridge;
+
+    let path = std::env::temp_dir().join(format!("salish-test-{}.sock", std::process::id()));
+
+    let router_a = MessageRouter::<()>::new();
+    let router_b = MessageRouter::<()>::new();
+
+    let received_a = Arc::new(AtomicU64::new(0));
+    let received_b = Arc::new(AtomicU64::new(0));
+
+    let count = received_a.clone();
+    let _endpoint_a = router_a
+        .create_endpoint::<Reading>()
+        .message(move |_src, msg| {
+            count.fetch_add(msg.sensor_id, Ordering::Relaxed);
+        });
+
+    let count = received_b.clone();
+    let _endpoint_b = router_b
+        .create_endpoint::<Reading>()
+        .message(move |_src, msg| {
+            count.fetch_add(msg.sensor_id, Ordering::Relaxed);
+        });
+
+    let listener = IpcBridge::listen(&path, &router_a).unwrap();
+    let client =
+        IpcBridge::connect_with_delay(&path, &router_b, Duration::from_millis(20)).unwrap();
+
+    let _forward_a = listener.register::<Reading>();
+    let _forward_b = client.register::<Reading>();
+
+    wait_for(|| listener.connections() == 1 && client.connections() == 1);
+
+    // Local messages are delivered locally, and forwarded to the other process
+    router_a.handle_message(Message::broadcast(Reading {
+        sensor_id: 3,
+        value: 1.0,
+    }));
+    wait_for(|| received_b.load(Ordering::Relaxed) == 3);
+    assert_eq!(received_a.load(Ordering::Relaxed), 3);
+
+    router_b.handle_message(Message::broadcast(Reading {
+        sensor_id: 4,
+        value: 2.0,
+    }));
+    wait_for(|| received_a.load(Ordering::Relaxed) == 7);
+    assert_eq!(received_b.load(Ordering::Relaxed), 7);
+
+    // Restart the listener. The client should reconnect automatically.
+    drop(_forward_a);
+    drop(listener);
+    wait_for(|| client.connections() == 0);
+
+    let listener = IpcBridge::listen(&path, &router_a).unwrap();
+    let _forward_a = listener.register::<Reading>();
+    wait_for(|| client.connections() == 1);
+
+    router_b.handle_message(Message::broadcast(Reading {
+        sensor_id: 10,
+        value: 3.0,
+    }));
+    wait_for(|| received_a.load(Ordering::Relaxed) == 17);
+}
+
+/// Transport exchanging frames over in-process channels
+struct ChannelTransport {
+    tx: Mutex<std::sync::mpsc::Sender<Vec<u8>>>,
+    rx: Mutex<std::sync::mpsc::Receiver<Vec<u8>>>,
+    sent: Arc<AtomicU64>,
+    sent_bytes: Arc<AtomicU64>,
+}
+
+impl ChannelTransport {
+    fn pair() -> (Self, Self) {
+        let (tx_a, rx_b) = std::sync::mpsc::channel();
+        let (tx_b, rx_a) = std::sync::mpsc::channel();
+        (
+            Self {
+                tx: Mutex::new(tx_a),
+                rx: Mutex::new(rx_a),
+                sent: Arc::default(),
+                sent_bytes: Arc::default(),
+            },
+            Self {
+                tx: Mutex::new(tx_b),
+                rx: Mutex::new(rx_b),
+                sent: Arc::default(),
+                sent_bytes: Arc::default(),
+            },
+        )
+    }
+}
+
+impl Transport for ChannelTransport {
+    fn connect(&self, _addr: &str) -> Result<(), BridgeError> {
+        Ok(())
+    }
+
+    fn send_frame(&self, frame: &[u8]) -> Result<(), BridgeError> {
+        self.sent.fetch_add(1, Ordering::Relaxed);
+        self.sent_bytes
+            .fetch_add(frame.len() as u64, Ordering::Relaxed);
+        self.tx
+            .lock()
+            .unwrap()
+            .send(frame.to_vec())
+            .map_err(|e| BridgeError::Transport(e.to_string()))
+    }
+
+    fn recv_frame(&self, timeout: Duration) -> Result<Option<Vec<u8>>, BridgeError> {
+        match self.rx.lock().unwrap().recv_timeout(timeout) {
+            Ok(frame) => Ok(Some(frame)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(e) => Err(BridgeError::Transport(e.to_string())),
+        }
+    }
+}
+
+#[traced_test]
+#[test]
+fn custom_transport() {
+    let router_a = MessageRouter::<()>::new();
+    let router_b = MessageRouter::<()>::new();
+
+    let (transport_a, transport_b) = ChannelTransport::pair();
+    let bridge_a = Bridge::new(transport_a, &router_a).unwrap();
+    let bridge_b = Bridge::new(transport_b, &router_b).unwrap();
+
+    let _forward_a = bridge_a.register::<Reading>();
+    let _forward_b = bridge_b.register::<Reading>();
+
+    let received = Arc::new(AtomicU64::new(0));
+    let count = received.clone();
+    let _endpoint = router_b
+        .create_endpoint::<Reading>()
+        .message(move |_src, msg| {
+            count.fetch_add(msg.sensor_id, Ordering::Relaxed);
+        });
+
+    router_a.handle_message(Message::broadcast(Reading {
+        sensor_id: 5,
+        value: 0.5,
+    }));
+    wait_for(|| received.load(Ordering::Relaxed) == 5);
+
+    bridge_a
+        .send(&Reading {
+            sensor_id: 6,
+            value: 0.6,
+        })
+        .unwrap();
+    wait_for(|| received.load(Ordering::Relaxed) == 11);
+}
+
+#[traced_test]
+#[test]
+fn batching() {
+    let router_a = MessageRouter::<()>::new();
+    let router_b = MessageRouter::<()>::new();
+
+    let config = BridgeConfig {
+        max_batch: 10,
+        max_delay: Duration::from_millis(50),
+        ..Default::default()
+    };
+
+    let (transport_a, transport_b) = ChannelTransport::pair();
+    let sent = transport_a.sent.clone();
+    let registry = Arc::new(TypeRegistry::new());
+    let bridge_a = Bridge::with_config(transport_a, &router_a, registry, config).unwrap();
+    let bridge_b = Bridge::new(transport_b, &router_b).unwrap();
+
+    let _forward_a = bridge_a.register::<Reading>();
+    let _forward_b = bridge_b.register::<Reading>();
+
+    let received = Arc::new(AtomicU64::new(0));
+    let count = received.clone();
+    let _endpoint = router_b
+        .create_endpoint::<Reading>()
+        .message(move |_src, _msg| {
+            count.fetch_add(1, Ordering::Relaxed);
+        });
+
+    // The capabilities of the bridge are announced when it starts
+    wait_for(|| sent.load(Ordering::Relaxed) == 1);
+
+    // Two full batches are sent immediately, and the remaining five after the maximum delay
+    for sensor_id in 0..25 {
+        router_a.handle_message(Message::broadcast(Reading {
+            sensor_id,
+            value: 0.0,
+        }));
+    }
+    wait_for(|| received.load(Ordering::Relaxed) == 25);
+    assert_eq!(sent.load(Ordering::Relaxed), 4);
+
+    // A single queued frame is sent as is
+    bridge_a
+        .send(&Reading {
+            sensor_id: 25,
+            value: 0.0,
+        })
+        .unwrap();
+    wait_for(|| received.load(Ordering::Relaxed) == 26);
+    assert_eq!(sent.load(Ordering::Relaxed), 5);
+
+    // Queued frames are sent when the bridge is dropped
+    for sensor_id in 26..30 {
+        router_a.handle_message(Message::broadcast(Reading {
+            sensor_id,
+            value: 0.0,
+        }));
+    }
+    drop(_forward_a);
+    drop(bridge_a);
+    wait_for(|| received.load(Ordering::Relaxed) == 30);
+}
+
+#[traced_test]
+#[test]
+fn flow_control() {
+    let router_a = MessageRouter::<()>::new();
+    let router_b = MessageRouter::<()>::new();
+
+    let config = BridgeConfig {
+        window: 4,
+        stall_timeout: Duration::from_secs(2),
+        ..Default::default()
+    };
+
+    let (transport_a, transport_b) = ChannelTransport::pair();
+    let bridge_a =
+        Bridge::with_config(transport_a, &router_a, Arc::new(TypeRegistry::new()), config)
+            .unwrap();
+    let bridge_b = Bridge::new(transport_b, &router_b).unwrap();
+
+    let _forward_a = bridge_a.register::<Reading>();
+    let _forward_b = bridge_b.register::<Reading>();
+
+    // Slow consumer
+    let received = Arc::new(AtomicU64::new(0));
+    let count = received.clone();
+    let _endpoint = router_b
+        .create_endpoint::<Reading>()
+        .message(move |_src, _msg| {
+            std::thread::sleep(Duration::from_millis(20));
+            count.fetch_add(1, Ordering::Relaxed);
+        });
+
+    let stalls = Arc::new(AtomicU64::new(0));
+    let count = stalls.clone();
+    let _stalled = router_a
+        .create_endpoint::<BridgeStalled>()
+        .message(move |_src, msg| {
+            assert_eq!(msg.window, 4);
+            count.fetch_add(1, Ordering::Relaxed);
+        });
+
+    // The sender can't get more than a window ahead of the consumer
+    for sensor_id in 0..12 {
+        bridge_a
+            .send(&Reading {
+                sensor_id,
+                value: 0.0,
+            })
+            .unwrap();
+        assert!(sensor_id + 1 - received.load(Ordering::Relaxed) <= 4);
+    }
+    wait_for(|| received.load(Ordering::Relaxed) == 12);
+    wait_for(|| stalls.load(Ordering::Relaxed) > 0);
+
+    // Frames are dropped if the peer doesn't consume them within the stall timeout
+    let config = BridgeConfig {
+        window: 2,
+        stall_timeout: Duration::from_millis(200),
+        ..Default::default()
+    };
+
+    let (transport, _peer) = ChannelTransport::pair();
+    let bridge =
+        Bridge::with_config(transport, &router_a, Arc::new(TypeRegistry::new()), config).unwrap();
+
+    let reading = Reading {
+        sensor_id: 0,
+        value: 0.0,
+    };
+    bridge.send(&reading).unwrap();
+    bridge.send(&reading).unwrap();
+    assert!(matches!(bridge.send(&reading), Err(BridgeError::Stalled)));
+}
+
+#[traced_test]
+#[test]
+fn discovery() {
+    let router_a = MessageRouter::<()>::new();
+    let router_b = MessageRouter::<()>::new();
+
+    let (transport_a, transport_b) = ChannelTransport::pair();
+    let bridge_a = Bridge::new(transport_a, &router_a)
+        .unwrap()
+        .with_discovery("a", Duration::from_secs(5));
+    let bridge_b = Bridge::new(transport_b, &router_b)
+        .unwrap()
+        .with_discovery("b", Duration::from_secs(5));
+
+    let _forward_a = bridge_a.register::<Reading>();
+    let _forward_b = bridge_b.register::<Reading>();
+
+    let received_b = Arc::new(AtomicU64::new(0));
+    let count = received_b.clone();
+    let _endpoint_b = router_b
+        .create_endpoint::<Reading>()
+        .message(move |_src, msg| {
+            count.fetch_add(msg.sensor_id, Ordering::Relaxed);
+        });
+
+    // B advertises Reading, and A has no endpoint for Reading so does not advertise it
+    wait_for(|| bridge_a.remote_nodes::<Reading>() == ["b"]);
+    assert!(bridge_b.remote_nodes::<Reading>().is_empty());
+
+    // Without a local endpoint, unicast messages are delivered to a single endpoint of B
+    let results = router_a.handle_message(Message::unicast(Reading {
+        sensor_id: 3,
+        value: 0.3,
+    }));
+    assert_eq!(results, DispatchResult::Delivered(smallvec![]));
+    wait_for(|| received_b.load(Ordering::Relaxed) == 3);
+
+    // Local endpoints are preferred over remote nodes
+    let received_a = Arc::new(AtomicU64::new(0));
+    let count = received_a.clone();
+    let _endpoint_a = router_a
+        .create_endpoint::<Reading>()
+        .message(move |_src, msg| {
+            count.fetch_add(msg.sensor_id, Ordering::Relaxed);
+        });
+
+    router_a.handle_message(Message::unicast(Reading {
+        sensor_id: 4,
+        value: 0.4,
+    }));
+    assert_eq!(received_a.load(Ordering::Relaxed), 4);
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(received_b.load(Ordering::Relaxed), 3);
+
+    // Dropping B withdraws its advertisement
+    drop(bridge_b);
+    wait_for(|| bridge_a.remote_nodes::<Reading>().is_empty());
+}
+
+#[traced_test]
+#[test]
+fn heartbeat() {
+    use crate::bridge::{
+        heartbeat::{Heartbeat, HEARTBEAT_ID},
+        PeerRecovered, PeerUnreachable,
+    };
+
+    let router = MessageRouter::<()>::new();
+
+    let config = BridgeConfig {
+        heartbeat_interval: Duration::from_millis(20),
+        heartbeat_timeout: Duration::from_millis(100),
+        ..Default::default()
+    };
+
+    let (transport, peer) = ChannelTransport::pair();
+    let _bridge =
+        Bridge::with_config(transport, &router, Arc::new(TypeRegistry::new()), config).unwrap();
+
+    let unreachable = Arc::new(Mutex::new(Vec::new()));
+    let recovered = Arc::new(Mutex::new(Vec::new()));
+
+    let events = unreachable.clone();
+    let _unreachable = router
+        .create_endpoint::<PeerUnreachable>()
+        .message(move |_src, msg| events.lock().unwrap().push(msg.peer));
+    let events = recovered.clone();
+    let _recovered = router
+        .create_endpoint::<PeerRecovered>()
+        .message(move |_src, msg| events.lock().unwrap().push(msg.peer));
+
+    // The bridge sends heartbeats to its peers
+    let heartbeats = std::iter::from_fn(|| peer.recv_frame(Duration::from_secs(1)).unwrap())
+        .map(|frame| WireMessage::from_frame(&Bincode, &frame).unwrap())
+        .filter(|wire| wire.type_name == HEARTBEAT_ID)
+        .take(2)
+        .count();
+    assert_eq!(heartbeats, 2);
+
+    let beat = || {
+        peer.send_frame(&Heartbeat { session: 7 }.to_frame(&Bincode).unwrap())
+            .unwrap()
+    };
+
+    // A peer sending heartbeats within the timeout is live
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_millis(250) {
+        beat();
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    assert!(unreachable.lock().unwrap().is_empty());
+
+    // A silent peer is reported once
+    wait_for(|| !unreachable.lock().unwrap().is_empty());
+    std::thread::sleep(Duration::from_millis(150));
+    assert_eq!(*unreachable.lock().unwrap(), vec![7]);
+    assert!(recovered.lock().unwrap().is_empty());
+
+    // The next heartbeat recovers the peer
+    beat();
+    wait_for(|| !recovered.lock().unwrap().is_empty());
+    assert_eq!(*recovered.lock().unwrap(), vec![7]);
+}
+
+#[traced_test]
+#[test]
+fn discovery_expiry() {
+    use crate::bridge::{
+        compression::CAPABILITIES_ID,
+        discovery::{Advertisement, ADVERTISEMENT_ID},
+    };
+
+    let router = MessageRouter::<()>::new();
+
+    let (transport, peer) = ChannelTransport::pair();
+    let bridge = Bridge::new(transport, &router)
+        .unwrap()
+        .with_discovery("local", Duration::from_secs(5));
+    let _forward = bridge.register::<Reading>();
+
+    // Advertise Reading from a peer which never renews its advertisement
+    let advertisement = Advertisement {
+        node: "peer".into(),
+        types: vec![std::any::type_name::<Reading>().into()],
+        ttl_ms: 200,
+    };
+    let wire = WireMessage {
+        type_name: ADVERTISEMENT_ID.into(),
+        payload: Bincode.encode(&advertisement).unwrap(),
+        dest: WireDest::Broadcast,
+        traceparent: None,
+    };
+    peer.send_frame(&wire.to_frame(&Bincode).unwrap()).unwrap();
+
+    wait_for(|| bridge.remote_nodes::<Reading>() == ["peer"]);
+
+    // Unicast messages are sent to the peer, addressed to its node
+    router.handle_message(Message::unicast(Reading {
+        sensor_id: 1,
+        value: 0.1,
+    }));
+
+    let sent = loop {
+        let frame = peer.recv_frame(Duration::from_secs(1)).unwrap().unwrap();
+        let wire = WireMessage::from_frame(&Bincode, &frame).unwrap();
+        if wire.type_name != ADVERTISEMENT_ID && wire.type_name != CAPABILITIES_ID {
+            break wire;
+        }
+    };
+    assert_eq!(sent.dest, WireDest::Node("peer".into()));
+
+    // The advertisement expires once its time-to-live has passed
+    wait_for(|| bridge.remote_nodes::<Reading>().is_empty());
+    assert_eq!(
+        router.handle_message(Message::unicast(Reading {
+            sensor_id: 2,
+            value: 0.2,
+        })),
+        DispatchResult::NoHandler
+    );
+}
+
+#[traced_test]
+#[test]
+fn remote_endpoint() {
+    let router_a = MessageRouter::<()>::new();
+    let router_b = MessageRouter::<()>::new();
+
+    let (transport_a, transport_b) = ChannelTransport::pair();
+    let bridge_a = Bridge::new(transport_a, &router_a)
+        .unwrap()
+        .with_discovery("a", Duration::from_secs(5));
+    let bridge_b = Bridge::new(transport_b, &router_b)
+        .unwrap()
+        .with_discovery("b", Duration::from_secs(5));
+
+    let _forward_a = bridge_a.register::<Reading>();
+    let _forward_b = bridge_b.register::<Reading>();
+
+    let first = Arc::new(AtomicU64::new(0));
+    let count = first.clone();
+    let _first = router_b
+        .create_endpoint::<Reading>()
+        .message(move |_src, msg| {
+            count.fetch_add(msg.sensor_id, Ordering::Relaxed);
+        });
+
+    let second = Arc::new(AtomicU64::new(0));
+    let count = second.clone();
+    let endpoint = router_b
+        .create_endpoint::<Reading>()
+        .message(move |_src, msg| {
+            count.fetch_add(msg.sensor_id, Ordering::Relaxed);
+        });
+
+    wait_for(|| bridge_a.remote_nodes::<Reading>() == ["b"]);
+
+    // Delivered only to the addressed endpoint of node B
+    let results = router_a.handle_message(
+        Message::unicast(Reading {
+            sensor_id: 5,
+            value: 0.5,
+        })
+        .with_dest(Destination::remote("b", endpoint.addr())),
+    );
+    assert_eq!(results, DispatchResult::Delivered(smallvec![]));
+    wait_for(|| second.load(Ordering::Relaxed) == 5);
+    assert_eq!(first.load(Ordering::Relaxed), 0);
+
+    // Nodes without live advertisements can't be reached
+    let results = router_a.handle_message(
+        Message::unicast(Reading {
+            sensor_id: 6,
+            value: 0.6,
+        })
+        .with_dest(Destination::remote("c", endpoint.addr())),
+    );
+    assert_eq!(results, DispatchResult::NoHandler);
+}
+
+#[cfg(feature = "encryption")]
+#[traced_test]
+#[test]
+fn encrypted_transport() {
+    use crate::bridge::encrypted::{EncryptedTransport, StaticKeys};
+
+    const TIMEOUT: Duration = Duration::from_millis(100);
+
+    let key_a = [1; 32];
+    let key_b = [2; 32];
+
+    // Frames sent by A arrive raw at `wire`, and frames sent into `inject` arrive at B
+    let (transport_a, wire) = ChannelTransport::pair();
+    let (inject, transport_b) = ChannelTransport::pair();
+
+    let a = EncryptedTransport::new(transport_a, StaticKeys::new("a", key_a)).unwrap();
+    let b = EncryptedTransport::new(
+        transport_b,
+        StaticKeys::new("b", key_b).peer("a", key_a),
+    )
+    .unwrap();
+
+    a.send_frame(b"plaintext").unwrap();
+    let sealed = wire.recv_frame(TIMEOUT).unwrap().unwrap();
+    assert!(!sealed.windows(9).any(|w| w == b"plaintext"));
+
+    inject.send_frame(&sealed).unwrap();
+    assert_eq!(b.recv_frame(TIMEOUT).unwrap().unwrap(), b"plaintext");
+
+    // Replayed frames are dropped
+    inject.send_frame(&sealed).unwrap();
+    assert_eq!(b.recv_frame(TIMEOUT).unwrap(), None);
+
+    // Tampered frames fail authentication
+    a.send_frame(b"plaintext").unwrap();
+    let mut tampered = wire.recv_frame(TIMEOUT).unwrap().unwrap();
+    *tampered.last_mut().unwrap() ^= 1;
+    inject.send_frame(&tampered).unwrap();
+    assert_eq!(b.recv_frame(TIMEOUT).unwrap(), None);
+
+    // Frames claiming the identity of A, but encrypted with another key, fail authentication
+    let (forger, forged) = ChannelTransport::pair();
+    let forger = EncryptedTransport::new(forger, StaticKeys::new("a", key_b)).unwrap();
+    forger.send_frame(b"forged").unwrap();
+    inject.send_frame(&forged.recv_frame(TIMEOUT).unwrap().unwrap()).unwrap();
+    assert_eq!(b.recv_frame(TIMEOUT).unwrap(), None);
+
+    // B does not trust C
+    let (transport_c, from_c) = ChannelTransport::pair();
+    let c = EncryptedTransport::new(transport_c, StaticKeys::new("c", [3; 32])).unwrap();
+    c.send_frame(b"untrusted").unwrap();
+    inject.send_frame(&from_c.recv_frame(TIMEOUT).unwrap().unwrap()).unwrap();
+    assert_eq!(b.recv_frame(TIMEOUT).unwrap(), None);
+
+    // Frames reordered within the replay window are accepted
+    a.send_frame(b"first").unwrap();
+    a.send_frame(b"second").unwrap();
+    let first = wire.recv_frame(TIMEOUT).unwrap().unwrap();
+    let second = wire.recv_frame(TIMEOUT).unwrap().unwrap();
+    inject.send_frame(&second).unwrap();
+    inject.send_frame(&first).unwrap();
+    assert_eq!(b.recv_frame(TIMEOUT).unwrap().unwrap(), b"second");
+    assert_eq!(b.recv_frame(TIMEOUT).unwrap().unwrap(), b"first");
+}
+
+#[cfg(feature = "encryption")]
+#[traced_test]
+#[test]
+fn encrypted_bridge() {
+    use crate::bridge::encrypted::{EncryptedTransport, StaticKeys};
+
+    let router_a = MessageRouter::<()>::new();
+    let router_b = MessageRouter::<()>::new();
+
+    let key_a = [1; 32];
+    let key_b = [2; 32];
+
+    let (transport_a, transport_b) = ChannelTransport::pair();
+    let transport_a = EncryptedTransport::new(
+        transport_a,
+        StaticKeys::new("a", key_a).peer("b", key_b),
+    )
+    .unwrap();
+    let transport_b = EncryptedTransport::new(
+        transport_b,
+        StaticKeys::new("b", key_b).peer("a", key_a),
+    )
+    .unwrap();
+
+    let bridge_a = Bridge::new(transport_a, &router_a).unwrap();
+    let bridge_b = Bridge::new(transport_b, &router_b).unwrap();
+
+    let _forward_a = bridge_a.register::<Reading>();
+    let _forward_b = bridge_b.register::<Reading>();
+
+    let received = Arc::new(AtomicU64::new(0));
+    let count = received.clone();
+    let _endpoint = router_b
+        .create_endpoint::<Reading>()
+        .message(move |_src, msg| {
+            count.fetch_add(msg.sensor_id, Ordering::Relaxed);
+        });
+
+    router_a.handle_message(Message::broadcast(Reading {
+        sensor_id: 9,
+        value: 0.9,
+    }));
+    wait_for(|| received.load(Ordering::Relaxed) == 9);
+}
+
+#[cfg(feature = "lz4")]
+#[traced_test]
+#[test]
+fn compressed_bridge() {
+    use crate::bridge::compression::{Capabilities, Compression, CAPABILITIES_ID, COMPRESSED_ID};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Log {
+        line: String,
+    }
+
+    let log = Log {
+        line: "sensor 1 reading nominal, ".repeat(100),
+    };
+    let config = BridgeConfig {
+        compression: Compression::Lz4,
+        compression_threshold: 64,
+        ..Default::default()
+    };
+
+    let router_a = MessageRouter::<()>::new();
+    let router_b = MessageRouter::<()>::new();
+
+    let (transport_a, transport_b) = ChannelTransport::pair();
+    let sent_bytes = transport_a.sent_bytes.clone();
+    let bridge_a =
+        Bridge::with_config(transport_a, &router_a, Arc::new(TypeRegistry::new()), config)
+            .unwrap();
+    let bridge_b = Bridge::new(transport_b, &router_b).unwrap();
+
+    let _forward_a = bridge_a.register::<Log>();
+    let _forward_b = bridge_b.register::<Log>();
+
+    let received = Arc::new(AtomicU64::new(0));
+    let count = received.clone();
+    let expected = log.clone();
+    let _endpoint = router_b.create_endpoint::<Log>().message(move |_src, msg| {
+        assert_eq!(msg, expected);
+        count.fetch_add(1, Ordering::Relaxed);
+    });
+
+    // Frames are compressed once the peer replies to the capability request
+    let mut sent = 0;
+    loop {
+        let before = sent_bytes.load(Ordering::Relaxed);
+        router_a.handle_message(Message::broadcast(log.clone()));
+        sent += 1;
+
+        if sent_bytes.load(Ordering::Relaxed) - before < log.line.len() as u64 / 4 {
+            break;
+        }
+        assert!(sent < 100, "Frames were never compressed");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    wait_for(|| received.load(Ordering::Relaxed) == sent);
+
+    // Frames are not compressed for peers which don't accept the algorithm
+    let (transport, peer) = ChannelTransport::pair();
+    let bridge = Bridge::with_config(transport, &router_a, Arc::new(TypeRegistry::new()), config)
+        .unwrap();
+
+    let wire = WireMessage {
+        type_name: CAPABILITIES_ID.into(),
+        payload: Bincode
+            .encode(&Capabilities {
+                compression: 0,
+                request: true,
+            })
+            .unwrap(),
+        dest: WireDest::Broadcast,
+        traceparent: None,
+    };
+    peer.send_frame(&wire.to_frame(&Bincode).unwrap()).unwrap();
+
+    // Wait for the reply, after which the capabilities of the peer are known
+    loop {
+        let frame = peer.recv_frame(Duration::from_secs(1)).unwrap().unwrap();
+        let wire = WireMessage::from_frame(&Bincode, &frame).unwrap();
+        let capabilities: Capabilities = Bincode.decode(&wire.payload).unwrap();
+        if !capabilities.request {
+            break;
+        }
+    }
+
+    bridge.send(&log).unwrap();
+    let frame = peer.recv_frame(Duration::from_secs(1)).unwrap().unwrap();
+    let wire = WireMessage::from_frame(&Bincode, &frame).unwrap();
+    assert_ne!(wire.type_name, COMPRESSED_ID);
+    assert_eq!(Bincode.decode::<Log>(&wire.payload).unwrap(), log);
+}
+
+#[test]
+fn backoff() {
+    use crate::bridge::Backoff;
+
+    let backoff = Backoff {
+        initial: Duration::from_millis(100),
+        max: Duration::from_secs(1),
+        multiplier: 2.0,
+        jitter: 0.0,
+    };
+    assert_eq!(backoff.delay(0), Duration::from_millis(100));
+    assert_eq!(backoff.delay(2), Duration::from_millis(400));
+    assert_eq!(backoff.delay(10), Duration::from_secs(1));
+    assert_eq!(backoff.delay(u32::MAX), Duration::from_secs(1));
+
+    let backoff = Backoff {
+        jitter: 0.5,
+        ..backoff
+    };
+    for attempt in 0..10 {
+        let delay = backoff.delay(attempt);
+        let max = Duration::from_millis(100 << attempt).min(Duration::from_secs(1));
+        assert!(delay <= max && delay >= max / 2, "{delay:?} outside jitter of {max:?}");
+    }
+}
+
+#[cfg(feature = "tokio-bridge")]
+#[traced_test]
+#[tokio::test]
+async fn tcp_reconnect() {
+    use crate::bridge::{tcp::TcpBridge, Backoff, BridgeConnected, BridgeDisconnected};
+
+    /// Wait for a condition to become true without blocking the runtime, or panic after a timeout
+    async fn eventually(condition: impl Fn() -> bool) {
+        let start = Instant::now();
+        while !condition() {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "Timed out waiting for condition"
+            );
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    let router_a = MessageRouter::<()>::new();
+    let router_b = MessageRouter::<()>::new();
+
+    let listener = TcpBridge::listen("127.0.0.1:0", &router_a).await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let _forward_a = listener.register::<Reading>();
+
+    let backoff = Backoff {
+        initial: Duration::from_millis(10),
+        max: Duration::from_millis(50),
+        ..Default::default()
+    };
+    let client = TcpBridge::connect(addr.to_string(), &router_b, backoff);
+    let _forward_b = client.register::<Reading>();
+
+    let received = Arc::new(AtomicU64::new(0));
+    let count = received.clone();
+    let _endpoint = router_a
+        .create_endpoint::<Reading>()
+        .message(move |_src, msg| {
+            count.fetch_add(msg.sensor_id, Ordering::Relaxed);
+        });
+
+    let connected = Arc::new(AtomicU64::new(0));
+    let count = connected.clone();
+    let _connected = router_b
+        .create_endpoint::<BridgeConnected>()
+        .message(move |_src, msg| {
+            assert_eq!(msg.peer, addr.to_string());
+            count.fetch_add(1, Ordering::Relaxed);
+        });
+
+    let disconnected = Arc::new(AtomicU64::new(0));
+    let count = disconnected.clone();
+    let _disconnected = router_b
+        .create_endpoint::<BridgeDisconnected>()
+        .message(move |_src, msg| {
+            assert!(msg.retry_in.is_some());
+            count.fetch_add(1, Ordering::Relaxed);
+        });
+
+    eventually(|| listener.connections() == 1 && client.connections() == 1).await;
+    assert_eq!(connected.load(Ordering::Relaxed), 1);
+
+    router_b.handle_message(Message::broadcast(Reading {
+        sensor_id: 3,
+        value: 0.3,
+    }));
+    eventually(|| received.load(Ordering::Relaxed) == 3).await;
+
+    // Restart the listener. The client should reconnect with backoff.
+    drop(_forward_a);
+    drop(listener);
+    eventually(|| disconnected.load(Ordering::Relaxed) == 1).await;
+
+    let listener = TcpBridge::listen(addr, &router_a).await.unwrap();
+    let _forward_a = listener.register::<Reading>();
+    eventually(|| connected.load(Ordering::Relaxed) == 2).await;
+
+    router_b.handle_message(Message::broadcast(Reading {
+        sensor_id: 4,
+        value: 0.4,
+    }));
+    eventually(|| received.load(Ordering::Relaxed) == 7).await;
+}
+
+#[cfg(feature = "zmq")]
+#[traced_test]
+#[test]
+fn zmq_transport() {
+    use crate::bridge::zmq::ZmqTransport;
+
+    let router_a = MessageRouter::<()>::new();
+    let router_b = MessageRouter::<()>::new();
+
+    let bridge_a =
+        Bridge::new(ZmqTransport::bind("tcp://127.0.0.1:*").unwrap(), &router_a).unwrap();
+    let bridge_b =
+        Bridge::new(ZmqTransport::bind("tcp://127.0.0.1:*").unwrap(), &router_b).unwrap();
+
+    bridge_a
+        .connect(&bridge_b.transport().endpoint().unwrap())
+        .unwrap();
+    bridge_b
+        .connect(&bridge_a.transport().endpoint().unwrap())
+        .unwrap();
+
+    let _forward_a = bridge_a.register::<Reading>();
+    let _forward_b = bridge_b.register::<Reading>();
+
+    let received = Arc::new(AtomicU64::new(0));
+    let count = received.clone();
+    let _endpoint = router_a
+        .create_endpoint::<Reading>()
+        .message(move |_src, msg| {
+            count.fetch_add(msg.sensor_id, Ordering::Relaxed);
+        });
+
+    // Frames published before the subscription is established are lost, so publish until one arrives
+    wait_for(|| {
+        bridge_b
+            .send(&Reading {
+                sensor_id: 1,
+                value: 0.0,
+            })
+            .unwrap();
+        received.load(Ordering::Relaxed) > 0
+    });
+
+    // Wait for any frames still in flight
+    std::thread::sleep(Duration::from_millis(100));
+    let connected = received.load(Ordering::Relaxed);
+
+    router_b.handle_message(Message::broadcast(Reading {
+        sensor_id: 9,
+        value: 9.0,
+    }));
+    wait_for(|| received.load(Ordering::Relaxed) == connected + 9);
+}
+
+/// Encode a message with a codec, and check it decodes to the same payload
+fn codec_roundtrip(codec: impl Codec) -> Vec<u8> {
+    let wire = WireMessage::encode(
+        &codec,
+        &Reading {
+            sensor_id: 12,
+            value: 1.5,
+        },
+    )
+    .unwrap();
+    let frame = wire.to_frame(&codec).unwrap();
+
+    let decoded = WireMessage::from_frame(&codec, &frame).unwrap();
+    assert_eq!(decoded.type_name, wire.type_name);
+
+    let reading: Reading = codec.decode(&decoded.payload).unwrap();
+    assert_eq!(reading.sensor_id, 12);
+    assert_eq!(reading.value, 1.5);
+
+    frame
+}
+
+#[test]
+fn codecs() {
+    codec_roundtrip(Bincode);
+
+    #[cfg(feature = "postcard")]
+    codec_roundtrip(crate::bridge::codec::Postcard);
+
+    #[cfg(feature = "json")]
+    {
+        let frame = codec_roundtrip(crate::bridge::codec::Json);
+        assert!(String::from_utf8(frame).unwrap().contains("Reading"));
+    }
+
+    // Frames from a different codec fail to decode rather than producing garbage
+    assert!(Bincode
+        .decode::<WireMessage>(&[0xff, 0xff, 0xff, 0xff])
+        .is_err());
+}
+
+#[cfg(feature = "json")]
+#[traced_test]
+#[test]
+fn custom_codec_bridge() {
+    use crate::bridge::codec::Json;
+
+    let router_a = MessageRouter::<()>::new();
+    let router_b = MessageRouter::<()>::new();
+
+    let (transport_a, transport_b) = ChannelTransport::pair();
+    let registry = Arc::new(TypeRegistry::with_codec(Json));
+    let bridge_a = Bridge::with_registry(transport_a, &router_a, registry.clone()).unwrap();
+    let bridge_b = Bridge::with_registry(transport_b, &router_b, registry).unwrap();
+
+    let _forward_a = bridge_a.register::<Reading>();
+    let _forward_b = bridge_b.register::<Reading>();
+
+    let received = Arc::new(AtomicU64::new(0));
+    let count = received.clone();
+    let _endpoint = router_b
+        .create_endpoint::<Reading>()
+        .message(move |_src, msg| {
+            count.fetch_add(msg.sensor_id, Ordering::Relaxed);
+        });
+
+    router_a.handle_message(Message::broadcast(Reading {
+        sensor_id: 8,
+        value: 0.8,
+    }));
+    wait_for(|| received.load(Ordering::Relaxed) == 8);
+}
+
+#[test]
+fn type_registry() {
+    let registry = TypeRegistry::new();
+    registry.register::<Reading>("reading_v1");
+
+    assert_eq!(registry.id::<Reading>().as_deref(), Some("reading_v1"));
+    assert!(registry.contains("reading_v1"));
+
+    let wire = registry
+        .encode(&Reading {
+            sensor_id: 2,
+            value: 0.2,
+        })
+        .unwrap();
+    assert_eq!(wire.type_name, "reading_v1");
+    assert!(registry.decode(&wire).unwrap().is_type::<Reading>());
+
+    // Unregistered types cannot be encoded
+    assert!(matches!(
+        registry.encode(&5u64),
+        Err(BridgeError::UnknownType(_))
+    ));
+
+    // Registering a type again replaces its identifier
+    registry.register::<Reading>("reading_v2");
+    assert!(!registry.contains("reading_v1"));
+    assert!(matches!(
+        registry.decode(&wire),
+        Err(BridgeError::UnknownType(_))
+    ));
+
+    // Registering an identifier for another type replaces the previous type
+    registry.register::<u64>("reading_v2");
+    assert_eq!(registry.id::<Reading>(), None);
+    assert_eq!(registry.id::<u64>().as_deref(), Some("reading_v2"));
+}
+
+#[traced_test]
+#[test]
+fn registry_bridge() {
+    let router_a = MessageRouter::<()>::new();
+    let router_b = MessageRouter::<()>::new();
+
+    // Each peer has its own registry, which agree on the identifier but not the type name
+    let registry_a = Arc::new(TypeRegistry::new());
+    let registry_b = Arc::new(TypeRegistry::new());
+    registry_a.register::<Reading>("reading");
+    registry_b.register::<Reading>("reading");
+    registry_b.register::<u64>("count");
+
+    let (transport_a, transport_b) = ChannelTransport::pair();
+    let bridge_a = Bridge::with_registry(transport_a, &router_a, registry_a).unwrap();
+    let bridge_b = Bridge::with_registry(transport_b, &router_b, registry_b).unwrap();
+
+    let _forward_a = bridge_a.register::<Reading>();
+    let _forward_b = bridge_b.register::<Reading>();
+
+    let received = Arc::new(AtomicU64::new(0));
+    let count = received.clone();
+    let _endpoint = router_b
+        .create_endpoint::<Reading>()
+        .message(move |_src, msg| {
+            count.fetch_add(msg.sensor_id, Ordering::Relaxed);
+        });
+
+    let counts = Arc::new(AtomicU64::new(0));
+    let count = counts.clone();
+    let _count_endpoint = router_b.create_endpoint::<u64>().message(move |_src, msg| {
+        count.fetch_add(msg, Ordering::Relaxed);
+    });
+
+    // Types in the registry which have not been registered with the bridge are not injected
+    let _forward_count = bridge_a.register::<u64>();
+    router_a.handle_message(Message::broadcast(3u64));
+
+    router_a.handle_message(Message::broadcast(Reading {
+        sensor_id: 4,
+        value: 0.4,
+    }));
+    wait_for(|| received.load(Ordering::Relaxed) == 4);
+
+    // Frames are received in order, so the count has already been dropped
+    assert_eq!(counts.load(Ordering::Relaxed), 0);
+}
+
+/// Previous version of [`Reading`], without a value
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ReadingV1 {
+    sensor_id: u32,
+}
+
+#[traced_test]
+#[test]
+fn versioned_upgrade() {
+    let router_a = MessageRouter::<()>::new();
+    let router_b = MessageRouter::<()>::new();
+
+    // Peer A runs an older build, which only knows the first version
+    let registry_a = Arc::new(TypeRegistry::new());
+    registry_a.register::<ReadingV1>("reading_v1");
+
+    // Peer B decodes the first version into the current type
+    let registry_b = Arc::new(TypeRegistry::new());
+    registry_b.register::<Reading>("reading_v2");
+    registry_b.register_upgrade("reading_v1", |old: ReadingV1| Reading {
+        sensor_id: old.sensor_id as u64,
+        value: f32::NAN,
+    });
+
+    assert_eq!(registry_b.id::<Reading>().as_deref(), Some("reading_v2"));
+    assert!(registry_b.contains("reading_v1"));
+
+    let (transport_a, transport_b) = ChannelTransport::pair();
+    let bridge_a = Bridge::with_registry(transport_a, &router_a, registry_a).unwrap();
+    let bridge_b = Bridge::with_registry(transport_b, &router_b, registry_b).unwrap();
+
+    let _forward_a = bridge_a.register::<ReadingV1>();
+    let _forward_b = bridge_b.register::<Reading>();
+
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let readings = received.clone();
+    let _endpoint = router_b
+        .create_endpoint::<Reading>()
+        .message(move |_src, msg| {
+            readings.lock().unwrap().push(msg);
+        });
+
+    router_a.handle_message(Message::broadcast(ReadingV1 { sensor_id: 11 }));
+    wait_for(|| received.lock().unwrap().len() == 1);
+
+    let reading = received.lock().unwrap()[0].clone();
+    assert_eq!(reading.sensor_id, 11);
+    assert!(reading.value.is_nan());
+}
@@ -0,0 +1,92 @@
+use std::sync::{Arc, Mutex};
+
+use tracing_test::traced_test;
+
+use crate::{config::RouterConfig, message::Message, router::MessageRouter};
+
+#[derive(Debug)]
+struct Click;
+
+#[derive(Debug)]
+struct Command;
+
+#[derive(Debug)]
+struct Status;
+
+/// Router where a click dispatches a command, which queues a status update
+fn router(capacity: usize) -> MessageRouter<'static, ()> {
+    let router = MessageRouter::<()>::with_config(RouterConfig::default().causality_log(capacity));
+
+    let dispatcher = router.clone();
+    router.static_endpoint(move |_src, _msg: Click| {
+        dispatcher.handle_message(Message::unicast(Command));
+    });
+
+    let sender = router.sender();
+    router.static_endpoint(move |_src, _msg: Command| {
+        sender.send(Message::unicast(Status));
+    });
+
+    router.static_endpoint(|_src, _msg: Status| {});
+    router
+}
+
+#[traced_test]
+#[test]
+fn causal_chain() {
+    let router = router(16);
+
+    let click = Message::unicast(Click);
+    let click_id = click.id();
+    assert_eq!(click.caused_by(), None);
+
+    router.handle_message(click);
+    router.drain();
+
+    let links = router.causal_links();
+    assert_eq!(links.len(), 3);
+    assert_eq!(links[2].type_name, std::any::type_name::<Status>());
+
+    // The status update is traced back through the command to the click
+    let chain: Vec<_> = router
+        .causal_chain(links[2].id)
+        .iter()
+        .map(|link| link.id)
+        .collect();
+    assert_eq!(chain, vec![links[2].id, links[1].id, click_id]);
+    assert_eq!(router.causal_chain(click_id).len(), 1);
+}
+
+#[traced_test]
+#[test]
+fn causal_chain_truncated() {
+    let router = router(2);
+    router.handle_message(Message::unicast(Click));
+    router.drain();
+
+    // The link of the click was discarded
+    let links = router.causal_links();
+    assert_eq!(links.len(), 2);
+    assert_eq!(router.causal_chain(links[1].id).len(), 2);
+    assert!(links[0].caused_by.is_some());
+}
+
+#[traced_test]
+#[test]
+fn context_message_id() {
+    let router = MessageRouter::<()>::new();
+    let seen = Arc::new(Mutex::new(Vec::new()));
+
+    let log = seen.clone();
+    let _endpoint = router
+        .create_endpoint::<Command>()
+        .message_ctx(move |ctx, _msg| {
+            log.lock().unwrap().push(ctx.message_id());
+        });
+
+    let command = Message::unicast(Command);
+    let id = command.id();
+    router.handle_message(command);
+
+    assert_eq!(*seen.lock().unwrap(), vec![Some(id)]);
+}
@@ -0,0 +1,141 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tracing_test::traced_test;
+
+use crate::{
+    bridge::udp::UdpTransport,
+    cluster::{Cluster, ClusterConfig, Election, Heartbeat, LeadershipChanged, NodeDown, NodeUp},
+    router::MessageRouter,
+};
+
+const CONFIG: ClusterConfig = ClusterConfig {
+    interval: Duration::from_millis(50),
+    timeout: Duration::from_millis(400),
+};
+
+/// Poll until a condition is true, panicking after a timeout
+fn wait_for(condition: impl Fn() -> bool) {
+    let start = Instant::now();
+    while !condition() {
+        assert!(
+            start.elapsed() < Duration::from_secs(5),
+            "Timed out waiting for condition"
+        );
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[traced_test]
+#[test]
+fn membership() {
+    let router_a = MessageRouter::<()>::new();
+    let router_b = MessageRouter::<()>::new();
+    let router_c = MessageRouter::<()>::new();
+
+    // A and C are only bridged to B
+    let transport_a = UdpTransport::bind("127.0.0.1:0", &router_a).unwrap();
+    let transport_b = UdpTransport::bind("127.0.0.1:0", &router_b).unwrap();
+    let transport_c = UdpTransport::bind("127.0.0.1:0", &router_c).unwrap();
+
+    let addr_b = transport_b.local_addr().unwrap();
+    transport_a.add_peer(addr_b).unwrap();
+    transport_c.add_peer(addr_b).unwrap();
+    transport_b
+        .add_peer(transport_a.local_addr().unwrap())
+        .unwrap();
+    transport_b
+        .add_peer(transport_c.local_addr().unwrap())
+        .unwrap();
+
+    let _forward_a = transport_a.register::<Heartbeat>();
+    let _forward_b = transport_b.register::<Heartbeat>();
+    let forward_c = transport_c.register::<Heartbeat>();
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let up = events.clone();
+    let _up = router_a
+        .create_endpoint::<NodeUp>()
+        .message(move |_src, msg| up.lock().unwrap().push(format!("up {}", msg.node)));
+    let down = events.clone();
+    let _down = router_a
+        .create_endpoint::<NodeDown>()
+        .message(move |_src, msg| down.lock().unwrap().push(format!("down {}", msg.node)));
+
+    let cluster_a = Cluster::new("a", &router_a, CONFIG).unwrap();
+    let _cluster_b = Cluster::new("b", &router_b, CONFIG).unwrap();
+    let cluster_c = Cluster::new("c", &router_c, CONFIG).unwrap();
+
+    // A learns of C through the heartbeats of B
+    wait_for(|| cluster_a.members() == ["b", "c"]);
+    assert!(cluster_a.is_up("c"));
+
+    // Once C stops sending heartbeats, it is considered down
+    drop(cluster_c);
+    drop(forward_c);
+    wait_for(|| cluster_a.members() == ["b"]);
+    assert!(!cluster_a.is_up("c"));
+
+    let mut events = events.lock().unwrap().clone();
+    events[..2].sort();
+    assert_eq!(events, ["up b", "up c", "down c"]);
+}
+
+#[traced_test]
+#[test]
+fn election() {
+    let router_a = MessageRouter::<()>::new();
+    let router_b = MessageRouter::<()>::new();
+
+    let transport_a = UdpTransport::bind("127.0.0.1:0", &router_a).unwrap();
+    let transport_b = UdpTransport::bind("127.0.0.1:0", &router_b).unwrap();
+    transport_a
+        .add_peer(transport_b.local_addr().unwrap())
+        .unwrap();
+    transport_b
+        .add_peer(transport_a.local_addr().unwrap())
+        .unwrap();
+
+    let _forward_a = transport_a.register::<Heartbeat>();
+    let forward_b = transport_b.register::<Heartbeat>();
+
+    let changes = Arc::new(Mutex::new(Vec::new()));
+    let changed = changes.clone();
+    let _changed = router_a
+        .create_endpoint::<LeadershipChanged>()
+        .message(move |_src, msg| changed.lock().unwrap().push(msg));
+
+    let cluster_a = Cluster::new("a", &router_a, CONFIG).unwrap();
+    let cluster_b = Cluster::new("b", &router_b, CONFIG).unwrap();
+    let election_a = Election::new(&cluster_a, &router_a).unwrap();
+    let election_b = Election::new(&cluster_b, &router_b).unwrap();
+
+    // The node with the highest identity leads
+    wait_for(|| election_a.leader().as_deref() == Some("b"));
+    wait_for(|| election_b.is_leader());
+    assert!(!election_a.is_leader());
+
+    // A takes over once B fails
+    drop(election_b);
+    drop(cluster_b);
+    drop(forward_b);
+    wait_for(|| election_a.is_leader());
+
+    assert_eq!(
+        *changes.lock().unwrap(),
+        [
+            LeadershipChanged {
+                leader: "b".into(),
+                previous: None,
+                is_local: false,
+            },
+            LeadershipChanged {
+                leader: "a".into(),
+                previous: Some("b".into()),
+                is_local: true,
+            },
+        ]
+    );
+}
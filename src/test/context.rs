@@ -0,0 +1,47 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use tracing_test::traced_test;
+
+use crate::{message::Message, router::MessageRouter};
+
+#[derive(Debug, Default)]
+struct Counter(AtomicU32);
+
+#[derive(Debug)]
+struct Scale(u32);
+
+#[traced_test]
+#[test]
+fn provide() {
+    let router = MessageRouter::<u32>::new();
+    router.provide(Counter::default());
+
+    let _endpoint = router.create_endpoint::<u32>().message_ctx(|ctx, msg| {
+        ctx.expect::<Counter>().0.fetch_add(1, Ordering::Relaxed);
+        let scale = ctx.get::<Scale>().map_or(1, |scale| scale.0);
+        assert_eq!(ctx.source().is_some(), msg == 2);
+        msg * scale
+    });
+
+    let result = router.handle_message(Message::unicast(1u32));
+    assert_eq!(result.results().unwrap(), [1]);
+
+    // Resources provided to a clone of the router are shared with handlers
+    assert!(router.clone().provide(Scale(10)).is_none());
+    let result = router.handle_message(Message::unicast(2u32).with_source(1u64));
+    assert_eq!(result.results().unwrap(), [20]);
+
+    assert_eq!(
+        router
+            .resource::<Counter>()
+            .unwrap()
+            .0
+            .load(Ordering::Relaxed),
+        2
+    );
+
+    // Replacing a resource returns the previous one
+    assert_eq!(router.provide(Scale(100)).unwrap().0, 10);
+    assert_eq!(router.withdraw::<Scale>().unwrap().0, 100);
+    assert!(router.resource::<Scale>().is_none());
+}
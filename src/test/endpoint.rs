@@ -1,21 +1,28 @@
-use std::iter::repeat_with;
+use std::{
+    iter::repeat_with,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
+use smallvec::smallvec;
 use tracing_test::traced_test;
 
 use crate::{
+    dispatch::DispatchResult,
+    filter::{FilterOp, SourceFilter},
     message::{Destination, Message},
+    middleware::DropReason,
     router::MessageRouter,
+    spawn::BoxFuture,
     traits::EndpointAddress as _,
 };
 
 use super::TestPayload;
 
-type TestSource = u64;
-
 #[traced_test]
 #[test]
 fn endpoint() {
-    let mut router = MessageRouter::<Result<u64, ()>, TestSource>::new();
+    let router = MessageRouter::<Result<u64, ()>>::new();
     let _endpoint = router
         .create_endpoint::<TestPayload>()
         .message(|_src, msg| {
@@ -35,7 +42,7 @@ fn endpoint() {
 #[traced_test]
 #[test]
 fn endpoint_deregister() {
-    let mut router = MessageRouter::<(), TestSource>::new();
+    let router = MessageRouter::<()>::new();
 
     // Create a Vec of 100 endpoints
     let endpoints: Vec<_> = repeat_with(|| {
@@ -65,7 +72,7 @@ fn endpoint_deregister() {
 #[traced_test]
 #[test]
 fn endpoint_address() {
-    let mut router = MessageRouter::<u32, TestSource>::new();
+    let router = MessageRouter::<u32>::new();
 
     let endpoint = router
         .create_endpoint::<TestPayload>()
@@ -75,30 +82,28 @@ fn endpoint_address() {
         });
 
     let message = Message::unicast(TestPayload::Integer(1234))
-        .with_dest(Destination::endpoint((&endpoint).addr()));
+        .with_dest(Destination::endpoint(endpoint.addr()));
 
     let result = router.handle_message(message);
-    assert!(result.is_some());
-    assert!(result.unwrap()[0] == 8675309);
+    assert_eq!(result, DispatchResult::Delivered(smallvec![8675309]));
 
     let message = Message::broadcast(TestPayload::Integer(1234))
         .with_dest(Destination::endpoint(endpoint.addr()));
 
     let result = router.handle_message(message);
-    assert!(result.is_some());
-    assert!(result.unwrap()[0] == 8675309);
+    assert_eq!(result, DispatchResult::Delivered(smallvec![8675309]));
 
     // Sending TestPayload to an unknown address should not be handled by the endpoint
     let message =
         Message::unicast(TestPayload::Integer(1234)).with_dest(Destination::endpoint(99999));
     let result = router.handle_message(message);
-    assert!(result.is_none());
+    assert_eq!(result, DispatchResult::NoHandler);
 
     // Sending TestPayload to an unknown address should not be handled by the endpoint
     let message =
         Message::broadcast(TestPayload::Integer(1234)).with_dest(Destination::endpoint(99999));
     let result = router.handle_message(message);
-    assert!(result.is_none());
+    assert_eq!(result, DispatchResult::NoHandler);
 
     drop(endpoint);
 }
@@ -106,7 +111,7 @@ fn endpoint_address() {
 #[traced_test]
 #[test]
 fn endpoint_boxed() {
-    let mut router = MessageRouter::<u32, TestSource>::new();
+    let router = MessageRouter::<u32>::new();
 
     let endpoint = router.create_endpoint::<Box<u32>>().message(|_src, msg| {
         println!("ENDPOINT RX {msg:?}");
@@ -117,14 +122,13 @@ fn endpoint_boxed() {
     let message = Message::broadcast(Box::new("this is the wrong type"))
         .with_dest(Destination::endpoint(endpoint.addr()));
     let result = router.handle_message(message);
-    assert!(result.is_none());
+    assert_eq!(result, DispatchResult::TypeMismatch);
 
     // Sending the correct message type in a box to an endpoint receiving a Box<u32> should yield results
     let message =
         Message::broadcast(Box::new(1u32)).with_dest(Destination::endpoint(endpoint.addr()));
     let result = router.handle_message(message);
-    assert!(result.is_some());
-    assert!(result.unwrap()[0] == 8675309);
+    assert_eq!(result, DispatchResult::Delivered(smallvec![8675309]));
 }
 
 #[traced_test]
@@ -145,7 +149,7 @@ fn endpoint_box_dyn() {
         }
     }
 
-    let mut router = MessageRouter::<u32, TestSource>::new();
+    let router = MessageRouter::<u32>::new();
 
     // Create an endpoint listening for Box<dyn TestTrait>
     let endpoint = router
@@ -159,8 +163,682 @@ fn endpoint_box_dyn() {
     let msg: Box<dyn TestTrait> = Box::new(Test { val: 1234 });
 
     // Sending the wrong message type in a box to an endpoint receiving a Box<u32> should yield no results
+    assert_eq!(msg.get(), 1234);
     let message = Message::unicast(msg).with_dest(Destination::endpoint(endpoint.addr()));
     let result = router.handle_message(message);
-    assert!(result.is_some());
-    assert!(result.unwrap()[0] == 8675309);
+    assert_eq!(result, DispatchResult::Delivered(smallvec![8675309]));
+}
+
+#[traced_test]
+#[test]
+fn endpoint_type_mismatch() {
+    let router = MessageRouter::<u32>::new();
+    let endpoint = router.create_endpoint::<u32>().message(|_src, msg| msg);
+
+    let dead_letters = Arc::new(Mutex::new(Vec::new()));
+    let dropped = dead_letters.clone();
+    router.on_dead_letter(move |dead_letter| {
+        dropped.lock().unwrap().push(dead_letter.reason);
+    });
+
+    // Messages of another payload type are passed to the dead-letter sink without calling the endpoint
+    let message = Message::unicast(5u64).with_dest(Destination::endpoint(endpoint.addr()));
+    assert_eq!(router.handle_message(message), DispatchResult::TypeMismatch);
+    assert_eq!(
+        *dead_letters.lock().unwrap(),
+        vec![DropReason::TypeMismatch]
+    );
+
+    let message = Message::unicast(5u32).with_dest(Destination::endpoint(endpoint.addr()));
+    assert_eq!(
+        router.handle_message(message),
+        DispatchResult::Delivered(smallvec![5])
+    );
+    assert_eq!(dead_letters.lock().unwrap().len(), 1);
+}
+
+#[traced_test]
+#[test]
+fn send_direct() {
+    let router = MessageRouter::<u32>::new();
+    let endpoint = router
+        .create_endpoint::<u32>()
+        .message(|src, msg| if src.is_none() { msg + 1 } else { 0 });
+
+    assert_eq!(
+        router.send_direct(endpoint.addr(), 5u32),
+        DispatchResult::Delivered(smallvec![6])
+    );
+    assert_eq!(
+        router.send_direct(endpoint.addr(), 5u64),
+        DispatchResult::TypeMismatch
+    );
+    assert_eq!(router.send_direct(99999, 5u32), DispatchResult::NoHandler);
+    assert_eq!(router.metrics().get::<u32>().unwrap().delivered, 1);
+}
+
+#[traced_test]
+#[test]
+fn mixed_sources() {
+    #[derive(Debug, Clone, Copy, Hash)]
+    struct Sensor(u32);
+
+    let router = MessageRouter::<u32>::new();
+    let _endpoint = router
+        .create_endpoint::<u32>()
+        .message(|src, msg| match src {
+            Some(src) if src.is::<Sensor>() => src.get::<Sensor>().unwrap().0 + msg,
+            Some(src) => src.get::<u64>().map_or(0, |id| id as u32 * msg),
+            None => msg,
+        });
+
+    assert_eq!(
+        router.handle_message(Message::unicast(3u32).with_source(Sensor(10))),
+        DispatchResult::Delivered(smallvec![13])
+    );
+    assert_eq!(
+        router.handle_message(Message::unicast(3u32).with_source(10u64)),
+        DispatchResult::Delivered(smallvec![30])
+    );
+    assert_eq!(
+        router.handle_message(Message::unicast(3u32).with_source("unknown")),
+        DispatchResult::Delivered(smallvec![0])
+    );
+    assert_eq!(
+        router.handle_message(Message::unicast(3u32)),
+        DispatchResult::Delivered(smallvec![3])
+    );
+}
+
+#[traced_test]
+#[test]
+fn endpoint_stats() {
+    let router = MessageRouter::<u32>::new();
+    let endpoint = router
+        .create_endpoint::<u32>()
+        .filter(SourceFilter::default().add(1u64))
+        .message(|_src, msg| {
+            std::thread::sleep(Duration::from_millis(5));
+            msg
+        });
+
+    let stats = router.endpoint_stats(endpoint.addr()).unwrap();
+    assert_eq!((stats.received, stats.last_activity), (0, None));
+
+    let start = Instant::now();
+    let dest = Destination::endpoint(endpoint.addr());
+    let _ = router.handle_message(
+        Message::unicast(1u32)
+            .with_dest(dest.clone())
+            .with_source(1u64),
+    );
+    let _ = router.handle_message(Message::unicast(2u64).with_dest(dest).with_source(1u64));
+    let _ = router.handle_message(Message::broadcast(3u32).with_source(2u64));
+    let _ = router.send_direct(endpoint.addr(), 4u32);
+
+    let stats = endpoint.stats();
+    assert_eq!(stats, router.endpoint_stats(endpoint.addr()).unwrap());
+    assert_eq!((stats.received, stats.filtered, stats.errors), (2, 1, 1));
+    assert!(stats.last_activity.unwrap() >= start);
+    assert!(stats.mean_duration >= Duration::from_millis(5));
+
+    drop(endpoint);
+    assert_eq!(router.endpoint_stats(0), None);
+}
+
+#[traced_test]
+#[test]
+fn on_demand() {
+    let router = MessageRouter::<u32>::new();
+    let built = Arc::new(Mutex::new(0));
+
+    let endpoint = router
+        .on_demand::<u32, _, _>({
+            let built = built.clone();
+            move || {
+                *built.lock().unwrap() += 1;
+                let offset = 100;
+                move |_src, msg| msg + offset
+            }
+        })
+        .idle_timeout(Duration::ZERO);
+
+    // The handler is only built when the first message arrives
+    assert!(!endpoint.is_instantiated());
+    assert_eq!(*built.lock().unwrap(), 0);
+
+    assert_eq!(
+        router.handle_message(Message::unicast(1u32)),
+        DispatchResult::Delivered(smallvec![101])
+    );
+    assert_eq!(
+        router.handle_message(Message::unicast(2u32)),
+        DispatchResult::Delivered(smallvec![102])
+    );
+    assert!(endpoint.is_instantiated());
+    assert_eq!(*built.lock().unwrap(), 1);
+
+    // An idle handler is released, and built again by the next message
+    assert!(endpoint.release_idle());
+    assert!(!endpoint.is_instantiated());
+    assert!(!endpoint.release_idle());
+
+    let _ = router.handle_message(Message::unicast(3u32));
+    assert_eq!(*built.lock().unwrap(), 2);
+
+    // Dropping deregisters the endpoint
+    drop(endpoint);
+    assert_eq!(router.num_endpoints(), 0);
+}
+
+#[traced_test]
+#[test]
+fn keyed() {
+    #[derive(Debug)]
+    struct Reading {
+        sensor: u32,
+        value: u32,
+    }
+
+    let router = MessageRouter::<u32>::new();
+
+    // Each sensor gets a handler summing its readings
+    let endpoint = router.keyed(
+        |reading: &Reading| reading.sensor,
+        |_sensor| {
+            let mut total = 0;
+            move |_src, reading: Reading| {
+                total += reading.value;
+                total
+            }
+        },
+    );
+
+    let send = |sensor, value| {
+        router
+            .handle_message(Message::unicast(Reading { sensor, value }))
+            .into_results()
+            .unwrap()[0]
+    };
+
+    assert_eq!(send(1, 10), 10);
+    assert_eq!(send(2, 5), 5);
+    assert_eq!(send(1, 20), 30);
+    assert_eq!(send(2, 5), 10);
+    assert_eq!(endpoint.num_instances(), 2);
+
+    // Removing a key starts a new handler for it
+    assert!(endpoint.remove(&1));
+    assert!(!endpoint.contains(&1));
+    assert_eq!(send(1, 1), 1);
+    assert_eq!(endpoint.num_instances(), 2);
+}
+
+#[traced_test]
+#[test]
+fn batched() {
+    let router = MessageRouter::<usize>::new();
+    let batches = Arc::new(Mutex::new(Vec::new()));
+
+    let endpoint = router.batched::<u32, _>(3, Duration::from_millis(50), {
+        let batches = batches.clone();
+        move |batch| {
+            let len = batch.len();
+            batches.lock().unwrap().push(batch);
+            len
+        }
+    });
+
+    let send = |msg: u32| {
+        router
+            .handle_message(Message::unicast(msg))
+            .into_results()
+            .unwrap()[0]
+    };
+
+    // The message completing a batch returns the result of the handler
+    assert_eq!(send(1), 0);
+    assert_eq!(send(2), 0);
+    assert_eq!(send(3), 3);
+    assert_eq!(*batches.lock().unwrap(), vec![vec![1, 2, 3]]);
+
+    // Batches are flushed by the next message once the delay has passed
+    assert_eq!(send(4), 0);
+    assert_eq!(endpoint.flush_expired(), None);
+    std::thread::sleep(Duration::from_millis(60));
+    assert_eq!(send(5), 2);
+
+    // Remaining messages are flushed when the endpoint is dropped
+    assert_eq!(send(6), 0);
+    assert_eq!(endpoint.pending(), 1);
+    drop(endpoint);
+
+    assert_eq!(
+        *batches.lock().unwrap(),
+        vec![vec![1, 2, 3], vec![4, 5], vec![6]]
+    );
+    assert_eq!(router.num_endpoints(), 0);
+}
+
+#[traced_test]
+#[test]
+fn map_return() {
+    #[derive(Debug, PartialEq)]
+    enum AppReturn {
+        Length(usize),
+        Flag(bool),
+    }
+
+    impl From<bool> for AppReturn {
+        fn from(flag: bool) -> Self {
+            AppReturn::Flag(flag)
+        }
+    }
+
+    let router = MessageRouter::<AppReturn>::new();
+    let _length = router
+        .create_endpoint::<String>()
+        .map_return(AppReturn::Length)
+        .message(|_src, msg| msg.len());
+    let _flag = router
+        .create_endpoint::<u32>()
+        .message_into(|_src, msg| msg > 10);
+
+    assert_eq!(
+        router.handle_message(Message::unicast(String::from("salish"))),
+        DispatchResult::Delivered(smallvec![AppReturn::Length(6)])
+    );
+    assert_eq!(
+        router.handle_message(Message::unicast(11u32)),
+        DispatchResult::Delivered(smallvec![AppReturn::Flag(true)])
+    );
+}
+
+#[traced_test]
+#[test]
+fn message_mut() {
+    let router = MessageRouter::<u32>::new();
+
+    // Cell isn't Sync, so this callback can only be registered with message_mut
+    let total = std::cell::Cell::new(0);
+    let _endpoint = router
+        .create_endpoint::<u32>()
+        .message_mut(move |_src, msg| {
+            total.set(total.get() + msg);
+            total.get()
+        });
+
+    router.handle_message(Message::unicast(2u32));
+    assert_eq!(
+        router.handle_message(Message::unicast(3u32)),
+        DispatchResult::Delivered(smallvec![5])
+    );
+}
+
+#[traced_test]
+#[test]
+fn message_once() {
+    let router = MessageRouter::<usize>::new();
+
+    let name = String::from("salish");
+    let endpoint = router
+        .create_endpoint::<u32>()
+        .message_once(move |_src, msg| name.len() + msg as usize);
+    assert_eq!(router.num_endpoints(), 1);
+
+    assert_eq!(
+        router.handle_message(Message::unicast(1u32)),
+        DispatchResult::Delivered(smallvec![7])
+    );
+
+    // The endpoint deregistered itself after the first message
+    assert_eq!(router.num_endpoints(), 0);
+    assert_eq!(
+        router.handle_message(Message::unicast(2u32)),
+        DispatchResult::NoHandler
+    );
+    assert_eq!(
+        router.send_direct(endpoint.addr(), 3u32),
+        DispatchResult::NoHandler
+    );
+
+    drop(endpoint);
+}
+
+#[traced_test]
+#[tokio::test]
+async fn oneshot() {
+    let router = MessageRouter::<()>::new();
+
+    let ready = router.oneshot::<u32>();
+    assert_eq!(router.num_endpoints(), 1);
+
+    // Messages can be dispatched from another thread while the future is pending
+    let sender = router.clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(10));
+        sender.handle_message(Message::broadcast(7u32));
+    });
+
+    assert_eq!(ready.await, 7);
+    assert_eq!(router.num_endpoints(), 0);
+
+    // Only messages matching the filter resolve a filtered one-shot endpoint
+    let response = router.oneshot_filtered::<u32>(SourceFilter::default().add(2u64));
+    router.handle_message(Message::unicast(1u32).with_source(1u64));
+    assert!(!response.is_received());
+    router.handle_message(Message::unicast(2u32).with_source(2u64));
+    assert!(response.is_received());
+    assert_eq!(response.await, 2);
+
+    // Dropping a pending future deregisters its endpoint
+    drop(router.oneshot::<u32>());
+    assert_eq!(router.num_endpoints(), 0);
+}
+
+#[traced_test]
+#[test]
+fn await_message() {
+    let router = MessageRouter::<()>::new();
+
+    // Messages dispatched before waiting are received
+    let started = router.await_message::<u32>(Duration::from_millis(100));
+    router.handle_message(Message::broadcast(1u32));
+    assert_eq!(started.wait(), Ok(1));
+
+    let ready = router.await_message::<u32>(Duration::from_secs(5));
+    let sender = router.clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(10));
+        sender.handle_message(Message::broadcast(2u32));
+    });
+    assert_eq!(ready.wait(), Ok(2));
+
+    let timeout = Duration::from_millis(20);
+    let start = Instant::now();
+    assert_eq!(
+        router.await_message::<u32>(timeout).wait(),
+        Err(crate::endpoint::Elapsed { timeout })
+    );
+    assert!(start.elapsed() >= timeout);
+    assert_eq!(router.num_endpoints(), 0);
+}
+
+#[traced_test]
+#[tokio::test]
+async fn await_message_async() {
+    let router = MessageRouter::<()>::new();
+
+    let ready = router.await_message::<u32>(Duration::from_secs(5));
+    router.handle_message(Message::broadcast(3u32));
+    assert_eq!(ready.await, Ok(3));
+
+    // The task is woken at the deadline without a runtime timer
+    let timeout = Duration::from_millis(20);
+    assert_eq!(
+        router.await_message::<u32>(timeout).await,
+        Err(crate::endpoint::Elapsed { timeout })
+    );
+    assert_eq!(router.num_endpoints(), 0);
+}
+
+#[traced_test]
+#[tokio::test]
+async fn select() {
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        Ready(u32),
+        Failed(String),
+    }
+
+    let router = MessageRouter::<()>::new();
+
+    let select = router.select::<Event>().on(Event::Ready).on(Event::Failed);
+    assert_eq!(router.num_endpoints(), 2);
+
+    let sender = router.clone();
+    std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(10));
+        sender.handle_message(Message::broadcast(String::from("refused")));
+        sender.handle_message(Message::broadcast(1u32));
+    });
+
+    // Only the first message selects a branch, and the endpoints of all branches are deregistered
+    assert_eq!(select.await, Event::Failed(String::from("refused")));
+    assert_eq!(router.num_endpoints(), 0);
+
+    let select = router.select::<Event>().on(Event::Ready);
+    assert_eq!(
+        select.wait_timeout(Duration::from_millis(10)),
+        Err(crate::endpoint::Elapsed {
+            timeout: Duration::from_millis(10)
+        })
+    );
+    router.handle_message(Message::broadcast(2u32));
+    assert!(select.is_selected());
+    assert_eq!(select.try_take(), Some(Event::Ready(2)));
+}
+
+#[traced_test]
+#[test]
+fn state_machine() {
+    use crate::endpoint::Transition;
+
+    #[derive(Debug, Clone, PartialEq)]
+    enum Link {
+        Disconnected,
+        Connecting(u32),
+        Connected,
+    }
+
+    #[derive(Debug, Clone)]
+    struct Connect;
+
+    #[derive(Debug, Clone)]
+    struct Ack;
+
+    #[derive(Debug, Clone)]
+    struct Timeout;
+
+    let router = MessageRouter::<()>::new();
+    let transitions = Arc::new(Mutex::new(Vec::new()));
+
+    let link = router
+        .state_machine(Link::Disconnected)
+        .on_in(Link::Disconnected, |_, _: Connect| {
+            Transition::To(Link::Connecting(1))
+        })
+        .on(|_, _: Connect| Transition::Stay)
+        .on(|state, _: Timeout| match state {
+            Link::Connecting(attempt) if *attempt < 2 => {
+                Transition::To(Link::Connecting(attempt + 1))
+            }
+            _ => Transition::To(Link::Disconnected),
+        })
+        .on_in(Link::Connecting(2), |_, _: Ack| {
+            Transition::To(Link::Connected)
+        })
+        .on_transition({
+            let transitions = transitions.clone();
+            move |from, to| transitions.lock().unwrap().push((from.clone(), to.clone()))
+        });
+
+    // One endpoint is registered per payload type
+    assert_eq!(router.num_endpoints(), 3);
+
+    let send = |msg: Message| {
+        router.handle_message(msg);
+    };
+
+    // Acks are ignored without a handler in the current state
+    send(Message::broadcast(Ack));
+    send(Message::broadcast(Connect));
+    send(Message::unicast(Connect));
+    assert_eq!(link.state(), Link::Connecting(1));
+
+    send(Message::broadcast(Timeout));
+    send(Message::broadcast(Ack));
+    assert!(link.is_in(&Link::Connected));
+
+    assert_eq!(
+        *transitions.lock().unwrap(),
+        vec![
+            (Link::Disconnected, Link::Connecting(1)),
+            (Link::Connecting(1), Link::Connecting(2)),
+            (Link::Connecting(2), Link::Connected),
+        ]
+    );
+
+    drop(link);
+    assert_eq!(router.num_endpoints(), 0);
+}
+
+#[traced_test]
+#[test]
+fn takeable_message() {
+    #[derive(Debug)]
+    struct Job(u32);
+
+    let router = MessageRouter::<u32>::new();
+
+    // Each endpoint only takes the jobs it's responsible for, and declines the others
+    let even = router
+        .create_endpoint::<Job>()
+        .message_takeable(|_src, msg| {
+            if msg.peek().0 % 2 == 0 {
+                Ok(msg.take().0)
+            } else {
+                Err(msg)
+            }
+        });
+    let _small = router
+        .create_endpoint::<Job>()
+        .message_takeable(|_src, msg| {
+            if msg.peek().0 < 10 {
+                Ok(msg.take().0 + 100)
+            } else {
+                Err(msg)
+            }
+        });
+
+    assert_eq!(
+        router.handle_message(Message::unicast(Job(3))),
+        DispatchResult::Delivered(smallvec![103])
+    );
+    assert_eq!(
+        router.handle_message(Message::unicast(Job(12))),
+        DispatchResult::Delivered(smallvec![12])
+    );
+    assert_eq!(
+        router.handle_message(Message::unicast(Job(13))),
+        DispatchResult::NoHandler
+    );
+
+    // Declined direct sends leave the endpoint untouched
+    assert_eq!(
+        router.send_direct(even.addr(), Job(13)),
+        DispatchResult::NoHandler
+    );
+    assert_eq!(even.stats().received, 1);
+}
+
+#[test]
+fn on_worker() {
+    let router = MessageRouter::<u64>::new();
+    let (tx, rx) = std::sync::mpsc::channel();
+
+    let _endpoint = router
+        .create_endpoint::<u64>()
+        .message(move |_src, msg| {
+            let thread = std::thread::current().name().map(String::from);
+            tx.send((thread, msg)).unwrap();
+            msg
+        })
+        .on_worker("audio");
+
+    assert_eq!(router.workers(), vec!["audio".to_string()]);
+
+    // Dispatch returns without waiting for the worker
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            assert_eq!(
+                router.handle_message(Message::broadcast(1u64)),
+                DispatchResult::Delivered(smallvec![0])
+            );
+        });
+    });
+    router.handle_message(Message::broadcast(2u64));
+
+    // Messages are handled on the worker thread in the order they were dispatched
+    for expected in [1, 2] {
+        let (thread, msg) = rx.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(thread.as_deref(), Some("salish-audio"));
+        assert_eq!(msg, expected);
+    }
+}
+
+#[test]
+fn blocking() {
+    let router = MessageRouter::<u64>::new();
+    let (tx, rx) = std::sync::mpsc::channel();
+
+    // Blocking jobs run on a thread spawned for each, and no async tasks are spawned
+    let spawner = |task: BoxFuture| drop(task);
+
+    let _endpoint = router
+        .create_endpoint::<u64>()
+        .message(move |_src, msg| {
+            let thread = std::thread::current().name().map(String::from);
+            tx.send((thread, msg)).unwrap();
+            msg
+        })
+        .blocking(spawner);
+
+    // Dispatch returns without waiting for the handler
+    for msg in 1..=3u64 {
+        assert_eq!(
+            router.handle_message(Message::broadcast(msg)),
+            DispatchResult::Delivered(smallvec![0])
+        );
+    }
+
+    // Messages are handled off the dispatching thread, in the order they were dispatched
+    for expected in 1..=3 {
+        let (thread, msg) = rx.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(thread.as_deref(), Some("salish-blocking"));
+        assert_eq!(msg, expected);
+    }
+}
+
+#[test]
+fn replace_handler() {
+    let router = MessageRouter::<u64>::new();
+    let endpoint = router
+        .create_endpoint::<u64>()
+        .named("plugin")
+        .filter(SourceFilter::new(FilterOp::Any).add("host"))
+        .message(|_src, msg| msg + 1);
+    let id = endpoint.addr();
+
+    let message = || Message::unicast(1u64).with_source("host");
+    assert_eq!(
+        router.handle_message(message()),
+        DispatchResult::Delivered(smallvec![2])
+    );
+
+    // The new handler keeps the address, name and filters of the endpoint
+    endpoint.replace_handler(|_src, msg| msg * 10);
+    assert_eq!(endpoint.addr(), id);
+    assert_eq!(
+        router.handle_message(message()),
+        DispatchResult::Delivered(smallvec![10])
+    );
+    assert_eq!(
+        router.handle_message(message().with_dest(Destination::named("plugin"))),
+        DispatchResult::Delivered(smallvec![10])
+    );
+    assert_eq!(
+        router.handle_message(Message::unicast(1u64).with_source("other")),
+        DispatchResult::Filtered
+    );
 }
@@ -1,8 +1,14 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
 use tracing_test::traced_test;
 
 use crate::{
-    filter::{Filter, SourceFilter},
-    Message,
+    filter::{Filter, FilterOp, PatternFilter, RangeFilter, SourceFilter},
+    router::MessageRouter,
+    DispatchResult, Message,
 };
 
 #[derive(Debug, Hash, Clone, Copy)]
@@ -22,26 +28,199 @@ fn filter_source() {
 
     let message = Message::unicast("foo").with_source(TestSource::Int(1234));
     let result = filter.filter(&message);
-    assert!(result == true);
+    assert!(result);
 
     let message = Message::unicast("foo").with_source(TestSource::String("pass"));
     let result = filter.filter(&message);
-    assert!(result == true);
+    assert!(result);
 
     let message = Message::unicast("foo").with_source(TestSource::Unsigned(5656));
     let result = filter.filter(&message);
-    assert!(result == true);
+    assert!(result);
 
     // These messages should not pass the filter
     let message = Message::unicast("foo").with_source(TestSource::Int(999));
     let result = filter.filter(&message);
-    assert!(result == false);
+    assert!(!result);
 
     let message = Message::unicast("foo").with_source(TestSource::String("fail"));
     let result = filter.filter(&message);
-    assert!(result == false);
+    assert!(!result);
 
     let message = Message::unicast("foo").with_source(TestSource::Unsigned(1234));
     let result = filter.filter(&message);
-    assert!(result == false);
+    assert!(!result);
+}
+
+#[traced_test]
+#[test]
+fn filter_ops() {
+    let all = SourceFilter::new(FilterOp::All).add(TestSource::Int(1));
+    assert!(all.filter(&Message::unicast("foo").with_source(TestSource::Int(1))));
+    assert!(!all.filter(&Message::unicast("foo").with_source(TestSource::Int(2))));
+
+    // A single source can't match multiple sources
+    let all = all.add(TestSource::Int(2));
+    assert!(!all.filter(&Message::unicast("foo").with_source(TestSource::Int(1))));
+
+    let negative = SourceFilter::new(FilterOp::Negative).add(TestSource::Int(1));
+    assert!(!negative.filter(&Message::unicast("foo").with_source(TestSource::Int(1))));
+    assert!(negative.filter(&Message::unicast("foo").with_source(TestSource::Int(2))));
+    assert!(negative.filter(&Message::unicast("foo")));
+
+    let any = SourceFilter::new(FilterOp::Any).add(TestSource::Int(1));
+    assert!(!any.filter(&Message::unicast("foo")));
+}
+
+#[test]
+fn live_filters() {
+    let router = MessageRouter::<u32>::new();
+    let endpoint = router.create_endpoint::<u32>().message(|_src, msg| msg);
+
+    let from = |source| Message::unicast(1u32).with_source(TestSource::Int(source));
+    assert!(router.handle_message(from(2)).is_delivered());
+
+    // Filters added to the live endpoint apply to subsequent dispatches
+    let one = endpoint.add_filter(SourceFilter::default().add(TestSource::Int(1)));
+    let two = endpoint.add_filter(SourceFilter::default().add(TestSource::Int(2)));
+    assert!(router.handle_message(from(1)).is_delivered());
+    assert!(router.handle_message(from(2)).is_delivered());
+    assert_eq!(router.handle_message(from(3)), DispatchResult::Filtered);
+
+    assert!(endpoint.remove_filter(two));
+    assert!(!endpoint.remove_filter(two));
+    assert_eq!(router.handle_message(from(2)), DispatchResult::Filtered);
+    assert!(router.handle_message(from(1)).is_delivered());
+
+    // Without filters, the endpoint receives all messages again
+    endpoint.clear_filters();
+    assert!(!endpoint.remove_filter(one));
+    assert!(router.handle_message(from(3)).is_delivered());
+}
+
+#[test]
+fn filter_cache() {
+    /// Source filter counting its evaluations
+    #[derive(Debug)]
+    struct Counting(SourceFilter, Arc<AtomicUsize>);
+
+    impl Filter for Counting {
+        fn filter(&self, message: &Message) -> bool {
+            self.1.fetch_add(1, Ordering::Relaxed);
+            self.0.filter(message)
+        }
+
+        fn by_source(&self) -> bool {
+            true
+        }
+    }
+
+    let evaluations = Arc::new(AtomicUsize::new(0));
+    let router = MessageRouter::<u32>::new();
+    let endpoint = router
+        .create_endpoint::<u32>()
+        .filter(Counting(
+            SourceFilter::default().add(TestSource::Int(1)),
+            evaluations.clone(),
+        ))
+        .message(|_src, msg| msg);
+
+    let from = |source| Message::unicast(1u32).with_source(TestSource::Int(source));
+
+    // The outcome is evaluated once per source
+    for _ in 0..3 {
+        assert!(router.handle_message(from(1)).is_delivered());
+        assert_eq!(router.handle_message(from(2)), DispatchResult::Filtered);
+    }
+    assert_eq!(evaluations.load(Ordering::Relaxed), 2);
+
+    // Changing the filters invalidates the cache
+    endpoint.add_filter(SourceFilter::default().add(TestSource::Int(2)));
+    assert!(router.handle_message(from(2)).is_delivered());
+    assert_eq!(evaluations.load(Ordering::Relaxed), 3);
+}
+
+#[test]
+fn pattern_filter() {
+    let sensors = PatternFilter::source("sensor/*/temp?");
+    let from = |source: &'static str| Message::unicast(1u32).with_source(source);
+
+    assert!(sensors.filter(&from("sensor/kitchen/temp1")));
+    assert!(sensors.filter(&from("sensor//temp2")));
+    assert!(!sensors.filter(&from("sensor/kitchen/temp")));
+    assert!(!sensors.filter(&from("actuator/kitchen/temp1")));
+
+    // Sources which aren't strings don't match
+    assert!(!PatternFilter::source("*").filter(&Message::unicast(1u32).with_source(1u64)));
+    assert!(!PatternFilter::source("*").filter(&Message::unicast(1u32)));
+
+    let tenant = PatternFilter::header("tenant", "acme-*");
+    assert!(tenant.filter(&Message::unicast(1u32).with_header("tenant", "acme-eu")));
+    assert!(!tenant.filter(&Message::unicast(1u32).with_header("tenant", "globex")));
+    assert!(!tenant.filter(&Message::unicast(1u32)));
+
+    assert!(sensors.by_source());
+    assert!(!tenant.by_source());
+}
+
+#[cfg(feature = "regex")]
+#[test]
+fn pattern_filter_regex() {
+    let sensors = PatternFilter::source_regex(r"^sensor/\d+$").unwrap();
+    assert!(sensors.filter(&Message::unicast(1u32).with_source("sensor/42")));
+    assert!(!sensors.filter(&Message::unicast(1u32).with_source("sensor/x")));
+
+    let region = PatternFilter::header_regex("region", "^(eu|us)-").unwrap();
+    assert!(region.filter(&Message::unicast(1u32).with_header("region", "eu-west")));
+    assert!(!region.filter(&Message::unicast(1u32).with_header("region", "ap-south")));
+
+    assert!(PatternFilter::source_regex("(").is_err());
+}
+
+#[test]
+fn range_filter() {
+    let from = |source: u32| Message::unicast(1u32).with_source(source);
+
+    let sensors = RangeFilter::new(100u32..200);
+    assert!(sensors.filter(&from(100)));
+    assert!(sensors.filter(&from(199)));
+    assert!(!sensors.filter(&from(200)));
+    assert!(!sensors.filter(&from(99)));
+
+    let inclusive = RangeFilter::new(..=200u32);
+    assert!(inclusive.filter(&from(0)));
+    assert!(inclusive.filter(&from(200)));
+    assert!(!inclusive.filter(&from(201)));
+
+    // Sources of other types don't match
+    assert!(!sensors.filter(&Message::unicast(1u32).with_source(150u64)));
+    assert!(!sensors.filter(&Message::unicast(1u32)));
+
+    // Endpoints can combine ranges
+    let router = MessageRouter::<u32>::new();
+    let _endpoint = router
+        .create_endpoint::<u32>()
+        .filter(RangeFilter::new(100u32..200))
+        .filter(RangeFilter::new(300u32..))
+        .message(|_src, msg| msg);
+    assert!(router.handle_message(from(150)).is_delivered());
+    assert!(router.handle_message(from(1000)).is_delivered());
+    assert_eq!(router.handle_message(from(250)), DispatchResult::Filtered);
+}
+
+#[test]
+fn filter_cache_source_type() {
+    let router = MessageRouter::<u32>::new();
+    let _endpoint = router
+        .create_endpoint::<u32>()
+        .filter(RangeFilter::new(0u32..10))
+        .message(|_src, msg| msg);
+
+    // Sources of different types can hash alike, but only the type of the range matches
+    let unsigned = Message::unicast(1u32).with_source(1u32);
+    let signed = Message::unicast(1u32).with_source(1i32);
+    assert_eq!(unsigned.source_hash(), signed.source_hash());
+
+    assert!(router.handle_message(unsigned).is_delivered());
+    assert_eq!(router.handle_message(signed), DispatchResult::Filtered);
 }
@@ -1,4 +1,6 @@
-use crate::{handler::MessageHandler, message::DynMessageSource};
+use tracing_test::traced_test;
+
+use crate::{handler::MessageHandler, message::SourceRef, router::MessageRouter, Message};
 
 use super::TestPayload;
 
@@ -8,18 +10,49 @@ struct TestHandler;
 impl MessageHandler for TestHandler {
     type Message = TestPayload;
     type Return = bool;
-    type Source = u64;
 
-    fn on_message(
-        &mut self,
-        _source: Option<Self::Source>,
-        message: Self::Message,
-    ) -> Self::Return {
+    fn on_message(&mut self, _source: Option<SourceRef>, message: Self::Message) -> Self::Return {
         println!("HANDLER MESSAGE {message:#?}");
         true
     }
 }
 
+#[test]
+fn handler_on_message() {
+    let mut handler = TestHandler;
+    assert!(handler.on_message(None, TestPayload::Integer(1234)));
+}
+
+#[derive(Default, Debug)]
+struct CountingHandler {
+    count: usize,
+}
+
+impl MessageHandler for CountingHandler {
+    type Message = TestPayload;
+    type Return = usize;
+
+    fn on_message(&mut self, _source: Option<SourceRef>, _message: Self::Message) -> Self::Return {
+        self.count += 1;
+        self.count
+    }
+}
+
+#[traced_test]
+#[test]
+fn register_handler() {
+    let router = MessageRouter::<usize>::new();
+    let endpoint = router.register_handler(CountingHandler::default());
+
+    let result = router.handle_message(Message::broadcast(TestPayload::Integer(1)));
+    assert_eq!(result.results().unwrap(), [1]);
+    let result = router.handle_message(Message::broadcast(TestPayload::Integer(2)));
+    assert_eq!(result.results().unwrap(), [2]);
+
+    drop(endpoint);
+    assert_eq!(router.num_handlers(), 0);
+}
+
 /*
 #[traced_test]
 #[test]
@@ -27,7 +60,7 @@ fn handler_arc_mutex() {
     let handler: Arc<anylock::StdMutex<TestHandler>> =
         Arc::new(AnyLock::new(TestHandler::default()));
 
-    let mut router = MessageRouter::new();
+    let router = MessageRouter::new();
     router.add_handler(0, handler);
 
     let _task = router.handle_message(&mut Message::new(TestPayload::String("hello")));
@@ -41,7 +74,7 @@ fn handler_rc_refcell() {
     let handler: Rc<core::cell::RefCell<TestHandler>> =
         Rc::new(AnyLock::new(TestHandler::default()));
 
-    let mut router = MessageRouter::new();
+    let router = MessageRouter::new();
     router.add_handler(0, handler);
 
     let _task = router.handle_message(&mut Message::new(TestPayload::String("hello")));
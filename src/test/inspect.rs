@@ -0,0 +1,80 @@
+use serde_json::{json, Value};
+use tokio::{
+    io::{AsyncReadExt as _, AsyncWriteExt as _},
+    net::TcpStream,
+};
+use tracing_test::traced_test;
+
+use crate::{
+    config::{RouterConfig, Unroutable},
+    inspect::Inspector,
+    message::Message,
+    router::MessageRouter,
+    traits::EndpointAddress as _,
+};
+
+/// Request `path` from an inspector, returning the status line and the body
+async fn get(inspector: &Inspector, method: &str, path: &str) -> (String, String) {
+    let mut stream = TcpStream::connect(inspector.local_addr()).await.unwrap();
+    stream
+        .write_all(format!("{method} {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").as_bytes())
+        .await
+        .unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    (head.lines().next().unwrap().to_string(), body.to_string())
+}
+
+async fn get_json(inspector: &Inspector, path: &str) -> Value {
+    let (status, body) = get(inspector, "GET", path).await;
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    serde_json::from_str(&body).unwrap()
+}
+
+#[traced_test]
+#[tokio::test]
+async fn inspect() {
+    let router = MessageRouter::<()>::with_config(
+        RouterConfig::default().unroutable(Unroutable::DeadLetter),
+    );
+    let endpoint = router.create_endpoint::<u32>().message(|_src, _msg| ());
+
+    router.handle_message(Message::unicast(1u32));
+    router.handle_message(Message::unicast(2u64));
+
+    let inspector = Inspector::serve("127.0.0.1:0", &router).await.unwrap();
+
+    let endpoints = get_json(&inspector, "/endpoints").await;
+    assert_eq!(endpoints[0]["id"], json!(endpoint.addr()));
+    assert_eq!(endpoints[0]["type"], "u32");
+    assert_eq!(endpoints[0]["received"], 1);
+    assert_eq!(endpoints[0]["forwarder"], false);
+
+    let types = get_json(&inspector, "/types").await;
+    assert_eq!(types, json!([{ "type": "u32", "endpoints": 1 }]));
+
+    let stats = get_json(&inspector, "/stats").await;
+    assert_eq!(stats["endpoints"], 1);
+    assert_eq!(stats["dead_letters"], 1);
+    assert_eq!(
+        stats["types"][0],
+        json!({ "type": "u32", "dispatched": 1, "delivered": 1, "dropped": 0 })
+    );
+
+    let dead_letters = get_json(&inspector, "/deadletter").await;
+    assert_eq!(dead_letters["total"], 1);
+    assert_eq!(dead_letters["recent"][0]["type"], "u64");
+    assert_eq!(dead_letters["recent"][0]["reason"], "unroutable");
+
+    assert_eq!(
+        get(&inspector, "GET", "/missing").await.0,
+        "HTTP/1.1 404 Not Found"
+    );
+    assert_eq!(
+        get(&inspector, "POST", "/stats").await.0,
+        "HTTP/1.1 405 Method Not Allowed"
+    );
+}
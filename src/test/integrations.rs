@@ -0,0 +1,307 @@
+#[cfg(feature = "tokio")]
+mod tokio {
+    use std::sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    };
+
+    use tokio::sync::{broadcast, mpsc, watch};
+    use tracing_test::traced_test;
+
+    use crate::{
+        handler::AsyncMessageHandler,
+        message::{Message, SourceRef},
+        router::MessageRouter,
+        test::TestPayload,
+    };
+
+    #[traced_test]
+    #[tokio::test]
+    async fn bridge_broadcast() {
+        let router = MessageRouter::<()>::new();
+        let (tx, mut rx) = broadcast::channel(16);
+
+        let endpoint = router.bridge_broadcast::<TestPayload>(tx);
+        router.handle_message(Message::broadcast(TestPayload::Integer(42)));
+
+        assert!(matches!(rx.recv().await, Ok(TestPayload::Integer(42))));
+
+        // Dropping the endpoint stops forwarding and closes the channel
+        drop(endpoint);
+        router.handle_message(Message::broadcast(TestPayload::Integer(43)));
+        assert!(rx.recv().await.is_err());
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn bridge_watch() {
+        let router = MessageRouter::<()>::new();
+        let (tx, mut rx) = watch::channel(0u32);
+
+        let _endpoint = router.bridge_watch::<u32>(tx);
+        router.handle_message(Message::unicast(7u32));
+
+        rx.changed().await.unwrap();
+        assert_eq!(*rx.borrow(), 7);
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn inject_broadcast() {
+        let router = MessageRouter::<()>::new();
+        let received = Arc::new(AtomicU64::new(0));
+
+        let count = received.clone();
+        let _endpoint = router.create_endpoint::<u64>().message(move |_src, msg| {
+            count.fetch_add(msg, Ordering::Relaxed);
+        });
+
+        let (tx, rx) = broadcast::channel(16);
+        let task = router.inject_broadcast(rx);
+
+        tx.send(5u64).unwrap();
+        tx.send(6u64).unwrap();
+        drop(tx);
+
+        // The task ends once the channel is closed and drained
+        task.await.unwrap();
+        assert_eq!(received.load(Ordering::Relaxed), 11);
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn inject_watch() {
+        let router = MessageRouter::<()>::new();
+        let received = Arc::new(AtomicU64::new(0));
+
+        let count = received.clone();
+        let _endpoint = router.create_endpoint::<u64>().message(move |_src, msg| {
+            count.store(msg, Ordering::Relaxed);
+        });
+
+        let (tx, rx) = watch::channel(0u64);
+        let task = router.inject_watch(rx);
+
+        tx.send(9).unwrap();
+        tokio::task::yield_now().await;
+        drop(tx);
+
+        task.await.unwrap();
+        assert_eq!(received.load(Ordering::Relaxed), 9);
+    }
+
+    #[derive(Debug)]
+    struct Summer {
+        total: u64,
+        tx: mpsc::UnboundedSender<u64>,
+    }
+
+    impl AsyncMessageHandler for Summer {
+        type Message = u64;
+
+        async fn on_message(&mut self, _source: Option<SourceRef>, message: u64) {
+            tokio::task::yield_now().await;
+            self.total += message;
+            self.tx.send(self.total).unwrap();
+        }
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn spawn_handler() {
+        let router = MessageRouter::<()>::new();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        let endpoint = router.spawn_handler(Summer { total: 0, tx });
+        router.handle_message(Message::unicast(2u64));
+        router.handle_message(Message::unicast(3u64));
+
+        // Messages are handled in order, each awaited before the next
+        assert_eq!(rx.recv().await, Some(2));
+        assert_eq!(rx.recv().await, Some(5));
+
+        // Dropping the endpoint ends the task, which drops the handler and its sender
+        drop(endpoint);
+        assert_eq!(rx.recv().await, None);
+    }
+}
+
+#[cfg(feature = "iced")]
+mod iced {
+    use iced_runtime::{
+        futures::futures::{executor::block_on, StreamExt as _},
+        task, Action, Task,
+    };
+    use tracing_test::traced_test;
+
+    use crate::{integrations::iced::UiRouter, message::Message, test::TestPayload};
+
+    /// Run a [`Task`] to completion, collecting the output values
+    fn outputs<T>(task: Task<T>) -> Vec<T> {
+        match task::into_stream(task) {
+            Some(stream) => block_on(
+                stream
+                    .filter_map(|action| async move {
+                        match action {
+                            Action::Output(output) => Some(output),
+                            _ => None,
+                        }
+                    })
+                    .collect(),
+            ),
+            None => Vec::new(),
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum AppMessage {
+        Received(u64),
+    }
+
+    #[traced_test]
+    #[test]
+    fn ui_router_task() {
+        let ui = UiRouter::<AppMessage>::new();
+        let _endpoint = ui
+            .router()
+            .create_endpoint::<TestPayload>()
+            .message(|_src, msg| match msg {
+                TestPayload::Integer(num) => AppMessage::Received(num),
+                TestPayload::String(_) => AppMessage::Received(0),
+            });
+
+        let task = ui.handle_message(Message::unicast(TestPayload::Integer(3)));
+        assert_eq!(outputs(task), vec![AppMessage::Received(3)]);
+
+        // Unhandled messages produce an empty task
+        let task = ui.handle_message(Message::unicast(1u32));
+        assert!(outputs(task).is_empty());
+    }
+
+    #[traced_test]
+    #[test]
+    fn ui_router_sender() {
+        let ui = UiRouter::<AppMessage>::new();
+        let _endpoint = ui
+            .router()
+            .create_endpoint::<u64>()
+            .message(|_src, msg| AppMessage::Received(msg));
+
+        // Senders can be cloned into widget callbacks
+        let sender = ui.sender();
+        let on_press = move |value: u64| sender.send(Message::unicast(value));
+        on_press(1);
+        on_press(2);
+
+        let task = ui.drain();
+        assert_eq!(
+            outputs(task),
+            vec![AppMessage::Received(1), AppMessage::Received(2)]
+        );
+    }
+}
+
+#[cfg(feature = "bevy")]
+mod bevy {
+    use bevy_app::{App, Startup, Update};
+    use bevy_ecs::{
+        event::EventReader,
+        system::{Res, ResMut, Resource},
+    };
+    use tracing_test::traced_test;
+
+    use crate::{
+        integrations::bevy::{RouterResult, SalishPlugin, SalishRouter},
+        message::Message,
+    };
+
+    type Router = SalishRouter<u64>;
+
+    #[derive(Resource, Default)]
+    struct Received(Vec<u64>);
+
+    fn register(router: Res<Router>) {
+        router.static_endpoint(|_src, msg: u32| msg as u64 * 2);
+    }
+
+    fn send(router: Res<Router>) {
+        router.sender().send(Message::unicast(21u32));
+    }
+
+    fn collect(mut events: EventReader<RouterResult<u64>>, mut received: ResMut<Received>) {
+        received.0.extend(events.read().map(|result| result.0));
+    }
+
+    #[traced_test]
+    #[test]
+    fn plugin() {
+        let mut app = App::new();
+        app.add_plugins(SalishPlugin::<u64>::default())
+            .init_resource::<Received>()
+            .add_systems(Startup, register)
+            .add_systems(Update, (send, collect));
+
+        // First frame queues a message, which is drained at the start of the next frame
+        app.update();
+        assert!(app.world().resource::<Received>().0.is_empty());
+
+        app.update();
+        assert_eq!(app.world().resource::<Received>().0, vec![42]);
+        assert_eq!(app.world().resource::<Router>().num_handlers(), 1);
+    }
+}
+
+#[cfg(feature = "mqtt")]
+mod mqtt {
+    use std::sync::{Arc, Mutex};
+
+    use rumqttc::{Client, MqttOptions, Publish, QoS};
+    use tracing_test::traced_test;
+
+    use crate::{integrations::mqtt::MqttGateway, router::MessageRouter};
+
+    #[traced_test]
+    #[test]
+    fn inject_decoded() {
+        let router = MessageRouter::<()>::new();
+        let received = Arc::new(Mutex::new(Vec::new()));
+
+        let _endpoint = {
+            let received = received.clone();
+            router.create_endpoint::<f32>().message(move |_src, msg| {
+                received.lock().unwrap().push(msg);
+            })
+        };
+
+        // The connection is not polled, so requests are only queued
+        let (client, _connection) = Client::new(MqttOptions::new("salish", "localhost", 1883), 16);
+        let gateway = MqttGateway::with_client(client, &router);
+
+        gateway
+            .subscribe(
+                "sensors/+/temperature",
+                QoS::AtMostOnce,
+                |_topic, payload| std::str::from_utf8(payload).ok()?.parse::<f32>().ok(),
+            )
+            .unwrap();
+
+        let publish = |topic: &str, payload: &str| {
+            Publish::new(topic, QoS::AtMostOnce, payload.as_bytes().to_vec())
+        };
+
+        assert_eq!(
+            gateway.inject(&publish("sensors/kitchen/temperature", "21.5")),
+            1
+        );
+        assert_eq!(
+            gateway.inject(&publish("sensors/kitchen/humidity", "40")),
+            0
+        );
+        assert_eq!(
+            gateway.inject(&publish("sensors/attic/temperature", "hot")),
+            0
+        );
+
+        assert_eq!(*received.lock().unwrap(), vec![21.5]);
+    }
+}
@@ -0,0 +1,47 @@
+use std::{cell::RefCell, rc::Rc};
+
+use smallvec::smallvec;
+
+use crate::{
+    dispatch::DispatchResult, local::LocalRouter, message::Message, router::MessageRouter,
+};
+
+#[test]
+fn local_router() {
+    let router = MessageRouter::<u32>::new();
+    let mut local = LocalRouter::new(&router);
+
+    // The handler holds an Rc, so it can't be registered with the shared router directly
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let id = local.message::<u32, _>({
+        let received = received.clone();
+        move |_src, msg| {
+            received.borrow_mut().push(msg);
+            msg * 2
+        }
+    });
+    assert_eq!(local.num_handlers(), 1);
+
+    // Messages dispatched on other threads are handled when the owner thread drains the local router
+    std::thread::scope(|scope| {
+        scope.spawn(|| {
+            assert_eq!(
+                router.handle_message(Message::broadcast(1u32)),
+                DispatchResult::Delivered(smallvec![0])
+            );
+        });
+    });
+    router.handle_message(Message::broadcast(2u32));
+    assert!(received.borrow().is_empty());
+
+    assert_eq!(local.drain(), vec![2, 4]);
+    assert_eq!(*received.borrow(), vec![1, 2]);
+    assert!(local.drain().is_empty());
+
+    // Removing the handler deregisters its endpoint
+    assert!(local.remove(id));
+    assert_eq!(
+        router.handle_message(Message::broadcast(3u32)),
+        DispatchResult::NoHandler
+    );
+}
@@ -1,7 +1,8 @@
-use std::{any::Any, sync::Arc};
+use std::{any::TypeId, time::Duration};
 
 use crate::{
-    message::{Message, MessageSource},
+    message::{BuildError, CloneError, Destination, Message, NodeId, Priority},
+    tagged::Tagged,
     traits::internal::SalishMessageInternal as _,
 };
 
@@ -52,6 +53,17 @@ fn source() {
     assert_eq!(src, 321);
 }
 
+#[test]
+fn source_ref() {
+    let msg = Message::unicast(1234).with_source(321u32);
+    let src = msg.source_ref().unwrap();
+
+    assert!(src.is::<u32>());
+    assert_eq!(src.downcast_ref::<u32>(), Some(&321));
+    assert_eq!(src.get::<u64>(), None);
+    assert!(Message::unicast(1234).source_ref().is_none());
+}
+
 #[test]
 fn owned() {
     let msg = Message::unicast(PayloadA::Foo(123456));
@@ -68,3 +80,84 @@ fn into_ref() {
         assert_eq!(*val, 123456)
     }
 }
+
+#[test]
+fn node_id() {
+    // FNV-1a hashes of the identity, which are stable across processes
+    assert_eq!(NodeId::new("").id(), 0xcbf29ce484222325);
+    assert_eq!(NodeId::new("a").id(), 0xaf63dc4c8601ec8c);
+    assert_eq!(NodeId::from("node-1"), NodeId::new("node-1"));
+    assert_ne!(NodeId::new("node-1"), NodeId::new("node-2"));
+}
+
+#[test]
+fn tagged() {
+    struct Meters;
+    struct Seconds;
+
+    let distance = Message::unicast_tagged::<Meters>(5u64);
+    let duration = Message::broadcast_tagged::<Seconds>(5u64);
+
+    assert!(distance.is_type::<Tagged<u64, Meters>>());
+    assert!(!distance.is_type::<u64>());
+    assert_ne!(distance.payload_type(), duration.payload_type());
+    assert_ne!(distance.payload_type(), TypeId::of::<u64>());
+
+    let seconds = duration
+        .try_clone()
+        .unwrap()
+        .into_inner::<Tagged<u64, Seconds>>()
+        .unwrap();
+    assert_eq!(*seconds, 5);
+    assert_eq!(seconds, Tagged::from(5));
+    assert!(format!("{seconds:?}").ends_with("Seconds>(5)"));
+}
+
+#[test]
+fn try_clone() {
+    let broadcast = Message::broadcast(5u64).with_source(1u64);
+    let cloned = broadcast.try_clone().unwrap();
+    assert_eq!(cloned.source::<u64>(), Some(1));
+    assert_eq!(cloned.into_inner::<u64>(), Some(5));
+
+    assert_eq!(
+        Message::unicast(PayloadA::Bar).try_clone().err(),
+        Some(CloneError {
+            type_name: std::any::type_name::<PayloadA>()
+        })
+    );
+}
+
+#[test]
+fn builder() {
+    assert_eq!(
+        Message::builder().build().unwrap_err(),
+        BuildError::MissingPayload
+    );
+
+    let message = Message::builder()
+        .unicast(PayloadA::Bar)
+        .dest(Destination::endpoint(7))
+        .source(3u8)
+        .header("trace", "abc")
+        .priority(Priority::High)
+        .ttl(Duration::from_secs(60))
+        .build()
+        .unwrap();
+
+    assert!(matches!(message.dest(), Destination::Endpoint(7)));
+    assert_eq!(message.source::<u8>(), Some(3));
+    assert_eq!(message.header("trace"), Some("abc"));
+    assert_eq!(message.priority(), Priority::High);
+    assert!(!message.is_expired());
+
+    // Broadcast payloads are broadcast unless a destination is set, and clones keep the properties
+    let message = Message::builder()
+        .broadcast(PayloadB::Baz(1))
+        .header("trace", "def")
+        .build()
+        .unwrap();
+
+    assert!(matches!(message.dest(), Destination::Broadcast(_)));
+    assert_eq!(message.try_clone().unwrap().header("trace"), Some("def"));
+}
@@ -0,0 +1,120 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use tracing_test::traced_test;
+
+use crate::{
+    config::{RouterConfig, Unroutable},
+    filter::SourceFilter,
+    message::{Destination, Message},
+    middleware::DropReason,
+    router::MessageRouter,
+    test::TestPayload,
+    traits::EndpointAddress as _,
+};
+
+#[traced_test]
+#[test]
+fn metrics_per_type() {
+    let router = MessageRouter::<u32>::new();
+    let _endpoint = router
+        .create_endpoint::<TestPayload>()
+        .message(|_src, _msg| 1);
+
+    let _ = router.handle_message(Message::unicast(TestPayload::Integer(1)));
+    let _ = router.handle_message(Message::broadcast(TestPayload::Integer(2)));
+
+    // No endpoint is registered for u32, so this message should be counted as dropped
+    let _ = router.handle_message(Message::unicast(1234u32));
+
+    let payload = router.metrics().get::<TestPayload>().unwrap();
+    assert_eq!(payload.dispatched, 2);
+    assert_eq!(payload.delivered, 2);
+    assert_eq!(payload.dropped, 0);
+    assert!(payload.type_name.ends_with("TestPayload"));
+
+    let unhandled = router.metrics().get::<u32>().unwrap();
+    assert_eq!(unhandled.dispatched, 1);
+    assert_eq!(unhandled.delivered, 0);
+    assert_eq!(unhandled.dropped, 1);
+
+    assert_eq!(router.metrics().snapshot().len(), 2);
+}
+
+#[traced_test]
+#[test]
+fn metrics_drop_reasons() {
+    let router = MessageRouter::<u32>::with_config(
+        RouterConfig::default().unroutable(Unroutable::DeadLetter),
+    );
+    let endpoint = router
+        .create_endpoint::<u32>()
+        .filter(SourceFilter::default().add(1u64))
+        .message(|_src, msg| msg);
+
+    // Rejected by the filter of the only endpoint
+    let _ = router.handle_message(Message::unicast(1u32).with_source(2u64));
+    let _ = router.handle_message(Message::unicast(2u32).with_source(3u64));
+
+    // Expired before it was dispatched
+    let _ = router.handle_message(Message::unicast(3u32).with_ttl(Duration::ZERO));
+
+    // Addressed to an endpoint of another payload type, and without any endpoint
+    let dest = Destination::endpoint(endpoint.addr());
+    let _ = router.handle_message(Message::unicast(4u64).with_dest(dest));
+    let _ = router.handle_message(Message::unicast(5u64));
+
+    assert!(router
+        .handle_message(Message::unicast(6u32).with_source(1u64))
+        .is_delivered());
+
+    let metrics = router.metrics().get::<u32>().unwrap();
+    assert_eq!(metrics.dropped, 3);
+    assert_eq!(
+        metrics.drop_reasons,
+        BTreeMap::from([("expired", 1), ("filtered", 2)])
+    );
+
+    assert_eq!(
+        router.metrics().drop_reasons(),
+        BTreeMap::from([
+            ("expired", 1),
+            ("filtered", 2),
+            ("type_mismatch", 1),
+            ("unroutable", 1)
+        ])
+    );
+
+    let reasons: Vec<_> = router
+        .recent_dead_letters()
+        .into_iter()
+        .map(|record| record.reason)
+        .collect();
+    assert_eq!(
+        reasons,
+        vec![
+            DropReason::Filtered,
+            DropReason::Filtered,
+            DropReason::Expired,
+            DropReason::TypeMismatch,
+            DropReason::Unroutable
+        ]
+    );
+}
+
+#[cfg(feature = "prometheus")]
+#[traced_test]
+#[test]
+fn metrics_prometheus() {
+    let router = MessageRouter::<u32>::new();
+    let _endpoint = router.create_endpoint::<u64>().message(|_src, _msg| 1);
+
+    let _ = router.handle_message(Message::unicast(1u64));
+
+    let text = router.metrics().encode().unwrap();
+    assert!(text.contains("salish_messages_dispatched_total{payload_type=\"u64\"} 1"));
+    assert!(text.contains("salish_messages_delivered_total{payload_type=\"u64\"} 1"));
+
+    let registry = router.metrics().registry().unwrap();
+    let families = registry.gather();
+    assert_eq!(families.len(), 4);
+}
@@ -0,0 +1,233 @@
+use std::{
+    any::TypeId,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use smallvec::smallvec;
+use tracing_test::traced_test;
+
+use crate::{
+    dispatch::DispatchResult,
+    message::Message,
+    middleware::{
+        AccessControl, AuditLog, Dedup, DropReason, Excess, Middleware, Rate, RateLimiter,
+    },
+    router::MessageRouter,
+    test::TestPayload,
+    traits::internal::SalishMessageInternal as _,
+};
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+enum Role {
+    Admin,
+    User,
+}
+
+#[traced_test]
+#[test]
+fn access_control() {
+    let router = MessageRouter::<()>::new();
+
+    let received = Arc::new(AtomicU64::new(0));
+    let count = received.clone();
+    let _config = router
+        .create_endpoint::<TestPayload>()
+        .message(move |_src, _msg| {
+            count.fetch_add(1, Ordering::Relaxed);
+        });
+    let _other = router.create_endpoint::<u64>().message(|_src, _msg| {});
+
+    let dead_letters = Arc::new(Mutex::new(Vec::new()));
+    let dropped = dead_letters.clone();
+    router.on_dead_letter(move |dead_letter| {
+        dropped.lock().unwrap().push(dead_letter.reason);
+    });
+
+    router.add_middleware(AccessControl::new().allow::<TestPayload>(Role::Admin));
+
+    let config = || Message::unicast(TestPayload::Integer(1));
+
+    assert!(router
+        .handle_message(config().with_source(Role::Admin))
+        .is_delivered());
+    assert_eq!(
+        router.handle_message(config().with_source(Role::User)),
+        DispatchResult::Rejected(DropReason::AccessDenied)
+    );
+    assert_eq!(
+        router.handle_message(config()),
+        DispatchResult::Rejected(DropReason::AccessDenied)
+    );
+
+    // Types without rules are accepted from any source
+    assert!(router
+        .handle_message(Message::unicast(5u64).with_source(Role::User))
+        .is_delivered());
+
+    assert_eq!(received.load(Ordering::Relaxed), 1);
+    assert_eq!(
+        *dead_letters.lock().unwrap(),
+        vec![DropReason::AccessDenied, DropReason::AccessDenied]
+    );
+    assert_eq!(router.metrics().get::<TestPayload>().unwrap().dropped, 2);
+}
+
+/// Middleware rejecting odd integers
+struct EvenOnly;
+
+impl Middleware for EvenOnly {
+    fn check(&self, message: &Message) -> Result<(), DropReason> {
+        if message.payload_type() != TypeId::of::<u64>() {
+            return Ok(());
+        }
+
+        match message.inner::<u64>() {
+            Some(num) if num % 2 == 1 => Err(DropReason::Rejected("odd".into())),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[traced_test]
+#[test]
+fn custom_middleware() {
+    let router = MessageRouter::<u64>::new();
+    let _endpoint = router.create_endpoint::<u64>().message(|_src, msg| msg);
+
+    router.add_middleware(EvenOnly);
+
+    assert_eq!(
+        router.handle_message(Message::unicast(2u64)),
+        DispatchResult::Delivered(smallvec![2])
+    );
+    assert_eq!(
+        router.handle_message(Message::unicast(3u64)),
+        DispatchResult::Rejected(DropReason::Rejected("odd".into()))
+    );
+}
+
+#[traced_test]
+#[test]
+fn audit_log() {
+    let router = MessageRouter::<u64>::new();
+    let _a = router.create_endpoint::<u64>().message(|_src, msg| msg);
+    let _b = router.create_endpoint::<u64>().message(|_src, msg| msg);
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    router.add_middleware(EvenOnly);
+    router.add_middleware(AuditLog::channel(tx));
+
+    router.handle_message(Message::broadcast(2u64).with_source(7u64));
+    router.handle_message(Message::unicast(3u64));
+    router.handle_message(Message::unicast(String::from("unhandled")));
+
+    let records: Vec<String> = rx.try_iter().collect();
+    assert_eq!(records.len(), 3);
+
+    assert!(records[0].contains("\"type\":\"u64\""));
+    assert!(records[0].contains("\"dest\":\"broadcast\""));
+    assert!(records[0].contains("\"outcome\":\"delivered\",\"handlers\":2}"));
+    assert!(!records[0].contains("\"source\":null"));
+
+    assert!(records[1].contains("\"source\":null"));
+    assert!(records[1].contains("\"outcome\":\"dropped\",\"reason\":\"rejected: odd\"}"));
+
+    assert!(records[2].contains("\"outcome\":\"no_handler\"}"));
+}
+
+#[traced_test]
+#[test]
+fn rate_limiter() {
+    let router = MessageRouter::<u64>::new();
+    let _endpoint = router.create_endpoint::<u64>().message(|_src, msg| msg);
+
+    router.add_middleware(
+        RateLimiter::new()
+            .limit_each_source::<u64>(Rate::per_second(0.001).burst(2))
+            .limit_source(Role::User, Rate::per_second(0.001).burst(1)),
+    );
+
+    for _ in 0..2 {
+        assert!(router
+            .handle_message(Message::unicast(1u64).with_source(Role::Admin))
+            .is_delivered());
+    }
+    assert_eq!(
+        router.handle_message(Message::unicast(1u64).with_source(Role::Admin)),
+        DispatchResult::Rejected(DropReason::RateLimited)
+    );
+
+    // Each source has its own bucket, and the source limit applies too
+    assert!(router
+        .handle_message(Message::unicast(1u64).with_source(Role::User))
+        .is_delivered());
+    assert_eq!(
+        router.handle_message(Message::unicast(1u64).with_source(Role::User)),
+        DispatchResult::Rejected(DropReason::RateLimited)
+    );
+
+    // Messages without a source are not limited per source
+    assert!(router.handle_message(Message::unicast(1u64)).is_delivered());
+
+    assert_eq!(router.dead_letters(), 2);
+    assert_eq!(
+        router.recent_dead_letters()[0].reason,
+        DropReason::RateLimited
+    );
+}
+
+#[traced_test]
+#[test]
+fn rate_limiter_queue() {
+    let router = MessageRouter::<u64>::new();
+    let _endpoint = router.create_endpoint::<u64>().message(|_src, msg| msg);
+
+    router.add_middleware(
+        RateLimiter::new()
+            .limit::<u64>(Rate::per_second(0.001).burst(1))
+            .excess(Excess::Queue(router.sender())),
+    );
+
+    assert!(router.handle_message(Message::unicast(1u64)).is_delivered());
+    assert_eq!(
+        router.handle_message(Message::unicast(2u64)),
+        DispatchResult::Rejected(DropReason::Requeue(router.sender()))
+    );
+
+    // The excess message is requeued rather than dead-lettered, and stays queued while the bucket is empty
+    assert_eq!(router.dead_letters(), 0);
+    assert_eq!(router.sender().queued(), 1);
+    assert!(router.drain().is_empty());
+    assert_eq!(router.sender().queued(), 1);
+}
+
+#[traced_test]
+#[test]
+fn dedup() {
+    let router = MessageRouter::<u64>::new();
+    let _endpoint = router.create_endpoint::<u64>().message(|_src, msg| msg);
+
+    router.add_middleware(Dedup::new(Duration::from_millis(50)).hashed::<u64>());
+
+    let reading = |value: u64, sensor: u64| Message::unicast(value).with_source(sensor);
+
+    assert!(router.handle_message(reading(1, 1)).is_delivered());
+    assert_eq!(
+        router.handle_message(reading(1, 1)),
+        DispatchResult::Rejected(DropReason::Discarded)
+    );
+
+    // Different payloads, or the same payload from another source, are not duplicates
+    assert!(router.handle_message(reading(2, 1)).is_delivered());
+    assert!(router.handle_message(reading(1, 2)).is_delivered());
+
+    // Repeats are delivered again once the window has passed
+    std::thread::sleep(Duration::from_millis(60));
+    assert!(router.handle_message(reading(1, 1)).is_delivered());
+
+    assert_eq!(router.dead_letters(), 0);
+}
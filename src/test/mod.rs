@@ -1,8 +1,33 @@
+#[cfg(feature = "bridge")]
+mod bridge;
+#[cfg(feature = "bridge")]
+mod cluster;
+mod causality;
+mod context;
 mod endpoint;
 mod filter;
 mod handler;
+#[cfg(feature = "inspect-http")]
+mod inspect;
+mod integrations;
+mod local;
 mod message;
+mod metrics;
+mod middleware;
+#[cfg(feature = "otel")]
+mod otel;
+mod queue;
+#[cfg(feature = "registration")]
+mod registration;
 mod router;
+#[cfg(feature = "routes")]
+mod routes;
+mod spawn;
+mod static_router;
+mod template;
+mod transaction;
+mod view;
+mod workflow;
 
 /// Payload used for tests
 #[allow(unused)]
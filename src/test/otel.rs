@@ -0,0 +1,41 @@
+use opentelemetry::trace::TraceContextExt as _;
+
+use crate::{
+    otel::{from_traceparent, traceparent},
+    Message,
+};
+
+const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+#[test]
+fn traceparent_roundtrip() {
+    let context = from_traceparent(TRACEPARENT).unwrap();
+    let span = context.span();
+    assert!(span.span_context().is_remote());
+    assert!(span.span_context().is_sampled());
+
+    assert_eq!(traceparent(&context).as_deref(), Some(TRACEPARENT));
+}
+
+#[test]
+fn traceparent_invalid() {
+    assert!(from_traceparent("").is_none());
+    assert!(from_traceparent("01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01").is_none());
+    assert!(from_traceparent("00-00000000000000000000000000000000-00f067aa0ba902b7-01").is_none());
+    assert!(from_traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7").is_none());
+    assert!(from_traceparent(&format!("{TRACEPARENT}-00")).is_none());
+}
+
+#[test]
+fn message_trace_context() {
+    // Without an OpenTelemetry layer, messages are created without a context
+    let message = Message::broadcast(5u64);
+    assert!(message.trace_context().is_none());
+
+    let message = message.with_trace_context(from_traceparent(TRACEPARENT).unwrap());
+    let cloned = message.try_clone().unwrap();
+    assert_eq!(
+        cloned.trace_context().and_then(traceparent).as_deref(),
+        Some(TRACEPARENT)
+    );
+}
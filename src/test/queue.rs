@@ -0,0 +1,257 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use tracing_test::traced_test;
+
+use crate::{
+    config::RouterConfig,
+    message::{Destination, Message, Priority},
+    queue::{QueueHighWatermark, QueueId, QueueRecovered},
+    router::MessageRouter,
+    test::TestPayload,
+    traits::EndpointAddress as _,
+};
+
+#[traced_test]
+#[test]
+fn queue_drain() {
+    let router = MessageRouter::<u64>::new();
+    let _endpoint = router
+        .create_endpoint::<TestPayload>()
+        .message(|_src, msg| match msg {
+            TestPayload::Integer(num) => num,
+            TestPayload::String(_) => 0,
+        });
+
+    let sender = router.sender();
+    let cloned = sender.clone();
+
+    sender.send(Message::unicast(TestPayload::Integer(1)));
+    cloned.send(Message::unicast(TestPayload::Integer(2)));
+
+    assert_eq!(router.queued(), 2);
+    assert_eq!(sender.queued(), 2);
+
+    let results = router.drain();
+    assert_eq!(results, vec![1, 2]);
+    assert_eq!(router.queued(), 0);
+
+    // Nothing left to dispatch
+    assert!(router.drain().is_empty());
+}
+
+#[traced_test]
+#[test]
+fn queue_notify() {
+    let router = MessageRouter::<()>::new();
+    let notified = Arc::new(AtomicUsize::new(0));
+
+    let count = notified.clone();
+    router.on_queued(move || {
+        count.fetch_add(1, Ordering::Relaxed);
+    });
+
+    router.sender().send(Message::unicast(1u64));
+    router.sender().send(Message::unicast(2u64));
+
+    assert_eq!(notified.load(Ordering::Relaxed), 2);
+}
+
+#[traced_test]
+#[test]
+fn queue_send_from_handler() {
+    let router = MessageRouter::<()>::new();
+    let sender = router.sender();
+
+    // Each u64 message queues another until zero
+    let _endpoint = router.create_endpoint::<u64>().message(move |_src, msg| {
+        if msg > 0 {
+            sender.send(Message::unicast(msg - 1));
+        }
+    });
+
+    router.sender().send(Message::unicast(2u64));
+
+    // Messages queued by handlers are dispatched on the following drain
+    assert_eq!(router.drain().len(), 1);
+    assert_eq!(router.queued(), 1);
+    assert_eq!(router.drain().len(), 1);
+    assert_eq!(router.drain().len(), 1);
+    assert_eq!(router.queued(), 0);
+}
+
+#[traced_test]
+#[test]
+fn queue_scoped_sender() {
+    let router = MessageRouter::<u64>::new();
+
+    let _integer = router.create_endpoint::<u32>().message(|_src, msg| msg as u64);
+    let _payload = router
+        .create_endpoint::<TestPayload>()
+        .message(|_src, msg| match msg {
+            TestPayload::Integer(num) => num,
+            TestPayload::String(_) => 0,
+        });
+
+    let sender = router.sender_for::<(u32, TestPayload)>();
+    let cloned = sender.clone();
+
+    sender.send(1u32);
+    cloned.broadcast(TestPayload::Integer(2));
+
+    assert_eq!(sender.queued(), 2);
+    assert_eq!(router.drain(), vec![1, 2]);
+}
+
+#[traced_test]
+#[test]
+fn queue_priority() {
+    let router = MessageRouter::<u64>::new();
+    let _endpoint = router.create_endpoint::<u64>().message(|_src, msg| msg);
+
+    let sender = router.sender();
+    sender.send(Message::unicast(1u64));
+    sender.send(Message::unicast(2u64).with_priority(Priority::Low));
+    sender.send(Message::unicast(3u64).with_priority(Priority::Critical));
+    sender.send(Message::unicast(4u64));
+    sender.send(Message::unicast(5u64).with_priority(Priority::Critical));
+
+    // Higher priorities are dispatched first, in the order they were queued within a priority
+    assert_eq!(router.drain(), vec![3, 5, 1, 4, 2]);
+}
+
+#[test]
+fn peek_queue() {
+    let router = MessageRouter::<u64>::new();
+    let fast = router.create_endpoint::<u64>().message(|_src, msg| msg);
+    let slow = router
+        .create_endpoint::<u64>()
+        .named("slow")
+        .message(|_src, msg| msg);
+    let _text = router
+        .create_endpoint::<String>()
+        .message(|_src, _msg| 0);
+
+    let sender = router.sender();
+    sender.send(Message::unicast(1u64).with_dest(Destination::named("slow")));
+    sender.send(Message::unicast(2u64).with_dest(Destination::Endpoint(slow.addr())));
+    sender.send(Message::unicast(3u64).with_dest(Destination::Endpoint(fast.addr())));
+    sender.send(Message::broadcast(4u64).with_priority(Priority::High));
+    sender.send(Message::broadcast(String::from("other type")));
+
+    let summary = router.peek_queue(slow.addr()).unwrap();
+    assert_eq!(summary.len(), 3);
+    assert_eq!(summary.counts.get("u64"), Some(&3));
+    assert_eq!(summary.messages[0].priority, Priority::High);
+    assert!(summary.oldest >= summary.messages.iter().map(|message| message.age).max());
+
+    assert_eq!(router.peek_queue(fast.addr()).unwrap().len(), 2);
+    assert_eq!(router.peek_queue(12345), None);
+
+    router.drain();
+    assert!(router.peek_queue(slow.addr()).unwrap().is_empty());
+}
+
+#[traced_test]
+#[test]
+fn queue_watermarks() {
+    let router = MessageRouter::<u64>::with_config(RouterConfig::default().queue_watermarks(3, 1));
+    let _endpoint = router.create_endpoint::<u64>().message(|_src, msg| msg);
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+
+    let log = events.clone();
+    let _high = router
+        .create_endpoint::<QueueHighWatermark>()
+        .message(move |_src, event| {
+            log.lock().unwrap().push(format!("high {}", event.depth));
+            assert_eq!(event.queue, QueueId::Ingest);
+            0
+        });
+
+    let log = events.clone();
+    let _recovered = router
+        .create_endpoint::<QueueRecovered>()
+        .message(move |_src, event| {
+            log.lock()
+                .unwrap()
+                .push(format!("recovered {}", event.depth));
+            0
+        });
+
+    let sender = router.sender();
+    sender.send(Message::unicast(1u64));
+    sender.send(Message::unicast(2u64));
+    assert!(events.lock().unwrap().is_empty());
+
+    // The high watermark event is queued ahead of the messages
+    sender.send(Message::unicast(3u64));
+    assert_eq!(router.queued(), 4);
+
+    assert_eq!(router.drain(), vec![0, 1, 2, 0, 3]);
+    assert_eq!(*events.lock().unwrap(), vec!["high 3", "recovered 1"]);
+
+    // Crossings are only published again once the queue recovered
+    sender.send(Message::unicast(4u64));
+    assert_eq!(router.queued(), 1);
+}
+
+#[traced_test]
+#[test]
+fn queue_shed_load() {
+    let router = MessageRouter::<u64>::with_config(
+        RouterConfig::default()
+            .queue_watermarks(2, 0)
+            .shed_load(Duration::ZERO),
+    );
+    let _integer = router.create_endpoint::<u64>().message(|_src, msg| msg);
+    let _telemetry = router
+        .create_endpoint::<u32>()
+        .message(|_src, msg| msg as u64);
+    router.sheddable::<u32>();
+
+    let sender = router.sender();
+    sender.send(Message::unicast(1u64));
+    sender.send(Message::unicast(2u32));
+
+    // The queue reached its high watermark, so sheddable messages are dropped unless they are critical
+    sender.send(Message::unicast(3u32));
+    sender.send(Message::unicast(4u64));
+    sender.send(Message::unicast(5u32).with_priority(Priority::Critical));
+
+    // Queued sheddable messages are dropped too
+    assert_eq!(router.drain(), vec![5, 1, 4]);
+    assert_eq!(router.metrics().get::<u32>().unwrap().shed, 2);
+    assert_eq!(router.metrics().get::<u64>().unwrap().shed, 0);
+
+    // Load is no longer shed once the queue recovered
+    sender.send(Message::unicast(6u32));
+    assert_eq!(router.drain(), vec![6]);
+}
+
+#[traced_test]
+#[test]
+fn queue_run_budget() {
+    let router = MessageRouter::<u64>::new();
+    let _endpoint = router.create_endpoint::<u64>().message(|_src, msg| msg);
+
+    let sender = router.sender();
+    for num in 1..=5u64 {
+        sender.send(Message::unicast(num));
+    }
+
+    assert_eq!(router.run_n(2), vec![1, 2]);
+    assert_eq!(router.queued(), 3);
+
+    // An elapsed budget leaves the queue untouched
+    assert!(router.run_for(Duration::ZERO).is_empty());
+    assert_eq!(router.queued(), 3);
+
+    assert_eq!(router.run_for(Duration::from_secs(10)), vec![3, 4, 5]);
+    assert!(router.run_n(10).is_empty());
+}
@@ -0,0 +1,50 @@
+use tracing_test::traced_test;
+
+use crate::{
+    message::{Message, SourceRef},
+    registration,
+    router::MessageRouter,
+};
+
+#[derive(Debug, Clone)]
+struct TempMessage(i32);
+
+#[crate::handler(TempMessage)]
+fn double(_src: Option<SourceRef>, msg: TempMessage) -> i32 {
+    msg.0 * 2
+}
+
+#[crate::handler(TempMessage)]
+fn negate(_src: Option<SourceRef>, msg: TempMessage) -> i32 {
+    -msg.0
+}
+
+#[crate::handler(TempMessage)]
+fn describe(_src: Option<SourceRef>, msg: TempMessage) -> String {
+    format!("{} degrees", msg.0)
+}
+
+#[traced_test]
+#[test]
+fn registered_handlers() {
+    let names: Vec<_> = registration::registered_handlers()
+        .map(|handler| handler.name())
+        .collect();
+    assert!(names.contains(&"salish::test::registration::double"));
+    assert!(names.contains(&"salish::test::registration::describe"));
+
+    // Only the handlers returning the result type of the router are added
+    let router = MessageRouter::<i32>::with_registered_handlers();
+    assert_eq!(router.num_handlers(), 2);
+
+    let result = router.handle_message(Message::broadcast(TempMessage(21)));
+    let mut results = result.results().unwrap().to_vec();
+    results.sort();
+    assert_eq!(results, vec![-21, 42]);
+
+    let router = MessageRouter::<String>::new();
+    assert_eq!(router.add_registered_handlers(), 1);
+
+    let result = router.handle_message(Message::broadcast(TempMessage(21)));
+    assert_eq!(result.results().unwrap(), ["21 degrees".to_string()]);
+}
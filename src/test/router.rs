@@ -1,11 +1,1215 @@
+use std::{
+    any::TypeId,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use smallvec::smallvec;
 use tracing_test::traced_test;
 
-use crate::{message::Message, router::MessageRouter, test::TestPayload};
+use crate::{
+    cancel::CancellationToken,
+    config::{RouterConfig, Unroutable},
+    dispatch::DispatchResult,
+    erased::ErasedRouter,
+    filter::{FilterOp, SourceFilter},
+    message::{Destination, Message},
+    middleware::DropReason,
+    policy::Policy,
+    router::MessageRouter,
+    test::TestPayload,
+    traits::EndpointAddress as _,
+};
 
 #[traced_test]
 #[test]
 fn create() {
-    let mut router = MessageRouter::<&'static str, &'static str>::new();
+    let router = MessageRouter::<&'static str>::new();
     let msg = Message::unicast(TestPayload::Integer(1234)).with_source("test");
     let _ = router.handle_message(msg);
 }
+
+#[traced_test]
+#[test]
+fn origin_skipped() {
+    let router = MessageRouter::<u64>::new();
+    let origin = router.create_endpoint::<u64>().message(|_src, _msg| 1);
+    let _other = router.create_endpoint::<u64>().message(|_src, _msg| 2);
+
+    // Broadcasts are not delivered back to the origin endpoint
+    let message = Message::broadcast(0u64).with_origin(origin.addr());
+    assert_eq!(
+        router.handle_message(message),
+        DispatchResult::Delivered(smallvec![2])
+    );
+
+    // Round robin skips over the origin endpoint
+    for _ in 0..4 {
+        let message = Message::unicast(0u64).with_origin(origin.addr());
+        assert_eq!(
+            router.handle_message(message),
+            DispatchResult::Delivered(smallvec![2])
+        );
+    }
+
+    let message = Message::unicast(0u64)
+        .with_dest(Destination::Any(Policy::Random))
+        .with_origin(origin.addr());
+    assert_eq!(
+        router.handle_message(message),
+        DispatchResult::Delivered(smallvec![2])
+    );
+
+    // A message with only the origin registered is not delivered
+    drop(_other);
+    let message = Message::broadcast(0u64).with_origin(origin.addr());
+    assert_eq!(router.handle_message(message), DispatchResult::NoHandler);
+}
+
+#[traced_test]
+#[test]
+fn filtered_any() {
+    let router = MessageRouter::<u64>::new();
+    let _first = router
+        .create_endpoint::<u64>()
+        .filter(SourceFilter::default().add(1u64))
+        .message(|_src, _msg| 1);
+    let _second = router
+        .create_endpoint::<u64>()
+        .filter(SourceFilter::default().add(1u64))
+        .message(|_src, _msg| 2);
+    let _excluding = router
+        .create_endpoint::<u64>()
+        .filter(SourceFilter::new(FilterOp::Negative).add(1u64))
+        .message(|_src, _msg| 3);
+    let _unfiltered = router.create_endpoint::<u64>().message(|_src, _msg| 4);
+
+    // The policy applies among all endpoints with a matching filter
+    let mut results: Vec<u64> = (0..4)
+        .flat_map(|_| {
+            router
+                .handle_message(Message::unicast(0u64).with_source(1u64))
+                .into_results()
+                .unwrap()
+        })
+        .collect();
+    results.sort();
+    assert_eq!(results, [1, 1, 2, 2]);
+
+    // Endpoints with filters take precedence over endpoints without filters
+    for _ in 0..4 {
+        assert_eq!(
+            router.handle_message(Message::unicast(0u64).with_source(2u64)),
+            DispatchResult::Delivered(smallvec![3])
+        );
+    }
+
+    // Messages matching no filter are delivered to endpoints without filters,
+    // and never to endpoints whose filters reject them
+    drop(_excluding);
+    for _ in 0..4 {
+        assert_eq!(
+            router.handle_message(Message::unicast(0u64).with_source(2u64)),
+            DispatchResult::Delivered(smallvec![4])
+        );
+    }
+
+    drop(_unfiltered);
+    assert_eq!(
+        router.handle_message(Message::unicast(0u64).with_source(2u64)),
+        DispatchResult::Filtered
+    );
+}
+
+#[traced_test]
+#[test]
+fn filtered_broadcast() {
+    let router = MessageRouter::<u64>::new();
+    let _filtered = router
+        .create_endpoint::<u64>()
+        .filter(SourceFilter::default().add(1u64))
+        .message(|_src, _msg| 1);
+    let _unfiltered = router.create_endpoint::<u64>().message(|_src, _msg| 2);
+
+    let mut results = router
+        .handle_message(Message::broadcast(0u64).with_source(1u64))
+        .into_results()
+        .unwrap();
+    results.sort();
+    assert_eq!(results.as_slice(), [1, 2]);
+
+    // Endpoints whose filters reject a broadcast don't receive it
+    assert_eq!(
+        router.handle_message(Message::broadcast(0u64).with_source(2u64)),
+        DispatchResult::Delivered(smallvec![2])
+    );
+
+    drop(_unfiltered);
+    assert_eq!(
+        router.handle_message(Message::broadcast(0u64).with_source(2u64)),
+        DispatchResult::Filtered
+    );
+
+    // Endpoints can opt out of filtering broadcasts
+    let _opted_out = router
+        .create_endpoint::<u64>()
+        .filter(SourceFilter::default().add(1u64))
+        .unfiltered_broadcasts()
+        .message(|_src, _msg| 3);
+    assert_eq!(
+        router.handle_message(Message::broadcast(0u64).with_source(2u64)),
+        DispatchResult::Delivered(smallvec![3])
+    );
+}
+
+#[traced_test]
+#[test]
+fn handler_types() {
+    let router = MessageRouter::<()>::new();
+    let _first = router.create_endpoint::<u64>().message(|_src, _msg| {});
+    let _second = router.create_endpoint::<u64>().message(|_src, _msg| {});
+    let endpoint = router
+        .create_endpoint::<TestPayload>()
+        .message(|_src, _msg| {});
+
+    assert_eq!(
+        router.handler_types(),
+        [("salish::test::TestPayload", 1), ("u64", 2)]
+    );
+
+    let handle = endpoint.handle();
+    assert_eq!(handle.type_name, "salish::test::TestPayload");
+    assert!(format!("{router:?}").contains("salish::test::TestPayload"));
+}
+
+#[traced_test]
+#[test]
+fn type_names() {
+    let router = MessageRouter::<()>::new();
+    let _endpoint = router.create_endpoint::<u64>().message(|_src, _msg| {});
+    assert_eq!(router.type_name(TypeId::of::<u64>()), Some("u64"));
+    assert_eq!(router.type_name(TypeId::of::<u32>()), None);
+
+    // Names of payload types without endpoints are recorded as messages are handled
+    let _ = router.handle_message(Message::unicast(5u32));
+    assert_eq!(router.type_name(TypeId::of::<u32>()), Some("u32"));
+    assert!(logs_contain("No handlers for type u32"));
+}
+
+#[traced_test]
+#[test]
+fn unroutable_dead_letter() {
+    let router = MessageRouter::<()>::with_config(
+        RouterConfig::default().unroutable(Unroutable::DeadLetter),
+    );
+
+    let dead_letters = Arc::new(Mutex::new(Vec::new()));
+    let dropped = dead_letters.clone();
+    router.on_dead_letter(move |dead_letter| {
+        dropped.lock().unwrap().push(dead_letter.reason);
+    });
+
+    assert_eq!(
+        router.handle_message(Message::unicast(5u64)),
+        DispatchResult::NoHandler
+    );
+    assert_eq!(
+        router.handle_message(Message::broadcast(5u64)),
+        DispatchResult::NoHandler
+    );
+    assert_eq!(
+        router.handle_message(Message::unicast(5u64).with_dest(Destination::endpoint(99999))),
+        DispatchResult::NoHandler
+    );
+    assert_eq!(
+        *dead_letters.lock().unwrap(),
+        vec![DropReason::Unroutable; 3]
+    );
+
+    assert_eq!(router.dead_letters(), 3);
+    let recent = router.recent_dead_letters();
+    assert!(recent
+        .iter()
+        .all(|record| record.type_name == "u64" && record.reason == DropReason::Unroutable));
+
+    // Messages with endpoints are routed as usual
+    let _endpoint = router.create_endpoint::<u64>().message(|_src, _msg| {});
+    assert!(router.handle_message(Message::unicast(5u64)).is_delivered());
+    assert_eq!(dead_letters.lock().unwrap().len(), 3);
+}
+
+#[traced_test]
+#[test]
+fn redeliver_dead_letters() {
+    let router = MessageRouter::<u32>::with_config(
+        RouterConfig::default()
+            .unroutable(Unroutable::DeadLetter)
+            .dead_letter_buffer(3),
+    );
+
+    let discarded = Arc::new(Mutex::new(Vec::new()));
+    let sink = discarded.clone();
+    router.on_dead_letter(move |dead_letter| {
+        sink.lock().unwrap().push(dead_letter.message.type_name());
+    });
+
+    // Messages sent before an endpoint exists are buffered, passing the oldest to the sink when full
+    for value in 1..=4u32 {
+        router.handle_message(Message::unicast(value));
+    }
+    router.handle_message(Message::unicast(5u64));
+    assert_eq!(router.buffered_dead_letters(), 3);
+    assert_eq!(router.dead_letters(), 5);
+    assert_eq!(*discarded.lock().unwrap(), vec!["u32", "u32"]);
+
+    let _endpoint = router
+        .create_endpoint::<u32>()
+        .message(|_src, msg| msg * 10);
+
+    let results = router.redeliver_dead_letters(|dead_letter| dead_letter.message.is_type::<u32>());
+    assert_eq!(
+        results,
+        vec![
+            DispatchResult::Delivered(smallvec![30]),
+            DispatchResult::Delivered(smallvec![40])
+        ]
+    );
+
+    // Messages which still can't be routed are buffered again
+    assert_eq!(router.buffered_dead_letters(), 1);
+    let results =
+        router.redeliver_dead_letters(|dead_letter| dead_letter.reason == DropReason::Unroutable);
+    assert_eq!(results, vec![DispatchResult::NoHandler]);
+    assert_eq!(router.buffered_dead_letters(), 1);
+}
+
+#[traced_test]
+#[test]
+fn buffer_unhandled() {
+    let router = MessageRouter::<()>::with_config(
+        RouterConfig::default()
+            .unroutable(Unroutable::DeadLetter)
+            .buffer_unhandled(2, Duration::from_secs(60)),
+    );
+
+    let dead_letters = Arc::new(Mutex::new(Vec::new()));
+    let dropped = dead_letters.clone();
+    router.on_dead_letter(move |dead_letter| {
+        dropped.lock().unwrap().push(dead_letter.reason);
+    });
+
+    // Messages are held until an endpoint is registered, discarding the oldest when full
+    for value in 1..=3u32 {
+        assert_eq!(
+            router.handle_message(Message::unicast(value)),
+            DispatchResult::Pending
+        );
+    }
+    assert_eq!(router.num_pending(), 2);
+    assert_eq!(*dead_letters.lock().unwrap(), vec![DropReason::Overflow]);
+
+    // Messages addressed to an endpoint are not held
+    assert_eq!(
+        router.handle_message(Message::unicast(4u32).with_dest(Destination::endpoint(99999))),
+        DispatchResult::NoHandler
+    );
+
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let sink = received.clone();
+    let _endpoint = router
+        .create_endpoint::<u32>()
+        .message(move |_src, msg| sink.lock().unwrap().push(msg));
+
+    assert_eq!(*received.lock().unwrap(), vec![2, 3]);
+    assert_eq!(router.num_pending(), 0);
+
+    let metrics = router.metrics().get::<u32>().unwrap();
+    assert_eq!((metrics.dispatched, metrics.delivered, metrics.dropped), (4, 2, 2));
+
+    // Expired messages are not delivered
+    let router = MessageRouter::<()>::with_config(
+        RouterConfig::default().buffer_unhandled(2, Duration::ZERO),
+    );
+    assert_eq!(
+        router.handle_message(Message::broadcast(1u64)),
+        DispatchResult::Pending
+    );
+
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let sink = received.clone();
+    let _endpoint = router
+        .create_endpoint::<u64>()
+        .message(move |_src, msg| sink.lock().unwrap().push(msg));
+
+    assert!(received.lock().unwrap().is_empty());
+    assert_eq!(router.num_pending(), 0);
+}
+
+#[traced_test]
+#[test]
+fn child_router() {
+    let router = MessageRouter::<u32>::new();
+    let parent_endpoint = router.create_endpoint::<u32>().message(|_src, _msg| 1);
+
+    let child = router.child();
+    let _child_endpoint = child.create_endpoint::<u64>().message(|_src, _msg| 2);
+
+    // Messages the child can't deliver bubble up to the parent
+    assert_eq!(
+        child.handle_message(Message::unicast(0u32)),
+        DispatchResult::Delivered(smallvec![1])
+    );
+    let dest = Destination::endpoint(parent_endpoint.addr());
+    assert_eq!(
+        child.handle_message(Message::unicast(0u32).with_dest(dest)),
+        DispatchResult::Delivered(smallvec![1])
+    );
+    assert_eq!(
+        child.handle_message(Message::unicast(0u64)),
+        DispatchResult::Delivered(smallvec![2])
+    );
+
+    // Messages are only passed down to children explicitly
+    assert_eq!(
+        router.handle_message(Message::unicast(0u64)),
+        DispatchResult::NoHandler
+    );
+    assert_eq!(
+        router.forward_to_children(Message::unicast(0u64)),
+        DispatchResult::Delivered(smallvec![2])
+    );
+
+    let other = router.child();
+    let _other_endpoint = other.create_endpoint::<u64>().message(|_src, _msg| 3);
+    assert_eq!(
+        router.forward_to_children(Message::broadcast(0u64)),
+        DispatchResult::Delivered(smallvec![2, 3])
+    );
+
+    // Dropping a child detaches it and its endpoints
+    drop(child);
+    assert_eq!(
+        router.forward_to_children(Message::broadcast(0u64)),
+        DispatchResult::Delivered(smallvec![3])
+    );
+    drop(other);
+    assert_eq!(
+        router.forward_to_children(Message::broadcast(0u64)),
+        DispatchResult::NoHandler
+    );
+}
+
+#[traced_test]
+#[test]
+fn namespaces() {
+    let router = MessageRouter::<&'static str>::new();
+
+    let _root = router.create_endpoint::<String>().message(|_src, _msg| "root");
+    let _audio = router
+        .namespace("audio")
+        .create_endpoint::<String>()
+        .message(|_src, _msg| "audio");
+    let _video = router
+        .namespace("video")
+        .create_endpoint::<String>()
+        .message(|_src, _msg| "video");
+
+    // Each namespace routes the same payload type independently
+    let send = |router: MessageRouter<_>| {
+        router.handle_message(Message::broadcast(String::from("play")))
+    };
+    assert_eq!(send(router.clone()), DispatchResult::Delivered(smallvec!["root"]));
+    assert_eq!(
+        send(router.namespace("audio")),
+        DispatchResult::Delivered(smallvec!["audio"])
+    );
+    assert_eq!(
+        send(router.namespace("video")),
+        DispatchResult::Delivered(smallvec!["video"])
+    );
+
+    // Messages are not passed out of a namespace
+    assert_eq!(send(router.namespace("empty")), DispatchResult::NoHandler);
+    assert_eq!(router.namespaces(), ["audio", "empty", "video"]);
+    assert_eq!(router.num_endpoints(), 1);
+}
+
+#[traced_test]
+#[test]
+fn erased_returns() {
+    let router = ErasedRouter::new();
+    let _name = router
+        .create_endpoint::<u32>()
+        .reply(|_src, msg| format!("#{msg}"));
+    let _double = router.create_endpoint::<u32>().reply(|_src, msg| msg * 2);
+    let _unit = router.create_endpoint::<u64>().reply(|_src, _msg| ());
+
+    // Handlers returning different types are registered with one router
+    let results = router
+        .handle_message(Message::broadcast(21u32))
+        .into_results()
+        .unwrap();
+    assert_eq!(results.len(), 2);
+
+    assert_eq!(router.request::<u32>(Message::broadcast(21u32)), Some(42));
+    assert_eq!(
+        router.request::<String>(Message::broadcast(21u32)),
+        Some(String::from("#21"))
+    );
+    assert_eq!(router.request::<()>(Message::unicast(1u64)), Some(()));
+    assert_eq!(router.request::<u32>(Message::unicast(1u64)), None);
+    assert_eq!(router.request::<u32>(Message::unicast(1u8)), None);
+}
+
+#[test]
+#[should_panic(expected = "No handlers for type u64")]
+fn unroutable_panic() {
+    let router = MessageRouter::<()>::with_config(
+        RouterConfig::default().unroutable(Unroutable::Panic),
+    );
+    let _ = router.handle_message(Message::unicast(5u64));
+}
+
+#[traced_test]
+#[test]
+fn register_during_dispatch() {
+    let router = MessageRouter::<()>::new();
+    let endpoints = Arc::new(Mutex::new(Vec::new()));
+
+    // Endpoints can be registered from handlers, as dispatch doesn't hold a lock on the registry
+    let registrar = router.clone();
+    let created = endpoints.clone();
+    let _endpoint = router.create_endpoint::<u64>().message(move |_src, _msg| {
+        let endpoint = registrar.create_endpoint::<u32>().message(|_src, _msg| {});
+        created.lock().unwrap().push(endpoint);
+    });
+
+    assert!(router.handle_message(Message::unicast(1u64)).is_delivered());
+    assert!(router.handle_message(Message::broadcast(1u64)).is_delivered());
+    assert_eq!(router.num_endpoints(), 3);
+    assert!(router.handle_message(Message::broadcast(1u32)).is_delivered());
+
+    endpoints.lock().unwrap().clear();
+    assert_eq!(router.num_endpoints(), 1);
+    assert_eq!(router.handler_types(), [("u64", 1)]);
+}
+
+#[traced_test]
+#[test]
+fn reentrant_round_robin() {
+    let router = MessageRouter::<u64>::new();
+
+    // Handlers of one type can dispatch messages of another type, as round robin doesn't lock the registry
+    let inner = router.clone();
+    let _outer = router.create_endpoint::<u64>().message(move |_src, msg| {
+        inner
+            .handle_message(Message::unicast(msg as u32))
+            .into_results()
+            .map_or(0, |results| results[0])
+    });
+    let _first = router.create_endpoint::<u32>().message(|_src, _msg| 1);
+    let _second = router.create_endpoint::<u32>().message(|_src, _msg| 2);
+
+    let results: Vec<u64> = (0..4)
+        .flat_map(|_| {
+            router
+                .handle_message(Message::unicast(0u64))
+                .into_results()
+                .unwrap()
+        })
+        .collect();
+    assert_eq!(results, [1, 2, 1, 2]);
+
+    // Concurrent dispatch shares the round robin counter
+    let counts: Vec<u64> = std::thread::scope(|scope| {
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let router = router.clone();
+                scope.spawn(move || {
+                    (0..100)
+                        .map(|_| router.handle_message(Message::unicast(0u32)))
+                        .filter(|results| *results == DispatchResult::Delivered(smallvec![1]))
+                        .count() as u64
+                })
+            })
+            .collect();
+
+        threads.into_iter().map(|t| t.join().unwrap()).collect()
+    });
+    assert_eq!(counts.iter().sum::<u64>(), 200);
+}
+
+#[traced_test]
+#[test]
+fn dump_graph() {
+    let router = MessageRouter::<()>::new();
+    let first = router.create_endpoint::<u64>().message(|_src, _msg| {});
+    let second = router
+        .create_endpoint::<u64>()
+        .filter(SourceFilter::default().add(1u64))
+        .message(|_src, _msg| {});
+    let third = router.create_endpoint::<&str>().message(|_src, _msg| {});
+
+    let graph = router.dump_graph();
+    assert!(graph.starts_with("digraph salish {"));
+    assert!(graph.contains(r#"type0 [shape=box, label="&str"];"#));
+    assert!(graph.contains(&format!("type0 -> endpoint{};", third.addr())));
+    assert!(graph.contains(&format!("type1 -> endpoint{};", first.addr())));
+    assert!(graph.contains(&format!("type1 -> endpoint{};", second.addr())));
+    assert!(graph.contains(&format!(
+        r#"endpoint{} [shape=ellipse, label="endpoint {}\nSourceFilter"#,
+        second.addr(),
+        second.addr()
+    )));
+}
+
+#[traced_test]
+#[test]
+fn handle_message_iter() {
+    let router = MessageRouter::<Option<u64>>::new();
+    let calls = Arc::new(Mutex::new(Vec::new()));
+
+    let endpoints: Vec<_> = (0..4u64)
+        .map(|id| {
+            let calls = calls.clone();
+            router.create_endpoint::<u64>().message(move |_src, msg| {
+                calls.lock().unwrap().push(id);
+                (id >= 1).then_some(msg + id)
+            })
+        })
+        .collect();
+
+    // Only the handlers up to the first response are called
+    let first = router
+        .handle_message_iter(Message::broadcast(10u64))
+        .flatten()
+        .next();
+    assert_eq!(first, Some(11));
+    assert_eq!(*calls.lock().unwrap(), vec![0, 1]);
+    assert_eq!(router.metrics().get::<u64>().unwrap().delivered, 2);
+
+    let all: Vec<_> = router
+        .handle_message_iter(Message::broadcast(10u64))
+        .collect();
+    assert_eq!(all, vec![None, Some(11), Some(12), Some(13)]);
+
+    // Unicast messages are delivered to a single endpoint
+    let dest = Destination::endpoint(endpoints[3].addr());
+    let unicast: Vec<_> = router
+        .handle_message_iter(Message::unicast(1u64).with_dest(dest))
+        .collect();
+    assert_eq!(unicast, vec![Some(4)]);
+
+    assert_eq!(
+        router.handle_message_iter(Message::broadcast(1u32)).count(),
+        0
+    );
+}
+
+#[traced_test]
+#[test]
+fn handle_message_cancellable() {
+    let router = MessageRouter::<u64>::new();
+    let token = CancellationToken::new();
+
+    let _endpoints: Vec<_> = (0..4u64)
+        .map(|id| {
+            let token = token.clone();
+            router.create_endpoint::<u64>().message(move |_src, msg| {
+                // The second handler cancels broadcasts of 20
+                if id == 1 && msg == 20 {
+                    token.cancel();
+                }
+                msg + id
+            })
+        })
+        .collect();
+
+    assert_eq!(
+        router.handle_message_cancellable(Message::broadcast(10u64), &token),
+        DispatchResult::Delivered(smallvec![10, 11, 12, 13])
+    );
+
+    assert_eq!(
+        router.handle_message_cancellable(Message::broadcast(20u64), &token),
+        DispatchResult::Cancelled(smallvec![20, 21])
+    );
+    assert!(token.is_cancelled());
+
+    // Cancelled tokens stop messages before any handler is called
+    assert_eq!(
+        router.handle_message_cancellable(Message::unicast(10u64), &token),
+        DispatchResult::Cancelled(smallvec![])
+    );
+}
+
+#[traced_test]
+#[test]
+fn lazy_dispatch_errors() {
+    use crate::error_channel::HandlerError;
+
+    let router = MessageRouter::<Result<u64, String>>::new();
+    router.publish_errors();
+
+    let _endpoints: Vec<_> = (0..2u64)
+        .map(|id| {
+            router
+                .create_endpoint::<u64>()
+                .filter(SourceFilter::default().add("sensor"))
+                .message(move |_src, msg| match id {
+                    0 => Err(format!("can't handle {msg}")),
+                    id => Ok(msg + id),
+                })
+        })
+        .collect();
+
+    let errors = Arc::new(Mutex::new(Vec::new()));
+    let _errors = router.create_endpoint::<HandlerError<String>>().message({
+        let errors = errors.clone();
+        move |_src, e| {
+            errors.lock().unwrap().push(e.error);
+            Ok(0)
+        }
+    });
+
+    // Errors of handlers called lazily are still published
+    let token = CancellationToken::new();
+    assert_eq!(
+        router.handle_message_cancellable(Message::broadcast(10u64).with_source("sensor"), &token),
+        DispatchResult::Delivered(smallvec![Err(String::from("can't handle 10")), Ok(11)])
+    );
+    assert_eq!(
+        router
+            .handle_message_iter(Message::broadcast(20u64).with_source("sensor"))
+            .count(),
+        2
+    );
+    assert_eq!(
+        *errors.lock().unwrap(),
+        vec![
+            String::from("can't handle 10"),
+            String::from("can't handle 20")
+        ]
+    );
+
+    // Broadcasts rejected by the filters of every endpoint are dropped
+    assert_eq!(
+        router.handle_message_cancellable(Message::broadcast(30u64).with_source("other"), &token),
+        DispatchResult::Filtered
+    );
+    assert_eq!(
+        router
+            .handle_message_iter(Message::broadcast(40u64).with_source("other"))
+            .count(),
+        0
+    );
+    assert_eq!(
+        router.metrics().get::<u64>().unwrap().drop_reasons,
+        std::collections::BTreeMap::from([("filtered", 2)])
+    );
+}
+
+#[traced_test]
+#[test]
+fn retained() {
+    #[derive(Debug, Clone, PartialEq)]
+    struct Mode(&'static str);
+
+    let router = MessageRouter::<&'static str>::new();
+    router.retain::<Mode>();
+
+    let _early = router.create_endpoint::<Mode>().message(|_src, msg| msg.0);
+    assert_eq!(router.retained::<Mode>(), None);
+
+    // Unicast messages are not retained
+    router.handle_message(Message::unicast(Mode("unicast")));
+    assert_eq!(router.retained::<Mode>(), None);
+
+    router.handle_message(Message::broadcast(Mode("idle")));
+    router.handle_message(Message::broadcast(Mode("running")));
+    assert_eq!(router.retained::<Mode>(), Some(Mode("running")));
+
+    // A late endpoint receives the last broadcast when it's ready
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let late = received.clone();
+    let _late = router.create_endpoint::<Mode>().message(move |_src, msg| {
+        late.lock().unwrap().push(msg.0);
+        "late"
+    });
+    assert_eq!(*received.lock().unwrap(), vec!["running"]);
+
+    // Types which are not retained are not delivered to late endpoints
+    router.handle_message(Message::broadcast(5u32));
+    let _counter = router.create_endpoint::<u32>().message(|_src, _msg| {
+        panic!("u32 is not retained");
+    });
+
+    assert_eq!(router.release_retained::<Mode>(), Some(Mode("running")));
+    let _released = router.create_endpoint::<Mode>().message(|_src, _msg| {
+        panic!("Mode is no longer retained");
+    });
+}
+
+#[traced_test]
+#[test]
+fn last_value() {
+    let router = MessageRouter::<()>::new();
+    let _endpoint = router.create_endpoint::<u32>().message(|_src, _msg| ());
+
+    router.handle_message(Message::unicast(1u32));
+    assert_eq!(router.last::<u32>(), None);
+
+    router.cache_last::<u32>();
+    router.cache_last::<String>();
+    assert_eq!(router.last::<u32>(), None);
+
+    // Unicast and broadcast payloads are cached, even without an endpoint
+    router.handle_message(Message::unicast(2u32));
+    assert_eq!(router.last::<u32>(), Some(2));
+    router.handle_message(Message::broadcast(3u32));
+    assert_eq!(router.last::<u32>(), Some(3));
+    router.handle_message(Message::unicast(String::from("unhandled")));
+    assert_eq!(router.last::<String>(), Some(String::from("unhandled")));
+
+    // Clones of the router share the cache
+    let clone = router.clone();
+    clone.handle_message(Message::unicast(4u32));
+    assert_eq!(router.last::<u32>(), Some(4));
+
+    router.uncache_last::<u32>();
+    assert_eq!(router.last::<u32>(), None);
+}
+
+#[traced_test]
+#[test]
+fn publish_errors() {
+    use crate::error_channel::HandlerError;
+
+    let router = MessageRouter::<Result<u32, String>>::new();
+    let _endpoint = router
+        .create_endpoint::<u32>()
+        .message(|_src, msg| match msg {
+            0 => Err(String::from("zero")),
+            msg => Ok(msg),
+        });
+
+    let errors = Arc::new(Mutex::new(Vec::new()));
+    let _errors = router.create_endpoint::<HandlerError<String>>().message({
+        let errors = errors.clone();
+        move |_src, e| {
+            errors.lock().unwrap().push(e.clone());
+            // Errors of error handlers are not republished
+            Err(e.error)
+        }
+    });
+
+    // Errors are not published until enabled
+    router.handle_message(Message::unicast(0u32));
+    assert!(errors.lock().unwrap().is_empty());
+
+    router.clone().publish_errors();
+    assert_eq!(
+        router.handle_message(Message::unicast(0u32)),
+        DispatchResult::Delivered(smallvec![Err(String::from("zero"))])
+    );
+    router.handle_message(Message::unicast(1u32));
+    assert_eq!(
+        *errors.lock().unwrap(),
+        vec![HandlerError {
+            error: String::from("zero"),
+            payload: std::any::type_name::<u32>(),
+        }]
+    );
+
+    router.stop_publishing_errors();
+    router.handle_message(Message::unicast(0u32));
+    assert_eq!(errors.lock().unwrap().len(), 1);
+}
+
+#[traced_test]
+#[test]
+fn named_endpoints() {
+    let router = MessageRouter::<u32>::new();
+    let logger = router
+        .create_endpoint::<u32>()
+        .named("temperature-logger")
+        .message(|_src, msg| msg * 10);
+    let _other = router.create_endpoint::<u32>().message(|_src, msg| msg);
+
+    assert_eq!(
+        router.endpoint_by_name("temperature-logger"),
+        Some(logger.addr())
+    );
+    assert_eq!(router.endpoint_by_name("missing"), None);
+
+    let info = router.endpoints();
+    assert_eq!(info[0].name.as_deref(), Some("temperature-logger"));
+    assert_eq!(info[1].name, None);
+
+    let named = Message::unicast(2u32).with_dest(Destination::named("temperature-logger"));
+    assert_eq!(
+        router.handle_message(named),
+        DispatchResult::Delivered(smallvec![20])
+    );
+
+    // Names are released when the endpoint is dropped
+    drop(logger);
+    assert_eq!(router.endpoint_by_name("temperature-logger"), None);
+    assert_eq!(
+        router.handle_message(
+            Message::unicast(2u32).with_dest(Destination::named("temperature-logger"))
+        ),
+        DispatchResult::NoHandler
+    );
+}
+
+#[traced_test]
+#[test]
+fn endpoint_ids() {
+    use crate::endpoint::EndpointIdError;
+
+    // Each router allocates IDs in creation order
+    let router = MessageRouter::<u32>::new();
+    let other = MessageRouter::<u32>::new();
+    let first = router.create_endpoint::<u32>().message(|_src, msg| msg);
+    let other_endpoint = other.create_endpoint::<u32>().message(|_src, msg| msg);
+    let base = first.addr();
+    #[cfg(not(feature = "wide-ids"))]
+    assert_eq!((base, other_endpoint.addr()), (0, 0));
+    #[cfg(feature = "wide-ids")]
+    assert_ne!(base, other_endpoint.addr());
+
+    // Reserved IDs are skipped by allocation until claimed
+    router.reserve_endpoint_id(base + 1).unwrap();
+    assert_eq!(
+        router.reserve_endpoint_id(base + 1),
+        Err(EndpointIdError::Reserved(base + 1))
+    );
+    let second = router.create_endpoint::<u32>().message(|_src, msg| msg);
+    assert_eq!(second.addr(), base + 2);
+
+    let configured = router
+        .create_endpoint_with_id::<u32>(base + 1)
+        .unwrap()
+        .message(|_src, msg| msg + 1);
+    assert_eq!(configured.addr(), base + 1);
+    assert_eq!(
+        router.create_endpoint_with_id::<u64>(base + 1).err(),
+        Some(EndpointIdError::InUse(base + 1))
+    );
+    assert_eq!(
+        router.reserve_endpoint_id(base + 2),
+        Err(EndpointIdError::InUse(base + 2))
+    );
+
+    // Child routers share the IDs of their parent
+    let child = router.child();
+    let child_endpoint = child.create_endpoint::<u32>().message(|_src, msg| msg);
+    assert_eq!(child_endpoint.addr(), base + 3);
+
+    // IDs of dropped endpoints can be claimed again
+    drop(configured);
+    let reconfigured = router
+        .create_endpoint_with_id::<u32>(base + 1)
+        .unwrap()
+        .message(|_src, msg| msg + 2);
+    assert_eq!(reconfigured.addr(), base + 1);
+    assert_eq!(
+        router.handle_message(Message::unicast(1u32).with_dest(Destination::endpoint(base + 1))),
+        DispatchResult::Delivered(smallvec![3])
+    );
+}
+
+#[traced_test]
+#[test]
+fn groups() {
+    use crate::message::{Address, EndpointName, GroupName};
+
+    let router = MessageRouter::<u32>::new();
+    let first = router
+        .create_endpoint::<u32>()
+        .join("sensors")
+        .message(|_src, msg| msg + 1);
+    let second = router
+        .create_endpoint::<u32>()
+        .join("sensors")
+        .join("loggers")
+        .message(|_src, msg| msg + 2);
+    let _outside = router.create_endpoint::<u32>().message(|_src, msg| msg + 3);
+
+    assert_eq!(
+        router.group_members("sensors"),
+        [first.addr(), second.addr()]
+    );
+
+    // Broadcasts reach all members, unicast messages the first member
+    assert_eq!(
+        router.handle_message(Message::broadcast(0u32).to(GroupName::new("sensors"))),
+        DispatchResult::Delivered(smallvec![1, 2])
+    );
+    assert_eq!(
+        router.handle_message(Message::unicast(0u32).to(GroupName::new("sensors"))),
+        DispatchResult::Delivered(smallvec![1])
+    );
+
+    // Addresses convert to destinations of each kind
+    assert_eq!(
+        Message::unicast(0u32).to(second.addr()).dest().address(),
+        Some(Address::Local(second.addr()))
+    );
+    assert_eq!(
+        Message::unicast(0u32)
+            .to(EndpointName::new("second"))
+            .dest()
+            .address(),
+        Some(Address::Named(EndpointName::new("second")))
+    );
+    assert_eq!(Message::unicast(0u32).dest().address(), None);
+
+    // Members leave groups explicitly or when dropped
+    router.leave_group(first.addr(), "sensors");
+    drop(second);
+    assert!(router.group_members("sensors").is_empty());
+    assert_eq!(
+        router.handle_message(Message::broadcast(0u32).to(GroupName::new("sensors"))),
+        DispatchResult::NoHandler
+    );
+}
+
+#[traced_test]
+#[test]
+fn hashed_endpoints() {
+    use crate::message::{EndpointName, HashEndpoint};
+
+    #[derive(Debug, Hash)]
+    struct SensorId(u32);
+
+    let router = MessageRouter::<u32>::new();
+    let north = router
+        .create_endpoint::<u32>()
+        .claim_hash(&SensorId(1))
+        .claim_hash(&SensorId(2))
+        .message(|_src, msg| msg + 100);
+    let south = router
+        .create_endpoint::<u32>()
+        .claim_hash(&SensorId(3))
+        .message(|_src, msg| msg + 300);
+
+    assert_eq!(router.endpoint_by_hash(&SensorId(2)), Some(north.addr()));
+    assert_eq!(router.endpoint_by_hash(&SensorId(3)), Some(south.addr()));
+    assert_eq!(router.endpoint_by_hash(&SensorId(4)), None);
+    assert_eq!(
+        HashEndpoint::new(&SensorId(1)).addr(),
+        EndpointName::hashed(&SensorId(1))
+    );
+    assert_eq!(router.endpoints()[1].name.as_deref(), Some("SensorId(3)"));
+
+    let to_sensor = |id| Message::unicast(7u32).with_dest(Destination::hashed(&SensorId(id)));
+    assert_eq!(
+        router.handle_message(to_sensor(2)),
+        DispatchResult::Delivered(smallvec![107])
+    );
+    assert_eq!(
+        router.handle_message(to_sensor(3)),
+        DispatchResult::Delivered(smallvec![307])
+    );
+    assert_eq!(
+        router.handle_message(to_sensor(4)),
+        DispatchResult::NoHandler
+    );
+}
+
+#[traced_test]
+#[test]
+fn multi_destination() {
+    let router = MessageRouter::<u32>::new();
+    let first = router.create_endpoint::<u32>().message(|_src, msg| msg + 1);
+    let second = router.create_endpoint::<u32>().message(|_src, msg| msg + 2);
+    let _third = router.create_endpoint::<u32>().message(|_src, msg| msg + 3);
+    let other = router.create_endpoint::<u64>().message(|_src, _msg| 0);
+
+    // Broadcasts are cloned to each listed endpoint
+    assert_eq!(
+        router.handle_message(Message::broadcast(0u32).to_many([first.addr(), second.addr()])),
+        DispatchResult::Delivered(smallvec![1, 2])
+    );
+
+    // Endpoints which couldn't receive the message are reported
+    let result =
+        router.handle_message(Message::broadcast(0u32).to_many([other.addr(), second.addr()]));
+    assert_eq!(result.results(), Some(&[2][..]));
+    assert_eq!(
+        result.failures(),
+        [(other.addr(), DispatchResult::TypeMismatch)]
+    );
+
+    // Unicast payloads are delivered to the first listed endpoint which can receive them
+    assert_eq!(
+        router.handle_message(Message::unicast(0u32).to_many([other.addr(), second.addr()])),
+        DispatchResult::Delivered(smallvec![2])
+    );
+}
+
+#[traced_test]
+#[test]
+fn unicast_payload_broadcast() {
+    #[derive(Debug)]
+    struct Token;
+
+    let router = MessageRouter::<u32>::new();
+    let first = router.create_endpoint::<Token>().message(|_src, _msg| 1);
+    let second = router.create_endpoint::<Token>().message(|_src, _msg| 2);
+
+    // Unicast payloads can't be cloned, so broadcasts of them reach a single endpoint instead of panicking
+    let broadcast = || Message::unicast(Token).with_dest(Destination::Broadcast(Policy::default()));
+    assert_eq!(
+        router.handle_message(broadcast()),
+        DispatchResult::Delivered(smallvec![1])
+    );
+    assert_eq!(router.handle_message_iter(broadcast()).count(), 1);
+    assert_eq!(
+        router.handle_message(Message::unicast(Token).to_many([second.addr(), first.addr()])),
+        DispatchResult::Delivered(smallvec![2])
+    );
+}
+
+#[traced_test]
+#[test]
+fn validators() {
+    #[derive(Debug, PartialEq)]
+    struct OutOfRange(u64);
+
+    impl std::fmt::Display for OutOfRange {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{} is out of range", self.0)
+        }
+    }
+
+    impl std::error::Error for OutOfRange {}
+
+    let router = MessageRouter::<u64>::new();
+    let _endpoint = router.create_endpoint::<u64>().message(|_src, msg| msg);
+
+    router.add_validator(|num: &u64| {
+        if *num <= 100 {
+            Ok(())
+        } else {
+            Err(OutOfRange(*num))
+        }
+    });
+
+    assert!(router.validate(&Message::unicast(5u64)).is_ok());
+    assert_eq!(
+        router.handle_message(Message::unicast(5u64)),
+        DispatchResult::Delivered(smallvec![5])
+    );
+
+    // Invalid messages are rejected before reaching the endpoint, with the error of the validator
+    let DispatchResult::Invalid(error) = router.handle_message(Message::unicast(500u64)) else {
+        panic!("Message was not rejected by the validator");
+    };
+    assert_eq!(error.type_name(), "u64");
+    assert_eq!(error.downcast_ref::<OutOfRange>(), Some(&OutOfRange(500)));
+    assert_eq!(
+        router.recent_dead_letters()[0].reason,
+        DropReason::Invalid("500 is out of range".into())
+    );
+
+    // Messages whose time to live elapsed are dropped
+    let expired = Message::builder()
+        .unicast(5u64)
+        .ttl(Duration::ZERO)
+        .build()
+        .unwrap();
+    assert_eq!(
+        router.handle_message(expired),
+        DispatchResult::Rejected(DropReason::Expired)
+    );
+
+    router.remove_validators::<u64>();
+    assert!(router
+        .handle_message(Message::unicast(500u64))
+        .is_delivered());
+}
+
+#[traced_test]
+#[test]
+fn shared_router() {
+    fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+    let router = Arc::new(MessageRouter::<u64>::new());
+    assert_send_sync(&router);
+
+    let _endpoint = router.create_endpoint::<u64>().message(|_src, msg| msg * 2);
+
+    // One router can be shared immutably by threads dispatching concurrently
+    let total: u64 = std::thread::scope(|scope| {
+        let threads: Vec<_> = (0..4u64)
+            .map(|thread| {
+                let router = &router;
+                scope.spawn(move || {
+                    (0..100u64)
+                        .filter_map(|num| {
+                            router
+                                .handle_message(Message::unicast(thread * 100 + num))
+                                .into_results()
+                        })
+                        .map(|results| results[0])
+                        .sum::<u64>()
+                })
+            })
+            .collect();
+
+        threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .sum()
+    });
+
+    assert_eq!(total, (0..400).sum::<u64>() * 2);
+    assert_eq!(router.metrics().get::<u64>().unwrap().delivered, 400);
+}
+
+#[test]
+fn try_handle_message() {
+    let router = MessageRouter::<u32>::new();
+
+    let (entered_tx, entered_rx) = std::sync::mpsc::channel();
+    let (release_tx, release_rx) = std::sync::mpsc::channel::<()>();
+    let release_rx = Mutex::new(release_rx);
+
+    // The handler of message 0 waits until it's released
+    let _endpoint = router.create_endpoint::<u32>().message(move |_src, msg| {
+        if msg == 0 {
+            entered_tx.send(()).unwrap();
+            release_rx.lock().unwrap().recv().unwrap();
+        }
+        msg
+    });
+
+    std::thread::scope(|scope| {
+        let busy = scope.spawn(|| router.handle_message(Message::unicast(0u32)));
+        entered_rx.recv().unwrap();
+
+        // The only endpoint is busy on the other thread
+        assert_eq!(
+            router.try_handle_message(Message::unicast(1u32)),
+            DispatchResult::WouldBlock
+        );
+        assert_eq!(
+            router.try_handle_message(Message::broadcast(2u32)),
+            DispatchResult::WouldBlock
+        );
+
+        release_tx.send(()).unwrap();
+        assert_eq!(
+            busy.join().unwrap(),
+            DispatchResult::Delivered(smallvec![0])
+        );
+    });
+
+    assert_eq!(
+        router.try_handle_message(Message::unicast(3u32)),
+        DispatchResult::Delivered(smallvec![3])
+    );
+}
@@ -0,0 +1,103 @@
+use crate::{
+    dispatch::DispatchResult,
+    message::{Destination, Message},
+    router::MessageRouter,
+    routes::{FilterSpec, RouteError, RouteSpec, RoutesConfig},
+};
+
+#[test]
+fn load_routes() {
+    let router = MessageRouter::<&'static str>::new();
+    let _kitchen = router
+        .create_endpoint::<u32>()
+        .named("kitchen")
+        .message(|_src, _msg| "kitchen");
+
+    let config = RoutesConfig {
+        routes: vec![RouteSpec {
+            endpoint: "kitchen".into(),
+            filters: vec![
+                FilterSpec::SourcePattern {
+                    pattern: "sensor/kitchen/*".into(),
+                },
+                FilterSpec::SourceRange {
+                    min: Some(100),
+                    max: Some(199),
+                },
+            ],
+            groups: vec!["displays".into()],
+        }],
+    };
+    router.load_routes(&config).unwrap();
+
+    let from = |source: &'static str| Message::unicast(1u32).with_source(source);
+    assert!(router
+        .handle_message(from("sensor/kitchen/1"))
+        .is_delivered());
+    assert!(router
+        .handle_message(Message::unicast(1u32).with_source(150u64))
+        .is_delivered());
+    assert_eq!(
+        router.handle_message(from("sensor/garage/1")),
+        DispatchResult::Filtered
+    );
+    assert!(router
+        .handle_message(Message::unicast(1u32).with_dest(Destination::group("displays")))
+        .is_delivered());
+
+    // Nothing is applied if a route is invalid
+    let invalid = RoutesConfig {
+        routes: vec![
+            RouteSpec {
+                endpoint: "kitchen".into(),
+                filters: vec![],
+                groups: vec!["lights".into()],
+            },
+            RouteSpec {
+                endpoint: "garage".into(),
+                filters: vec![],
+                groups: vec![],
+            },
+        ],
+    };
+    assert_eq!(
+        router.load_routes(&invalid),
+        Err(RouteError::UnknownEndpoint("garage".into()))
+    );
+    assert_eq!(
+        router.handle_message(Message::unicast(1u32).with_dest(Destination::group("lights"))),
+        DispatchResult::NoHandler
+    );
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn routes_json() {
+    let config: RoutesConfig = serde_json::from_str(
+        r#"{
+            "routes": [{
+                "endpoint": "kitchen",
+                "filters": [
+                    { "type": "header_pattern", "header": "room", "pattern": "kitchen*" },
+                    { "type": "source_range", "min": 100 }
+                ]
+            }]
+        }"#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        config.routes[0].filters,
+        vec![
+            FilterSpec::HeaderPattern {
+                header: "room".into(),
+                pattern: "kitchen*".into()
+            },
+            FilterSpec::SourceRange {
+                min: Some(100),
+                max: None
+            },
+        ]
+    );
+    assert!(config.routes[0].groups.is_empty());
+}
@@ -0,0 +1,75 @@
+use std::{
+    sync::{mpsc, Arc},
+    task::{Context, Poll, Wake, Waker},
+    thread::Thread,
+    time::Duration,
+};
+
+use tracing_test::traced_test;
+
+use crate::{
+    handler::AsyncMessageHandler,
+    message::{Message, SourceRef},
+    router::MessageRouter,
+    spawn::BoxFuture,
+};
+
+/// Wakes the thread running a task
+struct Unpark(Thread);
+
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Minimal executor running each task to completion on its own thread
+fn thread_spawner(task: BoxFuture) {
+    std::thread::spawn(move || {
+        let mut task = task;
+        let waker = Waker::from(Arc::new(Unpark(std::thread::current())));
+        let mut cx = Context::from_waker(&waker);
+
+        while task.as_mut().poll(&mut cx) == Poll::Pending {
+            std::thread::park();
+        }
+    });
+}
+
+#[derive(Debug)]
+struct Summer {
+    total: u64,
+    tx: mpsc::Sender<u64>,
+}
+
+impl AsyncMessageHandler for Summer {
+    type Message = u64;
+
+    async fn on_message(&mut self, _source: Option<SourceRef>, message: u64) {
+        self.total += message;
+        self.tx.send(self.total).unwrap();
+    }
+}
+
+#[traced_test]
+#[test]
+fn spawn_handler_on() {
+    let router = MessageRouter::<()>::new();
+    let (tx, rx) = mpsc::channel();
+    let timeout = Duration::from_secs(5);
+
+    let endpoint = router.spawn_handler_on(&thread_spawner, Summer { total: 0, tx });
+    router.handle_message(Message::unicast(2u64));
+    router.handle_message(Message::unicast(3u64));
+
+    // Messages are handled in order by the task on the executor
+    assert_eq!(rx.recv_timeout(timeout), Ok(2));
+    assert_eq!(rx.recv_timeout(timeout), Ok(5));
+
+    // Dropping the endpoint ends the task, which drops the handler and its sender
+    drop(endpoint);
+    assert_eq!(
+        rx.recv_timeout(timeout),
+        Err(mpsc::RecvTimeoutError::Disconnected)
+    );
+}
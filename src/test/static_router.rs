@@ -0,0 +1,106 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use tracing_test::traced_test;
+
+use crate::{
+    ring,
+    static_router::{MessageSet, StaticRouter},
+};
+
+enum Command {
+    Add(u64),
+    Reset,
+    Stop,
+}
+
+impl MessageSet for Command {
+    const COUNT: usize = 3;
+
+    fn index(&self) -> usize {
+        match self {
+            Command::Add(_) => 0,
+            Command::Reset => 1,
+            Command::Stop => 2,
+        }
+    }
+}
+
+#[traced_test]
+#[test]
+fn static_router() {
+    let total = Arc::new(AtomicU64::new(0));
+    let mut router = StaticRouter::<Command, u64>::new();
+
+    let sum = total.clone();
+    router
+        .on(0, move |command| match command {
+            Command::Add(num) => sum.fetch_add(*num, Ordering::Relaxed) + num,
+            _ => unreachable!(),
+        })
+        .on(0, |_command| 0)
+        .on(1, |_command| 1);
+    assert_eq!(router.num_handlers(), 3);
+
+    // All handlers of the variant are called, in the order they were registered
+    let results: Vec<u64> = router.dispatch(&Command::Add(5)).collect();
+    assert_eq!(results, [5, 0]);
+    assert_eq!(router.dispatch(&Command::Add(2)).next(), Some(7));
+
+    assert_eq!(router.dispatch(&Command::Reset).collect::<Vec<_>>(), [1]);
+    assert_eq!(router.dispatch(&Command::Stop).count(), 0);
+    assert_eq!(total.load(Ordering::Relaxed), 7);
+}
+
+#[test]
+#[should_panic(expected = "out of range")]
+fn static_router_index_range() {
+    StaticRouter::<Command>::new().on(Command::COUNT, |_command| {});
+}
+
+#[test]
+fn ring_ingress() {
+    let total = Arc::new(AtomicU64::new(0));
+    let mut router = StaticRouter::<Command>::new();
+
+    let sum = total.clone();
+    router.on(0, move |command| {
+        if let Command::Add(num) = command {
+            sum.fetch_add(*num, Ordering::Relaxed);
+        }
+    });
+
+    // The capacity is rounded up to a power of two, and a full ring hands the message back
+    let (mut tx, mut rx) = ring::channel::<Command>(3);
+    assert_eq!(tx.capacity(), 4);
+    for num in 1..=4 {
+        assert!(tx.try_send(Command::Add(num)).is_ok());
+    }
+    assert!(matches!(tx.try_send(Command::Add(5)), Err(Command::Add(5))));
+    assert_eq!(rx.len(), 4);
+
+    assert_eq!(router.drain(&mut rx), 4);
+    assert!(rx.is_empty());
+    assert_eq!(total.load(Ordering::Relaxed), 10);
+
+    // Messages sent by another thread wrap around the ring
+    std::thread::scope(|scope| {
+        scope.spawn(move || {
+            let mut sent = 0;
+            while sent < 100 {
+                if tx.try_send(Command::Add(1)).is_ok() {
+                    sent += 1;
+                }
+            }
+        });
+
+        let mut received = 0;
+        while received < 100 {
+            received += router.drain(&mut rx);
+        }
+    });
+
+    assert_eq!(total.load(Ordering::Relaxed), 110);
+}
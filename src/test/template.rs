@@ -0,0 +1,47 @@
+use smallvec::smallvec;
+use tracing_test::traced_test;
+
+use crate::{
+    dispatch::DispatchResult, message::Destination, router::MessageRouter,
+    template::MessageTemplate, traits::EndpointAddress as _,
+};
+
+#[traced_test]
+#[test]
+fn template_fire() {
+    let router = MessageRouter::<u64>::new();
+    let _a = router
+        .create_endpoint::<u64>()
+        .message(|src, msg| msg + src.and_then(|src| src.get::<u64>()).unwrap_or(0));
+    let _b = router.create_endpoint::<u64>().message(|_src, msg| msg);
+
+    let template = MessageTemplate::broadcast(5u64).with_source(10u64);
+
+    for _ in 0..3 {
+        assert_eq!(
+            template.fire(&router),
+            DispatchResult::Delivered(smallvec![15, 5])
+        );
+    }
+
+    assert_eq!(router.metrics().get::<u64>().unwrap().delivered, 6);
+}
+
+#[traced_test]
+#[test]
+fn template_dest() {
+    let router = MessageRouter::<u64>::new();
+    let _a = router.create_endpoint::<u64>().message(|_src, msg| msg);
+    let b = router.create_endpoint::<u64>().message(|_src, msg| msg * 2);
+
+    let template = MessageTemplate::unicast(5u64).with_dest(Destination::endpoint(b.addr()));
+    assert_eq!(
+        template.fire(&router),
+        DispatchResult::Delivered(smallvec![10])
+    );
+
+    let sender = router.sender();
+    template.queue(&sender);
+    template.queue(&sender);
+    assert_eq!(router.drain(), vec![10, 10]);
+}
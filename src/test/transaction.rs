@@ -0,0 +1,56 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use smallvec::smallvec;
+use tracing_test::traced_test;
+
+use crate::{dispatch::DispatchResult, router::MessageRouter};
+
+#[traced_test]
+#[test]
+fn transaction_commit() {
+    let router = MessageRouter::<u64>::new();
+    let _endpoint = router.create_endpoint::<u64>().message(|_src, msg| msg);
+
+    let results = router.transaction(|tx| {
+        tx.unicast(1u64);
+        tx.broadcast(2u64);
+        tx.unicast(3u32);
+        Ok::<_, ()>(())
+    });
+
+    assert_eq!(
+        results,
+        Ok(vec![
+            DispatchResult::Delivered(smallvec![1]),
+            DispatchResult::Delivered(smallvec![2]),
+            DispatchResult::NoHandler,
+        ])
+    );
+}
+
+#[traced_test]
+#[test]
+fn transaction_abort() {
+    let router = MessageRouter::<()>::new();
+
+    let received = Arc::new(AtomicU64::new(0));
+    let count = received.clone();
+    let _endpoint = router.create_endpoint::<u64>().message(move |_src, msg| {
+        count.fetch_add(msg, Ordering::Relaxed);
+    });
+
+    let results = router.transaction(|tx| {
+        tx.unicast(1u64);
+        tx.unicast(2u64);
+        if tx.len() > 1 {
+            return Err("too many");
+        }
+        Ok(())
+    });
+
+    assert_eq!(results, Err("too many"));
+    assert_eq!(received.load(Ordering::Relaxed), 0);
+}
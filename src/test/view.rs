@@ -0,0 +1,98 @@
+use tracing_test::traced_test;
+
+use crate::{message::Message, router::MessageRouter};
+
+#[derive(Debug, Clone)]
+struct Order {
+    customer: &'static str,
+    amount: u32,
+}
+
+#[traced_test]
+#[test]
+fn view() {
+    let router = MessageRouter::<u32>::new();
+    let _endpoint = router
+        .create_endpoint::<Order>()
+        .message(|_src, order| order.amount);
+
+    let totals = router.view(
+        |order: &Order| order.customer,
+        |total: &mut u32, order: Order| *total += order.amount,
+    );
+    let counts = router.view(
+        |order: &Order| order.customer,
+        |count: &mut usize, _| *count += 1,
+    );
+
+    let order = |customer, amount| Message::unicast(Order { customer, amount });
+
+    // Views see unicast messages, which are still delivered to the endpoint
+    assert!(router.handle_message(order("alice", 10)).is_delivered());
+    router.handle_message(order("bob", 5));
+    router.handle_message(Message::broadcast(Order {
+        customer: "alice",
+        amount: 20,
+    }));
+
+    assert_eq!(totals.get(&"alice"), Some(30));
+    assert_eq!(totals.get(&"bob"), Some(5));
+    assert_eq!(counts.get(&"alice"), Some(2));
+    assert_eq!(totals.len(), 2);
+    assert_eq!(totals.read(|map| map.values().sum::<u32>()), 35);
+
+    let snapshot = totals.snapshot();
+    assert_eq!(totals.remove(&"bob"), Some(5));
+    assert_eq!(snapshot.len(), 2);
+    assert!(!totals.contains_key(&"bob"));
+
+    // Dropped views stop updating
+    drop(counts);
+    router.handle_message(order("alice", 1));
+    assert_eq!(totals.get(&"alice"), Some(31));
+}
+
+#[traced_test]
+#[test]
+fn fold_journal() {
+    let router = MessageRouter::<u32>::new();
+
+    let journal = vec![
+        Order {
+            customer: "alice",
+            amount: 10,
+        },
+        Order {
+            customer: "bob",
+            amount: 5,
+        },
+    ];
+
+    let revenue = |total: &mut u32, order: Order| *total += order.amount;
+
+    // Live folds keep reducing dispatched messages after the journal
+    let live = router.fold_journal_live(journal.clone(), 100, revenue);
+    assert_eq!(live.get(), 115);
+
+    router.handle_message(Message::broadcast(Order {
+        customer: "carol",
+        amount: 7,
+    }));
+    assert_eq!(live.get(), 122);
+    assert_eq!(live.read(|total| *total * 2), 244);
+
+    drop(live);
+    router.handle_message(Message::broadcast(Order {
+        customer: "carol",
+        amount: 7,
+    }));
+
+    // Messages dispatched while the journal is replayed are not lost
+    let replay = journal.into_iter().inspect(|_| {
+        router.handle_message(Message::broadcast(Order {
+            customer: "dave",
+            amount: 1,
+        }));
+    });
+    assert_eq!(router.fold_journal_live(replay, 0, revenue).get(), 17);
+}
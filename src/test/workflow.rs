@@ -0,0 +1,167 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tracing_test::traced_test;
+
+use crate::{
+    endpoint::Endpoint,
+    message::Message,
+    router::MessageRouter,
+    workflow::{ProgressEvent, WorkflowProgress, WorkflowStatus},
+};
+
+#[derive(Debug, Clone)]
+struct Reserved(u32);
+
+#[derive(Debug, Clone)]
+struct Charged;
+
+#[derive(Debug, Clone)]
+struct Shipped(bool);
+
+/// Collect the progress events broadcast by workflows
+fn progress(
+    router: &MessageRouter<'static, ()>,
+) -> (
+    Endpoint<'static, WorkflowProgress, ()>,
+    Arc<Mutex<Vec<(usize, ProgressEvent)>>>,
+) {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let endpoint = router.create_endpoint::<WorkflowProgress>().message({
+        let events = events.clone();
+        move |_src, progress| {
+            events
+                .lock()
+                .unwrap()
+                .push((progress.index, progress.event))
+        }
+    });
+
+    (endpoint, events)
+}
+
+#[traced_test]
+#[test]
+fn workflow() {
+    let router = MessageRouter::<()>::new();
+    let (_endpoint, events) = progress(&router);
+    let reserved = Arc::new(Mutex::new(0));
+
+    let order = router
+        .workflow("order")
+        .step("reserve", {
+            let reserved = reserved.clone();
+            move |msg: Reserved| {
+                *reserved.lock().unwrap() += msg.0;
+                Ok(())
+            }
+        })
+        .step("charge", |_: Charged| Ok(()))
+        .step("ship", |_: Shipped| Ok(()));
+
+    assert_eq!(order.current_step(), Some("reserve"));
+
+    // Messages for other steps are ignored
+    router.handle_message(Message::broadcast(Charged));
+    assert_eq!(order.status(), WorkflowStatus::Running(0));
+
+    router.handle_message(Message::broadcast(Reserved(2)));
+    router.handle_message(Message::unicast(Charged));
+    assert_eq!(order.current_step(), Some("ship"));
+    router.handle_message(Message::broadcast(Shipped(true)));
+
+    assert_eq!(order.status(), WorkflowStatus::Completed);
+    assert_eq!(order.current_step(), None);
+    assert_eq!(*reserved.lock().unwrap(), 2);
+    assert_eq!(
+        *events.lock().unwrap(),
+        vec![
+            (0, ProgressEvent::StepCompleted),
+            (1, ProgressEvent::StepCompleted),
+            (2, ProgressEvent::StepCompleted),
+            (2, ProgressEvent::Completed),
+        ]
+    );
+}
+
+#[traced_test]
+#[test]
+fn workflow_compensation() {
+    let router = MessageRouter::<()>::new();
+    let (_endpoint, events) = progress(&router);
+    let undone = Arc::new(Mutex::new(Vec::new()));
+
+    let compensate = |step: &'static str| {
+        let undone = undone.clone();
+        move || undone.lock().unwrap().push(step)
+    };
+
+    let order = router
+        .workflow("order")
+        .step("reserve", |_: Reserved| Ok(()))
+        .compensate(compensate("reserve"))
+        .step("charge", |_: Charged| Ok(()))
+        .compensate(compensate("charge"))
+        .step("ship", |msg: Shipped| {
+            if msg.0 {
+                Ok(())
+            } else {
+                Err("out of stock".to_string())
+            }
+        })
+        .compensate(compensate("ship"));
+
+    router.handle_message(Message::broadcast(Reserved(1)));
+    router.handle_message(Message::broadcast(Charged));
+    router.handle_message(Message::broadcast(Shipped(false)));
+
+    // Completed steps are compensated in reverse order
+    assert_eq!(order.status(), WorkflowStatus::Failed(2));
+    assert_eq!(*undone.lock().unwrap(), vec!["charge", "reserve"]);
+    assert_eq!(
+        events.lock().unwrap()[2..],
+        [
+            (2, ProgressEvent::Failed("out of stock".to_string())),
+            (1, ProgressEvent::Compensated),
+            (0, ProgressEvent::Compensated),
+        ]
+    );
+}
+
+#[traced_test]
+#[test]
+fn workflow_timeout() {
+    let router = MessageRouter::<()>::new();
+    let (_endpoint, events) = progress(&router);
+    let undone = Arc::new(Mutex::new(false));
+
+    let order = router
+        .workflow("order")
+        .step("reserve", |_: Reserved| Ok(()))
+        .compensate({
+            let undone = undone.clone();
+            move || *undone.lock().unwrap() = true
+        })
+        .step("charge", |_: Charged| Ok(()))
+        .timeout(Duration::from_millis(20));
+
+    router.handle_message(Message::broadcast(Reserved(1)));
+    assert!(!order.check_timeouts());
+
+    std::thread::sleep(Duration::from_millis(30));
+    assert!(order.check_timeouts());
+    assert_eq!(order.status(), WorkflowStatus::TimedOut(1));
+    assert!(*undone.lock().unwrap());
+
+    // Messages arriving after the timeout are ignored
+    router.handle_message(Message::broadcast(Charged));
+    assert_eq!(
+        events.lock().unwrap()[1..],
+        [
+            (1, ProgressEvent::TimedOut),
+            (0, ProgressEvent::Compensated)
+        ]
+    );
+}
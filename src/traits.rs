@@ -72,7 +72,7 @@ pub trait SalishMessage: 'static {
     type Endpoint: EndpointAddress;
 
     /// Return a reference to the [`MessagePayload`]
-    fn payload<'b>(&'b self) -> &'b MessagePayload;
+    fn payload(&self) -> &MessagePayload;
 
     fn to_payload(self) -> MessagePayload;
 }
@@ -101,6 +101,9 @@ pub trait UnicastPayload: Payload {
 pub trait Payload: std::fmt::Debug + Send + Sync {
     fn as_any(&self) -> &dyn Any;
     fn into_any(self: Box<Self>) -> Box<dyn Any>;
+
+    /// Get the Rust type name of the concrete payload
+    fn type_name(&self) -> &'static str;
 }
 
 /// Implement [`BroadcastPayload`] for any type implementing
@@ -143,6 +146,10 @@ where
     fn into_any(self: Box<Self>) -> Box<dyn Any> {
         Box::new(*self)
     }
+
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<T>()
+    }
 }
 
 #[derive(Debug)]
@@ -158,16 +165,23 @@ impl MessagePayload {
             MessagePayload::Broadcast(broadcast_payload) => broadcast_payload.as_any(),
         }
     }
+
+    /// Get the Rust type name of the inner payload
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            MessagePayload::Unicast(unicast_payload) => (**unicast_payload).type_name(),
+            MessagePayload::Broadcast(broadcast_payload) => (**broadcast_payload).type_name(),
+        }
+    }
 }
 
-impl Clone for MessagePayload {
-    fn clone(&self) -> Self {
+impl MessagePayload {
+    /// Clone a broadcast payload, or get `None` for a unicast payload which can't be cloned
+    pub fn try_clone(&self) -> Option<Self> {
         match self {
-            MessagePayload::Unicast(_) => {
-                panic!("Cannot clone Unicast payload");
-            }
+            MessagePayload::Unicast(_) => None,
             MessagePayload::Broadcast(payload) => {
-                MessagePayload::Broadcast((*payload).clone_payload())
+                Some(MessagePayload::Broadcast((*payload).clone_payload()))
             }
         }
     }
@@ -0,0 +1,74 @@
+//! All-or-nothing dispatch of several messages
+//!
+//! [`MessageRouter::transaction()`] collects the messages sent in a closure, and only dispatches them once the
+//! closure returns `Ok`. If it returns an error, or panics, none of the messages are dispatched. This keeps
+//! state transitions which are spread over several messages consistent.
+//!
+//! ```
+//! use salish::{router::MessageRouter, Message};
+//!
+//! let router = MessageRouter::<u32>::new();
+//! let _endpoint = router.create_endpoint::<u32>().message(|_src, msg| msg);
+//!
+//! let results = router.transaction(|tx| {
+//!     tx.send(Message::unicast(1u32));
+//!     tx.send(Message::unicast(2u32));
+//!     Ok::<_, ()>(())
+//! });
+//! assert_eq!(results.unwrap().len(), 2);
+//!
+//! let results = router.transaction(|tx| {
+//!     tx.send(Message::unicast(3u32));
+//!     Err("aborted")
+//! });
+//! assert_eq!(results, Err("aborted"));
+//! ```
+//!
+//! [`MessageRouter::transaction()`]: crate::router::MessageRouter::transaction
+
+use crate::{
+    traits::{BroadcastPayload, UnicastPayload},
+    Message,
+};
+
+/// Messages sent within a transaction, which are dispatched in order if it succeeds
+#[derive(Debug, Default)]
+pub struct Transaction {
+    messages: Vec<Message>,
+}
+
+impl Transaction {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a message to the transaction
+    pub fn send(&mut self, message: Message) {
+        self.messages.push(message)
+    }
+
+    /// Add a payload to be dispatched to any handler of its type
+    pub fn unicast<P: UnicastPayload + 'static>(&mut self, payload: P) {
+        self.send(Message::unicast(payload))
+    }
+
+    /// Add a payload to be broadcast to all handlers of its type
+    pub fn broadcast<P: BroadcastPayload + 'static>(&mut self, payload: P) {
+        self.send(Message::broadcast(payload))
+    }
+
+    /// Get the number of messages in the transaction
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    /// Check if no messages were added to the transaction
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    /// Get the messages of a committed transaction
+    pub(crate) fn into_messages(self) -> Vec<Message> {
+        self.messages
+    }
+}
@@ -0,0 +1,133 @@
+//! Validation of message payloads at send time
+//!
+//! Validators registered with [`MessageRouter::add_validator()`](crate::router::MessageRouter::add_validator)
+//! check each message of their payload type before it's passed to the middleware, so invalid messages are rejected
+//! when they are sent rather than failing deep inside a handler. A rejected message is dispatched with
+//! [`DispatchResult::Invalid`](crate::dispatch::DispatchResult::Invalid) holding the [`ValidationError`], and
+//! passed to the dead-letter sink with [`DropReason::Invalid`](crate::middleware::DropReason::Invalid).
+//!
+//! Messages can also be checked before sending them with
+//! [`MessageRouter::validate()`](crate::router::MessageRouter::validate).
+
+use std::{any::TypeId, collections::HashMap, error::Error, sync::Arc};
+
+use anylock::{AnyLock, ParkingLotRwLock};
+
+use crate::{
+    traits::{internal::SalishMessageInternal as _, Payload},
+    Message,
+};
+
+/// Error returned by a validator rejecting a message. The error of the validator can be downcast to its type.
+#[derive(Debug, Clone)]
+pub struct ValidationError {
+    type_name: &'static str,
+    error: Arc<dyn Error + Send + Sync>,
+}
+
+impl ValidationError {
+    /// Rust type name of the payload which was rejected
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    /// Get the error returned by the validator
+    pub fn error(&self) -> &(dyn Error + Send + Sync + 'static) {
+        &*self.error
+    }
+
+    /// Get the error returned by the validator, if it is of type `E`
+    pub fn downcast_ref<E: Error + 'static>(&self) -> Option<&E> {
+        self.error.downcast_ref::<E>()
+    }
+}
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid {}: {}", self.type_name, self.error)
+    }
+}
+
+impl Error for ValidationError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&*self.error)
+    }
+}
+
+/// Validation errors are equal if they reject the same payload type with the same message
+impl PartialEq for ValidationError {
+    fn eq(&self, other: &Self) -> bool {
+        self.type_name == other.type_name && self.error.to_string() == other.error.to_string()
+    }
+}
+
+impl Eq for ValidationError {}
+
+/// Type erased validator, which downcasts the payload of a message to the type it validates
+type Validator = Box<dyn Fn(&Message) -> Result<(), ValidationError> + Send + Sync>;
+
+/// Validators by payload type, shared by all clones of a router
+pub(crate) struct Validators {
+    validators: ParkingLotRwLock<HashMap<TypeId, Vec<Validator>>>,
+}
+
+impl Default for Validators {
+    fn default() -> Self {
+        Self {
+            validators: ParkingLotRwLock::new(HashMap::new()),
+        }
+    }
+}
+
+impl std::fmt::Debug for Validators {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Validators")
+            .field("types", &self.validators.read().len())
+            .finish()
+    }
+}
+
+impl Validators {
+    /// Add a validator of payloads of type `M`
+    pub(crate) fn add<M, E, F>(&self, validator: F)
+    where
+        M: Payload + 'static,
+        E: Error + Send + Sync + 'static,
+        F: Fn(&M) -> Result<(), E> + Send + Sync + 'static,
+    {
+        let validator = move |message: &Message| {
+            let Some(payload) = message.inner::<M>() else {
+                return Ok(());
+            };
+
+            validator(payload).map_err(|error| ValidationError {
+                type_name: std::any::type_name::<M>(),
+                error: Arc::new(error),
+            })
+        };
+
+        self.validators
+            .write()
+            .entry(TypeId::of::<M>())
+            .or_default()
+            .push(Box::new(validator));
+    }
+
+    /// Remove the validators of a payload type
+    pub(crate) fn remove(&self, type_id: TypeId) {
+        self.validators.write().remove(&type_id);
+    }
+
+    /// Run the validators of the payload type of a message in the order they were added, stopping at the
+    /// first which rejects it
+    pub(crate) fn validate(&self, message: &Message) -> Result<(), ValidationError> {
+        let validators = self.validators.read();
+        let Some(validators) = validators.get(&message.payload_type()) else {
+            return Ok(());
+        };
+
+        validators
+            .iter()
+            .try_for_each(|validator| validator(message))
+    }
+}
@@ -0,0 +1,318 @@
+//! Materialized views of message streams
+//!
+//! A [`Fold`] reduces the messages of a payload type into a single state. It can start from a journal of persisted
+//! payloads read by the application, so applications using event sourcing rebuild their state at startup with
+//! [`MessageRouter::fold_journal_live()`](crate::router::MessageRouter::fold_journal_live), then keep it up to
+//! date as new messages are dispatched.
+//!
+//! A [`View`] folds the messages of a payload type dispatched by a router into a map, keyed by a key extracted
+//! from each message. Each message is passed to a reducer with the current value of its key, which is created
+//! with [`Default`] for new keys. Views observe messages as they are dispatched without being an endpoint, so
+//! they see unicast messages delivered to other endpoints as well as broadcasts, and don't affect routing.
+//!
+//! ```
+//! use salish::{router::MessageRouter, Message};
+//!
+//! #[derive(Debug, Clone)]
+//! struct Reading {
+//!     sensor: u32,
+//!     value: f32,
+//! }
+//!
+//! let router = MessageRouter::<()>::new();
+//! let maximums = router.view(
+//!     |reading: &Reading| reading.sensor,
+//!     |max: &mut f32, reading: Reading| *max = max.max(reading.value),
+//! );
+//!
+//! router.handle_message(Message::broadcast(Reading { sensor: 1, value: 2.5 }));
+//! router.handle_message(Message::broadcast(Reading { sensor: 1, value: 1.0 }));
+//! assert_eq!(maximums.get(&1), Some(2.5));
+//! ```
+
+use std::{
+    any::TypeId,
+    collections::HashMap,
+    hash::Hash,
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use anylock::{AnyLock, ParkingLotRwLock};
+
+use crate::{traits::internal::SalishMessageInternal as _, Message};
+
+/// Folds a message into a view, if it's of the payload type of the view
+pub(crate) type Update = Arc<dyn Fn(&Message) + Send + Sync>;
+
+/// Views by payload type, shared by all clones of a router
+pub(crate) struct Views {
+    views: ParkingLotRwLock<HashMap<TypeId, Vec<(u64, Update)>>>,
+    next_id: AtomicU64,
+}
+
+impl std::fmt::Debug for Views {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Views")
+            .field("types", &self.views.read().len())
+            .finish()
+    }
+}
+
+impl Default for Views {
+    fn default() -> Self {
+        Self {
+            views: ParkingLotRwLock::new(HashMap::new()),
+            next_id: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Views {
+    /// Fold a message into the views of its payload type
+    pub(crate) fn update(&self, message: &Message) {
+        let type_id = message.payload_type();
+
+        // Clone the updates, so views are not locked while the reducers run
+        let updates: Vec<Update> = match self.views.read().get(&type_id) {
+            Some(views) => views.iter().map(|(_, update)| update.clone()).collect(),
+            None => return,
+        };
+
+        for update in updates {
+            update(message);
+        }
+    }
+
+    pub(crate) fn add(&self, type_id: TypeId, update: Update) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.views
+            .write()
+            .entry(type_id)
+            .or_default()
+            .push((id, update));
+        id
+    }
+
+    pub(crate) fn remove(&self, type_id: TypeId, id: u64) {
+        let mut views = self.views.write();
+        if let Some(list) = views.get_mut(&type_id) {
+            list.retain(|(view_id, _)| *view_id != id);
+            if list.is_empty() {
+                views.remove(&type_id);
+            }
+        }
+    }
+}
+
+/// Map of values by key, updated by the messages of payload type `M` dispatched by a router.
+/// The view stops updating when this is dropped.
+pub struct View<K, V, M> {
+    state: Arc<ParkingLotRwLock<HashMap<K, V>>>,
+    views: Arc<Views>,
+    type_id: TypeId,
+    id: u64,
+    _payload: PhantomData<fn(M)>,
+}
+
+impl<K, V, M> std::fmt::Debug for View<K, V, M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("View")
+            .field("payload", &std::any::type_name::<M>())
+            .field("keys", &self.state.read().len())
+            .finish()
+    }
+}
+
+impl<K, V, M> View<K, V, M>
+where
+    K: Eq + Hash + Send + Sync + 'static,
+    V: Default + Send + Sync + 'static,
+    M: Clone + 'static,
+{
+    /// Register a view with `views`, folding each message into the value of the key extracted by `key`
+    pub(crate) fn new(
+        views: Arc<Views>,
+        key: impl Fn(&M) -> K + Send + Sync + 'static,
+        reduce: impl Fn(&mut V, M) + Send + Sync + 'static,
+    ) -> Self {
+        let state = Arc::new(ParkingLotRwLock::new(HashMap::new()));
+
+        let update: Update = {
+            let state = state.clone();
+
+            Arc::new(move |message: &Message| {
+                if let Some(payload) = message.inner::<M>() {
+                    let mut state = state.write();
+                    reduce(state.entry(key(payload)).or_default(), payload.clone());
+                }
+            })
+        };
+
+        let type_id = TypeId::of::<M>();
+        let id = views.add(type_id, update);
+
+        Self {
+            state,
+            views,
+            type_id,
+            id,
+            _payload: PhantomData,
+        }
+    }
+}
+
+impl<K, V, M> View<K, V, M>
+where
+    K: Eq + Hash,
+{
+    /// Get a clone of the value of `key`
+    pub fn get(&self, key: &K) -> Option<V>
+    where
+        V: Clone,
+    {
+        self.state.read().get(key).cloned()
+    }
+
+    /// Check if a message with `key` was folded into the view
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.state.read().contains_key(key)
+    }
+
+    /// Read the map of the view. Messages are not folded into the view while `f` runs.
+    pub fn read<T>(&self, f: impl FnOnce(&HashMap<K, V>) -> T) -> T {
+        f(&self.state.read())
+    }
+
+    /// Get a clone of the map of the view
+    pub fn snapshot(&self) -> HashMap<K, V>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        self.state.read().clone()
+    }
+
+    /// Get the number of keys in the view
+    pub fn len(&self) -> usize {
+        self.state.read().len()
+    }
+
+    /// Check if no messages were folded into the view
+    pub fn is_empty(&self) -> bool {
+        self.state.read().is_empty()
+    }
+
+    /// Remove the value of `key`, returning it if it existed
+    pub fn remove(&self, key: &K) -> Option<V> {
+        self.state.write().remove(key)
+    }
+}
+
+/// Stop updating the view
+impl<K, V, M> Drop for View<K, V, M> {
+    fn drop(&mut self) {
+        self.views.remove(self.type_id, self.id);
+    }
+}
+
+/// State reduced from the messages of payload type `M` dispatched by a router.
+/// The state stops updating when this is dropped.
+pub struct Fold<S, M> {
+    state: Arc<ParkingLotRwLock<S>>,
+    views: Arc<Views>,
+    type_id: TypeId,
+    id: u64,
+    _payload: PhantomData<fn(M)>,
+}
+
+impl<S: std::fmt::Debug, M> std::fmt::Debug for Fold<S, M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Fold")
+            .field("payload", &std::any::type_name::<M>())
+            .field("state", &*self.state.read())
+            .finish()
+    }
+}
+
+impl<S, M> Fold<S, M>
+where
+    S: Send + Sync + 'static,
+    M: Clone + 'static,
+{
+    /// Register a fold of `state` with `views`, reducing each message with `reducer`
+    pub(crate) fn new(
+        views: Arc<Views>,
+        state: S,
+        reducer: impl Fn(&mut S, M) + Send + Sync + 'static,
+    ) -> Self {
+        let state = Arc::new(ParkingLotRwLock::new(state));
+
+        let update: Update = {
+            let state = state.clone();
+
+            Arc::new(move |message: &Message| {
+                if let Some(payload) = message.inner::<M>() {
+                    reducer(&mut state.write(), payload.clone());
+                }
+            })
+        };
+
+        let type_id = TypeId::of::<M>();
+        let id = views.add(type_id, update);
+
+        Self {
+            state,
+            views,
+            type_id,
+            id,
+            _payload: PhantomData,
+        }
+    }
+
+    /// Register a fold of `init` with `views`, then reduce each payload of `journal` into it in order.
+    /// Messages dispatched while the journal is replayed are reduced as they arrive, between journal payloads.
+    pub(crate) fn with_journal(
+        views: Arc<Views>,
+        journal: impl IntoIterator<Item = M>,
+        init: S,
+        reducer: impl Fn(&mut S, M) + Send + Sync + 'static,
+    ) -> Self {
+        let reducer = Arc::new(reducer);
+        let fold = Self::new(views, init, {
+            let reducer = reducer.clone();
+            move |state: &mut S, payload: M| reducer(state, payload)
+        });
+
+        for payload in journal {
+            reducer(&mut fold.state.write(), payload);
+        }
+
+        fold
+    }
+}
+
+impl<S, M> Fold<S, M> {
+    /// Get a clone of the state
+    pub fn get(&self) -> S
+    where
+        S: Clone,
+    {
+        self.state.read().clone()
+    }
+
+    /// Read the state. Messages are not reduced into the state while `f` runs.
+    pub fn read<T>(&self, f: impl FnOnce(&S) -> T) -> T {
+        f(&self.state.read())
+    }
+}
+
+/// Stop updating the state
+impl<S, M> Drop for Fold<S, M> {
+    fn drop(&mut self) {
+        self.views.remove(self.type_id, self.id);
+    }
+}
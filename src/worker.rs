@@ -0,0 +1,127 @@
+//! Named worker threads owned by a router
+//!
+//! Endpoints assigned to a worker with [`Endpoint::on_worker()`](crate::endpoint::Endpoint::on_worker) are
+//! called on the worker's thread, whichever thread dispatches their messages. Handlers holding thread-affine
+//! resources, such as GL contexts or COM objects, are therefore always called on the same thread.
+//!
+//! Each worker runs a thread named `salish-<name>`, spawned when the first endpoint is assigned to it. Dispatch
+//! enqueues messages to the worker without waiting, and the worker handles them in the order they were enqueued.
+//! A thread is stopped once the router and the endpoints assigned to it are dropped, after handling the messages
+//! which were already enqueued.
+
+use std::{
+    collections::BTreeMap,
+    sync::{mpsc, Arc},
+    thread::JoinHandle,
+};
+
+use anylock::{AnyLock, ParkingLotMutex, ParkingLotRwLock};
+use tracing::{debug, warn};
+
+/// Work enqueued to a worker thread
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A named worker thread, handling the jobs sent to its queue
+pub(crate) struct Worker {
+    name: String,
+    queue: ParkingLotMutex<Option<mpsc::Sender<Job>>>,
+    thread: ParkingLotMutex<Option<JoinHandle<()>>>,
+}
+
+impl std::fmt::Debug for Worker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Worker").field("name", &self.name).finish()
+    }
+}
+
+impl Worker {
+    /// Spawn the thread of a worker
+    fn spawn(name: &str) -> Self {
+        let (tx, rx) = mpsc::channel::<Job>();
+
+        let thread = std::thread::Builder::new()
+            .name(format!("salish-{name}"))
+            .spawn(move || {
+                for job in rx {
+                    job();
+                }
+            })
+            .expect("Failed to spawn worker thread");
+
+        debug!("Spawned worker {name}");
+
+        Self {
+            name: name.into(),
+            queue: ParkingLotMutex::new(Some(tx)),
+            thread: ParkingLotMutex::new(Some(thread)),
+        }
+    }
+
+    /// Enqueue a job to run on the worker thread
+    pub(crate) fn execute(&self, job: impl FnOnce() + Send + 'static) {
+        let sent = match &*self.queue.read() {
+            Some(queue) => queue.send(Box::new(job)).is_ok(),
+            None => false,
+        };
+
+        // Sending only fails if a handler panicked on the worker thread
+        if !sent {
+            warn!("Worker {} has stopped", self.name);
+        }
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        // Closing the queue ends the thread once it has run the jobs which were already enqueued
+        self.queue.write().take();
+
+        if let Some(thread) = self.thread.write().take() {
+            // The last reference to the worker can be dropped by one of its own jobs
+            if thread.thread().id() != std::thread::current().id() && thread.join().is_err() {
+                warn!("Worker {} panicked", self.name);
+            }
+        }
+
+        debug!("Stopped worker {}", self.name);
+    }
+}
+
+/// Worker threads by name, shared by all clones of a router
+pub(crate) struct Workers {
+    workers: ParkingLotRwLock<BTreeMap<String, Arc<Worker>>>,
+}
+
+impl Default for Workers {
+    fn default() -> Self {
+        Self {
+            workers: ParkingLotRwLock::new(BTreeMap::new()),
+        }
+    }
+}
+
+impl std::fmt::Debug for Workers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.workers.read().keys()).finish()
+    }
+}
+
+impl Workers {
+    /// Get the worker named `name`, spawning its thread if it doesn't exist yet
+    pub(crate) fn get_or_spawn(&self, name: &str) -> Arc<Worker> {
+        if let Some(worker) = self.workers.read().get(name) {
+            return worker.clone();
+        }
+
+        self.workers
+            .write()
+            .entry(name.into())
+            .or_insert_with(|| Arc::new(Worker::spawn(name)))
+            .clone()
+    }
+
+    /// Names of the workers which were spawned
+    pub(crate) fn names(&self) -> Vec<String> {
+        self.workers.read().keys().cloned().collect()
+    }
+}
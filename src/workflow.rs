@@ -0,0 +1,372 @@
+//! Multi-step workflows with compensation
+//!
+//! A [`Workflow`] is a saga of steps run in order, each triggered by a message of its payload type arriving while
+//! the step is current. A step handler returning an error fails the workflow, and a step without a message within
+//! its timeout times it out. In both cases the compensation handlers of the completed steps are run in reverse
+//! order, to undo their effects.
+//!
+//! The workflow broadcasts a [`WorkflowProgress`] message into the router as each step completes, fails, times
+//! out or is compensated, and once the workflow completes, so other endpoints can follow its progress.
+//!
+//! ```
+//! use salish::{router::MessageRouter, workflow::WorkflowStatus, Message};
+//!
+//! #[derive(Debug, Clone)]
+//! struct PaymentAuthorized(u32);
+//!
+//! #[derive(Debug, Clone)]
+//! struct Shipped;
+//!
+//! let router = MessageRouter::<()>::new();
+//! let order = router
+//!     .workflow("order")
+//!     .step("payment", |_: PaymentAuthorized| Ok(()))
+//!     .compensate(|| println!("Refunding payment"))
+//!     .step("shipping", |_: Shipped| Err("no stock".to_string()));
+//!
+//! router.handle_message(Message::broadcast(PaymentAuthorized(42)));
+//! router.handle_message(Message::broadcast(Shipped));
+//! assert_eq!(order.status(), WorkflowStatus::Failed(1));
+//! ```
+//!
+//! Step timeouts are checked when a message of the workflow arrives, and by [`Workflow::check_timeouts()`], which
+//! should be called periodically such as from a timer of the application. The timeout of the first step starts
+//! when the workflow is created.
+//!
+//! Step handlers run while the workflow is locked, so they must not dispatch messages handled by the same workflow
+//! synchronously. Compensation handlers and progress messages run after the workflow is unlocked.
+
+use std::{
+    any::{Any, TypeId},
+    collections::HashSet,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anylock::{AnyLock, ParkingLotMutex};
+use tracing::{debug, trace, warn};
+
+use crate::{router::MessageRouter, traits::Payload, Message};
+
+/// Broadcast into the router as a workflow progresses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkflowProgress {
+    /// Name of the workflow
+    pub workflow: String,
+
+    /// Name of the step
+    pub step: &'static str,
+
+    /// Index of the step in the workflow
+    pub index: usize,
+
+    pub event: ProgressEvent,
+}
+
+/// Event reported by a [`WorkflowProgress`] message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProgressEvent {
+    /// The step handler succeeded
+    StepCompleted,
+
+    /// The last step completed, completing the workflow
+    Completed,
+
+    /// The step handler returned an error
+    Failed(String),
+
+    /// No message for the step arrived within its timeout
+    TimedOut,
+
+    /// The compensation handler of the step was run
+    Compensated,
+}
+
+/// Status of a [`Workflow`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkflowStatus {
+    /// Waiting for the message of the step at the index
+    Running(usize),
+
+    /// All steps completed
+    Completed,
+
+    /// The step at the index failed
+    Failed(usize),
+
+    /// The step at the index timed out
+    TimedOut(usize),
+}
+
+/// Step handler taking the payload out of an `Option<M>` slot, so steps of all payload types are stored together
+type StepHandler<'a> = Box<dyn FnMut(&mut dyn Any) -> Option<Result<(), String>> + Send + 'a>;
+
+/// Handler undoing the effects of a completed step
+type Compensation<'a> = Box<dyn FnOnce() + Send + 'a>;
+
+struct Step<'a> {
+    name: &'static str,
+    type_id: TypeId,
+    handler: StepHandler<'a>,
+    timeout: Option<Duration>,
+    compensate: Option<Compensation<'a>>,
+}
+
+/// Effects of a change of the workflow, applied once the workflow is unlocked
+#[derive(Default)]
+struct Effects<'a> {
+    progress: Vec<WorkflowProgress>,
+
+    /// Compensations to run in order, with the progress message reporting each of them
+    compensations: Vec<(Compensation<'a>, WorkflowProgress)>,
+}
+
+impl<'a> Effects<'a> {
+    fn apply<R: Send>(self, router: &MessageRouter<'a, R>) {
+        for progress in self.progress {
+            router.handle_message(Message::broadcast(progress));
+        }
+
+        for (compensate, progress) in self.compensations {
+            debug!(
+                "Compensating step {} of {}",
+                progress.step, progress.workflow
+            );
+            compensate();
+            router.handle_message(Message::broadcast(progress));
+        }
+    }
+}
+
+struct WorkflowState<'a> {
+    name: String,
+    steps: Vec<Step<'a>>,
+    status: WorkflowStatus,
+
+    /// Time the current step became current, for its timeout
+    step_started: Instant,
+}
+
+impl<'a> WorkflowState<'a> {
+    fn progress(&self, index: usize, event: ProgressEvent) -> WorkflowProgress {
+        WorkflowProgress {
+            workflow: self.name.clone(),
+            step: self.steps[index].name,
+            index,
+            event,
+        }
+    }
+
+    /// Pass the payload in `slot` to the current step, if it's of the payload type of the step
+    fn handle(&mut self, type_id: TypeId, slot: &mut dyn Any) -> Effects<'a> {
+        let mut effects = self.check_timeout();
+
+        let WorkflowStatus::Running(index) = self.status else {
+            trace!(
+                "Ignoring message for {} in status {:?}",
+                self.name,
+                self.status
+            );
+            return effects;
+        };
+
+        let step = &mut self.steps[index];
+        if step.type_id != type_id {
+            trace!("Ignoring message for {} in step {}", self.name, step.name);
+            return effects;
+        }
+
+        match (step.handler)(slot) {
+            Some(Ok(())) => {
+                effects
+                    .progress
+                    .push(self.progress(index, ProgressEvent::StepCompleted));
+
+                if index + 1 == self.steps.len() {
+                    debug!("Workflow {} completed", self.name);
+                    self.status = WorkflowStatus::Completed;
+                    effects
+                        .progress
+                        .push(self.progress(index, ProgressEvent::Completed));
+                } else {
+                    self.status = WorkflowStatus::Running(index + 1);
+                    self.step_started = Instant::now();
+                }
+            }
+            Some(Err(reason)) => {
+                warn!("Step {} of {} failed: {reason}", step.name, self.name);
+                self.status = WorkflowStatus::Failed(index);
+                effects
+                    .progress
+                    .push(self.progress(index, ProgressEvent::Failed(reason)));
+                self.compensate(index, &mut effects);
+            }
+            None => {}
+        }
+
+        effects
+    }
+
+    /// Time out the current step if no message arrived within its timeout
+    fn check_timeout(&mut self) -> Effects<'a> {
+        let mut effects = Effects::default();
+
+        let WorkflowStatus::Running(index) = self.status else {
+            return effects;
+        };
+
+        let Some(step) = self.steps.get(index) else {
+            return effects;
+        };
+
+        if step
+            .timeout
+            .is_some_and(|timeout| self.step_started.elapsed() >= timeout)
+        {
+            warn!("Step {} of {} timed out", step.name, self.name);
+            self.status = WorkflowStatus::TimedOut(index);
+            effects
+                .progress
+                .push(self.progress(index, ProgressEvent::TimedOut));
+            self.compensate(index, &mut effects);
+        }
+
+        effects
+    }
+
+    /// Take the compensations of the steps completed before `failed`, in reverse order
+    fn compensate(&mut self, failed: usize, effects: &mut Effects<'a>) {
+        for index in (0..failed).rev() {
+            if let Some(compensate) = self.steps[index].compensate.take() {
+                let progress = self.progress(index, ProgressEvent::Compensated);
+                effects.compensations.push((compensate, progress));
+            }
+        }
+    }
+}
+
+/// Workflow of steps triggered by messages of a router.
+/// The endpoints of the workflow are deregistered when this is dropped.
+pub struct Workflow<'a, R> {
+    router: MessageRouter<'a, R>,
+    state: Arc<ParkingLotMutex<WorkflowState<'a>>>,
+
+    /// Payload types with an endpoint
+    types: HashSet<TypeId>,
+
+    /// Type erased endpoints of the payload types of the steps
+    endpoints: Vec<Box<dyn Send + Sync + 'a>>,
+}
+
+impl<'a, R> std::fmt::Debug for Workflow<'a, R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = self.state.read();
+        f.debug_struct("Workflow")
+            .field("name", &state.name)
+            .field("steps", &state.steps.len())
+            .field("status", &state.status)
+            .finish()
+    }
+}
+
+impl<'a, R> Workflow<'a, R>
+where
+    R: Default + Send + 'a,
+{
+    pub(crate) fn new(router: MessageRouter<'a, R>, name: String) -> Self {
+        Self {
+            router,
+            state: Arc::new(ParkingLotMutex::new(WorkflowState {
+                name,
+                steps: Vec::new(),
+                status: WorkflowStatus::Running(0),
+                step_started: Instant::now(),
+            })),
+            types: HashSet::new(),
+            endpoints: Vec::new(),
+        }
+    }
+
+    /// Add a step triggered by a message of payload type `M`. The workflow fails if `handler` returns an error.
+    pub fn step<M>(
+        mut self,
+        name: &'static str,
+        mut handler: impl FnMut(M) -> Result<(), String> + Send + 'a,
+    ) -> Self
+    where
+        M: Payload + 'static,
+    {
+        let type_id = TypeId::of::<M>();
+
+        self.state.write().steps.push(Step {
+            name,
+            type_id,
+            handler: Box::new(move |slot| {
+                let msg = slot.downcast_mut::<Option<M>>()?.take()?;
+                Some(handler(msg))
+            }),
+            timeout: None,
+            compensate: None,
+        });
+
+        // Register a single endpoint per payload type, so unicast messages reach the workflow once
+        if self.types.insert(type_id) {
+            let state = self.state.clone();
+            let router = self.router.clone();
+
+            let endpoint = self
+                .router
+                .create_endpoint::<M>()
+                .message(move |_src, msg| {
+                    let effects = state.write().handle(type_id, &mut Some(msg));
+                    effects.apply(&router);
+                    R::default()
+                });
+
+            self.endpoints.push(Box::new(endpoint));
+        }
+
+        self
+    }
+
+    /// Time out the last added step if its message doesn't arrive within `timeout` of the step becoming current
+    pub fn timeout(self, timeout: Duration) -> Self {
+        if let Some(step) = self.state.write().steps.last_mut() {
+            step.timeout = Some(timeout);
+        }
+        self
+    }
+
+    /// Run `compensate` to undo the last added step if a later step fails or times out
+    pub fn compensate(self, compensate: impl FnOnce() + Send + 'a) -> Self {
+        if let Some(step) = self.state.write().steps.last_mut() {
+            step.compensate = Some(Box::new(compensate));
+        }
+        self
+    }
+
+    /// Time out the current step if its message didn't arrive within its timeout, returning true if it timed out
+    pub fn check_timeouts(&self) -> bool {
+        let effects = self.state.write().check_timeout();
+        let timed_out = !effects.progress.is_empty();
+
+        effects.apply(&self.router);
+        timed_out
+    }
+}
+
+impl<'a, R> Workflow<'a, R> {
+    /// Get the status of the workflow
+    pub fn status(&self) -> WorkflowStatus {
+        self.state.read().status
+    }
+
+    /// Get the name of the current step, while the workflow is running
+    pub fn current_step(&self) -> Option<&'static str> {
+        let state = self.state.read();
+        match state.status {
+            WorkflowStatus::Running(index) => state.steps.get(index).map(|step| step.name),
+            _ => None,
+        }
+    }
+}